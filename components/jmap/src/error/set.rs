@@ -96,6 +96,10 @@ pub enum SetErrorType {
     InvalidScript,
     #[serde(rename = "scriptIsActive")]
     ScriptIsActive,
+    #[serde(rename = "tooManyItems")]
+    TooManyItems,
+    #[serde(rename = "conflict")]
+    Conflict,
 }
 
 impl SetErrorType {
@@ -126,6 +130,8 @@ impl SetErrorType {
             SetErrorType::AlreadyExists => "alreadyExists",
             SetErrorType::InvalidScript => "invalidScript",
             SetErrorType::ScriptIsActive => "scriptIsActive",
+            SetErrorType::TooManyItems => "tooManyItems",
+            SetErrorType::Conflict => "conflict",
         }
     }
 }
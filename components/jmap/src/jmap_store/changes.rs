@@ -23,12 +23,13 @@
 
 use super::Object;
 use crate::{
+    error::method::MethodError,
     request::changes::{ChangesRequest, ChangesResponse},
     types::json_pointer::JSONPointerEval,
     types::state::JMAPState,
 };
 use store::{
-    core::{collection::Collection, error::StoreError},
+    core::collection::Collection,
     log::changes::{Change, Query},
     AccountId, JMAPStore, Store,
 };
@@ -71,7 +72,7 @@ where
         let collection = O::collection();
         let max_changes = request.max_changes.unwrap_or(0);
         let max_changes = if self.config.changes_max_results > 0
-            && self.config.changes_max_results < max_changes
+            && (max_changes == 0 || self.config.changes_max_results < max_changes)
         {
             self.config.changes_max_results
         } else {
@@ -96,11 +97,7 @@ where
                     collection,
                     Query::Since(*change_id),
                 )?
-                .ok_or_else(|| {
-                    StoreError::InvalidArguments(
-                        "The specified stateId does could not be found.".to_string(),
-                    )
-                })?,
+                .ok_or(MethodError::CannotCalculateChanges)?,
             ),
             JMAPState::Intermediate(intermediate_state) => {
                 let mut changelog = self
@@ -109,11 +106,7 @@ where
                         collection,
                         Query::RangeInclusive(intermediate_state.from_id, intermediate_state.to_id),
                     )?
-                    .ok_or_else(|| {
-                        StoreError::InvalidArguments(
-                            "The specified stateId does could not be found.".to_string(),
-                        )
-                    })?;
+                    .ok_or(MethodError::CannotCalculateChanges)?;
                 if intermediate_state.items_sent >= changelog.changes.len() {
                     (
                         0,
@@ -122,11 +115,7 @@ where
                             collection,
                             Query::Since(intermediate_state.to_id),
                         )?
-                        .ok_or_else(|| {
-                            StoreError::InvalidArguments(
-                                "The specified stateId does could not be found.".to_string(),
-                            )
-                        })?,
+                        .ok_or(MethodError::CannotCalculateChanges)?,
                     )
                 } else {
                     changelog.changes.drain(
@@ -73,7 +73,8 @@ where
         let account_id = request.account_id.get_document_id();
         let from_account_id = request.from_account_id.get_document_id();
 
-        if account_id == from_account_id {
+        // Emails can be copied within an account, e.g. to keep a copy in another mailbox
+        if account_id == from_account_id && collection != Collection::Mail {
             return Err(MethodError::InvalidArguments(
                 "From accountId is equal to fromAccountId".to_string(),
             ));
@@ -24,13 +24,14 @@
 use std::sync::Arc;
 
 use store::{
-    core::JMAPIdPrefix,
+    core::{collection::Collection, JMAPIdPrefix},
+    log::changes::ChangeId,
     read::{
         comparator::Comparator,
         filter::{Filter, FilterOperator, LogicalOperator},
     },
     roaring::RoaringBitmap,
-    AccountId, DocumentId, JMAPStore, SharedBitmap, Store,
+    AccountId, DocumentId, JMAPStore, QueryCacheEntry, QueryCacheKey, SharedBitmap, Store,
 };
 
 use crate::{
@@ -55,6 +56,7 @@ where
     pub request: QueryRequest<O>,
     pub filter: Filter,
     pub comparator: Comparator,
+    pub cache_key: Option<QueryCacheKey>,
 }
 
 pub trait QueryObject: Object {
@@ -98,9 +100,36 @@ where
             request,
             filter: Filter::None,
             comparator: Comparator::None,
+            cache_key: None,
         })
     }
 
+    /// Caches the results of this query, identified by its serialized filter and sort,
+    /// until the collection or any of the other collections it depends on changes.
+    /// Queries on shared accounts are not cached as access to them can be revoked.
+    pub fn cache_results(&mut self, query: String, depends_on: &[Collection]) -> crate::Result<()> {
+        if self.store.query_cache.is_none() || self.shared_documents.is_some() {
+            return Ok(());
+        }
+
+        let mut change_ids = Vec::with_capacity(depends_on.len() + 1);
+        for collection in [O::collection()].iter().chain(depends_on) {
+            change_ids.push(
+                self.store
+                    .get_last_change_id(self.account_id, *collection)?
+                    .unwrap_or(ChangeId::MAX),
+            );
+        }
+        self.cache_key = QueryCacheKey {
+            account_id: self.account_id,
+            collection: O::collection(),
+            change_ids,
+            query,
+        }
+        .into();
+        Ok(())
+    }
+
     pub fn parse_filter(
         &mut self,
         mut parse_fnc: impl FnMut(O::Filter) -> crate::Result<Filter>,
@@ -171,7 +200,10 @@ where
         if let Some(sort) = self.request.sort.take() {
             let mut terms: Vec<Comparator> = Vec::with_capacity(sort.len());
             for comp in sort {
-                terms.push(parse_fnc(comp)?);
+                match parse_fnc(comp)? {
+                    Comparator::List(list) => terms.extend(list),
+                    comp => terms.push(comp),
+                }
             }
             self.comparator = Comparator::List(terms);
         }
@@ -179,7 +211,7 @@ where
     }
 
     pub fn query<X, W>(
-        self,
+        mut self,
         filter_map_fnc: X,
         extra_filters: Option<W>,
     ) -> crate::Result<QueryResponse>
@@ -195,6 +227,7 @@ where
             total: None,
             limit: None,
             ids: Vec::with_capacity(0),
+            is_truncated: false,
             is_immutable: false,
             can_calculate_changes: true,
         };
@@ -206,6 +239,10 @@ where
             }
         }
 
+        if let Some(cache_key) = self.cache_key.take() {
+            return self.query_cached(result, cache_key, filter_map_fnc, extra_filters);
+        }
+
         let results_it = self.store.query_store::<X>(
             self.account_id,
             collection,
@@ -213,14 +250,21 @@ where
             self.comparator,
         )?;
 
+        // Only the first 'query-max-total' results are considered, the store results are
+        // collected first when they exceed it as filter_map_fnc may drop (or collapse) some.
+        let max_total = self.store.config.query_max_total;
+        let is_capped = max_total > 0 && results_it.len() > max_total;
+
         let limit = if let Some(limit) = &self.request.limit {
             if *limit > 0 {
                 std::cmp::min(*limit, self.store.config.query_max_results)
-            } else {
+            } else if !is_capped {
                 if self.request.calculate_total.unwrap_or(false) {
                     result.total = Some(results_it.len());
                 }
                 return Ok(result);
+            } else {
+                0
             }
         } else {
             self.store.config.query_max_results
@@ -236,8 +280,8 @@ where
         let anchor = self.request.anchor;
         let anchor_offset = self.request.anchor_offset.unwrap_or(0);
 
-        let total_results = if let Some(mut extra_filters) = extra_filters {
-            let results = if let Some(shared_documents) = self.shared_documents {
+        let total_results = if extra_filters.is_some() || is_capped {
+            let mut results = if let Some(shared_documents) = self.shared_documents {
                 // Filter out documents that are not shared
                 results_it
                     .set_filter_map(filter_map_fnc)
@@ -249,19 +293,29 @@ where
                             None
                         }
                     })
+                    .take(if is_capped { max_total + 1 } else { usize::MAX })
                     .collect::<Vec<JMAPId>>()
             } else {
                 results_it
                     .set_filter_map(filter_map_fnc)
                     .into_iter()
                     .map(|id| id.into())
+                    .take(if is_capped { max_total + 1 } else { usize::MAX })
                     .collect::<Vec<JMAPId>>()
             };
 
-            let results = extra_filters(results)?;
+            if is_capped && results.len() > max_total {
+                results.truncate(max_total);
+                result.is_truncated = true;
+            }
+            if let Some(mut extra_filters) = extra_filters {
+                results = extra_filters(results)?;
+            }
             let total_results = results.len();
 
-            result.paginate(results.into_iter(), limit, position, anchor, anchor_offset)?;
+            if self.request.limit != Some(0) {
+                result.paginate(results.into_iter(), limit, position, anchor, anchor_offset)?;
+            }
 
             total_results
         } else {
@@ -312,6 +366,104 @@ where
     }
 }
 
+impl<'y, O, T> QueryHelper<'y, O, T>
+where
+    T: for<'x> Store<'x> + 'static,
+    O: QueryObject,
+{
+    fn query_cached<X, W>(
+        self,
+        mut result: QueryResponse,
+        cache_key: QueryCacheKey,
+        filter_map_fnc: X,
+        extra_filters: Option<W>,
+    ) -> crate::Result<QueryResponse>
+    where
+        X: FnMut(DocumentId) -> store::Result<Option<store::JMAPId>>,
+        W: FnMut(Vec<JMAPId>) -> crate::Result<Vec<JMAPId>>,
+    {
+        let query_cache = self.store.query_cache.as_ref().unwrap();
+        let entry = if let Some(entry) = query_cache.get(&cache_key) {
+            entry
+        } else {
+            // All results are obtained so any page can be served from the cache
+            let results_it = self.store.query_store::<X>(
+                self.account_id,
+                O::collection(),
+                self.filter,
+                self.comparator,
+            )?;
+            let max_total = self.store.config.query_max_total;
+            let max_results = if max_total > 0 {
+                max_total + 1
+            } else {
+                usize::MAX
+            };
+            let mut total = results_it.len();
+            let mut ids = results_it
+                .set_filter_map(filter_map_fnc)
+                .into_iter()
+                .map(|id| id.into())
+                .take(max_results)
+                .collect::<Vec<JMAPId>>();
+
+            // Truncation is checked once filter_map_fnc has dropped (or collapsed) results
+            let is_truncated = max_total > 0 && ids.len() > max_total;
+            if is_truncated {
+                ids.truncate(max_total);
+                total = ids.len();
+            }
+            if let Some(mut extra_filters) = extra_filters {
+                ids = extra_filters(ids)?;
+                total = ids.len();
+            }
+
+            let entry = Arc::new(QueryCacheEntry {
+                ids: ids.into_iter().map(Into::into).collect(),
+                total,
+                is_truncated,
+            });
+            query_cache.insert(cache_key, entry.clone());
+            entry
+        };
+        result.is_truncated = entry.is_truncated;
+
+        let limit = match self.request.limit {
+            Some(0) => {
+                if self.request.calculate_total.unwrap_or(false) {
+                    result.total = Some(entry.total);
+                }
+                return Ok(result);
+            }
+            Some(limit) => std::cmp::min(limit, self.store.config.query_max_results),
+            None => self.store.config.query_max_results,
+        };
+
+        result.ids = Vec::with_capacity(if limit > 0 && limit < entry.ids.len() {
+            limit
+        } else {
+            entry.ids.len()
+        });
+        result.paginate(
+            entry.ids.iter().map(|id| JMAPId::new(*id)),
+            limit,
+            self.request.position.unwrap_or(0),
+            self.request.anchor,
+            self.request.anchor_offset.unwrap_or(0),
+        )?;
+
+        if limit > 0 && limit < entry.total {
+            result.limit = limit.into();
+        }
+
+        if self.request.calculate_total.unwrap_or(false) {
+            result.total = Some(entry.total);
+        }
+
+        Ok(result)
+    }
+}
+
 impl QueryResponse {
     pub fn paginate<W>(
         &mut self,
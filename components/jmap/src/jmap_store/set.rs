@@ -44,6 +44,7 @@ use store::core::vec_map::VecMap;
 use store::log::changes::ChangeId;
 use store::parking_lot::MutexGuard;
 use store::write::batch::WriteBatch;
+use store::write::update::Changes;
 use store::AccountId;
 use store::{roaring::RoaringBitmap, JMAPStore, Store};
 
@@ -87,6 +88,9 @@ where
         let collection = O::collection();
         let account_id = request.account_id.get_document_id();
 
+        // Lock the collection before reading its state, so no changes can be
+        // written between the ifInState check and the update.
+        let lock = store.lock_collection(account_id, collection);
         let old_state = store.get_state(account_id, collection)?;
         if let Some(if_in_state) = request.if_in_state.take() {
             if old_state != if_in_state {
@@ -100,7 +104,7 @@ where
             .unwrap_or_default();
         Ok(SetHelper {
             store,
-            lock: store.lock_collection(account_id, collection),
+            lock,
             changes: WriteBatch::new(account_id),
             document_ids: store
                 .get_document_ids(account_id, collection)?
@@ -283,18 +287,24 @@ where
 
     fn write(&mut self) -> crate::Result<()> {
         if let Some(changes) = self.store.write(self.changes.take())? {
-            self.change_id = changes.change_id;
-            for collection in changes.collections {
-                if let Ok(type_state) = TypeState::try_from(collection) {
-                    if let Some(entry) = self.state_changes.iter_mut().find(|e| e.0 == type_state) {
-                        entry.1 = changes.change_id;
-                    } else {
-                        self.state_changes.push((type_state, changes.change_id));
-                    }
+            self.add_changes(changes);
+        }
+        Ok(())
+    }
+
+    // Records changes written outside of the helper's batch, so their
+    // state is returned in the response and published.
+    pub fn add_changes(&mut self, changes: Changes) {
+        self.change_id = changes.change_id;
+        for collection in changes.collections {
+            if let Ok(type_state) = TypeState::try_from(collection) {
+                if let Some(entry) = self.state_changes.iter_mut().find(|e| e.0 == type_state) {
+                    entry.1 = changes.change_id;
+                } else {
+                    self.state_changes.push((type_state, changes.change_id));
                 }
             }
         }
-        Ok(())
     }
 
     pub fn commit_changes(&mut self) -> crate::Result<()> {
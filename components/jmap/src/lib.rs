@@ -50,6 +50,17 @@ pub enum URI {
     WebSocket,
     #[serde(rename(serialize = "urn:ietf:params:jmap:sieve"))]
     Sieve,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:mdn"))]
+    MDN,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:webpush-vapid"))]
+    WebPushVapid,
+}
+
+impl URI {
+    // Session-wide capabilities are not listed under the accounts.
+    pub fn is_account_capability(&self) -> bool {
+        !matches!(self, URI::WebPushVapid)
+    }
 }
 
 pub type Result<T> = std::result::Result<T, MethodError>;
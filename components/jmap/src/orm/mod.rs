@@ -23,6 +23,7 @@
 
 pub mod acl;
 pub mod merge;
+pub mod reindex;
 pub mod serialize;
 pub mod tags;
 pub mod update;
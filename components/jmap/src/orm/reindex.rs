@@ -0,0 +1,193 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::vec_map::VecMap;
+use store::serialize::key::{IndexKey, SCHEMA_VERSIONS_KEY};
+use store::serialize::{StoreDeserialize, StoreSerialize};
+use store::tracing::info;
+use store::write::batch::WriteBatch;
+use store::write::operation::WriteOperation;
+use store::{ColumnFamily, Direction, JMAPStore, LongInteger, Store};
+
+use crate::SUPERUSER_ID;
+
+use super::serialize::JMAPOrm;
+use super::Object;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+pub struct SchemaVersions {
+    versions: VecMap<Collection, LongInteger>,
+}
+
+pub trait JMAPReindex {
+    fn get_schema_versions(&self) -> store::Result<SchemaVersions>;
+
+    fn set_schema_versions(&self, versions: &SchemaVersions) -> store::Result<()>;
+
+    fn reindex_if_changed<O>(&self, versions: &mut SchemaVersions) -> store::Result<Option<usize>>
+    where
+        O: Object + 'static;
+
+    fn reindex<O>(&self) -> store::Result<usize>
+    where
+        O: Object + 'static;
+}
+
+impl<T> JMAPReindex for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn get_schema_versions(&self) -> store::Result<SchemaVersions> {
+        Ok(self
+            .db
+            .get::<SchemaVersions>(ColumnFamily::Values, SCHEMA_VERSIONS_KEY)?
+            .unwrap_or_default())
+    }
+
+    fn set_schema_versions(&self, versions: &SchemaVersions) -> store::Result<()> {
+        self.db.set(
+            ColumnFamily::Values,
+            SCHEMA_VERSIONS_KEY,
+            &versions.serialize().unwrap_or_default(),
+        )
+    }
+
+    fn reindex_if_changed<O>(&self, versions: &mut SchemaVersions) -> store::Result<Option<usize>>
+    where
+        O: Object + 'static,
+    {
+        let collection = O::collection();
+        let version = schema_version::<O>();
+        if versions.get(collection) == Some(version) {
+            return Ok(None);
+        }
+
+        info!(
+            "Indexing schema for {:?} changed, reindexing all objects.",
+            collection
+        );
+        let num_reindexed = self.reindex::<O>()?;
+        versions.set(collection, version);
+        info!("Reindexed {} {:?} objects.", num_reindexed, collection);
+
+        Ok(Some(num_reindexed))
+    }
+
+    fn reindex<O>(&self) -> store::Result<usize>
+    where
+        O: Object + 'static,
+    {
+        let collection = O::collection();
+        let mut account_ids = self
+            .get_document_ids(SUPERUSER_ID, Collection::Principal)?
+            .unwrap_or_default();
+        account_ids.insert(SUPERUSER_ID);
+        let mut num_reindexed = 0;
+
+        for account_id in account_ids {
+            let _lock = self.lock_collection(account_id, collection);
+            let document_ids =
+                if let Some(document_ids) = self.get_document_ids(account_id, collection)? {
+                    document_ids
+                } else {
+                    continue;
+                };
+
+            // Remove the keys written with the previous index definitions
+            let prefix = IndexKey::serialize_collection(account_id, collection);
+            let mut delete_batch = Vec::new();
+            for (key, _) in self
+                .db
+                .iterator(ColumnFamily::Indexes, &prefix, Direction::Forward)?
+            {
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                delete_batch.push(WriteOperation::delete(ColumnFamily::Indexes, key.to_vec()));
+            }
+            if !delete_batch.is_empty() {
+                self.db.write(delete_batch)?;
+            }
+
+            let mut batch = WriteBatch::new(account_id);
+
+            for document_id in document_ids {
+                if let Some(orm) = self.get_orm::<O>(account_id, document_id)? {
+                    let mut document = Document::new(collection, document_id);
+                    orm.index(&mut document);
+                    batch.update_document(document);
+                    num_reindexed += 1;
+                }
+            }
+
+            if !batch.is_empty() {
+                self.write(batch)?;
+            }
+        }
+
+        Ok(num_reindexed)
+    }
+}
+
+/// Returns a fingerprint of the index definitions of an object, which changes
+/// whenever a property is added to or removed from `Object::indexed()` or its
+/// index options are modified.
+pub fn schema_version<O>() -> LongInteger
+where
+    O: Object,
+{
+    // FNV-1a, which unlike the std hasher is stable across releases
+    let mut hash: LongInteger = 0xcbf29ce484222325;
+    for (property, options) in O::indexed() {
+        let property: u8 = property.clone().into();
+        for byte in std::iter::once(property).chain(options.to_le_bytes()) {
+            hash ^= byte as LongInteger;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+impl SchemaVersions {
+    pub fn get(&self, collection: Collection) -> Option<LongInteger> {
+        self.versions.get(&collection).copied()
+    }
+
+    pub fn set(&mut self, collection: Collection, version: LongInteger) {
+        self.versions.set(collection, version);
+    }
+}
+
+impl StoreSerialize for SchemaVersions {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        store::bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for SchemaVersions {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        store::bincode::deserialize(bytes).ok()
+    }
+}
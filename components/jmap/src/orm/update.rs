@@ -82,6 +82,11 @@ where
         self.update_document(document, true);
     }
 
+    /// Writes the indexes of an ORM object that is already stored.
+    pub fn index(self, document: &mut Document) {
+        self.update_document(document, false);
+    }
+
     fn update_document(self, document: &mut Document, is_delete: bool) {
         let indexed = T::indexed();
         if indexed.is_empty() && self.tags.is_empty() {
@@ -50,6 +50,8 @@ impl Display for Property {
             Property::Members => f.write_str("members"),
             Property::Aliases => f.write_str("aliases"),
             Property::ACL => f.write_str("acl"),
+            Property::Locale => f.write_str("locale"),
+            Property::ExpandedMembers => f.write_str("expandedMembers"),
             Property::Invalid => Ok(()),
         }
     }
@@ -78,6 +80,8 @@ impl From<FieldId> for Property {
             11 => Property::Picture,
             12 => Property::Members,
             13 => Property::ACL,
+            14 => Property::Locale,
+            15 => Property::ExpandedMembers,
             _ => Property::Invalid,
         }
     }
@@ -111,6 +115,8 @@ impl Property {
             "picture" => Property::Picture,
             "members" => Property::Members,
             "acl" => Property::ACL,
+            "locale" => Property::Locale,
+            "expandedMembers" => Property::ExpandedMembers,
             _ => Property::Invalid,
         }
     }
@@ -167,6 +167,7 @@ impl Object for Principal {
             (Property::Capabilities, 100 * 10),
             (Property::Description, 512),
             (Property::Timezone, 100),
+            (Property::Locale, 35),
             (Property::Secret, 2048),
             (Property::DKIM, 100),
         ]
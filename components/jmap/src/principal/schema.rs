@@ -51,10 +51,13 @@ pub enum Property {
     Picture = 11,
     Members = 12,
     ACL = 13,
-    Invalid = 14,
+    Locale = 14,
+    ExpandedMembers = 15,
+    Invalid = 16,
 }
 
 pub const ACCOUNTS_TO_DELETE: u8 = u8::MAX;
+pub const ACCOUNTS_WITH_SNOOZED: u8 = u8::MAX - 1;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Type {
@@ -112,6 +112,16 @@ impl<'de> serde::de::Visitor<'de> for PrincipalVisitor {
                         },
                     );
                 }
+                "locale" => {
+                    properties.append(
+                        Property::Locale,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "email" => {
                     properties.append(
                         Property::Email,
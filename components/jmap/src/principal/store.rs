@@ -39,6 +39,7 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn principal_to_email(&self, id: AccountId) -> crate::Result<Option<String>>;
+    fn principal_to_locale(&self, id: AccountId) -> crate::Result<Option<String>>;
     fn principal_to_id<U>(&self, email: &str) -> crate::error::set::Result<AccountId, U>;
 }
 
@@ -59,6 +60,19 @@ where
             }))
     }
 
+    fn principal_to_locale(&self, id: AccountId) -> crate::Result<Option<String>> {
+        Ok(self
+            .get_orm::<Principal>(SUPERUSER_ID, id)?
+            .and_then(|mut p| p.remove(&Property::Locale))
+            .and_then(|p| {
+                if let Value::Text { value } = p {
+                    Some(value)
+                } else {
+                    None
+                }
+            }))
+    }
+
     fn principal_to_id<U>(&self, email: &str) -> crate::error::set::Result<AccountId, U> {
         let email_clean = sanitize_email(email).ok_or_else(|| {
             SetError::invalid_properties()
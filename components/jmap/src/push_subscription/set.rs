@@ -31,15 +31,17 @@ use crate::types::date::JMAPDate;
 use crate::types::jmap::JMAPId;
 use crate::{jmap_store::set::SetObject, request::set::SetRequest};
 use store::chrono::Utc;
+use store::core::collection::Collection;
 use store::core::document::Document;
 use store::core::error::StoreError;
 use store::rand::distributions::Alphanumeric;
 use store::rand::{thread_rng, Rng};
+use store::write::batch::WriteBatch;
+use store::write::update::Changes;
 use store::{AccountId, JMAPStore, Store};
 
 use super::schema::{Property, PushSubscription, Value};
 
-const EXPIRES_MAX: i64 = 7 * 24 * 3600; // 7 days
 const VERIFICATION_CODE_LEN: usize = 32;
 
 impl SetObject for PushSubscription {
@@ -68,6 +70,11 @@ where
         account_id: AccountId,
         document: &mut Document,
     ) -> store::Result<()>;
+
+    fn push_subscription_purge_expired(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<Option<Changes>>;
 }
 
 impl<T> JMAPSetPushSubscription<T> for JMAPStore<T>
@@ -79,6 +86,7 @@ where
         request: SetRequest<PushSubscription>,
     ) -> crate::Result<SetResponse<PushSubscription>> {
         let mut helper = SetHelper::new(self, request)?;
+        let expires_max = self.config.push_expires_max as i64;
 
         helper.create(|_create_id, item, helper, document| {
             // Limit the number of subscriptions
@@ -125,13 +133,13 @@ where
             let current_time = Utc::now().timestamp();
             let expires = expires
                 .map(|dt| dt.timestamp())
-                .unwrap_or_else(|| current_time + EXPIRES_MAX);
+                .unwrap_or_else(|| current_time + expires_max);
             fields.set(
                 Property::Expires,
                 Value::DateTime {
                     value: JMAPDate::from_timestamp(
-                        if expires > current_time && (expires - current_time) > EXPIRES_MAX {
-                            current_time + EXPIRES_MAX
+                        if expires > current_time && (expires - current_time) > expires_max {
+                            current_time + expires_max
                         } else {
                             expires
                         },
@@ -188,7 +196,7 @@ where
                             }
                         }
                         (Property::Expires, Value::Null) => {
-                            expires = (Utc::now().timestamp() + EXPIRES_MAX).into();
+                            expires = (Utc::now().timestamp() + expires_max).into();
                             continue;
                         }
                         (Property::Types, Value::Null) => Value::Null,
@@ -210,8 +218,8 @@ where
                     Property::Expires,
                     Value::DateTime {
                         value: JMAPDate::from_timestamp(
-                            if expires > current_time && (expires - current_time) > EXPIRES_MAX {
-                                current_time + EXPIRES_MAX
+                            if expires > current_time && (expires - current_time) > expires_max {
+                                current_time + expires_max
                             } else {
                                 expires
                             },
@@ -254,4 +262,40 @@ where
 
         Ok(())
     }
+
+    fn push_subscription_purge_expired(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<Option<Changes>> {
+        let document_ids = if let Some(document_ids) =
+            self.get_document_ids(account_id, Collection::PushSubscription)?
+        {
+            document_ids
+        } else {
+            return Ok(None);
+        };
+        let current_time = Utc::now().timestamp();
+
+        let mut batch = WriteBatch::new(account_id);
+        for document_id in document_ids {
+            if let Some(orm) = self.get_orm::<PushSubscription>(account_id, document_id)? {
+                if orm
+                    .get(&Property::Expires)
+                    .and_then(|p| p.as_timestamp())
+                    .map_or(false, |expires| expires <= current_time)
+                {
+                    let mut document = Document::new(Collection::PushSubscription, document_id);
+                    orm.delete(&mut document);
+                    batch.delete_document(document);
+                    batch.log_delete(Collection::PushSubscription, document_id);
+                }
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write(batch)
+        } else {
+            Ok(None)
+        }
+    }
 }
@@ -62,6 +62,10 @@ pub struct CopyRequest<T: SetObject> {
     #[serde(rename = "destroyFromIfInState")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub destroy_from_if_in_state: Option<JMAPState>,
+
+    #[serde(rename = "idempotencyKey")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
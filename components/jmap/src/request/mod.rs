@@ -175,6 +175,7 @@ pub enum Method {
     QueryMailbox,
     QueryChangesMailbox,
     SetMailbox,
+    SetSearchMailbox,
     GetThread,
     ChangesThread,
     GetEmail,
@@ -185,7 +186,9 @@ pub enum Method {
     CopyEmail,
     ImportEmail,
     ParseEmail,
+    RevisionsEmail,
     GetSearchSnippet,
+    ParseMDN,
     GetIdentity,
     ChangesIdentity,
     SetIdentity,
@@ -222,6 +225,7 @@ impl serde::Serialize for Method {
             Method::QueryMailbox => "Mailbox/query",
             Method::QueryChangesMailbox => "Mailbox/queryChanges",
             Method::SetMailbox => "Mailbox/set",
+            Method::SetSearchMailbox => "Mailbox/setSearch",
             Method::GetThread => "Thread/get",
             Method::ChangesThread => "Thread/changes",
             Method::GetEmail => "Email/get",
@@ -232,7 +236,9 @@ impl serde::Serialize for Method {
             Method::CopyEmail => "Email/copy",
             Method::ImportEmail => "Email/import",
             Method::ParseEmail => "Email/parse",
+            Method::RevisionsEmail => "Email/revisions",
             Method::GetSearchSnippet => "SearchSnippet/get",
+            Method::ParseMDN => "MDN/parse",
             Method::GetIdentity => "Identity/get",
             Method::ChangesIdentity => "Identity/changes",
             Method::SetIdentity => "Identity/set",
@@ -278,6 +284,7 @@ impl<'de> serde::de::Visitor<'de> for MethodVisitor {
             "Mailbox/query" => Method::QueryMailbox,
             "Mailbox/queryChanges" => Method::QueryChangesMailbox,
             "Mailbox/set" => Method::SetMailbox,
+            "Mailbox/setSearch" => Method::SetSearchMailbox,
             "Thread/get" => Method::GetThread,
             "Thread/changes" => Method::ChangesThread,
             "Email/get" => Method::GetEmail,
@@ -288,7 +295,9 @@ impl<'de> serde::de::Visitor<'de> for MethodVisitor {
             "Email/copy" => Method::CopyEmail,
             "Email/import" => Method::ImportEmail,
             "Email/parse" => Method::ParseEmail,
+            "Email/revisions" => Method::RevisionsEmail,
             "SearchSnippet/get" => Method::GetSearchSnippet,
+            "MDN/parse" => Method::ParseMDN,
             "Identity/get" => Method::GetIdentity,
             "Identity/changes" => Method::ChangesIdentity,
             "Identity/set" => Method::SetIdentity,
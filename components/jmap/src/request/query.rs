@@ -113,6 +113,10 @@ fn is_true() -> bool {
     true
 }
 
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct QueryResponse {
     #[serde(rename = "accountId")]
@@ -138,6 +142,10 @@ pub struct QueryResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<usize>,
 
+    #[serde(rename = "isTruncated")]
+    #[serde(skip_serializing_if = "is_false")]
+    pub is_truncated: bool,
+
     #[serde(skip)]
     pub is_immutable: bool,
 }
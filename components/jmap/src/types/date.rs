@@ -23,6 +23,11 @@
 
 use std::fmt::Display;
 
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct JMAPDate {
     pub year: u16,
@@ -184,6 +189,40 @@ impl JMAPDate {
             + ((self.tz_hour as i64 * 3600 + self.tz_minute as i64 * 60)
                 * if self.tz_before_gmt { 1 } else { -1 })
     }
+
+    pub fn has_offset(&self) -> bool {
+        self.tz_hour != 0 || self.tz_minute != 0
+    }
+
+    // Formats the date as an RFC 5322 date-time, keeping the original offset
+    pub fn to_rfc822(&self) -> String {
+        let days = JMAPDate {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+            ..Default::default()
+        }
+        .timestamp()
+        .div_euclid(86400);
+
+        format!(
+            "{}, {} {} {:04} {:02}:{:02}:{:02} {}{:02}{:02}",
+            DAYS[(days + 4).rem_euclid(7) as usize],
+            self.day,
+            MONTHS[(self.month.clamp(1, 12) - 1) as usize],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second,
+            if self.tz_before_gmt && self.has_offset() {
+                "-"
+            } else {
+                "+"
+            },
+            self.tz_hour,
+            self.tz_minute,
+        )
+    }
 }
 
 impl Display for JMAPDate {
@@ -275,4 +314,29 @@ mod tests {
             assert_eq!(JMAPDate::from_timestamp(timestamp).timestamp(), timestamp);
         }
     }
+
+    #[test]
+    fn format_rfc822_date() {
+        for (input, expected_result) in [
+            (
+                "1997-11-21T09:55:06-06:00",
+                "Fri, 21 Nov 1997 09:55:06 -0600",
+            ),
+            (
+                "2005-07-02T11:52:37+02:00",
+                "Sat, 2 Jul 2005 11:52:37 +0200",
+            ),
+            (
+                "2018-07-10T11:03:11+10:00",
+                "Tue, 10 Jul 2018 11:03:11 +1000",
+            ),
+            (
+                "2022-03-01T10:00:00-03:30",
+                "Tue, 1 Mar 2022 10:00:00 -0330",
+            ),
+            ("2004-06-28T23:43:45Z", "Mon, 28 Jun 2004 23:43:45 +0000"),
+        ] {
+            assert_eq!(JMAPDate::parse(input).unwrap().to_rfc822(), expected_result);
+        }
+    }
 }
@@ -36,7 +36,7 @@ use jmap::types::date::JMAPDate;
 use jmap::types::jmap::JMAPId;
 use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
 use mail_parser::RfcHeader;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 use store::ahash::{AHashMap, AHashSet};
 use store::blob::BlobId;
 use store::core::collection::Collection;
@@ -102,6 +102,8 @@ where
         account_id: AccountId,
         document: &mut Document,
     ) -> store::Result<()>;
+
+    fn email_submission_rate_check(&self, account_id: AccountId, num_recipients: usize) -> bool;
 }
 
 impl<T> JMAPSetEmailSubmission<T> for JMAPStore<T>
@@ -203,7 +205,7 @@ where
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
                 .unwrap_or(0) as i64;
-            let mut envelope = if let Some(envelope) = envelope {
+            let mut envelope = if let Some(mut envelope) = envelope {
                 if !envelope.mail_from.email.eq_ignore_ascii_case(&mail_from) {
                     return Err(SetError::invalid_properties()
                         .with_property(Property::IdentityId)
@@ -213,16 +215,44 @@ where
                         )));
                 }
 
-                // Parse future release
-                if let Some(parameters) = &envelope.mail_from.parameters {
-                    if let Some(hold_for) = parameters
-                        .get("HOLDFOR")
-                        .and_then(|s| s.as_ref().and_then(|s| s.parse::<u64>().ok()))
-                    {
+                // Parse future release, holds are limited to the maximum delay to protect
+                // against skewed client clocks and past dates are sent immediately.
+                if let Some(parameters) = &mut envelope.mail_from.parameters {
+                    let max_delay = helper.store.config.submission_max_delay;
+                    if let Some(hold_for) = parameters.get("HOLDFOR") {
+                        let mut hold_for = hold_for
+                            .as_ref()
+                            .and_then(|s| s.parse::<u64>().ok())
+                            .ok_or_else(|| {
+                                SetError::invalid_properties()
+                                    .with_property(Property::Envelope)
+                                    .with_description("HOLDFOR has to be a number of seconds.")
+                            })?;
+                        if max_delay > 0 && hold_for > max_delay {
+                            hold_for = max_delay;
+                            parameters.insert("HOLDFOR".to_string(), hold_for.to_string().into());
+                        }
                         send_at += hold_for as i64;
-                    } else if let Some(Some(hold_until)) = parameters.get("HOLDUNTIL") {
-                        if let Some(hold_until) = JMAPDate::parse(hold_until) {
-                            send_at = hold_until.timestamp();
+                    } else if let Some(hold_until) = parameters.get("HOLDUNTIL") {
+                        let hold_until = hold_until
+                            .as_ref()
+                            .and_then(|s| JMAPDate::parse(s))
+                            .ok_or_else(|| {
+                                SetError::invalid_properties()
+                                    .with_property(Property::Envelope)
+                                    .with_description("HOLDUNTIL has to be a valid date.")
+                            })?
+                            .timestamp();
+                        if hold_until <= send_at {
+                            parameters.remove("HOLDUNTIL");
+                        } else if max_delay > 0 && hold_until - send_at > max_delay as i64 {
+                            send_at += max_delay as i64;
+                            parameters.insert(
+                                "HOLDUNTIL".to_string(),
+                                JMAPDate::from_timestamp(send_at).to_string().into(),
+                            );
+                        } else {
+                            send_at = hold_until;
                         }
                     }
                 }
@@ -330,11 +360,21 @@ where
             document.blob(message_data.raw_message, IndexOptions::new());
 
             // Insert envelope
+            let num_recipients = envelope.rcpt_to.len();
             fields.set(Property::Envelope, Value::Envelope { value: envelope });
 
             // Validate fields
             fields.insert_validate(document)?;
 
+            // Enforce outbound rate limits, administrators are exempt. This is the last
+            // check so that only successful submissions count towards the limits.
+            if !helper.acl.member_of.contains(&SUPERUSER_ID)
+                && !self.email_submission_rate_check(helper.account_id, num_recipients)
+            {
+                return Err(SetError::new(SetErrorType::RateLimit)
+                    .with_description("Outbound rate limit exceeded, please try again later."));
+            }
+
             // Update onSuccess actions
             if has_on_success {
                 let id_ref = MaybeIdReference::Reference(create_id.to_string());
@@ -403,7 +443,7 @@ where
                     } else {
                         None
                     },
-                    arguments: (),
+                    arguments: Default::default(),
                 }
                 .into();
             }
@@ -452,4 +492,36 @@ where
             )))
         }
     }
+
+    // Best-effort limit, the window is not persisted nor shared between cluster nodes
+    fn email_submission_rate_check(&self, account_id: AccountId, num_recipients: usize) -> bool {
+        let max_messages = self.config.submission_max_messages;
+        let max_recipients = self.config.submission_max_recipients;
+        if max_messages == 0 && max_recipients == 0 {
+            return true;
+        }
+
+        let window = self.submission_rates.get_with(account_id, Default::default);
+        let mut window = window.lock();
+
+        // Discard submissions that fall outside the rolling window
+        let window_size = Duration::from_secs(self.config.submission_rate_window);
+        while window
+            .front()
+            .map_or(false, |(sent_at, _)| sent_at.elapsed() >= window_size)
+        {
+            window.pop_front();
+        }
+
+        if (max_messages > 0 && window.len() >= max_messages)
+            || (max_recipients > 0
+                && window.iter().map(|(_, rcpts)| rcpts).sum::<usize>() + num_recipients
+                    > max_recipients)
+        {
+            false
+        } else {
+            window.push_back((Instant::now(), num_recipients));
+            true
+        }
+    }
 }
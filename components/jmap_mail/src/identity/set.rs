@@ -61,6 +61,8 @@ where
     fn identity_set(&self, request: SetRequest<Identity>) -> jmap::Result<SetResponse<Identity>>;
 
     fn identity_delete(&self, account_id: AccountId, document: &mut Document) -> store::Result<()>;
+
+    fn identity_has_address(&self, account_id: AccountId, email: &str) -> store::Result<bool>;
 }
 
 impl<T> JMAPSetIdentity<T> for JMAPStore<T>
@@ -88,26 +90,7 @@ where
                                     .with_property(Property::Email)
                                     .with_description("Invalid e-mail address.")
                             })?;
-                            if !helper
-                                .store
-                                .query_store::<FilterMapper>(
-                                    SUPERUSER_ID,
-                                    Collection::Principal,
-                                    Filter::or(vec![
-                                        Filter::eq(
-                                            principal::schema::Property::Email.into(),
-                                            Query::Index(value.clone()),
-                                        ),
-                                        Filter::eq(
-                                            principal::schema::Property::Aliases.into(),
-                                            Query::Index(value.clone()),
-                                        ),
-                                    ]),
-                                    Comparator::None,
-                                )?
-                                .into_iter()
-                                .any(|id| id.get_document_id() == helper.account_id)
-                            {
+                            if !principal_has_address(helper.store, helper.account_id, &value)? {
                                 return Err(SetError::invalid_properties()
                                     .with_property(Property::Email)
                                     .with_description(
@@ -206,4 +189,58 @@ where
 
         Ok(())
     }
+
+    fn identity_has_address(&self, account_id: AccountId, email: &str) -> store::Result<bool> {
+        let email = if let Some(email) = sanitize_email(email) {
+            email
+        } else {
+            return Ok(false);
+        };
+
+        // Look for an identity using this address
+        for document_id in self
+            .get_document_ids(account_id, Collection::Identity)?
+            .unwrap_or_default()
+        {
+            if let Some(Value::Text { value }) = self
+                .get_orm::<Identity>(account_id, document_id)?
+                .and_then(|mut fields| fields.remove(&Property::Email))
+            {
+                if value == email {
+                    return Ok(true);
+                }
+            }
+        }
+
+        // Otherwise accept the account's addresses and aliases
+        principal_has_address(self, account_id, &email)
+    }
+}
+
+fn principal_has_address<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    email: &str,
+) -> store::Result<bool>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    Ok(store
+        .query_store::<FilterMapper>(
+            SUPERUSER_ID,
+            Collection::Principal,
+            Filter::or(vec![
+                Filter::eq(
+                    principal::schema::Property::Email.into(),
+                    Query::Index(email.to_string()),
+                ),
+                Filter::eq(
+                    principal::schema::Property::Aliases.into(),
+                    Query::Index(email.to_string()),
+                ),
+            ]),
+            Comparator::None,
+        )?
+        .into_iter()
+        .any(|id| id.get_document_id() == account_id))
 }
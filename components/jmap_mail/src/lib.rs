@@ -25,6 +25,7 @@ pub mod email_submission;
 pub mod identity;
 pub mod mail;
 pub mod mailbox;
+pub mod mdn;
 pub mod thread;
 pub mod vacation_response;
 
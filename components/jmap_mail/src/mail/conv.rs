@@ -25,10 +25,11 @@ use std::borrow::Cow;
 
 use jmap::types::date::JMAPDate;
 use mail_parser::{parsers::MessageStream, Addr, Header, HeaderValue, RfcHeader};
+use store::{core::tag::Tag, Integer};
 
 use super::{
-    schema::{HeaderForm, Value},
-    HeaderName, MessageData, MimePart, MimePartType,
+    schema::{EmailAuthResults, EmailAutocrypt, EmailSnooze, EmailUnsubscribe, HeaderForm, Value},
+    GetRawHeader, HeaderName, MessageData, MimePart, MimePartType,
 };
 
 impl TryFrom<mail_parser::Addr<'_>> for super::EmailAddress {
@@ -248,6 +249,16 @@ impl HeaderValueInto for mail_parser::HeaderValue<'_> {
 }
 
 impl MimePart {
+    /// Returns `true` for parts that are displayed inline and referenced from the
+    /// HTML body by their Content-ID, such as embedded images.
+    pub fn is_inline_cid(&self) -> bool {
+        self.cid.is_some()
+            && self
+                .disposition
+                .as_ref()
+                .map_or(false, |d| d.eq_ignore_ascii_case("inline"))
+    }
+
     pub fn from_headers(
         headers: Vec<Header>,
         mime_type: MimePartType,
@@ -540,3 +551,228 @@ impl HeaderForm {
             .collect()
     }
 }
+
+impl EmailUnsubscribe {
+    pub fn from_headers(headers: &impl GetRawHeader, raw_message: &[u8]) -> Option<Self> {
+        let mut unsubscribe = EmailUnsubscribe::default();
+
+        if let Some(Value::TextList { value: urls }) = headers
+            .get_raw_header(&HeaderName::Rfc(RfcHeader::ListUnsubscribe))
+            .and_then(|offsets| {
+                HeaderForm::URLs
+                    .parse_offsets(&offsets, raw_message, false)
+                    .into_form(&HeaderForm::URLs, false)
+            })
+        {
+            for url in urls {
+                let scheme = url.split_once(':').map_or("", |(scheme, _)| scheme);
+                if scheme.eq_ignore_ascii_case("mailto") {
+                    unsubscribe.mailto.push(url);
+                } else if scheme.eq_ignore_ascii_case("http")
+                    || scheme.eq_ignore_ascii_case("https")
+                {
+                    unsubscribe.http.push(url);
+                }
+            }
+        }
+
+        if unsubscribe.mailto.is_empty() && unsubscribe.http.is_empty() {
+            return None;
+        }
+
+        // RFC 8058 one-click unsubscribe requires an HTTP target to POST to
+        let post = headers
+            .get_raw_header(&HeaderName::Other("List-Unsubscribe-Post".to_string()))
+            .and_then(|offsets| {
+                HeaderForm::Text
+                    .parse_offsets(&offsets, raw_message, false)
+                    .into_form(&HeaderForm::Text, false)
+            });
+        unsubscribe.one_click = !unsubscribe.http.is_empty()
+            && matches!(post, Some(Value::Text { value })
+                if value.trim().eq_ignore_ascii_case("List-Unsubscribe=One-Click"));
+
+        Some(unsubscribe)
+    }
+}
+
+impl EmailAutocrypt {
+    /// Returns the key advertised by the Autocrypt header matching the sender's address.
+    /// Following the Autocrypt Level 1 spec, invalid headers are ignored and no key is
+    /// returned when the sender address is advertised more than once.
+    pub fn from_headers(headers: &impl GetRawHeader, raw_message: &[u8]) -> Option<Self> {
+        let from = match headers
+            .get_raw_header(&HeaderName::Rfc(RfcHeader::From))
+            .and_then(|offsets| {
+                HeaderForm::Addresses
+                    .parse_offsets(&offsets, raw_message, false)
+                    .into_form(&HeaderForm::Addresses, false)
+            }) {
+            Some(Value::Addresses { value }) if value.len() == 1 => value.into_iter().next()?.email,
+            _ => return None,
+        };
+
+        let mut result = None;
+        if let Some(Value::TextList { value }) = headers
+            .get_raw_header(&HeaderName::Other("Autocrypt".to_string()))
+            .and_then(|offsets| {
+                HeaderForm::Raw
+                    .parse_offsets(&offsets, raw_message, true)
+                    .into_form(&HeaderForm::Raw, true)
+            })
+        {
+            for header in value {
+                match EmailAutocrypt::parse(&header) {
+                    Some(autocrypt) if autocrypt.addr.eq_ignore_ascii_case(&from) => {
+                        if result.is_some() {
+                            return None;
+                        }
+                        result = Some(autocrypt);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        result
+    }
+
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut addr = None;
+        let mut prefer_encrypt = "nopreference";
+        let mut key_data = None;
+
+        for attribute in header.split(';') {
+            if attribute.trim().is_empty() {
+                continue;
+            }
+            let (name, value) = attribute.split_once('=')?;
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "addr" => addr = value.into(),
+                "type" if value == "1" => (),
+                "prefer-encrypt" => {
+                    if value == "mutual" {
+                        prefer_encrypt = value;
+                    }
+                }
+                "keydata" => {
+                    let value = value
+                        .chars()
+                        .filter(|ch| !ch.is_ascii_whitespace())
+                        .collect::<String>();
+                    base64::decode(&value).ok().filter(|key| !key.is_empty())?;
+                    key_data = value.into();
+                }
+                // Unknown attributes are only allowed when non-critical
+                name if name.starts_with('_') => (),
+                _ => return None,
+            }
+        }
+
+        Some(EmailAutocrypt {
+            addr: addr.filter(|addr| addr.contains('@'))?.to_string(),
+            prefer_encrypt: prefer_encrypt.to_string(),
+            key_data: key_data?,
+        })
+    }
+}
+
+impl EmailAuthResults {
+    /// Parses the DKIM, SPF and DMARC verdicts of an RFC 8601 Authentication-Results
+    /// header, optionally only accepting results from the specified authserv-id.
+    pub fn parse(header: &str, authserv_id: Option<&str>) -> Option<Self> {
+        // Remove comments
+        let mut value = String::with_capacity(header.len());
+        let mut depth = 0;
+        for ch in header.chars() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                _ if depth == 0 => value.push(ch),
+                _ => (),
+            }
+        }
+
+        let mut results = value.split(';');
+        let header_authserv_id = results.next()?.split_whitespace().next()?;
+        if authserv_id.map_or(false, |id| !id.eq_ignore_ascii_case(header_authserv_id)) {
+            return None;
+        }
+
+        let mut auth_results = EmailAuthResults::default();
+        for result in results {
+            let (method, result) = if let Some((method, result)) = result.split_once('=') {
+                (
+                    method.trim().split('/').next().unwrap_or_default(),
+                    result.split_whitespace().next().unwrap_or_default(),
+                )
+            } else {
+                continue;
+            };
+            let verdict = if method.eq_ignore_ascii_case("dkim") {
+                &mut auth_results.dkim
+            } else if method.eq_ignore_ascii_case("spf") {
+                &mut auth_results.spf
+            } else if method.eq_ignore_ascii_case("dmarc") {
+                &mut auth_results.dmarc
+            } else {
+                continue;
+            };
+
+            // Messages with multiple signatures pass if any of them passes
+            if !result.is_empty() && verdict.as_ref().map_or(true, |v| v != "pass") {
+                *verdict = result.to_ascii_lowercase().into();
+            }
+        }
+
+        Some(auth_results).filter(|results| results != &EmailAuthResults::default())
+    }
+
+    pub fn into_tags(self) -> Vec<Tag> {
+        [
+            ("dkim", self.dkim),
+            ("spf", self.spf),
+            ("dmarc", self.dmarc),
+        ]
+        .into_iter()
+        .filter_map(|(method, verdict)| Tag::Text(format!("{}={}", method, verdict?)).into())
+        .collect()
+    }
+
+    pub fn from_tags<'x>(tags: impl Iterator<Item = &'x Tag>) -> Option<Self> {
+        let mut auth_results = EmailAuthResults::default();
+        for tag in tags {
+            if let Tag::Text(tag) = tag {
+                match tag.split_once('=') {
+                    Some(("dkim", verdict)) => auth_results.dkim = verdict.to_string().into(),
+                    Some(("spf", verdict)) => auth_results.spf = verdict.to_string().into(),
+                    Some(("dmarc", verdict)) => auth_results.dmarc = verdict.to_string().into(),
+                    _ => (),
+                }
+            }
+        }
+
+        Some(auth_results).filter(|results| results != &EmailAuthResults::default())
+    }
+}
+
+impl EmailSnooze {
+    // Snoozed messages are tagged with their wake up time, the default tag
+    // is used to quickly obtain all the snoozed messages of an account.
+    pub fn into_tags(self) -> Vec<Tag> {
+        vec![
+            Tag::Default,
+            Tag::Id(self.until.timestamp().clamp(0, Integer::MAX as i64) as Integer),
+        ]
+    }
+
+    pub fn from_tags<'x>(mut tags: impl Iterator<Item = &'x Tag>) -> Option<Self> {
+        tags.find_map(|tag| match tag {
+            Tag::Id(until) => EmailSnooze {
+                until: JMAPDate::from_timestamp(*until as i64),
+            }
+            .into(),
+            _ => None,
+        })
+    }
+}
@@ -27,10 +27,11 @@ use super::{
     sharing::JMAPShareMail,
     MessageData, MessageField,
 };
+use crate::mailbox::get::JMAPGetMailbox;
 use jmap::{
     error::set::SetError,
     jmap_store::copy::CopyHelper,
-    orm::TinyORM,
+    orm::{serialize::JMAPOrm, TinyORM},
     request::{
         copy::{CopyRequest, CopyResponse},
         set::SetRequest,
@@ -41,10 +42,12 @@ use jmap::{
 use store::core::acl::ACL;
 use store::{
     blob::BlobId,
-    core::{collection::Collection, error::StoreError, tag::Tag},
+    core::{
+        collection::Collection, document::Document, error::StoreError, tag::Tag, vec_map::VecMap,
+    },
     serialize::{StoreDeserialize, StoreSerialize},
-    write::options::IndexOptions,
-    JMAPStore, SharedBitmap, Store,
+    write::{batch::WriteBatch, options::IndexOptions},
+    AccountId, CopyIdempotencyKey, DocumentId, JMAPStore, SharedBitmap, Store,
 };
 
 pub trait JMAPCopyMail<T>
@@ -52,6 +55,15 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_copy(&self, request: CopyRequest<Email>) -> jmap::Result<CopyResponse<Email>>;
+
+    fn mail_copy_thread(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+        source_id: DocumentId,
+    ) -> store::Result<DocumentId>;
+
+    fn mail_copied(&self, account_id: AccountId, id: JMAPId) -> store::Result<Option<Email>>;
 }
 
 impl<T> JMAPCopyMail<T> for JMAPStore<T>
@@ -63,6 +75,7 @@ where
         let mailbox_ids = self
             .get_document_ids(helper.account_id, Collection::Mailbox)?
             .unwrap_or_default();
+        let search_folders = self.mailbox_search_folders(helper.account_id)?;
         let on_success_delete = helper
             .request
             .on_success_destroy_original
@@ -75,6 +88,61 @@ where
         let is_shared_source = helper.acl.is_shared(helper.from_account_id);
         let is_shared_target = helper.acl.is_shared(helper.account_id);
 
+        // Retried copies return the messages created by the original request,
+        // as long as the source messages can still be read.
+        let account_id = helper.account_id;
+        let from_account_id = helper.from_account_id;
+        let principal_id = helper.acl.primary_id();
+        let idempotency_key = helper.request.idempotency_key.take();
+        let copy_key = |create_id: &JMAPId| {
+            idempotency_key
+                .as_ref()
+                .map(|idempotency_key| CopyIdempotencyKey {
+                    account_id,
+                    from_account_id,
+                    collection: Collection::Mail,
+                    principal_id,
+                    idempotency_key: idempotency_key.clone(),
+                    create_id: (*create_id).into(),
+                })
+        };
+        if idempotency_key.is_some() {
+            let shared_messages = if is_shared_source {
+                Some(self.mail_shared_messages(
+                    from_account_id,
+                    &helper.acl.member_of,
+                    ACL::ReadItems,
+                )?)
+            } else {
+                None
+            };
+            let mut create = VecMap::with_capacity(helper.request.create.len());
+            for (create_id, item) in std::mem::take(&mut helper.request.create) {
+                if let Some(copied_id) = create_id
+                    .value()
+                    .filter(|id| {
+                        shared_messages.as_ref().map_or(true, |shared_messages| {
+                            shared_messages.has_access(id.get_document_id())
+                        })
+                    })
+                    .and_then(|id| self.copied_ids.get(&copy_key(id)?))
+                {
+                    if let Some(email) = self.mail_copied(account_id, copied_id.into())? {
+                        let create_id = create_id.unwrap_value().unwrap();
+                        if on_success_delete
+                            && helper.document_ids.contains(create_id.get_document_id())
+                        {
+                            destroy_ids.push(create_id);
+                        }
+                        helper.response.created.append(create_id, email);
+                        continue;
+                    }
+                }
+                create.append(create_id, item);
+            }
+            helper.request.create = create;
+        }
+
         helper.create(|copy_id, item, helper, document| {
             // Check ACL on source account
             let document_id = copy_id.get_document_id();
@@ -175,6 +243,18 @@ where
                     .with_description("Message has to belong to at least one mailbox."));
             }
 
+            // Search folders are read-only
+            for mailbox in fields.get_tags(&Property::MailboxIds).unwrap() {
+                if search_folders.contains(mailbox.as_id()) {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::MailboxIds)
+                        .with_description(format!(
+                            "Messages cannot be copied to search folder {}.",
+                            JMAPId::from(mailbox.as_id())
+                        )));
+                }
+            }
+
             // Check ACL on target account
             if is_shared_target {
                 let allowed_folders = helper.store.mail_shared_folders(
@@ -250,14 +330,38 @@ where
             );
             document.blob(metadata_blob_id, IndexOptions::new());
 
+            // Copy stored preview
+            if let Some(preview) = self.get_document_value::<Vec<u8>>(
+                helper.from_account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Preview.into(),
+            )? {
+                document.binary(MessageField::Preview, preview, IndexOptions::new());
+            }
+
+            // Authentication results belong to the message, so they are copied along
+            if let Some(auth_results) = self
+                .get_orm::<Email>(helper.from_account_id, document_id)?
+                .and_then(|source| source.get_tags(&Property::AuthenticationResults).cloned())
+            {
+                for tag in auth_results {
+                    fields.tag(Property::AuthenticationResults, tag);
+                }
+            }
+
             // Add fields
             fields.insert(document)?;
 
             // Lock collection
             let lock = self.lock_collection(helper.account_id, Collection::Mail);
 
-            // Obtain thread Id
-            let thread_id = self.mail_set_thread(&mut helper.changes, document)?;
+            // Obtain thread Id, copies within the same account join the thread of the original
+            let thread_id = if helper.account_id == helper.from_account_id {
+                self.mail_copy_thread(&mut helper.changes, document, document_id)?
+            } else {
+                self.mail_set_thread(&mut helper.changes, document)?
+            };
 
             // Build email result
             let mut email = Email::default();
@@ -268,6 +372,12 @@ where
             email.insert(Property::BlobId, raw_blob);
             email.insert(Property::ThreadId, JMAPId::from(thread_id));
             email.insert(Property::Size, size);
+            if let Some(copy_key) = copy_key(copy_id) {
+                self.copied_ids.insert(
+                    copy_key,
+                    JMAPId::from_parts(thread_id, document.document_id).into(),
+                );
+            }
 
             // Add to destroy list
             if on_success_delete {
@@ -287,11 +397,109 @@ where
                     create: None,
                     update: None,
                     destroy: Some(MaybeResultReference::Value(destroy_ids)),
-                    arguments: (),
+                    arguments: Default::default(),
                 }
                 .into()
             }
             r
         })
     }
+    fn mail_copy_thread(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+        source_id: DocumentId,
+    ) -> store::Result<DocumentId> {
+        let thread_id = self
+            .get_document_value::<DocumentId>(
+                batch.account_id,
+                Collection::Mail,
+                source_id,
+                MessageField::ThreadId.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to fetch threadId for {}:{}.",
+                    batch.account_id, source_id
+                ))
+            })?;
+
+        // Threads that reached the maximum size are not grown any further
+        let max_thread_size = self.config.mail_max_thread_size;
+        if max_thread_size > 0
+            && self
+                .get_tag(
+                    batch.account_id,
+                    Collection::Mail,
+                    MessageField::ThreadId.into(),
+                    Tag::Id(thread_id),
+                )?
+                .map_or(0, |document_ids| document_ids.len() as usize)
+                >= max_thread_size
+        {
+            return self.mail_set_thread(batch, document);
+        }
+
+        batch.log_child_update(Collection::Thread, thread_id);
+        document.tag(
+            MessageField::ThreadId,
+            Tag::Id(thread_id),
+            IndexOptions::new(),
+        );
+        document.number(
+            MessageField::ThreadId,
+            thread_id,
+            IndexOptions::new().store(),
+        );
+
+        Ok(thread_id)
+    }
+
+    fn mail_copied(&self, account_id: AccountId, id: JMAPId) -> store::Result<Option<Email>> {
+        let document_id = id.get_document_id();
+        if !self
+            .get_document_ids(account_id, Collection::Mail)?
+            .map_or(false, |document_ids| document_ids.contains(document_id))
+        {
+            return Ok(None);
+        }
+        let (thread_id, metadata_blob_id) = if let (Some(thread_id), Some(metadata_blob_id)) = (
+            self.get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?,
+            self.get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?,
+        ) {
+            (thread_id, metadata_blob_id)
+        } else {
+            return Ok(None);
+        };
+        let message_data =
+            MessageData::deserialize(&self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Could not find message metadata blob for {}.",
+                    document_id
+                ))
+            })?)
+            .ok_or_else(|| {
+                StoreError::InternalError(format!(
+                    "Failed to get deserialize message data for {}.",
+                    document_id
+                ))
+            })?;
+
+        let mut email = Email::default();
+        email.insert(Property::Id, JMAPId::from_parts(thread_id, document_id));
+        email.insert(Property::BlobId, JMAPBlob::from(&message_data.raw_message));
+        email.insert(Property::ThreadId, JMAPId::from(thread_id));
+        email.insert(Property::Size, message_data.size);
+        Ok(Some(email))
+    }
 }
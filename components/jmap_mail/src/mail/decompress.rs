@@ -0,0 +1,65 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use super::MimePart;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decompressed {
+    Data(Vec<u8>),
+    LimitExceeded,
+    Invalid,
+}
+
+impl MimePart {
+    pub fn is_gzip(&self) -> bool {
+        self.type_.as_ref().map_or(false, |t| {
+            t.eq_ignore_ascii_case("application/gzip")
+                || t.eq_ignore_ascii_case("application/x-gzip")
+        }) || self.name.as_ref().map_or(false, |n| {
+            n.len() > 3 && n[n.len() - 3..].eq_ignore_ascii_case(".gz")
+        })
+    }
+}
+
+pub fn decompress_gzip(data: &[u8], max_size: usize, max_ratio: usize) -> Decompressed {
+    // A limit of zero disables the check
+    let mut limit = if max_size > 0 { max_size } else { usize::MAX };
+    if max_ratio > 0 {
+        limit = std::cmp::min(limit, data.len().saturating_mul(max_ratio));
+    }
+
+    // Read one byte past the limit to detect whether it was exceeded
+    let mut decompressed = Vec::with_capacity(std::cmp::min(limit, data.len() * 4));
+    match GzDecoder::new(data)
+        .take((limit as u64).saturating_add(1))
+        .read_to_end(&mut decompressed)
+    {
+        Ok(size) if size > limit => Decompressed::LimitExceeded,
+        Ok(_) => Decompressed::Data(decompressed),
+        Err(_) => Decompressed::Invalid,
+    }
+}
@@ -23,14 +23,15 @@
 
 use super::{
     conv::IntoForm,
+    remote_resources::remote_resources,
     schema::{
-        BodyProperty, Email, EmailBodyPart, EmailBodyValue, EmailHeader, HeaderForm,
-        HeaderProperty, Property, Value,
+        BodyProperty, Email, EmailAuthResults, EmailAutocrypt, EmailBodyPart, EmailBodyValue,
+        EmailHeader, EmailSnooze, EmailUnsubscribe, HeaderForm, HeaderProperty, Property, Value,
     },
     sharing::JMAPShareMail,
     GetRawHeader, HeaderName, MessagePart,
 };
-use crate::mail::{MessageData, MessageField, MimePart, MimePartType};
+use crate::mail::{MessageData, MessageField, MessagePreview, MimePart, MimePartType};
 use jmap::{
     error::method::MethodError,
     jmap_store::get::{GetHelper, GetObject},
@@ -226,12 +227,14 @@ where
                 | Property::Header(HeaderProperty {
                     header: HeaderName::Other(_),
                     ..
-                }) => {
+                })
+                | Property::Unsubscribe
+                | Property::Autocrypt => {
                     if fetch_raw != FetchRaw::All {
                         fetch_raw = FetchRaw::Header;
                     }
                 }
-                Property::BodyStructure | Property::BodyValues | Property::Preview => {
+                Property::BodyStructure | Property::BodyValues | Property::RemoteResources => {
                     fetch_raw = FetchRaw::All;
                 }
                 Property::Id => {
@@ -425,8 +428,49 @@ where
                         },
                     }
                     .into(),
+                    Property::Unsubscribe => {
+                        match (message_data.mime_parts.first(), &raw_message) {
+                            (Some(root_part), Some(raw_message)) => {
+                                EmailUnsubscribe::from_headers(&root_part.raw_headers, raw_message)
+                                    .map(|value| Value::Unsubscribe { value })
+                            }
+                            _ => None,
+                        }
+                    }
+                    Property::Autocrypt => match (message_data.mime_parts.first(), &raw_message) {
+                        (Some(root_part), Some(raw_message)) => {
+                            EmailAutocrypt::from_headers(&root_part.raw_headers, raw_message)
+                                .map(|value| Value::Autocrypt { value })
+                        }
+                        _ => None,
+                    },
+                    Property::AuthenticationResults => fields
+                        .get_tags(&Property::AuthenticationResults)
+                        .and_then(|tags| EmailAuthResults::from_tags(tags.iter()))
+                        .map(|value| Value::AuthenticationResults { value }),
+                    Property::Snoozed => fields
+                        .get_tags(&Property::Snoozed)
+                        .and_then(|tags| EmailSnooze::from_tags(tags.iter()))
+                        .map(|value| Value::Snoozed { value }),
+                    // Only used when creating a message
+                    Property::ForwardedEmailId => None,
                     Property::Preview => {
-                        if !message_data.text_body.is_empty() || !message_data.html_body.is_empty()
+                        // Use the preview stored at ingest unless the preview length changed
+                        if let Some(preview) = self
+                            .get_document_value::<MessagePreview>(
+                                account_id,
+                                Collection::Mail,
+                                document_id,
+                                MessageField::Preview.into(),
+                            )?
+                            .filter(|preview| preview.max_len == self.config.mail_preview_length)
+                        {
+                            Value::Text {
+                                value: preview.preview,
+                            }
+                            .into()
+                        } else if !message_data.text_body.is_empty()
+                            || !message_data.html_body.is_empty()
                         {
                             let parts = if !message_data.text_body.is_empty() {
                                 &message_data.text_body
@@ -459,10 +503,24 @@ where
                                 }
                             };
 
+                            let raw_body;
+                            let raw_message = if fetch_raw == FetchRaw::All {
+                                raw_message.as_ref().unwrap()
+                            } else {
+                                raw_body =
+                                    self.blob_get(&message_data.raw_message)?.ok_or_else(|| {
+                                        StoreError::NotFound(format!(
+                                            "Raw email message not found for {}/{}.",
+                                            account_id, document_id
+                                        ))
+                                    })?;
+                                &raw_body
+                            };
+
                             Value::Text {
                                 value: preview_fnc(
                                     part.decode_text(
-                                        raw_message.as_ref().unwrap(),
+                                        raw_message,
                                         mime_part.charset.as_deref(),
                                         true,
                                     )
@@ -474,7 +532,7 @@ where
                                         "".to_string()
                                     })
                                     .into(),
-                                    256,
+                                    self.config.mail_preview_length,
                                 )
                                 .into_owned(),
                             }
@@ -521,6 +579,25 @@ where
                         }
                         Value::BodyValues { value: body_values }.into()
                     }
+                    Property::RemoteResources => {
+                        let mut resources = Vec::new();
+                        for mime_part in message_data
+                            .html_body
+                            .iter()
+                            .filter_map(|part_id| message_data.mime_parts.get(*part_id))
+                        {
+                            if let MimePartType::Html { part } = &mime_part.mime_type {
+                                if let Some(html) = part.decode_text(
+                                    raw_message.as_ref().unwrap(),
+                                    mime_part.charset.as_deref(),
+                                    true,
+                                ) {
+                                    remote_resources(&html, &mut resources);
+                                }
+                            }
+                        }
+                        Value::RemoteResources { value: resources }.into()
+                    }
                     Property::TextBody => Some(
                         message_data
                             .mime_parts
@@ -581,19 +658,33 @@ where
     ) -> store::Result<BlobResult> {
         if !self.blob_account_has_access(&blob.id, &acl.member_of)? && !acl.is_member(SUPERUSER_ID)
         {
-            if let Some(shared_ids) = self
-                .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
-                .as_ref()
-            {
-                if !self.blob_document_has_access(
-                    &blob.id,
-                    account_id,
-                    Collection::Mail,
-                    shared_ids,
-                )? {
-                    return Ok(BlobResult::Unauthorized);
+            // The blob may belong to a message shared from this or any other account,
+            // as is the case when forwarding an attachment from a shared mailbox.
+            let mut has_access = false;
+            for shared_account_id in [account_id].into_iter().chain(
+                acl.access_to
+                    .iter()
+                    .filter(|(id, collections)| {
+                        *id != account_id && collections.contains(Collection::Mail)
+                    })
+                    .map(|(id, _)| *id),
+            ) {
+                if let Some(shared_ids) = self
+                    .mail_shared_messages(shared_account_id, &acl.member_of, ACL::ReadItems)?
+                    .as_ref()
+                {
+                    if self.blob_document_has_access(
+                        &blob.id,
+                        shared_account_id,
+                        Collection::Mail,
+                        shared_ids,
+                    )? {
+                        has_access = true;
+                        break;
+                    }
                 }
-            } else {
+            }
+            if !has_access {
                 return Ok(BlobResult::Unauthorized);
             }
         }
@@ -626,6 +717,8 @@ where
 }
 
 impl MimePart {
+    // The partId of a body part is its index in the list of parsed MIME parts,
+    // which is also the key used for its entry in bodyValues.
     pub fn as_body_part(
         &self,
         part_id: usize,
@@ -55,15 +55,19 @@ use store::read::filter::{Filter, Query};
 use store::read::FilterMapper;
 use store::serialize::StoreSerialize;
 
-use store::tracing::error;
+use store::tracing::{debug, error};
 use store::write::batch::WriteBatch;
 use store::write::options::{IndexOptions, Options};
 use store::{AccountId, JMAPStore, SharedBitmap, Store, ThreadId};
 use store::{DocumentId, Integer, LongInteger};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-use crate::mail::MessageField;
+use crate::mail::{MessageField, MessagePreview};
+use crate::mailbox::get::JMAPGetMailbox;
 
 use super::conv::HeaderValueInto;
+use super::decompress::{decompress_gzip, Decompressed};
 use super::get::{BlobResult, JMAPGetMail};
 use super::schema::{Email, Keyword, Property};
 use super::sharing::JMAPShareMail;
@@ -111,6 +115,10 @@ pub struct EmailImportResponse {
     #[serde(rename = "notCreated")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub not_created: Option<VecMap<String, SetError<Property>>>,
+
+    #[serde(rename = "mergedThreadIds")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merged_thread_ids: Option<VecMap<String, Vec<JMAPId>>>,
 }
 
 pub trait JMAPMailImport {
@@ -124,7 +132,7 @@ pub trait JMAPMailImport {
         mailbox_ids: Vec<DocumentId>,
         keywords: Vec<Tag>,
         received_at: Option<i64>,
-    ) -> jmap::Result<Email>;
+    ) -> jmap::Result<(Email, Vec<ThreadId>)>;
 
     fn mail_parse_item(
         &self,
@@ -140,6 +148,12 @@ pub trait JMAPMailImport {
         document: &mut Document,
     ) -> store::Result<DocumentId>;
 
+    fn mail_set_thread_with_merges(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+    ) -> store::Result<(DocumentId, Vec<ThreadId>)>;
+
     fn mail_merge_threads(
         &self,
         documents: &mut WriteBatch,
@@ -152,10 +166,15 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_import(&self, request: EmailImportRequest) -> jmap::Result<EmailImportResponse> {
+        let max_items = self.config.mail_import_max_items;
+        if request.emails.len() > max_items && !self.config.mail_import_partial {
+            return Err(MethodError::RequestTooLarge);
+        }
         let account_id = request.account_id.get_document_id();
         let mailbox_document_ids = self
             .get_document_ids(account_id, Collection::Mailbox)?
             .unwrap_or_default();
+        let search_folders = self.mailbox_search_folders(account_id)?;
         let acl = request.acl.unwrap();
         let is_shared_account = acl.is_shared(account_id);
 
@@ -168,8 +187,21 @@ where
 
         let mut created = VecMap::with_capacity(request.emails.len());
         let mut not_created = VecMap::with_capacity(request.emails.len());
+        let mut merged_thread_ids = VecMap::new();
+
+        'outer: for (pos, (id, item)) in request.emails.into_iter().enumerate() {
+            // Items past the limit are rejected when partial imports are enabled
+            if pos >= max_items {
+                not_created.append(
+                    id,
+                    SetError::new(SetErrorType::TooManyItems).with_description(format!(
+                        "Only {} messages can be imported per request.",
+                        max_items
+                    )),
+                );
+                continue;
+            }
 
-        'outer: for (id, item) in request.emails {
             if let Some(mailbox_ids) = item.mailbox_ids {
                 let mailbox_ids = mailbox_ids
                     .unwrap_value()
@@ -195,6 +227,17 @@ where
                                 )),
                         );
                         continue 'outer;
+                    } else if search_folders.contains(document_id) {
+                        not_created.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(Property::MailboxIds)
+                                .with_description(format!(
+                                    "Messages cannot be imported into search folder {}.",
+                                    mailbox_id
+                                )),
+                        );
+                        continue 'outer;
                     } else if is_shared_account
                         && !self
                             .mail_shared_folders(account_id, &acl.member_of, ACL::AddItems)?
@@ -266,37 +309,58 @@ where
                     }
                 }
 
+                // Enforce the maximum number of messages per account
+                let max_messages = self.config.mail_max_messages;
+                if max_messages > 0
+                    && self
+                        .get_document_ids(account_id, Collection::Mail)?
+                        .map_or(0, |document_ids| document_ids.len() as usize)
+                        >= max_messages
+                {
+                    not_created.append(
+                        id,
+                        SetError::new(SetErrorType::OverQuota).with_description(
+                            "Maximum number of messages exceeded, please delete some and try again.",
+                        ),
+                    );
+                    continue;
+                }
+
                 match self.mail_blob_get(account_id, &acl, &item.blob_id)? {
                     BlobResult::Blob(blob) => {
-                        created.append(
-                            id,
-                            self.mail_import_item(
-                                account_id,
-                                item.blob_id.id,
-                                &blob,
-                                mailbox_ids
-                                    .into_iter()
-                                    .filter_map(|(id, set)| {
-                                        if set {
-                                            id.get_document_id().into()
-                                        } else {
-                                            None
-                                        }
-                                    })
-                                    .collect(),
-                                item.keywords
-                                    .map(|keywords| {
-                                        keywords
-                                            .into_iter()
-                                            .filter_map(
-                                                |(k, set)| if set { k.tag.into() } else { None },
-                                            )
-                                            .collect()
-                                    })
-                                    .unwrap_or_default(),
-                                item.received_at.map(|t| t.timestamp()),
-                            )?,
-                        );
+                        let (email, merged_ids) = self.mail_import_item(
+                            account_id,
+                            item.blob_id.id,
+                            &blob,
+                            mailbox_ids
+                                .into_iter()
+                                .filter_map(|(id, set)| {
+                                    if set {
+                                        id.get_document_id().into()
+                                    } else {
+                                        None
+                                    }
+                                })
+                                .collect(),
+                            item.keywords
+                                .map(|keywords| {
+                                    keywords
+                                        .into_iter()
+                                        .filter_map(
+                                            |(k, set)| if set { k.tag.into() } else { None },
+                                        )
+                                        .collect()
+                                })
+                                .unwrap_or_default(),
+                            item.received_at.map(|t| t.timestamp()),
+                        )?;
+                        if !merged_ids.is_empty() {
+                            merged_thread_ids.append(
+                                id.clone(),
+                                merged_ids.into_iter().map(JMAPId::from).collect(),
+                            );
+                        }
+                        created.append(id, email);
                     }
                     BlobResult::Unauthorized => {
                         not_created.append(
@@ -343,6 +407,11 @@ where
             } else {
                 None
             },
+            merged_thread_ids: if !merged_thread_ids.is_empty() {
+                merged_thread_ids.into()
+            } else {
+                None
+            },
         })
     }
 
@@ -354,7 +423,7 @@ where
         mailbox_ids: Vec<DocumentId>,
         keywords: Vec<Tag>,
         received_at: Option<i64>,
-    ) -> jmap::Result<Email> {
+    ) -> jmap::Result<(Email, Vec<ThreadId>)> {
         let document_id = self.assign_document_id(account_id, Collection::Mail)?;
         let mut batch = WriteBatch::new(account_id);
         let mut document = Document::new(Collection::Mail, document_id);
@@ -390,7 +459,8 @@ where
         let _lock = self.lock_collection(batch.account_id, Collection::Mail);
 
         // Obtain thread Id
-        let thread_id = self.mail_set_thread(&mut batch, &mut document)?;
+        let (thread_id, merged_thread_ids) =
+            self.mail_set_thread_with_merges(&mut batch, &mut document)?;
 
         // Write document to store
         let id = JMAPId::from_parts(thread_id, document_id);
@@ -405,7 +475,7 @@ where
         email.insert(Property::ThreadId, JMAPId::from(thread_id));
         email.insert(Property::Size, size);
 
-        Ok(email)
+        Ok((email, merged_thread_ids))
     }
 
     fn mail_parse_item(
@@ -523,6 +593,8 @@ where
                 encoding: message_part.encoding,
             };
             let part_language = message_part.get_language().unwrap_or(message_language);
+            let mut is_attachment = false;
+            let mut binary_contents = None;
             let (mime_type, part_size) = match message_part.body {
                 PartType::Html(html) => {
                     let field = if message_data.text_body.contains(&part_id)
@@ -530,7 +602,7 @@ where
                     {
                         MessageField::Body
                     } else {
-                        has_attachments = true;
+                        is_attachment = true;
                         MessageField::Attachment
                     };
 
@@ -549,7 +621,7 @@ where
                     {
                         MessageField::Body
                     } else {
-                        has_attachments = true;
+                        is_attachment = true;
                         MessageField::Attachment
                     };
 
@@ -563,16 +635,14 @@ where
                     (MimePartType::Text { part }, text_len)
                 }
                 PartType::Binary(binary) => {
-                    if !has_attachments {
-                        has_attachments = true;
-                    }
-                    (MimePartType::Other { part }, binary.len())
+                    is_attachment = true;
+                    let binary_len = binary.len();
+                    binary_contents = Some(binary);
+                    (MimePartType::Other { part }, binary_len)
                 }
                 PartType::InlineBinary(binary) => (MimePartType::Other { part }, binary.len()),
                 PartType::Message(mut nested_message) => {
-                    if !has_attachments {
-                        has_attachments = true;
-                    }
+                    is_attachment = true;
                     let size = nested_message.parts[0].raw_len();
                     document.add_message(&mut nested_message, (part_id) as u32);
 
@@ -581,12 +651,45 @@ where
                 PartType::Multipart(subparts) => (MimePartType::MultiPart { subparts }, 0),
             };
 
-            message_data.mime_parts.push(MimePart::from_headers(
+            let mut mime_part = MimePart::from_headers(
                 message_part.headers,
                 mime_type,
                 message_part.is_encoding_problem,
                 part_size,
-            ));
+            );
+            if is_attachment && !mime_part.is_inline_cid() {
+                has_attachments = true;
+            }
+
+            // Index the text contents of compressed attachments
+            if let Some(binary) = binary_contents.filter(|_| mime_part.is_gzip()) {
+                match decompress_gzip(
+                    &binary,
+                    self.config.mail_decompress_max_size,
+                    self.config.mail_decompress_max_ratio,
+                ) {
+                    Decompressed::Data(bytes) => {
+                        if let Ok(text) = String::from_utf8(bytes) {
+                            document.text(
+                                MessageField::Attachment,
+                                text,
+                                part_language,
+                                IndexOptions::new().full_text((part_id + 1) as u32),
+                            );
+                        }
+                    }
+                    Decompressed::LimitExceeded => {
+                        debug!(
+                            "Compressed attachment {} exceeds decompression limits.",
+                            part_id
+                        );
+                        mime_part.is_encoding_problem = true;
+                    }
+                    Decompressed::Invalid => (),
+                }
+            }
+
+            message_data.mime_parts.push(mime_part);
         }
 
         // Set attachment properties
@@ -594,6 +697,23 @@ where
             message_data.has_attachments = true;
         }
 
+        // Store preview
+        if let Some(preview) = message_data.preview(
+            message.raw_message.as_ref(),
+            self.config.mail_preview_length,
+        ) {
+            document.binary(
+                MessageField::Preview,
+                MessagePreview {
+                    max_len: self.config.mail_preview_length,
+                    preview,
+                }
+                .serialize()
+                .unwrap(),
+                IndexOptions::new(),
+            );
+        }
+
         // Link blob and set message data field
         let metadata_bytes = message_data
             .serialize()
@@ -617,6 +737,15 @@ where
         batch: &mut WriteBatch,
         document: &mut Document,
     ) -> store::Result<DocumentId> {
+        self.mail_set_thread_with_merges(batch, document)
+            .map(|(thread_id, _)| thread_id)
+    }
+
+    fn mail_set_thread_with_merges(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+    ) -> store::Result<(DocumentId, Vec<ThreadId>)> {
         // Obtain thread name and reference ids
         let mut reference_ids = Vec::new();
         let mut thread_name = None;
@@ -629,6 +758,7 @@ where
         }
 
         // Obtain thread id
+        let mut merged_thread_ids = Vec::new();
         let thread_id = if !reference_ids.is_empty() {
             // Obtain thread ids for all matching document ids
             let thread_ids = self
@@ -665,7 +795,61 @@ where
                 )?
                 .into_iter()
                 .flatten()
-                .collect::<AHashSet<ThreadId>>();
+                .collect::<AHashSet<ThreadId>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+
+            // Do not grow threads beyond the maximum size
+            let max_thread_size = self.config.mail_max_thread_size;
+            let thread_ids = if max_thread_size > 0 && !thread_ids.is_empty() {
+                let mut open_threads = thread_ids
+                    .iter()
+                    .copied()
+                    .zip(
+                        self.get_tags(
+                            batch.account_id,
+                            Collection::Mail,
+                            MessageField::ThreadId.into(),
+                            &thread_ids
+                                .iter()
+                                .map(|id| Tag::Id(*id))
+                                .collect::<Vec<Tag>>(),
+                        )?
+                        .into_iter()
+                        .map(|document_set| document_set.map_or(0, |d| d.len() as usize)),
+                    )
+                    .filter(|(_, size)| *size < max_thread_size)
+                    .collect::<Vec<_>>();
+                if open_threads.len() < thread_ids.len() {
+                    debug!(
+                        "Account {}: {} thread(s) reached the maximum size of {} messages.",
+                        batch.account_id,
+                        thread_ids.len() - open_threads.len(),
+                        max_thread_size
+                    );
+                }
+
+                // Merging would exceed the limit, join the largest thread instead
+                if open_threads.len() > 1
+                    && open_threads.iter().map(|(_, size)| *size).sum::<usize>() >= max_thread_size
+                {
+                    debug!(
+                        "Account {}: not merging {} threads as their size exceeds {} messages.",
+                        batch.account_id,
+                        open_threads.len(),
+                        max_thread_size
+                    );
+                    open_threads.sort_unstable_by_key(|(_, size)| *size);
+                    open_threads.drain(..open_threads.len() - 1);
+                }
+
+                open_threads
+                    .into_iter()
+                    .map(|(thread_id, _)| thread_id)
+                    .collect()
+            } else {
+                thread_ids
+            };
 
             match thread_ids.len() {
                 1 => {
@@ -675,7 +859,12 @@ where
                 0 => None,
                 _ => {
                     // Merge all matching threads
-                    Some(self.mail_merge_threads(batch, thread_ids.into_iter().collect())?)
+                    let thread_id = self.mail_merge_threads(batch, thread_ids.clone())?;
+                    merged_thread_ids = thread_ids
+                        .into_iter()
+                        .filter(|id| *id != thread_id)
+                        .collect();
+                    Some(thread_id)
                 }
             }
         } else {
@@ -703,7 +892,7 @@ where
             IndexOptions::new().store(),
         );
 
-        Ok(thread_id)
+        Ok((thread_id, merged_thread_ids))
     }
 
     fn mail_merge_threads(
@@ -910,6 +1099,22 @@ impl MessageData {
             );
         }
 
+        // Index attachment filenames for text searches
+        for part_id in &self.attachments {
+            if let Some(name) = self
+                .mime_parts
+                .get(*part_id)
+                .and_then(|part| part.name.as_ref())
+            {
+                document.text(
+                    MessageField::Attachment,
+                    name.to_string(),
+                    Language::Unknown,
+                    IndexOptions::new().tokenize() | options,
+                );
+            }
+        }
+
         for (header_name, mut values) in self.headers {
             document.tag(
                 MessageField::HasHeader,
@@ -959,7 +1164,10 @@ impl MessageData {
                                     last_is_space = true;
                                 }
                                 found_addr = is_addr;
-                                'outer: for ch in value.chars() {
+
+                                // Sort by the display name when present, ignoring case
+                                // and diacritics so that "Émile" sorts next to "Emma".
+                                'outer: for ch in value.nfd().filter(|ch| !is_combining_mark(*ch)) {
                                     for ch in ch.to_lowercase() {
                                         if sort_text.len() < MAX_SORT_FIELD_LENGTH {
                                             let is_space = ch.is_whitespace();
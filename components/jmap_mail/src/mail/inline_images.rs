@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use mail_builder::{
+    headers::{content_type::ContentType, message_id::MessageId},
+    mime::{BodyPart, MimePart},
+};
+use mail_parser::{Message, PartType};
+
+struct InlineImage {
+    cid: String,
+    content_type: String,
+    contents: Vec<u8>,
+}
+
+// Moves the base64 data: URIs of at least `min_size` bytes found in the HTML parts
+// of a message to inline attachments referenced by cid: URLs. Each modified HTML part
+// is replaced by a multipart/related part, the rest of the message is left untouched.
+pub fn externalize_inline_images(raw_message: &[u8], min_size: usize) -> Option<Vec<u8>> {
+    let message = Message::parse(raw_message)?;
+    let mut output = Vec::with_capacity(raw_message.len());
+    let mut last_offset = 0;
+    let mut num_images = 0;
+
+    for (part_id, part) in message.parts.iter().enumerate() {
+        let mut images = Vec::new();
+        let html = match &part.body {
+            PartType::Html(html) => {
+                if let Some(html) = extract_images(html, min_size, num_images, &mut images) {
+                    html
+                } else {
+                    continue;
+                }
+            }
+            _ => continue,
+        };
+        num_images += images.len();
+
+        let mut parts = Vec::with_capacity(images.len() + 1);
+        parts.push(MimePart {
+            headers: vec![(
+                "Content-Type".into(),
+                ContentType::new("text/html")
+                    .attribute("charset", "utf-8")
+                    .into(),
+            )],
+            contents: BodyPart::Text(html.into()),
+        });
+        for image in images {
+            parts.push(MimePart {
+                headers: vec![
+                    (
+                        "Content-Type".into(),
+                        ContentType::new(image.content_type).into(),
+                    ),
+                    (
+                        "Content-Disposition".into(),
+                        ContentType::new("inline").into(),
+                    ),
+                    ("Content-ID".into(), MessageId::new(image.cid).into()),
+                ],
+                contents: BodyPart::Binary(image.contents.into()),
+            });
+        }
+
+        if part_id == 0 {
+            // Single part messages keep all headers but the ones describing the body
+            write_message_headers(raw_message.get(..part.offset_body)?, &mut output);
+        } else {
+            output.extend_from_slice(raw_message.get(last_offset..part.offset_header)?);
+        }
+        MimePart {
+            headers: vec![(
+                "Content-Type".into(),
+                ContentType::new("multipart/related").into(),
+            )],
+            contents: BodyPart::Multipart(parts),
+        }
+        .write_part(&mut output)
+        .ok()?;
+        last_offset = part.offset_end;
+    }
+
+    if num_images > 0 {
+        output.extend_from_slice(raw_message.get(last_offset..)?);
+        Some(output)
+    } else {
+        None
+    }
+}
+
+fn extract_images(
+    html: &str,
+    min_size: usize,
+    num_images: usize,
+    images: &mut Vec<InlineImage>,
+) -> Option<String> {
+    let mut result = String::with_capacity(html.len());
+    let mut last_pos = 0;
+    let mut pos = 0;
+
+    while let Some(start) = html[pos..].find("data:image/") {
+        let start = pos + start;
+        pos = start + 1;
+
+        // Only quoted attribute values are replaced
+        let quote = match html[..start].chars().next_back() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => continue,
+        };
+        let end = if let Some(end) = html[start..].find(quote) {
+            start + end
+        } else {
+            break;
+        };
+        let (header, data) = if let Some(uri) = html[start + 5..end].split_once(',') {
+            uri
+        } else {
+            continue;
+        };
+        if data.len() < min_size
+            || !header
+                .rsplit(';')
+                .next()
+                .map_or(false, |encoding| encoding.eq_ignore_ascii_case("base64"))
+        {
+            continue;
+        }
+        let contents = if let Ok(contents) = base64::decode(
+            data.bytes()
+                .filter(|ch| !ch.is_ascii_whitespace())
+                .collect::<Vec<_>>(),
+        ) {
+            contents
+        } else {
+            continue;
+        };
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let cid = format!(
+            "{:016x}.{}@inline",
+            hasher.finish(),
+            num_images + images.len() + 1
+        );
+
+        result.push_str(&html[last_pos..start]);
+        result.push_str("cid:");
+        result.push_str(&cid);
+        images.push(InlineImage {
+            cid,
+            content_type: header.split(';').next().unwrap_or_default().to_lowercase(),
+            contents,
+        });
+        last_pos = end;
+        pos = end;
+    }
+
+    if !images.is_empty() {
+        result.push_str(&html[last_pos..]);
+        Some(result)
+    } else {
+        None
+    }
+}
+
+fn write_message_headers(headers: &[u8], output: &mut Vec<u8>) {
+    let mut is_content_header = false;
+    for line in headers.split_inclusive(|&ch| ch == b'\n') {
+        if line.iter().all(|ch| ch.is_ascii_whitespace()) {
+            // The blank line ending the header section is written by the new part
+            break;
+        } else if !matches!(line.first(), Some(b' ' | b'\t')) {
+            is_content_header = line
+                .get(..8)
+                .map_or(false, |name| name.eq_ignore_ascii_case(b"content-"));
+        }
+        if !is_content_header {
+            output.extend_from_slice(line);
+        }
+    }
+}
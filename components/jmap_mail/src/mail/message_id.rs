@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use store::{
+    rand::{self, Rng},
+    JMAPStore, Store,
+};
+
+pub trait JMAPMailMessageId {
+    fn mail_message_id(&self, sender: Option<&str>) -> String;
+}
+
+impl<T> JMAPMailMessageId for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Generates a Message-ID for a message created by the server. The configured
+    /// 'mail-message-id-domain' is used if set, otherwise the domain of the sender.
+    fn mail_message_id(&self, sender: Option<&str>) -> String {
+        let domain = self
+            .config
+            .mail_message_id_domain
+            .as_deref()
+            .or_else(|| {
+                sender
+                    .and_then(|sender| sender.rsplit_once('@'))
+                    .map(|(_, domain)| domain.trim_end_matches('>').trim())
+                    .filter(|domain| !domain.is_empty())
+            })
+            .unwrap_or("localhost");
+
+        format!(
+            "{:x}.{:x}@{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0),
+            rand::thread_rng().gen::<u64>(),
+            domain.to_lowercase()
+        )
+    }
+}
@@ -24,16 +24,24 @@
 pub mod changes;
 pub mod conv;
 pub mod copy;
+pub mod decompress;
 pub mod get;
 pub mod import;
+pub mod inline_images;
+pub mod message_id;
 pub mod parse;
+pub mod pgp;
 pub mod query;
 pub mod raft;
+pub mod remote_resources;
+pub mod revisions;
 pub mod schema;
 pub mod search_snippet;
 pub mod serialize;
 pub mod set;
 pub mod sharing;
+pub mod snooze;
+pub mod transfer_encoding;
 
 use jmap::{jmap_store::Object, types::jmap::JMAPId};
 use serde::{Deserialize, Serialize};
@@ -44,6 +52,7 @@ use mail_parser::{
         base64::decode_base64, charsets::map::get_charset_decoder,
         quoted_printable::decode_quoted_printable,
     },
+    parsers::preview::{preview_html, preview_text},
     Encoding, Header, MessagePartId, RfcHeader,
 };
 
@@ -122,6 +131,51 @@ impl StoreDeserialize for MessageData {
     }
 }
 
+impl MessageData {
+    // Builds the preview from the first text body part, or the first HTML part if there is none.
+    pub fn preview(&self, raw_message: &[u8], max_len: usize) -> Option<String> {
+        let mime_part = self
+            .text_body
+            .first()
+            .or_else(|| self.html_body.first())
+            .and_then(|part_id| self.mime_parts.get(*part_id))?;
+
+        #[allow(clippy::type_complexity)]
+        let (preview_fnc, part): (fn(Cow<str>, usize) -> Cow<str>, _) = match &mime_part.mime_type {
+            MimePartType::Text { part } => (preview_text, part),
+            MimePartType::Html { part } => (preview_html, part),
+            _ => return None,
+        };
+
+        preview_fnc(
+            part.decode_text(raw_message, mime_part.charset.as_deref(), true)?
+                .into(),
+            max_len,
+        )
+        .into_owned()
+        .into()
+    }
+}
+
+/// Preview computed at ingest, `max_len` is the preview length in effect at the time.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessagePreview {
+    pub max_len: usize,
+    pub preview: String,
+}
+
+impl StoreSerialize for MessagePreview {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for MessagePreview {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize_from(bytes).ok()
+    }
+}
+
 pub trait GetRawHeader {
     fn get_raw_header(&self, name: &HeaderName) -> Option<Vec<(usize, usize)>>;
 }
@@ -327,6 +381,8 @@ pub enum MessageField {
     ThreadId = 136,
     Mailbox = 137,
     HasHeader = 138,
+    DraftRevisions = 139,
+    Preview = 140,
 }
 
 impl From<MessageField> for FieldId {
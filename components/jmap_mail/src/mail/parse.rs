@@ -24,7 +24,8 @@
 use super::{
     conv::{HeaderValueInto, IntoForm},
     get::{AsBodyParts, AsBodyStructure, AsEmailHeaders, BlobResult, JMAPGetMail},
-    schema::{BodyProperty, Email, HeaderForm, Property, Value},
+    remote_resources::remote_resources,
+    schema::{BodyProperty, Email, EmailAutocrypt, EmailUnsubscribe, HeaderForm, Property, Value},
     GetRawHeader, MessagePart,
 };
 use crate::mail::{MimePart, MimePartType};
@@ -76,6 +77,10 @@ pub struct EmailParseRequest {
 
     #[serde(rename = "maxBodyValueBytes")]
     max_body_value_bytes: Option<usize>,
+
+    #[serde(rename = "lightweight")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lightweight: Option<bool>,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -103,8 +108,27 @@ struct EmailParseProperties {
     fetch_html_body_values: bool,
     fetch_all_body_values: bool,
     max_body_value_bytes: usize,
+    lightweight: bool,
 }
 
+// Properties that can be obtained without building the body structure
+const LIGHTWEIGHT_PROPERTIES: &[Property] = &[
+    Property::BlobId,
+    Property::Size,
+    Property::HasAttachment,
+    Property::MessageId,
+    Property::InReplyTo,
+    Property::References,
+    Property::Sender,
+    Property::From,
+    Property::To,
+    Property::Cc,
+    Property::Bcc,
+    Property::ReplyTo,
+    Property::Subject,
+    Property::SentAt,
+];
+
 pub trait JMAPMailParse<T>
 where
     T: for<'x> Store<'x> + 'static,
@@ -126,11 +150,32 @@ where
             not_parsable: Vec::new(),
             not_found: Vec::new(),
         };
+        let lightweight = request.lightweight.unwrap_or(false);
+        let properties = request
+            .properties
+            .and_then(|p| if !p.is_empty() { Some(p) } else { None });
         let parse_properties = EmailParseProperties {
-            properties: request
-                .properties
-                .and_then(|p| if !p.is_empty() { Some(p) } else { None })
-                .unwrap_or_else(Email::default_properties),
+            lightweight: lightweight
+                || properties.as_ref().map_or(false, |p| {
+                    p.iter().all(|p| LIGHTWEIGHT_PROPERTIES.contains(p))
+                }),
+            properties: match properties {
+                Some(properties) if lightweight => properties
+                    .into_iter()
+                    .filter(|p| LIGHTWEIGHT_PROPERTIES.contains(p))
+                    .collect(),
+                Some(properties) => properties,
+                None if lightweight => vec![
+                    Property::BlobId,
+                    Property::From,
+                    Property::To,
+                    Property::Subject,
+                    Property::SentAt,
+                    Property::Size,
+                    Property::HasAttachment,
+                ],
+                None => Email::default_properties(),
+            },
             body_properties: request
                 .body_properties
                 .take()
@@ -223,30 +268,27 @@ impl IntoParsedEmail for Message<'_> {
                 encoding: message_part.encoding,
             };
 
+            let mut is_attachment = false;
             let (mime_type, part_size) = match &mut message_part.body {
                 PartType::Html(html) => {
                     if !text_body.contains(&part_id) && !html_body.contains(&part_id) {
-                        has_attachments = true;
+                        is_attachment = true;
                     }
                     (MimePartType::Html { part }, html.len())
                 }
                 PartType::Text(text) => {
                     if !text_body.contains(&part_id) && !html_body.contains(&part_id) {
-                        has_attachments = true;
+                        is_attachment = true;
                     }
                     (MimePartType::Text { part }, text.len())
                 }
                 PartType::Binary(binary) => {
-                    if !has_attachments {
-                        has_attachments = true;
-                    }
+                    is_attachment = true;
                     (MimePartType::Other { part }, binary.len())
                 }
                 PartType::InlineBinary(binary) => (MimePartType::Other { part }, binary.len()),
                 PartType::Message(nested_message) => {
-                    if !has_attachments {
-                        has_attachments = true;
-                    }
+                    is_attachment = true;
 
                     (
                         MimePartType::Other { part },
@@ -261,12 +303,23 @@ impl IntoParsedEmail for Message<'_> {
                 ),
             };
 
-            mime_parts.push(MimePart::from_headers(
+            // In lightweight mode only attachments are inspected
+            if request.lightweight && !is_attachment {
+                continue;
+            }
+
+            let mime_part = MimePart::from_headers(
                 std::mem::take(&mut message_part.headers),
                 mime_type,
                 message_part.is_encoding_problem,
                 part_size,
-            ));
+            );
+            if is_attachment && !mime_part.is_inline_cid() {
+                has_attachments = true;
+            }
+            if !request.lightweight {
+                mime_parts.push(mime_part);
+            }
         }
 
         let mut email = VecMap::with_capacity(request.properties.len());
@@ -300,6 +353,10 @@ impl IntoParsedEmail for Message<'_> {
                     },
                 }
                 .into(),
+                Property::Unsubscribe => EmailUnsubscribe::from_headers(&headers, raw_message)
+                    .map(|value| Value::Unsubscribe { value }),
+                Property::Autocrypt => EmailAutocrypt::from_headers(&headers, raw_message)
+                    .map(|value| Value::Autocrypt { value }),
                 Property::Header(header) => match (&header.header, &header.form) {
                     (super::HeaderName::Other(_), _)
                     | (super::HeaderName::Rfc(_), HeaderForm::Raw) => {
@@ -397,6 +454,15 @@ impl IntoParsedEmail for Message<'_> {
                     }
                     Value::BodyValues { value: body_values }.into()
                 }
+                Property::RemoteResources => {
+                    let mut resources = Vec::new();
+                    for part_id in &html_body {
+                        if let PartType::Html(html) = &self.parts[*part_id].body {
+                            remote_resources(html, &mut resources);
+                        }
+                    }
+                    Value::RemoteResources { value: resources }.into()
+                }
                 Property::TextBody => Some(
                     mime_parts
                         .as_body_parts(
@@ -435,6 +501,9 @@ impl IntoParsedEmail for Message<'_> {
                 | Property::MailboxIds
                 | Property::Keywords
                 | Property::ReceivedAt
+                | Property::AuthenticationResults
+                | Property::Snoozed
+                | Property::ForwardedEmailId
                 | Property::Invalid(_) => None,
             };
 
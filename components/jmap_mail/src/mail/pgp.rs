@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, path::PathBuf, process::Command, time::Duration};
+
+use mail_builder::{
+    headers::{content_type::ContentType, text::Text},
+    mime::{BodyPart, MimePart},
+    MessageBuilder,
+};
+use store::{
+    core::process::run_with_timeout,
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+};
+
+// Keys are stored as <address>.asc (public) and <address>.key (secret) in a directory
+pub struct PgpKeys<'x> {
+    path: &'x str,
+}
+
+pub struct PgpRecipient {
+    pub key: PathBuf,
+    pub is_hidden: bool,
+}
+
+pub struct PgpSigner<'x> {
+    pub address: &'x str,
+    pub key: PathBuf,
+}
+
+impl<'x> PgpKeys<'x> {
+    pub fn new(path: &'x str) -> Self {
+        PgpKeys { path }
+    }
+
+    pub fn encryption_key(&self, address: &str) -> Option<PathBuf> {
+        self.key_path(address, "asc").filter(|path| path.is_file())
+    }
+
+    pub fn signing_key(&self, address: &str) -> Option<PathBuf> {
+        self.key_path(address, "key").filter(|path| path.is_file())
+    }
+
+    fn key_path(&self, address: &str, extension: &str) -> Option<PathBuf> {
+        let address = address.trim().to_lowercase();
+        if !address.is_empty()
+            && !address.starts_with('.')
+            && !address.contains(|ch| matches!(ch, '/' | '\\'))
+        {
+            let mut path = PathBuf::from(self.path);
+            path.push(format!("{}.{}", address, extension));
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+// Replaces the body of a message with a PGP/MIME (RFC 3156) multipart/encrypted part,
+// signing the encrypted contents first when a signer is provided. Hidden recipients
+// are not listed in the encrypted message, which is used for Bcc recipients.
+pub fn pgp_encrypt(
+    builder: &mut MessageBuilder,
+    recipients: &[PgpRecipient],
+    signer: Option<&PgpSigner>,
+    timeout: Duration,
+) -> Result<(), String> {
+    // Build the MIME entity to encrypt out of the message body parts
+    let mut inner = MessageBuilder::new();
+    inner.text_body = builder.text_body.take();
+    inner.html_body = builder.html_body.take();
+    inner.attachments = builder.attachments.take();
+    inner.body = builder.body.take();
+    let mut entity = Vec::with_capacity(1024);
+    inner
+        .write_to(&mut entity)
+        .map_err(|_| "Failed to write to memory.".to_string())?;
+
+    let encrypted = GnuPGHome::new(timeout)?.encrypt(&mime_entity(&entity), recipients, signer)?;
+
+    builder.body = MimePart {
+        headers: vec![(
+            "Content-Type".into(),
+            ContentType::new("multipart/encrypted")
+                .attribute("protocol", "application/pgp-encrypted")
+                .into(),
+        )],
+        contents: BodyPart::Multipart(vec![
+            MimePart {
+                headers: vec![
+                    (
+                        "Content-Type".into(),
+                        ContentType::new("application/pgp-encrypted").into(),
+                    ),
+                    (
+                        "Content-Description".into(),
+                        Text::new("PGP/MIME version identification").into(),
+                    ),
+                ],
+                contents: BodyPart::Text("Version: 1\r\n".into()),
+            },
+            MimePart {
+                headers: vec![
+                    (
+                        "Content-Type".into(),
+                        ContentType::new("application/octet-stream")
+                            .attribute("name", "encrypted.asc")
+                            .into(),
+                    ),
+                    (
+                        "Content-Disposition".into(),
+                        ContentType::new("inline")
+                            .attribute("filename", "encrypted.asc")
+                            .into(),
+                    ),
+                ],
+                contents: BodyPart::Text(String::from_utf8_lossy(&encrypted).into_owned().into()),
+            },
+        ]),
+    }
+    .into();
+
+    Ok(())
+}
+
+// Encryption and signing are delegated to GnuPG, using a temporary home directory
+// that only holds the signing key so that no keyring needs to be maintained.
+// The directory is only accessible by the server and the message to encrypt is
+// passed through stdin, so neither cleartext nor secret keys are exposed to other users.
+struct GnuPGHome {
+    path: PathBuf,
+    has_agent: bool,
+    timeout: Duration,
+}
+
+impl GnuPGHome {
+    fn new(timeout: Duration) -> Result<Self, String> {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "jmap-gnupg-{}",
+            thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(16)
+                .map(char::from)
+                .collect::<String>()
+        ));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path).map_err(|err| {
+            format!(
+                "Failed to create GnuPG home directory {}: {}",
+                path.display(),
+                err
+            )
+        })?;
+        Ok(GnuPGHome {
+            path,
+            has_agent: false,
+            timeout,
+        })
+    }
+
+    fn encrypt(
+        &mut self,
+        entity: &[u8],
+        recipients: &[PgpRecipient],
+        signer: Option<&PgpSigner>,
+    ) -> Result<Vec<u8>, String> {
+        let mut command = self.command("gpg");
+        command.args(["--trust-model", "always", "--armor", "--encrypt"]);
+        for recipient in recipients {
+            command
+                .arg(if recipient.is_hidden {
+                    "--hidden-recipient-file"
+                } else {
+                    "--recipient-file"
+                })
+                .arg(&recipient.key);
+        }
+        if let Some(signer) = signer {
+            // Secret keys are handled by gpg-agent, which is stopped once done
+            self.has_agent = true;
+            self.run(self.command("gpg").arg("--import").arg(&signer.key), &[])?;
+            command.args(["--sign", "--local-user", signer.address]);
+        }
+        self.run(command.arg("--output").arg("-"), entity)
+    }
+
+    fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        command.arg("--homedir").arg(&self.path);
+        if program == "gpg" {
+            command.args(["--batch", "--quiet", "--no-tty"]);
+        }
+        command
+    }
+
+    fn run(&self, command: &mut Command, input: &[u8]) -> Result<Vec<u8>, String> {
+        let output = run_with_timeout(command, input, self.timeout)
+            .map_err(|err| format!("Failed to run GnuPG: {}", err))?;
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+impl Drop for GnuPGHome {
+    fn drop(&mut self) {
+        if self.has_agent {
+            let _ = self.run(self.command("gpgconf").args(["--kill", "gpg-agent"]), &[]);
+        }
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Strips all headers other than Content-* from a message, leaving a MIME entity
+fn mime_entity(message: &[u8]) -> Vec<u8> {
+    let mut entity = Vec::with_capacity(message.len());
+    let mut keep_header = false;
+    let mut pos = 0;
+
+    while pos < message.len() {
+        let end = message[pos..]
+            .iter()
+            .position(|&ch| ch == b'\n')
+            .map_or(message.len(), |end| pos + end + 1);
+        let line = &message[pos..end];
+
+        if line == b"\r\n" || line == b"\n" {
+            entity.extend_from_slice(&message[pos..]);
+            break;
+        } else if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            keep_header = line.len() > 8 && line[..8].eq_ignore_ascii_case(b"content-");
+        }
+        if keep_header {
+            entity.extend_from_slice(line);
+        }
+        pos = end;
+    }
+
+    entity
+}
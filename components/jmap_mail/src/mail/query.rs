@@ -21,23 +21,28 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use super::schema::{Comparator, Email, Filter};
 use super::sharing::JMAPShareMail;
 use crate::mail::MessageField;
+use crate::mailbox::get::JMAPGetMailbox;
 use jmap::error::method::MethodError;
+use jmap::jmap_store::get::SharedDocsFnc;
 use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
-use jmap::request::query::{QueryRequest, QueryResponse};
+use jmap::request::query::{self, QueryRequest, QueryResponse};
 use jmap::types::jmap::JMAPId;
 use mail_parser::{HeaderName, RfcHeader};
 use store::ahash::AHashSet;
-use store::core::acl::ACL;
+use store::core::acl::{ACLToken, ACL};
 use store::core::collection::Collection;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
 use store::nlp::Language;
 use store::read::comparator::{self, DocumentSetComparator, FieldComparator};
 use store::read::filter::{self, Query};
-use store::{roaring::RoaringBitmap, AccountId, JMAPStore, Store};
+use store::read::FilterMapper;
+use store::{roaring::RoaringBitmap, AccountId, DocumentId, JMAPStore, Store};
 use store::{FieldId, Integer, LongInteger};
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
@@ -59,6 +64,23 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_query(&self, request: QueryRequest<Email>) -> jmap::Result<QueryResponse>;
+    fn mail_query_filter(
+        &self,
+        account_id: AccountId,
+        filter: Filter,
+        document_ids: &mut Option<Option<RoaringBitmap>>,
+        is_immutable_filter: &mut bool,
+    ) -> jmap::Result<filter::Filter>;
+    fn mail_mailbox_filter(
+        &self,
+        account_id: AccountId,
+        mailbox_id: DocumentId,
+    ) -> jmap::Result<filter::Filter>;
+    fn mail_search_ids(
+        &self,
+        account_id: AccountId,
+        filter: query::Filter<Filter>,
+    ) -> jmap::Result<RoaringBitmap>;
     fn get_thread_keywords(
         &self,
         account_id: AccountId,
@@ -86,217 +108,22 @@ where
         let mut is_immutable_filter = true;
         let mut is_immutable_sort = true;
 
-        helper.parse_filter(|filter| {
-            Ok(match filter {
-                Filter::InMailbox { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::eq(
-                        MessageField::Mailbox.into(),
-                        Query::Tag(Tag::Id(value.get_document_id())),
-                    )
-                }
-                Filter::InMailboxOtherThan { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::not(
-                        value
-                            .into_iter()
-                            .map(|mailbox| {
-                                filter::Filter::eq(
-                                    MessageField::Mailbox.into(),
-                                    Query::Tag(Tag::Id(mailbox.get_document_id())),
-                                )
-                            })
-                            .collect::<Vec<filter::Filter>>(),
-                    )
-                }
-                Filter::Before { value } => filter::Filter::lt(
-                    MessageField::ReceivedAt.into(),
-                    Query::LongInteger(value.timestamp() as LongInteger),
-                ),
-                Filter::After { value } => filter::Filter::gt(
-                    MessageField::ReceivedAt.into(),
-                    Query::LongInteger(value.timestamp() as LongInteger),
-                ),
-                Filter::MinSize { value } => {
-                    filter::Filter::ge(MessageField::Size.into(), Query::Integer(value as Integer))
-                }
-                Filter::MaxSize { value } => {
-                    filter::Filter::lt(MessageField::Size.into(), Query::Integer(value as Integer))
-                }
-                Filter::AllInThreadHaveKeyword { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::DocumentSet(
-                        self.get_thread_keywords(account_id, value.tag, true)?,
-                    )
-                }
-                Filter::SomeInThreadHaveKeyword { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::DocumentSet(
-                        self.get_thread_keywords(account_id, value.tag, false)?,
-                    )
-                }
-                Filter::NoneInThreadHaveKeyword { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::not(vec![filter::Filter::DocumentSet(
-                        self.get_thread_keywords(account_id, value.tag, false)?,
-                    )])
-                }
-                Filter::HasKeyword { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::eq(MessageField::Keyword.into(), Query::Tag(value.tag))
-                }
-                Filter::NotKeyword { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::not(vec![filter::Filter::eq(
-                        MessageField::Keyword.into(),
-                        Query::Tag(value.tag),
-                    )])
-                }
-                Filter::HasAttachment { value } => {
-                    let filter = filter::Filter::eq(
-                        MessageField::Attachment.into(),
-                        Query::Tag(Tag::Static(0)),
-                    );
-                    if !value {
-                        filter::Filter::not(vec![filter])
-                    } else {
-                        filter
-                    }
-                }
-                Filter::Text { value } => filter::Filter::or(vec![
-                    filter::Filter::eq(RfcHeader::From.into(), Query::Tokenize(value.clone())),
-                    filter::Filter::eq(RfcHeader::To.into(), Query::Tokenize(value.clone())),
-                    filter::Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value.clone())),
-                    filter::Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value.clone())),
-                    filter::Filter::eq(
-                        RfcHeader::Subject.into(),
-                        Query::match_text(value.clone(), Language::Unknown),
-                    ),
-                    filter::Filter::eq(
-                        MessageField::Body.into(),
-                        Query::match_text(value.clone(), Language::Unknown),
-                    ),
-                    filter::Filter::eq(
-                        MessageField::Attachment.into(),
-                        Query::match_text(value, Language::Unknown),
-                    ),
-                ]),
-                Filter::From { value } => {
-                    filter::Filter::eq(RfcHeader::From.into(), Query::Tokenize(value))
-                }
-                Filter::To { value } => {
-                    filter::Filter::eq(RfcHeader::To.into(), Query::Tokenize(value))
-                }
-                Filter::Cc { value } => {
-                    filter::Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value))
-                }
-                Filter::Bcc { value } => {
-                    filter::Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value))
-                }
-                Filter::Subject { value } => filter::Filter::eq(
-                    RfcHeader::Subject.into(),
-                    Query::match_text(value, Language::Unknown),
-                ),
-                Filter::Body { value } => filter::Filter::eq(
-                    MessageField::Body.into(),
-                    Query::match_text(value, Language::Unknown),
-                ),
-                Filter::Header { mut value } => {
-                    let (value, header) = match value.len() {
-                        1 => (None, value.pop().unwrap()),
-                        2 => (Some(value.pop().unwrap()), value.pop().unwrap()),
-                        _ => {
-                            return Err(MethodError::InvalidArguments(
-                                "Expected array of length 1 or 2.".to_string(),
-                            ));
-                        }
-                    };
-                    let header =
-                        if let Some(HeaderName::Rfc(rfc_header)) = HeaderName::parse(&header) {
-                            rfc_header
-                        } else {
-                            return Err(MethodError::InvalidArguments(format!(
-                                "Querying non-RFC header '{}' is not allowed.",
-                                header
-                            )));
-                        };
-
-                    if let Some(value) = value {
-                        filter::Filter::eq(
-                            if !matches!(
-                                header,
-                                RfcHeader::InReplyTo
-                                    | RfcHeader::References
-                                    | RfcHeader::ResentMessageId
-                            ) {
-                                header as FieldId
-                            } else {
-                                MessageField::MessageIdRef as FieldId
-                            },
-                            Query::Keyword(value),
-                        )
-                    } else {
-                        filter::Filter::eq(
-                            MessageField::HasHeader.into(),
-                            Query::Tag(Tag::Static(header.into())),
-                        )
-                    }
-                }
-
-                // Non-standard
-                Filter::Id { value } => {
-                    let mut set = RoaringBitmap::new();
-                    let document_ids = document_ids.get_or_insert_with(|| {
-                        self.get_document_ids(account_id, Collection::Mail)
-                            .unwrap_or(None)
-                    });
-                    if let Some(document_ids) = &document_ids {
-                        for jmap_id in value {
-                            let id = jmap_id.get_document_id();
-                            if document_ids.contains(id) {
-                                set.insert(id);
-                            }
-                        }
-                    }
-
-                    filter::Filter::DocumentSet(set)
-                }
-                Filter::SentBefore { value } => filter::Filter::lt(
-                    RfcHeader::Date.into(),
-                    Query::LongInteger(value.timestamp() as LongInteger),
-                ),
-                Filter::SentAfter { value } => filter::Filter::gt(
-                    RfcHeader::Date.into(),
-                    Query::LongInteger(value.timestamp() as LongInteger),
-                ),
-                Filter::InThread { value } => {
-                    if is_immutable_filter {
-                        is_immutable_filter = false;
-                    }
-                    filter::Filter::eq(
-                        MessageField::ThreadId.into(),
-                        Query::Tag(Tag::Id(value.get_document_id())),
-                    )
-                }
+        // Search folders are resolved from the mailbox collection
+        helper.cache_results(
+            format!(
+                "{:?}/{:?}/{:?}",
+                helper.request.filter, helper.request.sort, helper.request.arguments
+            ),
+            &[Collection::Mailbox],
+        )?;
 
-                Filter::Unsupported { value } => {
-                    return Err(MethodError::UnsupportedFilter(value));
-                }
-            })
+        helper.parse_filter(|filter| {
+            self.mail_query_filter(
+                account_id,
+                filter,
+                &mut document_ids,
+                &mut is_immutable_filter,
+            )
         })?;
 
         helper.parse_comparator(|comparator| {
@@ -321,10 +148,33 @@ where
                     field: MessageField::ThreadName.into(),
                     ascending: comparator.is_ascending,
                 }),
-                Comparator::SentAt => comparator::Comparator::Field(FieldComparator {
-                    field: RfcHeader::Date.into(),
-                    ascending: comparator.is_ascending,
-                }),
+                Comparator::SentAt => {
+                    let sent_at = comparator::Comparator::Field(FieldComparator {
+                        field: RfcHeader::Date.into(),
+                        ascending: comparator.is_ascending,
+                    });
+
+                    // Messages without a Date header are not indexed and always sort last,
+                    // unless they are configured to be treated as sent at the epoch.
+                    if self.config.mail_sort_missing_date_epoch && comparator.is_ascending {
+                        comparator::Comparator::List(vec![
+                            comparator::Comparator::DocumentSet(DocumentSetComparator {
+                                set: self
+                                    .get_tag(
+                                        account_id,
+                                        Collection::Mail,
+                                        MessageField::HasHeader.into(),
+                                        Tag::Static(RfcHeader::Date.into()),
+                                    )?
+                                    .unwrap_or_else(RoaringBitmap::new),
+                                ascending: false,
+                            }),
+                            sent_at,
+                        ])
+                    } else {
+                        sent_at
+                    }
+                }
                 Comparator::HasKeyword { keyword } => {
                     if is_immutable_sort {
                         is_immutable_sort = false;
@@ -368,6 +218,16 @@ where
             })
         })?;
 
+        // Sort by arrival date when the client does not request a specific order.
+        if let (comparator::Comparator::None, Some(ascending)) =
+            (&helper.comparator, self.config.mail_default_sort)
+        {
+            helper.comparator = comparator::Comparator::Field(FieldComparator {
+                field: MessageField::ReceivedAt.into(),
+                ascending,
+            });
+        }
+
         let mut seen_threads = AHashSet::default();
         helper
             .query(
@@ -397,6 +257,288 @@ where
             })
     }
 
+    fn mail_query_filter(
+        &self,
+        account_id: AccountId,
+        filter: Filter,
+        document_ids: &mut Option<Option<RoaringBitmap>>,
+        is_immutable_filter: &mut bool,
+    ) -> jmap::Result<filter::Filter> {
+        Ok(match filter {
+            Filter::InMailbox { value } => {
+                *is_immutable_filter = false;
+                self.mail_mailbox_filter(account_id, value.get_document_id())?
+            }
+            Filter::InMailboxOtherThan { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::not(
+                    value
+                        .into_iter()
+                        .map(|mailbox| {
+                            self.mail_mailbox_filter(account_id, mailbox.get_document_id())
+                        })
+                        .collect::<jmap::Result<Vec<filter::Filter>>>()?,
+                )
+            }
+            Filter::Before { value } => filter::Filter::lt(
+                MessageField::ReceivedAt.into(),
+                Query::LongInteger(value.timestamp() as LongInteger),
+            ),
+            Filter::After { value } => filter::Filter::gt(
+                MessageField::ReceivedAt.into(),
+                Query::LongInteger(value.timestamp() as LongInteger),
+            ),
+            Filter::MinSize { value } => {
+                filter::Filter::ge(MessageField::Size.into(), Query::Integer(value as Integer))
+            }
+            Filter::MaxSize { value } => {
+                filter::Filter::lt(MessageField::Size.into(), Query::Integer(value as Integer))
+            }
+            Filter::AllInThreadHaveKeyword { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::DocumentSet(self.get_thread_keywords(
+                    account_id,
+                    value.into_filter().tag,
+                    true,
+                )?)
+            }
+            Filter::SomeInThreadHaveKeyword { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::DocumentSet(self.get_thread_keywords(
+                    account_id,
+                    value.into_filter().tag,
+                    false,
+                )?)
+            }
+            Filter::NoneInThreadHaveKeyword { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::not(vec![filter::Filter::DocumentSet(
+                    self.get_thread_keywords(account_id, value.into_filter().tag, false)?,
+                )])
+            }
+            Filter::HasKeyword { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::eq(
+                    MessageField::Keyword.into(),
+                    Query::Tag(value.into_filter().tag),
+                )
+            }
+            Filter::NotKeyword { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::not(vec![filter::Filter::eq(
+                    MessageField::Keyword.into(),
+                    Query::Tag(value.into_filter().tag),
+                )])
+            }
+            Filter::HasAttachment { value } => {
+                let filter =
+                    filter::Filter::eq(MessageField::Attachment.into(), Query::Tag(Tag::Static(0)));
+                if !value {
+                    filter::Filter::not(vec![filter])
+                } else {
+                    filter
+                }
+            }
+            Filter::Text { value } => filter::Filter::or(vec![
+                filter::Filter::eq(RfcHeader::From.into(), Query::Tokenize(value.clone())),
+                filter::Filter::eq(RfcHeader::To.into(), Query::Tokenize(value.clone())),
+                filter::Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value.clone())),
+                filter::Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value.clone())),
+                filter::Filter::eq(
+                    RfcHeader::Subject.into(),
+                    Query::match_text(value.clone(), Language::Unknown),
+                ),
+                filter::Filter::eq(
+                    MessageField::Body.into(),
+                    Query::match_text(value.clone(), Language::Unknown),
+                ),
+                filter::Filter::eq(
+                    MessageField::Attachment.into(),
+                    Query::match_text(value, Language::Unknown),
+                ),
+            ]),
+            Filter::From { value } => {
+                filter::Filter::eq(RfcHeader::From.into(), Query::Tokenize(value))
+            }
+            Filter::To { value } => {
+                filter::Filter::eq(RfcHeader::To.into(), Query::Tokenize(value))
+            }
+            Filter::Cc { value } => {
+                filter::Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value))
+            }
+            Filter::Bcc { value } => {
+                filter::Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value))
+            }
+            Filter::Subject { value } => filter::Filter::eq(
+                RfcHeader::Subject.into(),
+                Query::match_text(value, Language::Unknown),
+            ),
+            Filter::Body { value } => filter::Filter::eq(
+                MessageField::Body.into(),
+                Query::match_text(value, Language::Unknown),
+            ),
+            Filter::Header { mut value } => {
+                let (value, header) = match value.len() {
+                    1 => (None, value.pop().unwrap()),
+                    2 => (Some(value.pop().unwrap()), value.pop().unwrap()),
+                    _ => {
+                        return Err(MethodError::InvalidArguments(
+                            "Expected array of length 1 or 2.".to_string(),
+                        ));
+                    }
+                };
+                let header = if let Some(HeaderName::Rfc(rfc_header)) = HeaderName::parse(&header) {
+                    rfc_header
+                } else {
+                    return Err(MethodError::InvalidArguments(format!(
+                        "Querying non-RFC header '{}' is not allowed.",
+                        header
+                    )));
+                };
+
+                if let Some(value) = value {
+                    filter::Filter::eq(
+                        if !matches!(
+                            header,
+                            RfcHeader::InReplyTo
+                                | RfcHeader::References
+                                | RfcHeader::ResentMessageId
+                        ) {
+                            header as FieldId
+                        } else {
+                            MessageField::MessageIdRef as FieldId
+                        },
+                        Query::Keyword(value),
+                    )
+                } else {
+                    filter::Filter::eq(
+                        MessageField::HasHeader.into(),
+                        Query::Tag(Tag::Static(header.into())),
+                    )
+                }
+            }
+
+            // Non-standard
+            Filter::Id { value } => {
+                let mut set = RoaringBitmap::new();
+                let document_ids = document_ids.get_or_insert_with(|| {
+                    self.get_document_ids(account_id, Collection::Mail)
+                        .unwrap_or(None)
+                });
+                if let Some(document_ids) = &document_ids {
+                    for jmap_id in value {
+                        let id = jmap_id.get_document_id();
+                        if document_ids.contains(id) {
+                            set.insert(id);
+                        }
+                    }
+                }
+
+                filter::Filter::DocumentSet(set)
+            }
+            Filter::SentBefore { value } => filter::Filter::lt(
+                RfcHeader::Date.into(),
+                Query::LongInteger(value.timestamp() as LongInteger),
+            ),
+            Filter::SentAfter { value } => filter::Filter::gt(
+                RfcHeader::Date.into(),
+                Query::LongInteger(value.timestamp() as LongInteger),
+            ),
+            Filter::InThread { value } => {
+                *is_immutable_filter = false;
+                filter::Filter::eq(
+                    MessageField::ThreadId.into(),
+                    Query::Tag(Tag::Id(value.get_document_id())),
+                )
+            }
+
+            Filter::Unsupported { value } => {
+                return Err(MethodError::UnsupportedFilter(value));
+            }
+        })
+    }
+
+    fn mail_mailbox_filter(
+        &self,
+        account_id: AccountId,
+        mailbox_id: DocumentId,
+    ) -> jmap::Result<filter::Filter> {
+        // Search folders contain the messages matching their saved filter
+        Ok(
+            if let Some(search_filter) = self.mailbox_search_filter(account_id, mailbox_id)? {
+                filter::Filter::DocumentSet(self.mail_search_ids(account_id, search_filter)?)
+            } else {
+                filter::Filter::eq(
+                    MessageField::Mailbox.into(),
+                    Query::Tag(Tag::Id(mailbox_id)),
+                )
+            },
+        )
+    }
+
+    fn mail_search_ids(
+        &self,
+        account_id: AccountId,
+        filter: query::Filter<Filter>,
+    ) -> jmap::Result<RoaringBitmap> {
+        let mut helper = QueryHelper::new(
+            self,
+            QueryRequest::<Email> {
+                acl: Arc::new(ACLToken {
+                    member_of: vec![account_id],
+                    access_to: vec![],
+                })
+                .into(),
+                account_id: JMAPId::from(account_id),
+                filter: filter.into(),
+                sort: None,
+                position: None,
+                anchor: None,
+                anchor_offset: None,
+                limit: None,
+                calculate_total: None,
+                arguments: QueryArguments::default(),
+            },
+            None::<SharedDocsFnc>,
+        )?;
+        let mut document_ids = None;
+        let mut is_immutable_filter = true;
+
+        // Search folders referenced by a saved filter are not expanded
+        helper.parse_filter(|filter| match filter {
+            Filter::InMailbox { value } => Ok(filter::Filter::eq(
+                MessageField::Mailbox.into(),
+                Query::Tag(Tag::Id(value.get_document_id())),
+            )),
+            Filter::InMailboxOtherThan { value } => Ok(filter::Filter::not(
+                value
+                    .into_iter()
+                    .map(|mailbox| {
+                        filter::Filter::eq(
+                            MessageField::Mailbox.into(),
+                            Query::Tag(Tag::Id(mailbox.get_document_id())),
+                        )
+                    })
+                    .collect::<Vec<filter::Filter>>(),
+            )),
+            filter => self.mail_query_filter(
+                account_id,
+                filter,
+                &mut document_ids,
+                &mut is_immutable_filter,
+            ),
+        })?;
+
+        Ok(self
+            .query_store::<FilterMapper>(
+                account_id,
+                Collection::Mail,
+                helper.filter,
+                comparator::Comparator::None,
+            )?
+            .into_bitmap())
+    }
+
     fn get_thread_keywords(
         &self,
         account_id: AccountId,
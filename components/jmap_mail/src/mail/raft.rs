@@ -37,6 +37,7 @@ use store::{
 use super::schema::Email;
 use super::MessageData;
 use super::MessageField;
+use super::MessagePreview;
 
 impl<T> RaftObject<T> for Email
 where
@@ -59,19 +60,40 @@ where
             })?;
 
             // Build index from message metadata
-            MessageData::deserialize(&store.blob_get(&metadata_blob_id)?.ok_or_else(|| {
-                StoreError::NotFound(format!(
-                    "Could not find message metadata blob for {}.",
-                    document.document_id
-                ))
-            })?)
-            .ok_or_else(|| {
-                StoreError::InternalError(format!(
-                    "Failed to get deserialize message data for {}.",
-                    document.document_id
-                ))
-            })?
-            .build_index(document, true)?;
+            let message_data =
+                MessageData::deserialize(&store.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                    StoreError::NotFound(format!(
+                        "Could not find message metadata blob for {}.",
+                        document.document_id
+                    ))
+                })?)
+                .ok_or_else(|| {
+                    StoreError::InternalError(format!(
+                        "Failed to get deserialize message data for {}.",
+                        document.document_id
+                    ))
+                })?;
+
+            // Rebuild preview
+            let preview = store
+                .blob_get(&message_data.raw_message)?
+                .and_then(|raw_message| {
+                    message_data.preview(&raw_message, store.config.mail_preview_length)
+                });
+            if let Some(preview) = preview {
+                document.binary(
+                    MessageField::Preview,
+                    MessagePreview {
+                        max_len: store.config.mail_preview_length,
+                        preview,
+                    }
+                    .serialize()
+                    .unwrap(),
+                    IndexOptions::new(),
+                );
+            }
+
+            message_data.build_index(document, true)?;
 
             // Add thread id
             let thread_id = jmap_id.get_prefix_id();
@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::EmailRemoteResource;
+
+// Adds the remote URLs referenced by the src and background attributes of an HTML
+// body to `resources`. Images with a width and height of at most one pixel are
+// reported as tracking pixels.
+pub fn remote_resources(html: &str, resources: &mut Vec<EmailRemoteResource>) {
+    let mut pos = 0;
+
+    while let Some(start) = html[pos..].find('<') {
+        pos += start + 1;
+
+        // Skip comments, closing tags and declarations
+        if html[pos..].starts_with("!--") {
+            pos = html[pos..]
+                .find("-->")
+                .map_or(html.len(), |end| pos + end + 3);
+            continue;
+        }
+        let element_len = html[pos..]
+            .find(|ch: char| !ch.is_ascii_alphanumeric())
+            .unwrap_or(html.len() - pos);
+        if element_len == 0 {
+            continue;
+        }
+        let element = html[pos..pos + element_len].to_ascii_lowercase();
+        pos += element_len;
+
+        let mut urls = Vec::new();
+        let mut width = None;
+        let mut height = None;
+        while let Some((name, value)) = next_attribute(html, &mut pos) {
+            match name.to_ascii_lowercase().as_str() {
+                attribute @ ("src" | "background") if is_remote_url(&value) => {
+                    urls.push((attribute.to_string(), value));
+                }
+                "width" => width = parse_pixels(&value),
+                "height" => height = parse_pixels(&value),
+                _ => (),
+            }
+        }
+
+        let is_tracking_pixel =
+            element == "img" && width.map_or(false, |w| w <= 1) && height.map_or(false, |h| h <= 1);
+        for (attribute, url) in urls {
+            resources.push(EmailRemoteResource {
+                url,
+                element: element.clone(),
+                attribute,
+                is_tracking_pixel,
+            });
+        }
+    }
+}
+
+fn next_attribute(html: &str, pos: &mut usize) -> Option<(String, String)> {
+    let bytes = html.as_bytes();
+
+    // Skip whitespace and self-closing slashes
+    while *pos < bytes.len() && (bytes[*pos].is_ascii_whitespace() || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() || bytes[*pos] == b'>' {
+        return None;
+    }
+
+    let name_start = *pos;
+    while *pos < bytes.len()
+        && !bytes[*pos].is_ascii_whitespace()
+        && !matches!(bytes[*pos], b'=' | b'>' | b'/')
+    {
+        *pos += 1;
+    }
+    let name = html[name_start..*pos].to_string();
+
+    while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() || bytes[*pos] != b'=' {
+        return Some((name, String::new()));
+    }
+    *pos += 1;
+    while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+
+    let value = match bytes.get(*pos) {
+        Some(&quote @ (b'"' | b'\'')) => {
+            let value_start = *pos + 1;
+            *pos = html[value_start..]
+                .find(quote as char)
+                .map_or(html.len(), |end| value_start + end);
+            let value = &html[value_start..*pos];
+            *pos = std::cmp::min(*pos + 1, html.len());
+            value
+        }
+        _ => {
+            let value_start = *pos;
+            while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() && bytes[*pos] != b'>' {
+                *pos += 1;
+            }
+            &html[value_start..*pos]
+        }
+    };
+
+    Some((name, value.trim().replace("&amp;", "&")))
+}
+
+fn is_remote_url(url: &str) -> bool {
+    url.starts_with("//")
+        || url.split_once(':').map_or(false, |(scheme, _)| {
+            scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+        })
+}
+
+fn parse_pixels(value: &str) -> Option<u32> {
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError,
+    request::ACLEnforce,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use store::{
+    bincode,
+    blob::BlobId,
+    core::{
+        acl::{ACLToken, ACL},
+        collection::Collection,
+        vec_map::VecMap,
+    },
+    serialize::{StoreDeserialize, StoreSerialize},
+    JMAPStore, SharedBitmap, Store,
+};
+
+use super::{sharing::JMAPShareMail, MessageField};
+
+/// Raw message blobs of the versions a draft replaced, oldest first.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct DraftRevisions {
+    pub blob_ids: Vec<BlobId>,
+}
+
+impl StoreSerialize for DraftRevisions {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for DraftRevisions {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize_from(bytes).ok()
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EmailRevisionsRequest {
+    #[serde(skip)]
+    pub acl: Option<Arc<ACLToken>>,
+
+    #[serde(rename = "accountId")]
+    pub account_id: JMAPId,
+
+    #[serde(rename = "ids")]
+    ids: Vec<JMAPId>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EmailRevisionsResponse {
+    #[serde(rename = "accountId")]
+    account_id: JMAPId,
+
+    #[serde(rename = "revisions")]
+    revisions: VecMap<JMAPId, Vec<JMAPBlob>>,
+
+    #[serde(rename = "notFound")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    not_found: Vec<JMAPId>,
+}
+
+pub trait JMAPMailRevisions<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_revisions(
+        &self,
+        request: EmailRevisionsRequest,
+    ) -> jmap::Result<EmailRevisionsResponse>;
+}
+
+impl<T> JMAPMailRevisions<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_revisions(
+        &self,
+        request: EmailRevisionsRequest,
+    ) -> jmap::Result<EmailRevisionsResponse> {
+        if request.ids.len() > self.config.max_objects_in_get {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let account_id = request.account_id.get_document_id();
+        let acl = request.acl.unwrap();
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Mail)?
+            .unwrap_or_default();
+        let shared_messages = if acl.is_shared(account_id) {
+            self.mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
+                .into()
+        } else {
+            None
+        };
+
+        let mut response = EmailRevisionsResponse {
+            account_id: request.account_id,
+            revisions: VecMap::with_capacity(request.ids.len()),
+            not_found: Vec::new(),
+        };
+
+        for id in request.ids {
+            let document_id = id.get_document_id();
+            if !document_ids.contains(document_id)
+                || shared_messages.as_ref().map_or(false, |shared_messages| {
+                    !shared_messages.has_access(document_id)
+                })
+            {
+                response.not_found.push(id);
+                continue;
+            }
+
+            // Most recent revision first
+            response.revisions.append(
+                id,
+                self.get_document_value::<DraftRevisions>(
+                    account_id,
+                    Collection::Mail,
+                    document_id,
+                    MessageField::DraftRevisions.into(),
+                )?
+                .unwrap_or_default()
+                .blob_ids
+                .iter()
+                .rev()
+                .map(JMAPBlob::from)
+                .collect(),
+            );
+        }
+
+        Ok(response)
+    }
+}
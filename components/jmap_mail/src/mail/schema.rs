@@ -101,6 +101,44 @@ pub struct EmailHeader {
     pub value: String,
 }
 
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailUnsubscribe {
+    pub mailto: Vec<String>,
+    pub http: Vec<String>,
+    #[serde(rename = "oneClick")]
+    pub one_click: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailAutocrypt {
+    pub addr: String,
+    #[serde(rename = "preferEncrypt")]
+    pub prefer_encrypt: String,
+    #[serde(rename = "keyData")]
+    pub key_data: String,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailAuthResults {
+    pub dkim: Option<String>,
+    pub spf: Option<String>,
+    pub dmarc: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailSnooze {
+    pub until: JMAPDate,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailRemoteResource {
+    pub url: String,
+    pub element: String,
+    pub attribute: String,
+    #[serde(rename = "isTrackingPixel")]
+    pub is_tracking_pixel: bool,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Keyword {
     pub tag: Tag,
@@ -127,32 +165,8 @@ impl Keyword {
     }
 
     pub fn parse(value: &str) -> Self {
-        if let Some(k) = value.strip_prefix('$') {
-            if k.eq_ignore_ascii_case("seen") {
-                return Keyword::new(Tag::Static(Self::SEEN));
-            } else if k.eq_ignore_ascii_case("draft") {
-                return Keyword::new(Tag::Static(Self::DRAFT));
-            } else if k.eq_ignore_ascii_case("flagged") {
-                return Keyword::new(Tag::Static(Self::FLAGGED));
-            } else if k.eq_ignore_ascii_case("answered") {
-                return Keyword::new(Tag::Static(Self::ANSWERED));
-            } else if k.eq_ignore_ascii_case("recent") {
-                return Keyword::new(Tag::Static(Self::RECENT));
-            } else if k.eq_ignore_ascii_case("important") {
-                return Keyword::new(Tag::Static(Self::IMPORTANT));
-            } else if k.eq_ignore_ascii_case("phishing") {
-                return Keyword::new(Tag::Static(Self::PHISHING));
-            } else if k.eq_ignore_ascii_case("junk") {
-                return Keyword::new(Tag::Static(Self::JUNK));
-            } else if k.eq_ignore_ascii_case("notjunk") {
-                return Keyword::new(Tag::Static(Self::NOTJUNK));
-            } else if k.eq_ignore_ascii_case("deleted") {
-                return Keyword::new(Tag::Static(Self::DELETED));
-            } else if k.eq_ignore_ascii_case("forwarded") {
-                return Keyword::new(Tag::Static(Self::FORWARDED));
-            } else if k.eq_ignore_ascii_case("mdnsent") {
-                return Keyword::new(Tag::Static(Self::MDN_SENT));
-            }
+        if let Some(tag) = value.strip_prefix('$').and_then(Self::system_flag) {
+            return Keyword::new(tag);
         }
 
         Keyword::new(if value.len() < MAX_KEYWORD_LENGTH {
@@ -167,6 +181,46 @@ impl Keyword {
             )
         })
     }
+
+    // Email/query filters also accept system flags in their IMAP (\Seen) and bare (Seen)
+    // forms, which are not valid keywords anywhere else.
+    pub fn into_filter(self) -> Self {
+        let tag = match &self.tag {
+            Tag::Text(value) => Self::system_flag(value.strip_prefix('\\').unwrap_or(value)),
+            _ => None,
+        };
+        tag.map_or(self, Keyword::new)
+    }
+
+    fn system_flag(name: &str) -> Option<Tag> {
+        Some(Tag::Static(if name.eq_ignore_ascii_case("seen") {
+            Self::SEEN
+        } else if name.eq_ignore_ascii_case("draft") {
+            Self::DRAFT
+        } else if name.eq_ignore_ascii_case("flagged") {
+            Self::FLAGGED
+        } else if name.eq_ignore_ascii_case("answered") {
+            Self::ANSWERED
+        } else if name.eq_ignore_ascii_case("recent") {
+            Self::RECENT
+        } else if name.eq_ignore_ascii_case("important") {
+            Self::IMPORTANT
+        } else if name.eq_ignore_ascii_case("phishing") {
+            Self::PHISHING
+        } else if name.eq_ignore_ascii_case("junk") {
+            Self::JUNK
+        } else if name.eq_ignore_ascii_case("notjunk") {
+            Self::NOTJUNK
+        } else if name.eq_ignore_ascii_case("deleted") {
+            Self::DELETED
+        } else if name.eq_ignore_ascii_case("forwarded") {
+            Self::FORWARDED
+        } else if name.eq_ignore_ascii_case("mdnsent") {
+            Self::MDN_SENT
+        } else {
+            return None;
+        }))
+    }
 }
 
 impl From<&Tag> for Keyword {
@@ -234,6 +288,12 @@ pub enum Property {
     BodyStructure,
     Headers,
     Header(HeaderProperty),
+    Unsubscribe,
+    AuthenticationResults,
+    Snoozed,
+    RemoteResources,
+    Autocrypt,
+    ForwardedEmailId,
     Invalid(String),
 }
 
@@ -266,6 +326,12 @@ impl Property {
             "attachments" => Property::Attachments,
             "bodyStructure" => Property::BodyStructure,
             "headers" => Property::Headers,
+            "unsubscribe" => Property::Unsubscribe,
+            "authenticationResults" => Property::AuthenticationResults,
+            "snoozed" => Property::Snoozed,
+            "remoteResources" => Property::RemoteResources,
+            "autocrypt" => Property::Autocrypt,
+            "forwardedEmailId" => Property::ForwardedEmailId,
             _ if value.starts_with("header:") => {
                 if let Some(header) = HeaderProperty::parse(value) {
                     Property::Header(header)
@@ -329,6 +395,12 @@ impl Display for Property {
             Property::BodyStructure => write!(f, "bodyStructure"),
             Property::Headers => write!(f, "headers"),
             Property::Header(header) => header.fmt(f),
+            Property::Unsubscribe => write!(f, "unsubscribe"),
+            Property::AuthenticationResults => write!(f, "authenticationResults"),
+            Property::Snoozed => write!(f, "snoozed"),
+            Property::RemoteResources => write!(f, "remoteResources"),
+            Property::Autocrypt => write!(f, "autocrypt"),
+            Property::ForwardedEmailId => write!(f, "forwardedEmailId"),
             Property::Invalid(value) => write!(f, "{}", value),
         }
     }
@@ -573,6 +645,21 @@ pub enum Value {
     Headers {
         value: Vec<EmailHeader>,
     },
+    Unsubscribe {
+        value: EmailUnsubscribe,
+    },
+    AuthenticationResults {
+        value: EmailAuthResults,
+    },
+    Snoozed {
+        value: EmailSnooze,
+    },
+    RemoteResources {
+        value: Vec<EmailRemoteResource>,
+    },
+    Autocrypt {
+        value: EmailAutocrypt,
+    },
     Null,
 }
 
@@ -693,6 +780,12 @@ impl From<Property> for FieldId {
             Property::Headers => 22,
             Property::Header(_) => 23,
             Property::Invalid(_) => 24,
+            Property::Unsubscribe => 25,
+            Property::AuthenticationResults => 26,
+            Property::Snoozed => 27,
+            Property::RemoteResources => 28,
+            Property::Autocrypt => 29,
+            Property::ForwardedEmailId => 30,
         }
     }
 }
@@ -723,6 +816,12 @@ impl From<FieldId> for Property {
             20 => Property::Attachments,
             21 => Property::BodyStructure,
             22 => Property::Headers,
+            25 => Property::Unsubscribe,
+            26 => Property::AuthenticationResults,
+            27 => Property::Snoozed,
+            28 => Property::RemoteResources,
+            29 => Property::Autocrypt,
+            30 => Property::ForwardedEmailId,
             136 => Property::ThreadId,
             137 => Property::MailboxIds,
             132 => Property::Keywords,
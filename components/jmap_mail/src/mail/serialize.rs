@@ -37,10 +37,11 @@ use super::{
     get::GetArguments,
     import::EmailImport,
     schema::{
-        BodyProperty, Email, EmailAddress, EmailBodyPart, EmailHeader, Filter, HeaderForm,
-        HeaderProperty, Keyword, Property, Value,
+        BodyProperty, Email, EmailAddress, EmailBodyPart, EmailHeader, EmailSnooze, Filter,
+        HeaderForm, HeaderProperty, Keyword, Property, Value,
     },
     search_snippet::SearchSnippetGetRequest,
+    set::SetArguments,
 };
 
 // Email de/serialization
@@ -73,6 +74,11 @@ impl Serialize for Email {
                 Value::GroupedAddresses { value } => map.serialize_entry(name, value)?,
                 Value::GroupedAddressesList { value } => map.serialize_entry(name, value)?,
                 Value::Headers { value } => map.serialize_entry(name, value)?,
+                Value::Unsubscribe { value } => map.serialize_entry(name, value)?,
+                Value::AuthenticationResults { value } => map.serialize_entry(name, value)?,
+                Value::Snoozed { value } => map.serialize_entry(name, value)?,
+                Value::RemoteResources { value } => map.serialize_entry(name, value)?,
+                Value::Autocrypt { value } => map.serialize_entry(name, value)?,
                 Value::Null => map.serialize_entry(name, &None::<&str>)?,
             }
         }
@@ -110,6 +116,16 @@ impl<'de> serde::de::Visitor<'de> for EmailVisitor {
                             .append(Property::MailboxIds, Value::MailboxIds { value, set: true });
                     }
                 }
+                "snoozed" => {
+                    properties.append(
+                        Property::Snoozed,
+                        if let Some(value) = map.next_value::<Option<EmailSnooze>>()? {
+                            Value::Snoozed { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "messageId" => {
                     if let Some(value) = map.next_value::<Option<Vec<String>>>()? {
                         properties.append(Property::MessageId, Value::TextList { value });
@@ -219,6 +235,11 @@ impl<'de> serde::de::Visitor<'de> for EmailVisitor {
                         properties.append(Property::ThreadId, Value::Id { value });
                     }
                 }
+                "forwardedEmailId" => {
+                    if let Some(value) = map.next_value::<Option<JMAPId>>()? {
+                        properties.append(Property::ForwardedEmailId, Value::Id { value });
+                    }
+                }
                 "size" => {
                     if let Some(value) = map.next_value::<Option<usize>>()? {
                         properties.append(Property::Size, Value::Size { value });
@@ -419,6 +440,11 @@ impl Serialize for EmailBodyPart {
                 Value::GroupedAddresses { value } => map.serialize_entry(name, value)?,
                 Value::GroupedAddressesList { value } => map.serialize_entry(name, value)?,
                 Value::Headers { value } => map.serialize_entry(name, value)?,
+                Value::Unsubscribe { value } => map.serialize_entry(name, value)?,
+                Value::AuthenticationResults { value } => map.serialize_entry(name, value)?,
+                Value::Snoozed { value } => map.serialize_entry(name, value)?,
+                Value::RemoteResources { value } => map.serialize_entry(name, value)?,
+                Value::Autocrypt { value } => map.serialize_entry(name, value)?,
                 Value::Null => map.serialize_entry(name, &None::<&str>)?,
             }
         }
@@ -725,6 +751,33 @@ impl<'de> Deserialize<'de> for Keyword {
 }
 
 // Argument serializers
+impl ArgumentDeserializer for SetArguments {
+    fn deserialize<'x: 'y, 'y, 'z>(
+        &'y mut self,
+        property: &'z str,
+        value: &mut impl serde::de::MapAccess<'x>,
+    ) -> Result<(), String> {
+        if property == "requireRecipients" {
+            self.require_recipients = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "validateFrom" {
+            self.validate_from = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "encrypt" {
+            self.encrypt = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "sign" {
+            self.sign = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "replaceDrafts" {
+            self.replace_drafts = value.next_value().map_err(|err| err.to_string())?;
+        } else if property == "ifKeywords" {
+            self.if_keywords = value.next_value().map_err(|err| err.to_string())?;
+        } else {
+            value
+                .next_value::<IgnoredAny>()
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
 impl ArgumentDeserializer for GetArguments {
     fn deserialize<'x: 'y, 'y, 'z>(
         &'y mut self,
@@ -21,13 +21,23 @@
  * for more details.
 */
 
+use super::conv::HeaderValueInto;
 use super::get::{BlobResult, JMAPGetMail};
+use super::message_id::JMAPMailMessageId;
+use super::pgp::{pgp_encrypt, PgpKeys, PgpRecipient, PgpSigner};
+use super::revisions::DraftRevisions;
 use super::schema::{
-    BodyProperty, Email, EmailBodyPart, EmailBodyValue, HeaderForm, Keyword, Property, Value,
+    BodyProperty, Email, EmailAddress, EmailBodyPart, EmailBodyValue, HeaderForm, Keyword,
+    Property, Value,
 };
 use super::sharing::JMAPShareMail;
+use super::snooze::{move_mailbox, JMAPMailSnooze};
+use super::transfer_encoding::TransferEncoding;
 use super::{HeaderName, MessageData, MessageField};
+use crate::identity::set::JMAPSetIdentity;
 use crate::mail::import::JMAPMailImport;
+use crate::mailbox::get::JMAPGetMailbox;
+use jmap::error::method::MethodError;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::{SetHelper, SetObject};
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
@@ -44,9 +54,13 @@ use mail_builder::headers::text::Text;
 use mail_builder::headers::url::URL;
 use mail_builder::mime::{BodyPart, MimePart};
 use mail_builder::MessageBuilder;
-use mail_parser::{Message, RfcHeader};
-use std::sync::Arc;
-use store::ahash::AHashSet;
+use mail_parser::{parsers::MessageStream, Message, RfcHeader};
+use std::{
+    borrow::Cow,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use store::ahash::{AHashMap, AHashSet};
 use store::blob::BlobId;
 use store::core::acl::{ACLToken, ACL};
 use store::core::collection::Collection;
@@ -54,14 +68,24 @@ use store::core::document::Document;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
 use store::core::vec_map::VecMap;
-use store::serialize::StoreDeserialize;
+use store::serialize::{StoreDeserialize, StoreSerialize};
 use store::tracing::error;
-use store::write::batch::WriteBatch;
+use store::write::batch::{WriteAction, WriteBatch};
 use store::write::options::{IndexOptions, Options};
 use store::{AccountId, DocumentId, JMAPStore, SharedBitmap, Store};
 
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    pub require_recipients: Option<bool>,
+    pub validate_from: Option<bool>,
+    pub encrypt: Option<bool>,
+    pub sign: Option<bool>,
+    pub replace_drafts: Option<AHashMap<String, JMAPId>>,
+    pub if_keywords: Option<AHashMap<JMAPId, VecMap<Keyword, bool>>>,
+}
+
 impl SetObject for Email {
-    type SetArguments = ();
+    type SetArguments = SetArguments;
 
     type NextCall = SetRequest<Email>;
 
@@ -134,14 +158,90 @@ where
         let mailbox_ids = self
             .get_document_ids(helper.account_id, Collection::Mailbox)?
             .unwrap_or_default();
+        let search_folders = self.mailbox_search_folders(helper.account_id)?;
         let account_id = helper.account_id;
+        let require_recipients = helper.request.arguments.require_recipients.unwrap_or(false);
+        // Validation can be requested per call but not disabled when enabled in the config
+        let validate_from = self.config.mail_validate_from
+            || helper.request.arguments.validate_from.unwrap_or(false);
+        let encrypt = helper.request.arguments.encrypt.unwrap_or(false);
+        let sign = helper.request.arguments.sign.unwrap_or(false);
+        let pgp_keys = if encrypt {
+            Some(PgpKeys::new(
+                self.config.mail_pgp_keys_path.as_deref().ok_or_else(|| {
+                    MethodError::InvalidArguments(
+                        "PGP encryption is not enabled on this server.".to_string(),
+                    )
+                })?,
+            ))
+        } else if sign {
+            return Err(MethodError::InvalidArguments(
+                "Signing is only supported for encrypted messages.".to_string(),
+            ));
+        } else {
+            None
+        };
+        let replace_drafts = helper
+            .request
+            .arguments
+            .replace_drafts
+            .take()
+            .unwrap_or_default();
+        if !replace_drafts.is_empty() && self.config.mail_draft_revisions == 0 {
+            return Err(MethodError::InvalidArguments(
+                "Draft revisions are not enabled on this server.".to_string(),
+            ));
+        }
+        let if_keywords = helper
+            .request
+            .arguments
+            .if_keywords
+            .take()
+            .unwrap_or_default();
+
+        // Messages snoozed by the account owner are moved out of the Inbox
+        let (inbox_id, mut snoozed_id) = if !helper.acl.is_shared(account_id)
+            && helper.request.update.as_ref().map_or(false, |update| {
+                update
+                    .values()
+                    .any(|item| item.properties.contains_key(&Property::Snoozed))
+            }) {
+            (
+                self.mailbox_get_by_role(account_id, "inbox")?,
+                self.mailbox_get_by_role(account_id, "snoozed")?,
+            )
+        } else {
+            (None, None)
+        };
 
         helper.disable_write_batch();
 
-        helper.create(|_create_id, item, helper, document| {
+        helper.create(|create_id, item, helper, document| {
+            let max_messages = helper.store.config.mail_max_messages;
+            if max_messages > 0 && helper.document_ids.len() as usize >= max_messages {
+                return Err(SetError::new(SetErrorType::OverQuota).with_description(
+                    "Maximum number of messages exceeded, please delete some and try again.",
+                ));
+            }
+
             let mut builder = MessageBuilder::new();
             let mut fields = TinyORM::<Email>::new();
 
+            // Past dates are kept as they are so that old messages can be migrated,
+            // dates too far in the future are replaced by the current time.
+            let max_future_date = helper.store.config.mail_max_future_date;
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0) as i64;
+            let clamp_date = |timestamp: i64| {
+                if max_future_date > 0 && timestamp > now + max_future_date {
+                    now
+                } else {
+                    timestamp
+                }
+            };
+
             let mut received_at = None;
             let body_values = item
                 .properties
@@ -150,8 +250,20 @@ where
                     Value::BodyValues { value } => Some(value),
                     _ => None,
                 });
-            let max_size_attachments = helper.store.config.mail_attachments_max_size;
-            let mut size_attachments = 0;
+            if body_values.map_or(false, |body_values| {
+                body_values.values().any(|body_value| {
+                    body_value.is_truncated.unwrap_or(false)
+                        || body_value.is_encoding_problem.unwrap_or(false)
+                })
+            }) {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::BodyValues)
+                    .with_description(
+                        "Body values cannot be truncated or have encoding problems.",
+                    ));
+            }
+            let mut parts_size = PartsSize::new(helper.store.config.mail_attachments_max_size);
+            let mut forwarded_message = None;
 
             for (property, value) in &item.properties {
                 match (property, value) {
@@ -216,15 +328,21 @@ where
                         }
                     }
                     (Property::ReceivedAt, Value::Date { value }) => {
-                        received_at = value.timestamp().into();
+                        received_at = clamp_date(value.timestamp()).into();
                     }
-                    (
-                        Property::MessageId | Property::InReplyTo | Property::References,
-                        Value::TextList { value },
-                    ) => {
+                    (Property::MessageId | Property::InReplyTo, Value::TextList { value }) => {
                         builder = builder
                             .header(property.as_rfc_header(), MessageId::from(value.as_slice()));
                     }
+                    (Property::References, Value::TextList { value }) => {
+                        builder = builder.header(
+                            property.as_rfc_header(),
+                            MessageId::from(
+                                trim_references(value, helper.store.config.mail_max_references)
+                                    .as_ref(),
+                            ),
+                        );
+                    }
                     (
                         Property::Sender
                         | Property::From
@@ -243,7 +361,12 @@ where
                         builder = builder.subject(value);
                     }
                     (Property::SentAt, Value::Date { value }) => {
-                        builder = builder.date(Date::new(value.timestamp()));
+                        let timestamp = clamp_date(value.timestamp());
+                        builder = if value.has_offset() && timestamp == value.timestamp() {
+                            builder.header("Date", Raw::new(value.to_rfc822()))
+                        } else {
+                            builder.date(Date::new(timestamp))
+                        };
                     }
                     (Property::TextBody, Value::BodyPartList { value }) => {
                         if item.properties.contains_key(&Property::BodyStructure) {
@@ -266,19 +389,10 @@ where
                                     account_id,
                                     body_values,
                                     "text/plain".into(),
+                                    "inline".into(),
+                                    parts_size.for_property(Property::TextBody),
                                 )?
                                 .0;
-                            if max_size_attachments > 0 {
-                                size_attachments += text_body.size();
-                                if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_properties()
-                                        .with_property(Property::TextBody)
-                                        .with_description(format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        )));
-                                }
-                            }
                             builder.text_body = text_body.into();
                         }
                     }
@@ -303,19 +417,10 @@ where
                                     account_id,
                                     body_values,
                                     "text/html".into(),
+                                    "inline".into(),
+                                    parts_size.for_property(Property::HtmlBody),
                                 )?
                                 .0;
-                            if max_size_attachments > 0 {
-                                size_attachments += html_body.size();
-                                if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_properties()
-                                        .with_property(Property::HtmlBody)
-                                        .with_description(format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        )));
-                                }
-                            }
                             builder.html_body = html_body.into();
                         }
                     }
@@ -330,27 +435,106 @@ where
 
                         let mut attachments = Vec::with_capacity(value.len());
                         for attachment in value {
+                            // Embedded parts referenced by a Content-ID are shown inline
+                            let disposition =
+                                if attachment.properties.contains_key(&BodyProperty::Cid) {
+                                    "inline"
+                                } else {
+                                    "attachment"
+                                };
                             let attachment = attachment
-                                .parse(self, &helper.acl, account_id, body_values, None)?
+                                .parse(
+                                    self,
+                                    &helper.acl,
+                                    account_id,
+                                    body_values,
+                                    None,
+                                    disposition.into(),
+                                    parts_size.for_property(Property::Attachments),
+                                )?
                                 .0;
-                            if max_size_attachments > 0 {
-                                size_attachments += attachment.size();
-                                if size_attachments > max_size_attachments {
-                                    return Err(SetError::invalid_properties()
-                                        .with_property(Property::Attachments)
-                                        .with_description(format!(
-                                            "Message exceeds maximum size of {} bytes.",
-                                            max_size_attachments
-                                        )));
-                                }
-                            }
                             attachments.push(attachment);
                         }
                         builder.attachments = attachments.into();
                     }
+                    (Property::ForwardedEmailId, Value::Id { value }) => {
+                        if item.properties.contains_key(&Property::BodyStructure) {
+                            return Err(SetError::invalid_properties()
+                                .with_properties([
+                                    Property::ForwardedEmailId,
+                                    Property::BodyStructure,
+                                ])
+                                .with_description(
+                                    "Cannot set both \"forwardedEmailId\" and \"bodyStructure\".",
+                                ));
+                        }
+
+                        let forwarded_document_id = value.get_document_id();
+                        if !helper.document_ids.contains(forwarded_document_id) {
+                            return Err(SetError::invalid_properties()
+                                .with_property(Property::ForwardedEmailId)
+                                .with_description(format!("Email {} does not exist.", value)));
+                        } else if helper.acl.is_shared(account_id)
+                            && !self
+                                .mail_shared_messages(
+                                    account_id,
+                                    &helper.acl.member_of,
+                                    ACL::ReadItems,
+                                )?
+                                .has_access(forwarded_document_id)
+                        {
+                            return Err(SetError::forbidden().with_description(format!(
+                                "You do not have access to email {}.",
+                                value
+                            )));
+                        }
+
+                        let metadata_blob_id = self
+                            .get_document_value::<BlobId>(
+                                account_id,
+                                Collection::Mail,
+                                forwarded_document_id,
+                                MessageField::Metadata.into(),
+                            )?
+                            .ok_or_else(|| {
+                                StoreError::NotFound(format!(
+                                    "Message data for {}:{} not found.",
+                                    account_id, forwarded_document_id
+                                ))
+                            })?;
+                        let raw_message = MessageData::deserialize(
+                            &self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                                StoreError::NotFound(format!(
+                                    "Message data blob for {}:{} not found.",
+                                    account_id, forwarded_document_id
+                                ))
+                            })?,
+                        )
+                        .and_then(|message_data| {
+                            self.blob_get(&message_data.raw_message).transpose()
+                        })
+                        .transpose()?
+                        .ok_or_else(|| {
+                            StoreError::NotFound(format!(
+                                "Raw message for {}:{} not found.",
+                                account_id, forwarded_document_id
+                            ))
+                        })?;
+                        parts_size
+                            .for_property(Property::ForwardedEmailId)
+                            .add(raw_message.len())?;
+                        forwarded_message = Some(raw_message);
+                    }
                     (Property::BodyStructure, Value::BodyPart { value }) => {
-                        let (mut mime_part, sub_parts) =
-                            value.parse(self, &helper.acl, account_id, body_values, None)?;
+                        let (mut mime_part, sub_parts) = value.parse(
+                            self,
+                            &helper.acl,
+                            account_id,
+                            body_values,
+                            None,
+                            None,
+                            parts_size.for_property(Property::BodyStructure),
+                        )?;
 
                         if let Some(sub_parts) = sub_parts {
                             let mut stack = Vec::new();
@@ -364,20 +548,10 @@ where
                                         account_id,
                                         body_values,
                                         None,
+                                        None,
+                                        &mut parts_size,
                                     )?;
 
-                                    if max_size_attachments > 0 {
-                                        size_attachments += sub_mime_part.size();
-                                        if size_attachments > max_size_attachments {
-                                            return Err(SetError::invalid_properties()
-                                                .with_property(Property::BodyStructure)
-                                                .with_description(format!(
-                                                    "Message exceeds maximum size of {} bytes.",
-                                                    max_size_attachments
-                                                )));
-                                        }
-                                    }
-
                                     if let Some(sub_parts) = sub_parts {
                                         stack.push((mime_part, it));
                                         mime_part = sub_mime_part;
@@ -407,14 +581,24 @@ where
                                 .headers(header.header.as_str(), value.iter().map(Raw::from));
                         }
                         (HeaderForm::Date, Value::Date { value }) => {
-                            builder = builder
-                                .header(header.header.as_str(), Date::new(value.timestamp()));
+                            builder = if value.has_offset() {
+                                builder.header(header.header.as_str(), Raw::new(value.to_rfc822()))
+                            } else {
+                                builder.header(header.header.as_str(), Date::new(value.timestamp()))
+                            };
                         }
                         (HeaderForm::Date, Value::DateList { value }) => {
-                            builder = builder.headers(
-                                header.header.as_str(),
-                                value.iter().map(|v| Date::new(v.timestamp())),
-                            );
+                            builder = if value.iter().any(|v| v.has_offset()) {
+                                builder.headers(
+                                    header.header.as_str(),
+                                    value.iter().map(|v| Raw::new(v.to_rfc822())),
+                                )
+                            } else {
+                                builder.headers(
+                                    header.header.as_str(),
+                                    value.iter().map(|v| Date::new(v.timestamp())),
+                                )
+                            };
                         }
                         (HeaderForm::Text, Value::Text { value }) => {
                             builder = builder.header(header.header.as_str(), Text::from(value));
@@ -477,6 +661,26 @@ where
                 }
             }
 
+            // The forwarded message is attached after any other attachments
+            if let Some(forwarded_message) = forwarded_message {
+                builder
+                    .attachments
+                    .get_or_insert_with(Vec::new)
+                    .push(MimePart {
+                        headers: vec![
+                            (
+                                "Content-Type".into(),
+                                ContentType::new("message/rfc822").into(),
+                            ),
+                            (
+                                "Content-Disposition".into(),
+                                ContentType::new("attachment").into(),
+                            ),
+                        ],
+                        contents: BodyPart::Binary(forwarded_message.into()),
+                    });
+            }
+
             // Make sure the message is at least in one mailbox
             if !fields.has_tags(&Property::MailboxIds) {
                 return Err(SetError::invalid_properties()
@@ -484,6 +688,18 @@ where
                     .with_description("Message has to belong to at least one mailbox."));
             }
 
+            // Search folders are read-only
+            for mailbox in fields.get_tags(&Property::MailboxIds).unwrap() {
+                if search_folders.contains(mailbox.as_id()) {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::MailboxIds)
+                        .with_description(format!(
+                            "Messages cannot be added to search folder {}.",
+                            JMAPId::from(mailbox.as_id())
+                        )));
+                }
+            }
+
             // Check ACLs
             if helper.acl.is_shared(helper.account_id) {
                 let allowed_folders = helper.store.mail_shared_folders(
@@ -514,6 +730,156 @@ where
                     .with_description("Message has to have at least one header or body part."));
             }
 
+            // Make sure the message has at least one recipient, if requested
+            if require_recipients
+                && !item
+                    .properties
+                    .iter()
+                    .any(|(property, value)| match property {
+                        Property::To | Property::Cc | Property::Bcc => {
+                            matches!(value, Value::Addresses { value } if !value.is_empty())
+                        }
+                        Property::Header(header) => {
+                            ["To", "Cc", "Bcc"]
+                                .iter()
+                                .any(|name| header.header.as_str().eq_ignore_ascii_case(name))
+                                && !matches!(value, Value::Null)
+                        }
+                        _ => false,
+                    })
+            {
+                return Err(SetError::invalid_properties()
+                    .with_properties([Property::To, Property::Cc, Property::Bcc])
+                    .with_description("Message has to have at least one recipient."));
+            }
+
+            // Make sure the sender matches one of the account's identities, if enabled
+            if validate_from {
+                for (property, value) in item.properties.iter() {
+                    for address in header_addresses(property, value, &[RfcHeader::From]) {
+                        if !helper.store.identity_has_address(account_id, &address)? {
+                            return Err(SetError::invalid_properties()
+                                .with_property(Property::From)
+                                .with_description(format!(
+                                    "From address {} does not match any identity.",
+                                    address
+                                )));
+                        }
+                    }
+                }
+            }
+
+            // Generate a Message-ID if none was provided
+            if !builder
+                .headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("Message-ID"))
+            {
+                let sender = [Property::From, Property::Sender]
+                    .iter()
+                    .find_map(|property| match item.properties.get(property) {
+                        Some(Value::Addresses { value }) => value.first(),
+                        _ => None,
+                    })
+                    .map(|address| address.email.as_str());
+                builder =
+                    builder.header("Message-ID", MessageId::new(self.mail_message_id(sender)));
+            }
+
+            // Encrypt the message body for its recipients, if requested
+            if let Some(pgp_keys) = &pgp_keys {
+                let mut recipients = Vec::new();
+                let mut sender = None;
+                for (property, value) in item.properties.iter() {
+                    for (header, is_hidden) in [
+                        (RfcHeader::To, false),
+                        (RfcHeader::Cc, false),
+                        (RfcHeader::Bcc, true),
+                    ] {
+                        for address in header_addresses(property, value, &[header]) {
+                            recipients.push(PgpRecipient {
+                                key: pgp_keys.encryption_key(&address).ok_or_else(|| {
+                                    SetError::invalid_properties()
+                                        .with_properties([
+                                            Property::To,
+                                            Property::Cc,
+                                            Property::Bcc,
+                                        ])
+                                        .with_description(format!(
+                                            "No public key found for recipient {}.",
+                                            address
+                                        ))
+                                })?,
+                                is_hidden,
+                            });
+                        }
+                    }
+                    if sender.is_none() {
+                        sender = header_addresses(property, value, &[RfcHeader::From])
+                            .into_iter()
+                            .next();
+                    }
+                }
+                if recipients.is_empty() {
+                    return Err(SetError::invalid_properties()
+                        .with_properties([Property::To, Property::Cc, Property::Bcc])
+                        .with_description(
+                            "Encrypted messages have to have at least one recipient.",
+                        ));
+                }
+
+                // Encrypt to the sender as well so the stored copy can be read back
+                let signer = if let Some(sender) = &sender {
+                    if let Some(key) = pgp_keys.encryption_key(sender) {
+                        recipients.push(PgpRecipient {
+                            key,
+                            is_hidden: false,
+                        });
+                    }
+                    if sign {
+                        // Only the account's own addresses can be used to sign
+                        if !helper.store.identity_has_address(account_id, sender)? {
+                            return Err(SetError::invalid_properties()
+                                .with_property(Property::From)
+                                .with_description(format!(
+                                    "Sender {} does not match any identity.",
+                                    sender
+                                )));
+                        }
+                        PgpSigner {
+                            address: sender,
+                            key: pgp_keys.signing_key(sender).ok_or_else(|| {
+                                SetError::invalid_properties()
+                                    .with_property(Property::From)
+                                    .with_description(format!(
+                                        "No signing key found for sender {}.",
+                                        sender
+                                    ))
+                            })?,
+                        }
+                        .into()
+                    } else {
+                        None
+                    }
+                } else if sign {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::From)
+                        .with_description("Signed messages have to have a sender."));
+                } else {
+                    None
+                };
+
+                pgp_encrypt(
+                    &mut builder,
+                    &recipients,
+                    signer.as_ref(),
+                    Duration::from_millis(self.config.mail_pgp_timeout),
+                )
+                .map_err(|err| {
+                    StoreError::InternalError(format!("Failed to encrypt message: {}", err))
+                })?;
+            }
+
             // In test, sort headers to avoid randomness
             #[cfg(feature = "debug")]
             {
@@ -530,6 +896,106 @@ where
             builder.write_to(&mut blob).map_err(|_| {
                 StoreError::SerializeError("Failed to write to memory.".to_string())
             })?;
+
+            // Many small parts can add up to a large message once encoded
+            let max_size = helper.store.config.mail_build_max_size;
+            if max_size > 0 && blob.len() > max_size {
+                return Err(
+                    SetError::new(SetErrorType::TooLarge).with_description(format!(
+                        "Message size of {} bytes exceeds maximum size of {} bytes.",
+                        blob.len(),
+                        max_size
+                    )),
+                );
+            }
+
+            // Keep the replaced draft and its revisions linked to the new version
+            if let Some(draft_id) = replace_drafts.get(create_id) {
+                let draft_document_id = draft_id.get_document_id();
+                let is_draft = helper.document_ids.contains(draft_document_id)
+                    && self
+                        .get_orm::<Email>(account_id, draft_document_id)?
+                        .and_then(|fields| {
+                            fields
+                                .get_tags(&Property::Keywords)
+                                .map(|tags| tags.contains(&Tag::Static(Keyword::DRAFT)))
+                        })
+                        .unwrap_or(false);
+                if !is_draft {
+                    return Err(SetError::invalid_properties()
+                        .with_description(format!("Email {} is not a draft.", draft_id)));
+                } else if !helper.will_destroy.contains(draft_id) {
+                    return Err(SetError::invalid_properties().with_description(format!(
+                        "Draft {} must also be listed in destroy.",
+                        draft_id
+                    )));
+                } else if helper.acl.is_shared(account_id)
+                    && !self
+                        .mail_shared_messages(account_id, &helper.acl.member_of, ACL::RemoveItems)?
+                        .has_access(draft_document_id)
+                {
+                    return Err(SetError::forbidden().with_description(format!(
+                        "You are not allowed to replace draft {}.",
+                        draft_id
+                    )));
+                }
+
+                let metadata_blob_id = self
+                    .get_document_value::<BlobId>(
+                        account_id,
+                        Collection::Mail,
+                        draft_document_id,
+                        MessageField::Metadata.into(),
+                    )?
+                    .ok_or_else(|| {
+                        StoreError::NotFound(format!(
+                            "Message data for {}:{} not found.",
+                            account_id, draft_document_id
+                        ))
+                    })?;
+                let message_data = MessageData::deserialize(
+                    &self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                        StoreError::NotFound(format!(
+                            "Message data blob for {}:{} not found.",
+                            account_id, draft_document_id
+                        ))
+                    })?,
+                )
+                .ok_or_else(|| {
+                    StoreError::DataCorruption(format!(
+                        "Failed to deserialize message data for {}:{}.",
+                        account_id, draft_document_id
+                    ))
+                })?;
+
+                // Oldest revisions are dropped first
+                let mut revisions = self
+                    .get_document_value::<DraftRevisions>(
+                        account_id,
+                        Collection::Mail,
+                        draft_document_id,
+                        MessageField::DraftRevisions.into(),
+                    )?
+                    .unwrap_or_default();
+                revisions.blob_ids.push(message_data.raw_message);
+                let max_revisions = self.config.mail_draft_revisions;
+                if revisions.blob_ids.len() > max_revisions {
+                    revisions
+                        .blob_ids
+                        .drain(..revisions.blob_ids.len() - max_revisions);
+                }
+                for blob_id in &revisions.blob_ids {
+                    document.blob(blob_id.clone(), IndexOptions::new());
+                }
+                document.binary(
+                    MessageField::DraftRevisions,
+                    revisions.serialize().ok_or_else(|| {
+                        StoreError::SerializeError("Failed to serialize revisions.".to_string())
+                    })?,
+                    IndexOptions::new().store(),
+                );
+            }
+
             let blob_id = BlobId::new_external(&blob);
             let raw_blob: JMAPBlob = (&blob_id).into();
 
@@ -571,10 +1037,35 @@ where
             Ok(email)
         })?;
 
+        let mut has_snoozed = false;
         helper.update(|id, item, helper, document| {
             let current_fields = self
                 .get_orm::<Email>(account_id, id.get_document_id())?
                 .ok_or_else(|| SetError::new(SetErrorType::NotFound))?;
+
+            // Keyword preconditions are checked against the current state of the message
+            if let Some(conditions) = if_keywords.get(&id) {
+                for (keyword, is_set) in conditions {
+                    if current_fields
+                        .get_tags(&Property::Keywords)
+                        .map_or(false, |tags| tags.contains(&keyword.tag))
+                        != *is_set
+                    {
+                        return Err(SetError::new(SetErrorType::Conflict).with_description(
+                            format!(
+                                "Message {} keyword '{}'.",
+                                if *is_set {
+                                    "does not have"
+                                } else {
+                                    "already has"
+                                },
+                                keyword
+                            ),
+                        ));
+                    }
+                }
+            }
+
             let mut fields = TinyORM::track_changes(&current_fields);
 
             for (property, value) in item.properties {
@@ -646,7 +1137,61 @@ where
                             }
                         }
                     }
-                    _ => (),
+                    (Property::Snoozed, Value::Snoozed { value }) => {
+                        fields.untag_all(&Property::Snoozed);
+                        for tag in value.into_tags() {
+                            fields.tag(Property::Snoozed, tag);
+                        }
+                    }
+                    (Property::Snoozed, Value::Null) => {
+                        fields.untag_all(&Property::Snoozed);
+                    }
+                    (
+                        property @ (Property::MailboxIds | Property::Keywords | Property::Snoozed),
+                        _,
+                    ) => {
+                        return Err(SetError::invalid_properties()
+                            .with_property(property)
+                            .with_description("Unexpected value."));
+                    }
+                    (property, _) => {
+                        return Err(SetError::invalid_properties()
+                            .with_property(property)
+                            .with_description("Property is immutable and cannot be updated."));
+                    }
+                }
+            }
+
+            // Snoozed messages are moved from the Inbox to the snoozed mailbox and back
+            let is_snoozed = fields.has_tags(&Property::Snoozed);
+            has_snoozed |= is_snoozed;
+            if current_fields.has_tags(&Property::Snoozed) != is_snoozed {
+                if helper.acl.is_shared(helper.account_id) {
+                    return Err(SetError::forbidden()
+                        .with_description("Only the account owner can snooze messages."));
+                } else if let Some(inbox_id) = inbox_id {
+                    if is_snoozed {
+                        // The snoozed mailbox is created the first time a message leaves the Inbox
+                        if fields
+                            .get_tags(&Property::MailboxIds)
+                            .map_or(false, |tags| tags.contains(&Tag::Id(inbox_id)))
+                        {
+                            let snoozed_id = if let Some(snoozed_id) = snoozed_id {
+                                snoozed_id
+                            } else {
+                                let (document_id, changes) =
+                                    self.mail_create_snoozed_mailbox(account_id)?;
+                                if let Some(changes) = changes {
+                                    helper.add_changes(changes);
+                                }
+                                snoozed_id = Some(document_id);
+                                document_id
+                            };
+                            move_mailbox(&mut fields, inbox_id, snoozed_id);
+                        }
+                    } else if let Some(snoozed_id) = snoozed_id {
+                        move_mailbox(&mut fields, snoozed_id, inbox_id);
+                    }
                 }
             }
 
@@ -658,6 +1203,18 @@ where
             }
             let changed_tags = current_fields.get_changed_tags(&fields, &Property::Keywords);
 
+            // Search folders are read-only
+            for mailbox in current_fields.get_added_tags(&fields, &Property::MailboxIds) {
+                if search_folders.contains(mailbox.as_id()) {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::MailboxIds)
+                        .with_description(format!(
+                            "Messages cannot be added to search folder {}.",
+                            JMAPId::from(mailbox.as_id())
+                        )));
+                }
+            }
+
             // Check ACLs
             if helper.acl.is_shared(helper.account_id) {
                 // All folders have to allow insertions
@@ -748,6 +1305,11 @@ where
             Ok(None)
         })?;
 
+        // Index the account so the housekeeper can find its snoozed messages
+        if has_snoozed {
+            self.mail_set_snoozed_account(account_id, true)?;
+        }
+
         helper.destroy(|_id, helper, document| {
             // Check ACLs
             if helper.acl.is_shared(helper.account_id)
@@ -829,6 +1391,23 @@ where
             IndexOptions::new().store().clear(),
         );
 
+        // Unlink draft revisions
+        if let Some(revisions) = self.get_document_value::<DraftRevisions>(
+            account_id,
+            Collection::Mail,
+            document_id,
+            MessageField::DraftRevisions.into(),
+        )? {
+            for blob_id in revisions.blob_ids {
+                document.blob(blob_id, IndexOptions::new().clear());
+            }
+            document.binary(
+                MessageField::DraftRevisions,
+                Vec::with_capacity(0),
+                IndexOptions::new().clear(),
+            );
+        }
+
         // Unlink metadata
         document.blob(metadata_blob_id, IndexOptions::new().clear());
         document.binary(
@@ -836,6 +1415,11 @@ where
             Vec::with_capacity(0),
             IndexOptions::new().clear(),
         );
+        document.binary(
+            MessageField::Preview,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
 
         // Fetch ORM
         let fields = self
@@ -849,12 +1433,21 @@ where
 
         // Log thread and mailbox changes
         if let Some(batch) = batch {
-            if let Some(message_doc_ids) = self.get_tag(
+            if let Some(mut message_doc_ids) = self.get_tag(
                 account_id,
                 Collection::Mail,
                 MessageField::ThreadId.into(),
                 Tag::Id(thread_id),
             )? {
+                // Discount messages already deleted earlier in this batch, otherwise
+                // destroying a whole thread at once would log it as updated.
+                for action in &batch.documents {
+                    if let WriteAction::Delete(document) = action {
+                        if document.collection == Collection::Mail {
+                            message_doc_ids.remove(document.document_id);
+                        }
+                    }
+                }
                 if message_doc_ids.len() > 1 {
                     batch.log_child_update(Collection::Thread, thread_id);
                 } else {
@@ -877,6 +1470,41 @@ where
     }
 }
 
+struct PartsSize {
+    total: usize,
+    max: usize,
+    property: Property,
+}
+
+impl PartsSize {
+    fn new(max: usize) -> Self {
+        PartsSize {
+            total: 0,
+            max,
+            property: Property::BodyStructure,
+        }
+    }
+
+    fn for_property(&mut self, property: Property) -> &mut Self {
+        self.property = property;
+        self
+    }
+
+    fn add(&mut self, size: usize) -> jmap::error::set::Result<(), Property> {
+        self.total += size;
+        if self.max > 0 && self.total > self.max {
+            Err(SetError::invalid_properties()
+                .with_property(self.property.clone())
+                .with_description(format!(
+                    "Message exceeds maximum size of {} bytes.",
+                    self.max
+                )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 impl EmailBodyPart {
     fn parse<'y, T>(
         &'y self,
@@ -885,6 +1513,8 @@ impl EmailBodyPart {
         account_id: AccountId,
         body_values: Option<&'y VecMap<String, EmailBodyValue>>,
         strict_type: Option<&'static str>,
+        default_disposition: Option<&'static str>,
+        parts_size: &mut PartsSize,
     ) -> jmap::error::set::Result<(MimePart<'y>, Option<&'y Vec<EmailBodyPart>>), Property>
     where
         T: for<'x> Store<'x> + 'static,
@@ -902,6 +1532,7 @@ impl EmailBodyPart {
         }
 
         let is_multipart = content_type.starts_with("multipart/");
+        let is_text = content_type.starts_with("text/");
         let mut mime_part = MimePart {
             headers: Vec::new(),
             contents: if is_multipart {
@@ -909,35 +1540,38 @@ impl EmailBodyPart {
             } else if let Some(part_id) = self.get_text(BodyProperty::PartId) {
                 if self.properties.contains_key(&BodyProperty::BlobId) {
                     return Err(SetError::invalid_properties().with_description(
-                        "Cannot specify both \"partId\" and \"bodyValues\".".to_string(),
+                        "Cannot specify both \"partId\" and \"blobId\".".to_string(),
                     ));
                 } else if self.properties.contains_key(&BodyProperty::Charset) {
                     return Err(SetError::invalid_properties().with_description(
                         "Cannot specify a character set when providing a \"partId\".".to_string(),
                     ));
                 }
-                BodyPart::Text(
-                    body_values
-                        .as_ref()
-                        .ok_or_else(|| {
-                            SetError::invalid_properties().with_description(
-                                "Missing \"bodyValues\" object containing partId.".to_string(),
-                            )
-                        })?
-                        .get(part_id)
-                        .ok_or_else(|| {
-                            SetError::invalid_properties().with_description(format!(
-                                "Missing body value for partId \"{}\"",
-                                part_id
-                            ))
-                        })?
-                        .value
-                        .as_str()
-                        .into(),
-                )
+                let value = body_values
+                    .as_ref()
+                    .ok_or_else(|| {
+                        SetError::invalid_properties().with_description(
+                            "Missing \"bodyValues\" object containing partId.".to_string(),
+                        )
+                    })?
+                    .get(part_id)
+                    .ok_or_else(|| {
+                        SetError::invalid_properties().with_description(format!(
+                            "Missing body value for partId \"{}\"",
+                            part_id
+                        ))
+                    })?
+                    .value
+                    .as_str();
+                parts_size.add(value.len())?;
+                BodyPart::Text(value.into())
             } else if let Some(blob_id) = self.get_blob(BodyProperty::BlobId) {
                 BodyPart::Binary(match store.mail_blob_get(account_id, acl, blob_id) {
-                    Ok(BlobResult::Blob(bytes)) => bytes.into(),
+                    Ok(BlobResult::Blob(bytes)) => {
+                        // Reject oversized parts before they are encoded into the message.
+                        parts_size.add(bytes.len())?;
+                        bytes.into()
+                    }
                     Ok(BlobResult::NotFound) => {
                         return Err(SetError::new(SetErrorType::BlobNotFound).with_description(
                             format!("blob {} does not exist on this server.", blob_id),
@@ -977,7 +1611,8 @@ impl EmailBodyPart {
             }
 
             match (
-                self.get_text(BodyProperty::Disposition),
+                self.get_text(BodyProperty::Disposition)
+                    .or(default_disposition),
                 self.get_text(BodyProperty::Name),
             ) {
                 (Some(disposition), Some(filename)) => {
@@ -1008,6 +1643,7 @@ impl EmailBodyPart {
             .push(("Content-Type".into(), content_type.into()));
 
         let mut sub_parts = None;
+        let mut transfer_encoding = None;
 
         for (property, value) in self.properties.iter() {
             match (property, value) {
@@ -1032,7 +1668,21 @@ impl EmailBodyPart {
                         .with_description("Headers have to be set individually."));
                 }
                 (BodyProperty::Header(header), value) => {
-                    if header.header != HeaderName::Rfc(RfcHeader::ContentTransferEncoding) {
+                    if header.header == HeaderName::Rfc(RfcHeader::ContentTransferEncoding) {
+                        // Text parts accept an encoding hint, the contents are encoded below
+                        transfer_encoding = match value {
+                            Value::Text { value } if is_text => TransferEncoding::parse(value),
+                            _ => None,
+                        };
+                        if transfer_encoding.is_none()
+                            || !matches!(mime_part.contents, BodyPart::Text(_))
+                        {
+                            return Err(SetError::invalid_properties().with_description(concat!(
+                                "Content-Transfer-Encoding can only be set to ",
+                                "\"base64\" or \"quoted-printable\" on text parts."
+                            )));
+                        }
+                    } else {
                         match value {
                             Value::Text { value } => {
                                 mime_part
@@ -1049,9 +1699,6 @@ impl EmailBodyPart {
                             }
                             _ => (),
                         }
-                    } else {
-                        return Err(SetError::invalid_properties()
-                            .with_description("Cannot specify Content-Transfer-Encoding header."));
                     }
                 }
                 (BodyProperty::Size, _) => {
@@ -1068,6 +1715,26 @@ impl EmailBodyPart {
             }
         }
 
+        // Encode text parts using either the requested, configured or smallest encoding
+        if let (true, BodyPart::Text(text)) = (is_text, &mime_part.contents) {
+            if let Some(transfer_encoding) = transfer_encoding
+                .or_else(|| {
+                    store
+                        .config
+                        .mail_text_encoding
+                        .as_ref()
+                        .and_then(|v| TransferEncoding::parse(v))
+                })
+                .or_else(|| TransferEncoding::detect(text))
+            {
+                mime_part.contents = BodyPart::Text(transfer_encoding.encode(text).into());
+                mime_part.headers.push((
+                    "Content-Transfer-Encoding".into(),
+                    Raw::new(transfer_encoding.as_str()).into(),
+                ));
+            }
+        }
+
         // In test, sort headers to avoid randomness
         #[cfg(feature = "debug")]
         {
@@ -1082,3 +1749,70 @@ impl EmailBodyPart {
         Ok((mime_part, if is_multipart { sub_parts } else { None }))
     }
 }
+
+// Long References headers keep the first message id, which identifies the thread root,
+// followed by the most recent ones (RFC 5537, section 3.4.4).
+fn trim_references(references: &[String], max_references: usize) -> Cow<'_, [String]> {
+    if max_references == 0 || references.len() <= max_references {
+        return Cow::Borrowed(references);
+    }
+    let max_references = max_references.max(2);
+    let mut trimmed = Vec::with_capacity(max_references);
+    trimmed.push(references[0].clone());
+    trimmed.extend_from_slice(&references[references.len() - (max_references - 1)..]);
+    Cow::Owned(trimmed)
+}
+
+// Returns the addresses set on any of the given headers, either as a property or a header form
+fn header_addresses(property: &Property, value: &Value, headers: &[RfcHeader]) -> Vec<String> {
+    let addresses = match (property, value) {
+        (Property::From, Value::Addresses { value }) if headers.contains(&RfcHeader::From) => {
+            value.clone()
+        }
+        (Property::To, Value::Addresses { value }) if headers.contains(&RfcHeader::To) => {
+            value.clone()
+        }
+        (Property::Cc, Value::Addresses { value }) if headers.contains(&RfcHeader::Cc) => {
+            value.clone()
+        }
+        (Property::Bcc, Value::Addresses { value }) if headers.contains(&RfcHeader::Bcc) => {
+            value.clone()
+        }
+        (Property::Header(header), value)
+            if headers
+                .iter()
+                .any(|name| header.header == HeaderName::Rfc(*name)) =>
+        {
+            match value {
+                Value::Addresses { value } => value.clone(),
+                Value::AddressesList { value } => value.concat(),
+                Value::GroupedAddresses { value } => value
+                    .iter()
+                    .flat_map(|group| group.addresses.clone())
+                    .collect(),
+                Value::GroupedAddressesList { value } => value
+                    .iter()
+                    .flatten()
+                    .flat_map(|group| group.addresses.clone())
+                    .collect(),
+                Value::Text { value } => parse_addresses(value),
+                Value::TextList { value } => value
+                    .iter()
+                    .flat_map(|value| parse_addresses(value.as_str()))
+                    .collect(),
+                _ => return Vec::new(),
+            }
+        }
+        _ => return Vec::new(),
+    };
+
+    addresses.into_iter().map(|addr| addr.email).collect()
+}
+
+fn parse_addresses(value: &str) -> Vec<EmailAddress> {
+    MessageStream::new(format!("{}\n", value).as_bytes())
+        .parse_address()
+        .into_address()
+        .and_then(|addresses| addresses.into_addresses())
+        .unwrap_or_default()
+}
@@ -0,0 +1,214 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, SystemTime};
+
+use super::schema::{Email, EmailSnooze, Property};
+use super::MessageField;
+use crate::mailbox::{get::JMAPGetMailbox, schema::Mailbox, CreateMailbox};
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::principal::schema::ACCOUNTS_WITH_SNOOZED;
+use jmap::types::jmap::JMAPId;
+use jmap::SUPERUSER_ID;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::roaring::RoaringBitmap;
+use store::tracing::debug;
+use store::write::batch::WriteBatch;
+use store::write::options::{IndexOptions, Options};
+use store::write::update::Changes;
+use store::{AccountId, DocumentId, JMAPStore, Store};
+
+pub trait JMAPMailSnooze<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_create_snoozed_mailbox(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<(DocumentId, Option<Changes>)>;
+    fn mail_wake_snoozed(&self, account_id: AccountId) -> store::Result<Option<Changes>>;
+    fn mail_snoozed_accounts(&self) -> store::Result<Option<RoaringBitmap>>;
+    fn mail_set_snoozed_account(
+        &self,
+        account_id: AccountId,
+        is_snoozed: bool,
+    ) -> store::Result<()>;
+}
+
+impl<T> JMAPMailSnooze<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_create_snoozed_mailbox(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<(DocumentId, Option<Changes>)> {
+        // Lock collection
+        let _lock = self
+            .try_lock_collection(account_id, Collection::Mailbox, Duration::from_millis(200))
+            .ok_or_else(|| StoreError::InternalError("Failed to obtain lock".to_string()))?;
+
+        if let Some(snoozed_id) = self.mailbox_get_by_role(account_id, "snoozed")? {
+            return Ok((snoozed_id, None));
+        }
+
+        let mut batch = WriteBatch::new(account_id);
+        let mut document = Document::new(
+            Collection::Mailbox,
+            self.assign_document_id(account_id, Collection::Mailbox)?,
+        );
+        TinyORM::<Mailbox>::new_mailbox("Snoozed", "snoozed").insert(&mut document)?;
+        batch.log_insert(Collection::Mailbox, document.document_id);
+        let snoozed_id = document.document_id;
+        batch.insert_document(document);
+
+        Ok((snoozed_id, self.write(batch)?))
+    }
+
+    fn mail_wake_snoozed(&self, account_id: AccountId) -> store::Result<Option<Changes>> {
+        // The lock is also held by Email/set while snoozing, so the account cannot
+        // be removed from the index right after a message was snoozed
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+        let snoozed_ids = if let Some(snoozed_ids) = self.get_tag(
+            account_id,
+            Collection::Mail,
+            Property::Snoozed.into(),
+            Tag::Default,
+        )? {
+            snoozed_ids
+        } else {
+            self.mail_set_snoozed_account(account_id, false)?;
+            return Ok(None);
+        };
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let inbox_id = self.mailbox_get_by_role(account_id, "inbox")?;
+        let snoozed_id = self.mailbox_get_by_role(account_id, "snoozed")?;
+
+        let mut batch = WriteBatch::new(account_id);
+        let mut has_pending = false;
+        for document_id in snoozed_ids {
+            let current_fields =
+                if let Some(current_fields) = self.get_orm::<Email>(account_id, document_id)? {
+                    current_fields
+                } else {
+                    debug!("Email ORM for {}:{} not found", account_id, document_id);
+                    continue;
+                };
+            if current_fields
+                .get_tags(&Property::Snoozed)
+                .and_then(|tags| EmailSnooze::from_tags(tags.iter()))
+                .map_or(false, |snooze| snooze.until.timestamp() > now)
+            {
+                has_pending = true;
+                continue;
+            }
+
+            // Messages still in the snoozed mailbox are moved back to the Inbox
+            let mut fields = TinyORM::track_changes(&current_fields);
+            fields.untag_all(&Property::Snoozed);
+            if let (Some(inbox_id), Some(snoozed_id)) = (inbox_id, snoozed_id) {
+                move_mailbox(&mut fields, snoozed_id, inbox_id);
+            }
+            for mailbox in current_fields.get_changed_tags(&fields, &Property::MailboxIds) {
+                batch.log_child_update(Collection::Mailbox, mailbox.as_id());
+            }
+
+            let thread_id = self
+                .get_document_value::<DocumentId>(
+                    account_id,
+                    Collection::Mail,
+                    document_id,
+                    MessageField::ThreadId.into(),
+                )?
+                .ok_or_else(|| {
+                    StoreError::DataCorruption(format!(
+                        "Failed to fetch threadId for {}:{}.",
+                        account_id, document_id
+                    ))
+                })?;
+            let mut document = Document::new(Collection::Mail, document_id);
+            current_fields.merge(&mut document, fields)?;
+            batch.update_document(document);
+            batch.log_update(Collection::Mail, JMAPId::from_parts(thread_id, document_id));
+        }
+
+        if !has_pending {
+            self.mail_set_snoozed_account(account_id, false)?;
+        }
+
+        if !batch.is_empty() {
+            self.write(batch)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn mail_snoozed_accounts(&self) -> store::Result<Option<RoaringBitmap>> {
+        self.get_tag(
+            SUPERUSER_ID,
+            Collection::Principal,
+            ACCOUNTS_WITH_SNOOZED,
+            Tag::Static(ACCOUNTS_WITH_SNOOZED),
+        )
+    }
+
+    fn mail_set_snoozed_account(
+        &self,
+        account_id: AccountId,
+        is_snoozed: bool,
+    ) -> store::Result<()> {
+        let mut batch = WriteBatch::new(SUPERUSER_ID);
+        let mut document = Document::new(Collection::Principal, account_id);
+        document.tag(
+            ACCOUNTS_WITH_SNOOZED,
+            Tag::Static(ACCOUNTS_WITH_SNOOZED),
+            if is_snoozed {
+                IndexOptions::new()
+            } else {
+                IndexOptions::new().clear()
+            },
+        );
+        batch.update_document(document);
+        self.write(batch)?;
+        Ok(())
+    }
+}
+
+// Moves a message from one mailbox to another, messages that are not in the
+// source mailbox are left untouched.
+pub fn move_mailbox(fields: &mut TinyORM<Email>, from_id: DocumentId, to_id: DocumentId) {
+    if fields
+        .get_tags(&Property::MailboxIds)
+        .map_or(false, |tags| tags.contains(&Tag::Id(from_id)))
+    {
+        fields.untag(&Property::MailboxIds, &Tag::Id(from_id));
+        fields.tag(Property::MailboxIds, Tag::Id(to_id));
+    }
+}
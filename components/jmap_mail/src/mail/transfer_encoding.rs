@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Write;
+
+const MAX_LINE_LENGTH: usize = 76;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferEncoding {
+    Base64,
+    QuotedPrintable,
+}
+
+impl TransferEncoding {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case("base64") {
+            Some(TransferEncoding::Base64)
+        } else if value.eq_ignore_ascii_case("quoted-printable") {
+            Some(TransferEncoding::QuotedPrintable)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferEncoding::Base64 => "base64",
+            TransferEncoding::QuotedPrintable => "quoted-printable",
+        }
+    }
+
+    // Quoted-printable grows each non-ASCII byte to three characters while base64
+    // grows the whole part by a third, so QP is smaller (and readable) as long as
+    // less than one in six bytes is non-ASCII. Pure ASCII text is left to the builder.
+    pub fn detect(text: &str) -> Option<Self> {
+        let non_ascii = text.bytes().filter(|b| !b.is_ascii()).count();
+        if non_ascii == 0 {
+            None
+        } else if non_ascii * 6 < text.len() {
+            Some(TransferEncoding::QuotedPrintable)
+        } else {
+            Some(TransferEncoding::Base64)
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> String {
+        match self {
+            TransferEncoding::Base64 => encode_base64(text.as_bytes()),
+            TransferEncoding::QuotedPrintable => encode_quoted_printable(text),
+        }
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let encoded = base64::encode(bytes);
+    let mut result =
+        String::with_capacity(encoded.len() + (encoded.len() / MAX_LINE_LENGTH + 1) * 2);
+    for line in encoded.as_bytes().chunks(MAX_LINE_LENGTH) {
+        result.push_str(std::str::from_utf8(line).unwrap_or_default());
+        result.push_str("\r\n");
+    }
+    result
+}
+
+fn encode_quoted_printable(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() * 2);
+
+    for (line_num, line) in text.split('\n').enumerate() {
+        if line_num > 0 {
+            result.push_str("\r\n");
+        }
+        let line = line.strip_suffix('\r').unwrap_or(line).as_bytes();
+        let mut line_len = 0;
+
+        for (pos, &ch) in line.iter().enumerate() {
+            // Trailing whitespace has to be encoded, otherwise it could be stripped in transit
+            let is_literal = match ch {
+                b' ' | b'\t' => pos + 1 < line.len(),
+                b'=' => false,
+                33..=126 => true,
+                _ => false,
+            };
+            let ch_len = if is_literal { 1 } else { 3 };
+
+            // Insert a soft line break, leaving room for the trailing '='
+            if line_len + ch_len >= MAX_LINE_LENGTH {
+                result.push_str("=\r\n");
+                line_len = 0;
+            }
+
+            if is_literal {
+                result.push(ch as char);
+            } else {
+                write!(result, "={:02X}", ch).ok();
+            }
+            line_len += ch_len;
+        }
+    }
+
+    result
+}
@@ -21,18 +21,23 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use super::schema::{Mailbox, MailboxRights, Property, Value};
+use super::{localized_role_name, split_mailbox_path};
+use crate::mail::query::JMAPMailQuery;
 use crate::mail::schema::Keyword;
 use crate::mail::sharing::JMAPShareMail;
-use crate::mail::MessageField;
+use crate::mail::{self, MessageField};
 use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject};
 use jmap::orm::serialize::JMAPOrm;
 use jmap::principal::store::JMAPPrincipals;
 use jmap::request::get::{GetRequest, GetResponse};
+use jmap::request::query;
 use jmap::request::ACLEnforce;
 use jmap::types::jmap::JMAPId;
 use store::ahash::AHashSet;
-use store::core::acl::ACL;
+use store::core::acl::{ACLToken, ACL};
 use store::core::collection::Collection;
 use store::core::error::StoreError;
 use store::core::tag::Tag;
@@ -41,7 +46,7 @@ use store::read::comparator::Comparator;
 use store::read::filter::{ComparisonOperator, Filter, Query};
 use store::read::FilterMapper;
 use store::roaring::RoaringBitmap;
-use store::{AccountId, JMAPStore, SharedBitmap};
+use store::{AccountId, JMAPStore, LongInteger, SharedBitmap};
 use store::{DocumentId, Store};
 
 impl GetObject for Mailbox {
@@ -89,9 +94,15 @@ where
     fn mailbox_unread_tags(
         &self,
         account_id: AccountId,
-        document_id: DocumentId,
+        mailbox_ids: Option<RoaringBitmap>,
         mail_document_ids: Option<&RoaringBitmap>,
     ) -> store::Result<Option<RoaringBitmap>>;
+    fn mailbox_search_ids(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        acl: &Arc<ACLToken>,
+    ) -> jmap::Result<Option<RoaringBitmap>>;
     fn mailbox_get_by_name(
         &self,
         account_id: AccountId,
@@ -102,6 +113,17 @@ where
         account_id: AccountId,
         role: &str,
     ) -> store::Result<Option<DocumentId>>;
+    fn mailbox_search_folders(&self, account_id: AccountId) -> store::Result<RoaringBitmap>;
+    fn mailbox_descendants(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<RoaringBitmap>;
+    fn mailbox_search_filter(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<query::Filter<mail::schema::Filter>>>;
 }
 
 impl<T> JMAPGetMailbox<T> for JMAPStore<T>
@@ -125,12 +147,32 @@ where
                     | Property::ParentId
                     | Property::Role
                     | Property::SortOrder
+                    | Property::RetentionDays
+                    | Property::Color
+                    | Property::Icon
+                    | Property::SearchFilter
+                    | Property::DisplayName
                     | Property::ACL
             )
         });
+        let count_messages = helper.properties.iter().any(|p| {
+            matches!(
+                p,
+                Property::TotalEmails
+                    | Property::UnreadEmails
+                    | Property::TotalThreads
+                    | Property::UnreadThreads
+            )
+        });
         let account_id = helper.account_id;
         let acl = helper.acl.clone();
         let mail_document_ids = self.get_document_ids(account_id, Collection::Mail)?;
+        let search_folders = self.mailbox_search_folders(account_id)?;
+        let locale = if helper.properties.contains(&Property::DisplayName) {
+            self.principal_to_locale(account_id)?
+        } else {
+            None
+        };
 
         // Add Id Property
         if !helper.properties.contains(&Property::Id) {
@@ -150,11 +192,52 @@ where
                 None
             };
             let mut mailbox = VecMap::with_capacity(properties.len());
+            let is_search_folder = search_folders.contains(document_id);
+
+            // Search folders are counted using the results of their saved filter
+            let mailbox_ids = if !count_messages {
+                None
+            } else if is_search_folder {
+                self.mailbox_search_ids(account_id, document_id, &acl)?
+            } else {
+                self.mailbox_tags(account_id, document_id)?
+            };
 
             for property in properties {
                 let value = match property {
                     Property::Id => Value::Id { value: id },
-                    Property::Name | Property::Role => fields
+                    Property::Name => fields
+                        .as_ref()
+                        .unwrap()
+                        .get(property)
+                        .cloned()
+                        .unwrap_or_default(),
+                    Property::DisplayName => {
+                        // Special-use mailboxes are displayed using the account's locale, while
+                        // their name is kept unchanged as it is used to refer to them.
+                        let fields = fields.as_ref().unwrap();
+                        match (
+                            &locale,
+                            fields.get(&Property::Role),
+                            fields.get(&Property::Name),
+                        ) {
+                            (
+                                Some(locale),
+                                Some(Value::Text { value: role }),
+                                Some(Value::Text { value: name }),
+                            ) => localized_role_name(role, name, locale).map(|name| Value::Text {
+                                value: name.to_string(),
+                            }),
+                            _ => None,
+                        }
+                        .or_else(|| fields.get(&Property::Name).cloned())
+                        .unwrap_or_default()
+                    }
+                    Property::Role
+                    | Property::RetentionDays
+                    | Property::Color
+                    | Property::Icon
+                    | Property::SearchFilter => fields
                         .as_mut()
                         .unwrap()
                         .remove(property)
@@ -176,39 +259,33 @@ where
                         })
                         .unwrap_or_default(),
                     Property::TotalEmails => Value::Number {
-                        value: self
-                            .mailbox_tags(account_id, document_id)?
-                            .map(|v| v.len() as u32)
-                            .unwrap_or(0),
+                        value: mailbox_ids.as_ref().map(|v| v.len() as u32).unwrap_or(0),
                     },
                     Property::UnreadEmails => Value::Number {
                         value: self
                             .mailbox_unread_tags(
                                 account_id,
-                                document_id,
+                                mailbox_ids.clone(),
                                 mail_document_ids.as_ref(),
                             )?
                             .map(|v| v.len() as u32)
                             .unwrap_or(0),
                     },
                     Property::TotalThreads => Value::Number {
-                        value: self.mailbox_count_threads(
-                            account_id,
-                            self.mailbox_tags(account_id, document_id)?,
-                        )? as u32,
+                        value: self.mailbox_count_threads(account_id, mailbox_ids.clone())? as u32,
                     },
                     Property::UnreadThreads => Value::Number {
                         value: self.mailbox_count_threads(
                             account_id,
                             self.mailbox_unread_tags(
                                 account_id,
-                                document_id,
+                                mailbox_ids.clone(),
                                 mail_document_ids.as_ref(),
                             )?,
                         )? as u32,
                     },
-                    Property::MyRights => Value::MailboxRights {
-                        value: if acl.is_shared(account_id) {
+                    Property::MyRights => {
+                        let rights = if acl.is_shared(account_id) {
                             MailboxRights::shared(self.get_acl(
                                 &acl.member_of,
                                 account_id,
@@ -217,8 +294,15 @@ where
                             )?)
                         } else {
                             MailboxRights::owner()
-                        },
-                    },
+                        };
+                        Value::MailboxRights {
+                            value: if is_search_folder {
+                                rights.search_folder()
+                            } else {
+                                rights
+                            },
+                        }
+                    }
                     Property::IsSubscribed => fields
                         .as_ref()
                         .unwrap()
@@ -296,54 +380,70 @@ where
     fn mailbox_unread_tags(
         &self,
         account_id: AccountId,
-        document_id: DocumentId,
+        mailbox_ids: Option<RoaringBitmap>,
         mail_document_ids: Option<&RoaringBitmap>,
     ) -> store::Result<Option<RoaringBitmap>> {
-        if let Some(mail_document_ids) = mail_document_ids {
-            match self.mailbox_tags(account_id, document_id) {
-                Ok(Some(mailbox)) => {
-                    match self.get_tag(
-                        account_id,
-                        Collection::Mail,
-                        MessageField::Keyword.into(),
-                        Tag::Static(Keyword::SEEN),
-                    ) {
-                        Ok(Some(mut seen)) => {
-                            seen ^= mail_document_ids;
-                            seen &= &mailbox;
-                            if !seen.is_empty() {
-                                Ok(Some(seen))
-                            } else {
-                                Ok(None)
-                            }
-                        }
-                        Ok(None) => Ok(mailbox.into()),
-                        Err(e) => Err(e),
+        if let (Some(mailbox), Some(mail_document_ids)) = (mailbox_ids, mail_document_ids) {
+            match self.get_tag(
+                account_id,
+                Collection::Mail,
+                MessageField::Keyword.into(),
+                Tag::Static(Keyword::SEEN),
+            ) {
+                Ok(Some(mut seen)) => {
+                    seen ^= mail_document_ids;
+                    seen &= &mailbox;
+                    if !seen.is_empty() {
+                        Ok(Some(seen))
+                    } else {
+                        Ok(None)
                     }
                 }
-                other => other,
+                Ok(None) => Ok(mailbox.into()),
+                Err(e) => Err(e),
             }
         } else {
             Ok(None)
         }
     }
 
+    fn mailbox_search_ids(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        acl: &Arc<ACLToken>,
+    ) -> jmap::Result<Option<RoaringBitmap>> {
+        let filter = if let Some(filter) = self.mailbox_search_filter(account_id, document_id)? {
+            filter
+        } else {
+            return Ok(None);
+        };
+        let mut document_ids = self.mail_search_ids(account_id, filter)?;
+
+        // Shared search folders only include the messages the caller can read
+        if acl.is_shared(account_id) {
+            match self
+                .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)?
+                .as_ref()
+            {
+                Some(shared_messages) => document_ids &= shared_messages,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(if !document_ids.is_empty() {
+            Some(document_ids)
+        } else {
+            None
+        })
+    }
+
     fn mailbox_get_by_name(
         &self,
         account_id: AccountId,
         path: &str,
     ) -> store::Result<Option<DocumentId>> {
-        let path = path
-            .split('/')
-            .filter_map(|p| {
-                let p = p.trim();
-                if !p.is_empty() {
-                    p.into()
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+        let path = split_mailbox_path(path, self.config.mailbox_path_separator);
         if path.is_empty() || path.len() > self.config.mailbox_max_depth {
             return Ok(None);
         }
@@ -394,7 +494,7 @@ where
         let mut next_parent_id = 0;
         'outer: for name in path {
             for (part, parent_id, document_id) in &found_names {
-                if part.eq(name) && *parent_id == next_parent_id {
+                if part.eq(&name) && *parent_id == next_parent_id {
                     next_parent_id = *document_id;
                     continue 'outer;
                 }
@@ -402,7 +502,16 @@ where
             return Ok(None);
         }
 
-        Ok(Some(next_parent_id - 1))
+        // Search folders are read-only views, they can't be used as filing targets
+        let document_id = next_parent_id - 1;
+        if !self
+            .mailbox_search_folders(account_id)?
+            .contains(document_id)
+        {
+            Ok(Some(document_id))
+        } else {
+            Ok(None)
+        }
     }
 
     fn mailbox_get_by_role(
@@ -422,4 +531,78 @@ where
         )
         .map(|r| r.into_bitmap().min())
     }
+
+    fn mailbox_search_folders(&self, account_id: AccountId) -> store::Result<RoaringBitmap> {
+        Ok(self
+            .get_tag(
+                account_id,
+                Collection::Mailbox,
+                Property::SearchFilter.into(),
+                Tag::Default,
+            )?
+            .unwrap_or_default())
+    }
+
+    fn mailbox_descendants(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<RoaringBitmap> {
+        let mut descendants = RoaringBitmap::new();
+        let mut parent_ids = vec![document_id];
+
+        for _ in 0..self.config.mailbox_max_depth {
+            let mut child_ids = Vec::new();
+            for parent_id in parent_ids {
+                for child_id in self
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mailbox,
+                        Filter::new_condition(
+                            Property::ParentId.into(),
+                            ComparisonOperator::Equal,
+                            Query::LongInteger((parent_id + 1) as LongInteger),
+                        ),
+                        Comparator::None,
+                    )?
+                    .into_bitmap()
+                {
+                    if descendants.insert(child_id) {
+                        child_ids.push(child_id);
+                    }
+                }
+            }
+            if child_ids.is_empty() {
+                break;
+            }
+            parent_ids = child_ids;
+        }
+
+        Ok(descendants)
+    }
+
+    fn mailbox_search_filter(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<query::Filter<mail::schema::Filter>>> {
+        if !self
+            .mailbox_search_folders(account_id)?
+            .contains(document_id)
+        {
+            return Ok(None);
+        }
+        match self
+            .get_orm::<Mailbox>(account_id, document_id)?
+            .and_then(|mut fields| fields.remove(&Property::SearchFilter))
+        {
+            Some(Value::Text { value }) => serde_json::from_str(&value).map(Some).map_err(|err| {
+                StoreError::DataCorruption(format!(
+                    "Failed to parse search filter of mailbox {}:{}: {}",
+                    account_id, document_id, err
+                ))
+            }),
+            _ => Ok(None),
+        }
+    }
 }
@@ -25,7 +25,9 @@ pub mod changes;
 pub mod get;
 pub mod query;
 pub mod raft;
+pub mod retention;
 pub mod schema;
+pub mod search;
 pub mod serialize;
 pub mod set;
 
@@ -111,7 +113,172 @@ impl CreateMailbox for TinyORM<Mailbox> {
 #[inline(always)]
 pub fn is_valid_role(role: &str) -> bool {
     [
-        "inbox", "trash", "spam", "junk", "drafts", "archive", "sent",
+        "inbox", "trash", "spam", "junk", "drafts", "archive", "sent", "snoozed",
     ]
     .contains(&role)
 }
+
+pub const MAX_ICON_LEN: usize = 64;
+
+// Colors are stored as CSS hex colors, either #rgb or #rrggbb.
+pub fn is_valid_color(color: &str) -> bool {
+    color.strip_prefix('#').map_or(false, |hex| {
+        matches!(hex.len(), 3 | 6) && hex.chars().all(|ch| ch.is_ascii_hexdigit())
+    })
+}
+
+// Splits a hierarchical mailbox path into mailbox names. Separators that are part
+// of a name are escaped with a backslash, as are backslashes themselves.
+pub fn split_mailbox_path(path: &str, separator: char) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut name = String::with_capacity(path.len());
+    let mut chars = path.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if matches!(chars.peek(), Some(&next) if next == separator || next == '\\') => {
+                name.push(chars.next().unwrap());
+            }
+            _ if ch == separator => {
+                let trimmed = name.trim();
+                if !trimmed.is_empty() {
+                    names.push(trimmed.to_string());
+                }
+                name.clear();
+            }
+            _ => name.push(ch),
+        }
+    }
+    let trimmed = name.trim();
+    if !trimmed.is_empty() {
+        names.push(trimmed.to_string());
+    }
+
+    names
+}
+
+// Builds a hierarchical mailbox path, the reverse of split_mailbox_path.
+pub fn join_mailbox_path<'x>(names: impl IntoIterator<Item = &'x str>, separator: char) -> String {
+    let mut path = String::new();
+    for (pos, name) in names.into_iter().enumerate() {
+        if pos > 0 {
+            path.push(separator);
+        }
+        for ch in name.chars() {
+            if ch == separator || ch == '\\' {
+                path.push('\\');
+            }
+            path.push(ch);
+        }
+    }
+    path
+}
+
+// Localized names of special-use mailboxes, along with the canonical
+// names these mailboxes are stored under.
+static ROLE_NAMES: &[(&str, &[&str], &[(&str, &str)])] = &[
+    (
+        "inbox",
+        &["Inbox"],
+        &[
+            ("de", "Posteingang"),
+            ("es", "Bandeja de entrada"),
+            ("fr", "Boîte de réception"),
+            ("it", "Posta in arrivo"),
+            ("nl", "Postvak IN"),
+            ("pt", "Caixa de entrada"),
+        ],
+    ),
+    (
+        "sent",
+        &["Sent", "Sent Items"],
+        &[
+            ("de", "Gesendet"),
+            ("es", "Enviados"),
+            ("fr", "Éléments envoyés"),
+            ("it", "Posta inviata"),
+            ("nl", "Verzonden items"),
+            ("pt", "Itens enviados"),
+        ],
+    ),
+    (
+        "drafts",
+        &["Drafts"],
+        &[
+            ("de", "Entwürfe"),
+            ("es", "Borradores"),
+            ("fr", "Brouillons"),
+            ("it", "Bozze"),
+            ("nl", "Concepten"),
+            ("pt", "Rascunhos"),
+        ],
+    ),
+    (
+        "trash",
+        &["Trash", "Deleted Items"],
+        &[
+            ("de", "Papierkorb"),
+            ("es", "Papelera"),
+            ("fr", "Corbeille"),
+            ("it", "Cestino"),
+            ("nl", "Verwijderde items"),
+            ("pt", "Lixeira"),
+        ],
+    ),
+    (
+        "junk",
+        &["Junk", "Junk Mail"],
+        &[
+            ("de", "Spam"),
+            ("es", "Correo no deseado"),
+            ("fr", "Courrier indésirable"),
+            ("it", "Posta indesiderata"),
+            ("nl", "Ongewenste e-mail"),
+            ("pt", "Lixo eletrônico"),
+        ],
+    ),
+    (
+        "spam",
+        &["Spam"],
+        &[
+            ("de", "Spam"),
+            ("es", "Spam"),
+            ("fr", "Spam"),
+            ("it", "Spam"),
+            ("nl", "Spam"),
+            ("pt", "Spam"),
+        ],
+    ),
+    (
+        "archive",
+        &["Archive"],
+        &[
+            ("de", "Archiv"),
+            ("es", "Archivo"),
+            ("fr", "Archives"),
+            ("it", "Archivio"),
+            ("nl", "Archief"),
+            ("pt", "Arquivo"),
+        ],
+    ),
+];
+
+pub fn localized_role_name(role: &str, name: &str, locale: &str) -> Option<&'static str> {
+    // Only the language subtag is used, i.e. "de-AT" -> "de"
+    let language = locale
+        .split(|c| c == '-' || c == '_')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let (_, names, translations) = ROLE_NAMES.iter().find(|(r, _, _)| *r == role)?;
+
+    // Mailboxes renamed by the user are never localized
+    if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+        return None;
+    }
+
+    translations
+        .iter()
+        .find(|(lang, _)| *lang == language)
+        .map(|(_, name)| *name)
+}
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+use super::get::JMAPGetMailbox;
 use super::schema::{Comparator, Filter, Mailbox, Property};
 use crate::mail::sharing::JMAPShareMail;
 use jmap::error::method::MethodError;
@@ -135,41 +136,55 @@ where
             })
         })?;
 
+        // Unread counts are not indexed, mailboxes are sorted by them once the query has run
+        let mut sort_by_unread = None;
+        let mut num_comparators = 0;
         helper.parse_comparator(|comparator| {
-            Ok(comparator::Comparator::Field(FieldComparator {
-                field: {
-                    match comparator.property {
-                        Comparator::Name => Property::Name,
-                        Comparator::SortOrder => Property::SortOrder,
-                        Comparator::ParentId => Property::ParentId,
+            num_comparators += 1;
+            let field = match comparator.property {
+                Comparator::Name => Property::Name,
+                Comparator::SortOrder => Property::SortOrder,
+                Comparator::ParentId => Property::ParentId,
+                Comparator::UnreadEmails => {
+                    if num_comparators > 1 {
+                        return Err(MethodError::UnsupportedSort(
+                            "unreadEmails has to be the first sort criterion.".to_string(),
+                        ));
                     }
+                    sort_by_unread = comparator.is_ascending.into();
+                    return Ok(comparator::Comparator::List(Vec::new()));
                 }
-                .into(),
+            };
+            Ok(comparator::Comparator::Field(FieldComparator {
+                field: field.into(),
                 ascending: comparator.is_ascending,
             }))
         })?;
+        let acl = helper.request.acl.clone().unwrap();
 
-        if filter_as_tree || sort_as_tree {
+        if filter_as_tree || sort_as_tree || sort_by_unread.is_some() {
             helper.query(
                 default_filter_mapper,
                 Some(|mut results: Vec<JMAPId>| {
                     let mut hierarchy = AHashMap::default();
                     let mut tree = AHashMap::default();
 
-                    for doc_id in self
-                        .get_document_ids(account_id, Collection::Mailbox)?
-                        .unwrap_or_default()
-                    {
-                        let parent_id = self
-                            .get_orm::<Mailbox>(account_id, doc_id)?
-                            .and_then(|fields| {
-                                fields.get(&Property::ParentId).and_then(|v| v.as_id())
-                            })
-                            .unwrap_or_default();
-                        hierarchy.insert((doc_id + 1) as u64, parent_id);
-                        tree.entry(parent_id)
-                            .or_insert_with(AHashSet::default)
-                            .insert((doc_id + 1) as u64);
+                    if filter_as_tree || sort_as_tree {
+                        for doc_id in self
+                            .get_document_ids(account_id, Collection::Mailbox)?
+                            .unwrap_or_default()
+                        {
+                            let parent_id = self
+                                .get_orm::<Mailbox>(account_id, doc_id)?
+                                .and_then(|fields| {
+                                    fields.get(&Property::ParentId).and_then(|v| v.as_id())
+                                })
+                                .unwrap_or_default();
+                            hierarchy.insert((doc_id + 1) as u64, parent_id);
+                            tree.entry(parent_id)
+                                .or_insert_with(AHashSet::default)
+                                .insert((doc_id + 1) as u64);
+                        }
                     }
 
                     if filter_as_tree {
@@ -203,6 +218,40 @@ where
                         }
                     }
 
+                    // The sort is stable, so the remaining comparators break ties
+                    if let (Some(ascending), true) = (sort_by_unread, results.len() > 1) {
+                        let mail_document_ids =
+                            self.get_document_ids(account_id, Collection::Mail)?;
+                        let search_folders = self.mailbox_search_folders(account_id)?;
+                        let mut unread_emails = AHashMap::with_capacity(results.len());
+                        for id in &results {
+                            let document_id = id.get_document_id();
+                            let mailbox_ids = if search_folders.contains(document_id) {
+                                self.mailbox_search_ids(account_id, document_id, &acl)?
+                            } else {
+                                self.mailbox_tags(account_id, document_id)?
+                            };
+                            unread_emails.insert(
+                                document_id,
+                                self.mailbox_unread_tags(
+                                    account_id,
+                                    mailbox_ids,
+                                    mail_document_ids.as_ref(),
+                                )?
+                                .map_or(0, |unread_ids| unread_ids.len()),
+                            );
+                        }
+                        results.sort_by(|a, b| {
+                            let ordering = unread_emails[&a.get_document_id()]
+                                .cmp(&unread_emails[&b.get_document_id()]);
+                            if ascending {
+                                ordering
+                            } else {
+                                ordering.reverse()
+                            }
+                        });
+                    }
+
                     if sort_as_tree && results.len() > 1 {
                         let mut stack = Vec::new();
                         let mut sorted_list = Vec::with_capacity(results.len());
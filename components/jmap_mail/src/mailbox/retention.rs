@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use super::schema::{Mailbox, Property, Value};
+use crate::mail::set::JMAPSetMail;
+use crate::mail::{self, schema::Email, MessageField};
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::types::jmap::JMAPId;
+use store::ahash::AHashMap;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::read::comparator::Comparator;
+use store::read::filter::{Filter, Query};
+use store::read::FilterMapper;
+use store::tracing::debug;
+use store::write::batch::WriteBatch;
+use store::write::update::Changes;
+use store::{AccountId, DocumentId, JMAPStore, LongInteger, Store};
+
+pub trait JMAPMailboxRetention<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mailbox_purge_expired(&self, account_id: AccountId) -> store::Result<Option<Changes>>;
+}
+
+impl<T> JMAPMailboxRetention<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mailbox_purge_expired(&self, account_id: AccountId) -> store::Result<Option<Changes>> {
+        let mailbox_ids =
+            if let Some(mailbox_ids) = self.get_document_ids(account_id, Collection::Mailbox)? {
+                mailbox_ids
+            } else {
+                return Ok(None);
+            };
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+
+        // Obtain the expired messages of each mailbox with a retention policy
+        let mut expired_messages: AHashMap<DocumentId, Vec<DocumentId>> = AHashMap::default();
+        for mailbox_id in mailbox_ids {
+            let retention_days = match self
+                .get_orm::<Mailbox>(account_id, mailbox_id)?
+                .and_then(|mut fields| fields.remove(&Property::RetentionDays))
+            {
+                Some(Value::Number { value }) if value > 0 => value as u64,
+                _ => continue,
+            };
+
+            for message_document_id in self
+                .query_store::<FilterMapper>(
+                    account_id,
+                    Collection::Mail,
+                    Filter::and(vec![
+                        Filter::eq(
+                            MessageField::Mailbox.into(),
+                            Query::Tag(Tag::Id(mailbox_id)),
+                        ),
+                        Filter::lt(
+                            MessageField::ReceivedAt.into(),
+                            Query::LongInteger(
+                                now.saturating_sub(retention_days * 86400) as LongInteger
+                            ),
+                        ),
+                    ]),
+                    Comparator::None,
+                )?
+                .into_bitmap()
+            {
+                expired_messages
+                    .entry(message_document_id)
+                    .or_insert_with(Vec::new)
+                    .push(mailbox_id);
+            }
+        }
+
+        if expired_messages.is_empty() {
+            return Ok(None);
+        }
+
+        let mut batch = WriteBatch::new(account_id);
+        for (message_document_id, mailbox_ids) in expired_messages {
+            let mut document = Document::new(Collection::Mail, message_document_id);
+            let current_fields = if let Some(current_fields) =
+                self.get_orm::<Email>(account_id, message_document_id)?
+            {
+                current_fields
+            } else {
+                debug!(
+                    "Email ORM for {}:{} not found",
+                    account_id, message_document_id
+                );
+                continue;
+            };
+
+            // Messages that are also in mailboxes without an expired policy are
+            // only removed from the expired mailboxes, otherwise they are deleted.
+            match current_fields.get_tags(&mail::schema::Property::MailboxIds) {
+                Some(tags) if tags.len() > mailbox_ids.len() => {
+                    let thread_id = self
+                        .get_document_value::<DocumentId>(
+                            account_id,
+                            Collection::Mail,
+                            message_document_id,
+                            MessageField::ThreadId.into(),
+                        )?
+                        .ok_or_else(|| {
+                            StoreError::DataCorruption(format!(
+                                "Failed to fetch threadId for {}:{}.",
+                                account_id, message_document_id
+                            ))
+                        })?;
+                    let mut fields = TinyORM::track_changes(&current_fields);
+                    for mailbox_id in mailbox_ids {
+                        fields.untag(&mail::schema::Property::MailboxIds, &Tag::Id(mailbox_id));
+                        batch.log_child_update(Collection::Mailbox, mailbox_id);
+                    }
+                    current_fields.merge(&mut document, fields)?;
+                    batch.update_document(document);
+                    batch.log_update(
+                        Collection::Mail,
+                        JMAPId::from_parts(thread_id, message_document_id),
+                    );
+                }
+                _ => {
+                    if let Some(id) =
+                        self.mail_delete(account_id, Some(&mut batch), &mut document)?
+                    {
+                        batch.delete_document(document);
+                        batch.log_delete(Collection::Mail, id);
+                    }
+                }
+            }
+        }
+
+        self.write(batch)
+    }
+}
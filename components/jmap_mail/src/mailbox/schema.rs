@@ -193,7 +193,12 @@ pub enum Property {
     MyRights = 9,
     IsSubscribed = 10,
     ACL = 11,
-    Invalid = 12,
+    RetentionDays = 12,
+    Color = 13,
+    Icon = 14,
+    SearchFilter = 15,
+    DisplayName = 16,
+    Invalid = 17,
 }
 
 impl Display for Property {
@@ -211,6 +216,11 @@ impl Display for Property {
             Property::MyRights => write!(f, "myRights"),
             Property::IsSubscribed => write!(f, "isSubscribed"),
             Property::ACL => write!(f, "acl"),
+            Property::RetentionDays => write!(f, "retentionDays"),
+            Property::Color => write!(f, "color"),
+            Property::Icon => write!(f, "icon"),
+            Property::SearchFilter => write!(f, "searchFilter"),
+            Property::DisplayName => write!(f, "displayName"),
             Property::Invalid => Ok(()),
         }
     }
@@ -231,6 +241,11 @@ impl Property {
             "unreadThreads" => Property::UnreadThreads,
             "myRights" => Property::MyRights,
             "acl" => Property::ACL,
+            "retentionDays" => Property::RetentionDays,
+            "color" => Property::Color,
+            "icon" => Property::Icon,
+            "searchFilter" => Property::SearchFilter,
+            "displayName" => Property::DisplayName,
             _ => Property::Invalid,
         }
     }
@@ -255,6 +270,8 @@ pub enum Comparator {
     SortOrder,
     #[serde(rename = "parentId")]
     ParentId,
+    #[serde(rename = "unreadEmails")]
+    UnreadEmails,
 }
 
 impl From<Property> for FieldId {
@@ -278,6 +295,11 @@ impl From<FieldId> for Property {
             9 => Property::MyRights,
             10 => Property::IsSubscribed,
             11 => Property::ACL,
+            12 => Property::RetentionDays,
+            13 => Property::Color,
+            14 => Property::Icon,
+            15 => Property::SearchFilter,
+            16 => Property::DisplayName,
             _ => Property::Invalid,
         }
     }
@@ -322,4 +344,13 @@ impl MailboxRights {
             may_submit: acl.contains(ACL::Submit),
         }
     }
+
+    // Search folders are read-only views of other mailboxes
+    pub fn search_folder(mut self) -> Self {
+        self.may_add_items = false;
+        self.may_remove_items = false;
+        self.may_set_seen = false;
+        self.may_set_keywords = false;
+        self
+    }
 }
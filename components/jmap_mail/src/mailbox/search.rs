@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    jmap_store::changes::JMAPChanges,
+    orm::{serialize::JMAPOrm, TinyORM},
+    request::{query, ACLEnforce},
+    types::{jmap::JMAPId, state::JMAPState},
+};
+use store::{
+    core::{acl::ACLToken, collection::Collection, document::Document, tag::Tag, vec_map::VecMap},
+    log::changes::ChangeId,
+    roaring::RoaringBitmap,
+    write::batch::WriteBatch,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use super::{
+    get::JMAPGetMailbox,
+    schema::{Mailbox, Property, Value},
+};
+use crate::mail::{query::JMAPMailQuery, schema::Filter};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MailboxSetSearchRequest {
+    #[serde(skip)]
+    pub acl: Option<Arc<ACLToken>>,
+
+    #[serde(rename = "accountId")]
+    pub account_id: JMAPId,
+
+    #[serde(rename = "update")]
+    pub update: VecMap<JMAPId, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MailboxSetSearchResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: JMAPId,
+
+    #[serde(rename = "oldState")]
+    pub old_state: JMAPState,
+
+    #[serde(rename = "newState")]
+    pub new_state: JMAPState,
+
+    #[serde(rename = "updated")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub updated: Vec<JMAPId>,
+
+    #[serde(rename = "notUpdated")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub not_updated: VecMap<JMAPId, SetError<Property>>,
+}
+
+pub trait JMAPMailboxSearch<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mailbox_set_search(
+        &self,
+        request: MailboxSetSearchRequest,
+    ) -> jmap::Result<MailboxSetSearchResponse>;
+    fn mailbox_set_search_item(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        search_filter: serde_json::Value,
+        search_folders: &RoaringBitmap,
+        batch: &mut WriteBatch,
+    ) -> jmap::Result<Result<(), SetError<Property>>>;
+}
+
+impl<T> JMAPMailboxSearch<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mailbox_set_search(
+        &self,
+        request: MailboxSetSearchRequest,
+    ) -> jmap::Result<MailboxSetSearchResponse> {
+        if request.update.len() > self.config.max_objects_in_set {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let account_id = request.account_id.get_document_id();
+        let acl = request.acl.unwrap();
+
+        // Search folders can only be managed by the account owner
+        if !acl.is_member(account_id) {
+            return Err(MethodError::Forbidden(
+                "Search folders can only be managed by the account owner.".to_string(),
+            ));
+        }
+
+        let _lock = self.lock_collection(account_id, Collection::Mailbox);
+        let old_state = self.get_state(account_id, Collection::Mailbox)?;
+        let search_folders = self.mailbox_search_folders(account_id)?;
+        let mut response = MailboxSetSearchResponse {
+            account_id: request.account_id,
+            new_state: old_state.clone(),
+            old_state,
+            updated: Vec::with_capacity(request.update.len()),
+            not_updated: VecMap::new(),
+        };
+
+        let mut batch = WriteBatch::new(account_id);
+        for (id, search_filter) in request.update {
+            match self.mailbox_set_search_item(
+                account_id,
+                id.get_document_id(),
+                search_filter,
+                &search_folders,
+                &mut batch,
+            )? {
+                Ok(()) => response.updated.push(id),
+                Err(err) => response.not_updated.append(id, err),
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write(batch)?;
+            response.new_state = self.get_state(account_id, Collection::Mailbox)?;
+        }
+
+        Ok(response)
+    }
+
+    fn mailbox_set_search_item(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        search_filter: serde_json::Value,
+        search_folders: &RoaringBitmap,
+        batch: &mut WriteBatch,
+    ) -> jmap::Result<Result<(), SetError<Property>>> {
+        let current_fields =
+            if let Some(current_fields) = self.get_orm::<Mailbox>(account_id, document_id)? {
+                current_fields
+            } else {
+                return Ok(Err(SetError::new(SetErrorType::NotFound)));
+            };
+        let mut fields = TinyORM::track_changes(&current_fields);
+
+        if !search_filter.is_null() {
+            if current_fields.has_property(&Property::Role) {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::Role)
+                    .with_description(
+                        "Mailboxes with a role cannot be search folders.",
+                    )));
+            } else if !search_folders.contains(document_id)
+                && self
+                    .mailbox_tags(account_id, document_id)?
+                    .map_or(false, |messages| !messages.is_empty())
+            {
+                return Ok(Err(SetError::new(SetErrorType::MailboxHasEmail)
+                    .with_description("Only empty mailboxes can be search folders.")));
+            }
+
+            let filter =
+                match serde_json::from_value::<query::Filter<Filter>>(search_filter.clone()) {
+                    Ok(filter) => filter,
+                    Err(err) => {
+                        return Ok(Err(SetError::invalid_properties()
+                            .with_property(Property::SearchFilter)
+                            .with_description(format!("Invalid search filter: {}", err))));
+                    }
+                };
+
+            // Search folders cannot be nested to avoid cycles
+            let mut mailbox_ids = Vec::new();
+            filter_mailbox_ids(&filter, &mut mailbox_ids);
+            if mailbox_ids
+                .iter()
+                .any(|id| *id == document_id || search_folders.contains(*id))
+            {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::SearchFilter)
+                    .with_description(
+                        "Search filters cannot reference search folders.",
+                    )));
+            }
+
+            if self.mail_search_ids(account_id, filter).is_err() {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::SearchFilter)
+                    .with_description("Unsupported search filter.")));
+            }
+
+            fields.set(
+                Property::SearchFilter,
+                Value::Text {
+                    value: search_filter.to_string(),
+                },
+            );
+            fields.tag(Property::SearchFilter, Tag::Default);
+        } else if search_folders.contains(document_id) {
+            fields.set(Property::SearchFilter, Value::Null);
+            fields.untag(&Property::SearchFilter, &Tag::Default);
+        } else {
+            return Ok(Ok(()));
+        }
+
+        let mut document = Document::new(Collection::Mailbox, document_id);
+        if current_fields.merge(&mut document, fields)? {
+            batch.update_document(document);
+            batch.log_update(Collection::Mailbox, document_id);
+        }
+
+        Ok(Ok(()))
+    }
+}
+
+fn filter_mailbox_ids(filter: &query::Filter<Filter>, mailbox_ids: &mut Vec<DocumentId>) {
+    match filter {
+        query::Filter::FilterOperator(op) => {
+            for condition in &op.conditions {
+                filter_mailbox_ids(condition, mailbox_ids);
+            }
+        }
+        query::Filter::FilterCondition(Filter::InMailbox { value }) => {
+            mailbox_ids.push(value.get_document_id());
+        }
+        query::Filter::FilterCondition(Filter::InMailboxOtherThan { value }) => {
+            mailbox_ids.extend(value.iter().map(|id| id.get_document_id()));
+        }
+        _ => (),
+    }
+}
+
+impl MailboxSetSearchResponse {
+    pub fn account_id(&self) -> AccountId {
+        self.account_id.get_document_id()
+    }
+
+    pub fn has_changes(&self) -> Option<ChangeId> {
+        if self.old_state != self.new_state {
+            self.new_state.get_change_id().into()
+        } else {
+            None
+        }
+    }
+}
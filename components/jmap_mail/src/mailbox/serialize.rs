@@ -161,6 +161,36 @@ impl<'de> serde::de::Visitor<'de> for MailboxVisitor {
                         },
                     );
                 }
+                "retentionDays" => {
+                    properties.append(
+                        Property::RetentionDays,
+                        if let Some(value) = map.next_value::<Option<u32>>()? {
+                            Value::Number { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "color" => {
+                    properties.append(
+                        Property::Color,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "icon" => {
+                    properties.append(
+                        Property::Icon,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "isSubscribed" => {
                     properties.append(
                         Property::IsSubscribed,
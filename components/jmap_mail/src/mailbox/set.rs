@@ -23,8 +23,9 @@
 
 use std::time::Duration;
 
-use super::is_valid_role;
+use super::get::JMAPGetMailbox;
 use super::schema::{Mailbox, Property, Value};
+use super::{is_valid_color, is_valid_role, split_mailbox_path, MAX_ICON_LEN};
 use crate::mail::schema::Email;
 use crate::mail::set::JMAPSetMail;
 use crate::mail::sharing::JMAPShareMail;
@@ -120,10 +121,27 @@ where
             .on_destroy_remove_emails
             .unwrap_or(false);
 
+        let mut created_roles = Vec::new();
         helper.create(|_create_id, mailbox, helper, document| {
             // Set values
             let mut mailbox = TinyORM::<Mailbox>::new().mailbox_set(helper, mailbox, None, None)?;
 
+            // Roles have to be unique among the mailboxes created in this request as well
+            let role = mailbox
+                .get(&Property::Role)
+                .and_then(|r| r.as_text())
+                .map(|r| r.to_string());
+            if let Some(role) = &role {
+                if created_roles.contains(role) {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::Role)
+                        .with_description(format!(
+                            "A mailbox with role '{}' already exists.",
+                            role
+                        )));
+                }
+            }
+
             // Check ACLs
             if helper.acl.is_shared(helper.account_id) {
                 match mailbox.get(&Property::ParentId) {
@@ -155,6 +173,10 @@ where
             }
             mailbox.insert_validate(document)?;
 
+            if let Some(role) = role {
+                created_roles.push(role);
+            }
+
             Ok(Mailbox::new(document.document_id.into()))
         })?;
 
@@ -181,6 +203,15 @@ where
                 ));
             }
 
+            // Search folders cannot be assigned a role
+            if current_fields.has_property(&Property::SearchFilter)
+                && matches!(fields.get(&Property::Role), Some(Value::Text { .. }))
+            {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Role)
+                    .with_description("Search folders cannot have a role."));
+            }
+
             // Check ACLs
             if helper.acl.is_shared(helper.account_id) {
                 if !helper
@@ -208,9 +239,22 @@ where
                 }
             }
 
+            // Renaming or moving a mailbox changes the path of its descendants
+            let is_path_change = [Property::Name, Property::ParentId].iter().any(|property| {
+                fields.has_property(property)
+                    && fields.get(property) != current_fields.get(property)
+            });
+
             // Merge changes
             current_fields.merge_validate(document, fields)?;
 
+            // Log descendants as updated so clients refresh their paths
+            if is_path_change {
+                for child_id in self.mailbox_descendants(helper.account_id, document_id)? {
+                    helper.changes.log_update(Collection::Mailbox, child_id);
+                }
+            }
+
             Ok(None)
         })?;
 
@@ -386,17 +430,7 @@ where
         account_id: AccountId,
         path: &str,
     ) -> store::Result<Option<(DocumentId, Option<Changes>)>> {
-        let path = path
-            .split('/')
-            .filter_map(|p| {
-                let p = p.trim();
-                if !p.is_empty() {
-                    p.into()
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
+        let path = split_mailbox_path(path, self.config.mailbox_path_separator);
         if path.is_empty() || path.len() > self.config.mailbox_max_depth {
             return Ok(None);
         }
@@ -461,13 +495,21 @@ where
 
         // Create missing folders
         if path.peek().is_some() {
+            // Names are validated and the mailbox limit enforced as in Mailbox/set
+            let total_mailboxes = self
+                .get_document_ids(account_id, Collection::Mailbox)?
+                .map_or(0, |document_ids| document_ids.len() as usize);
+            if total_mailboxes + path.len() > self.config.mailbox_max_total
+                || path
+                    .clone()
+                    .any(|name| name.len() >= self.config.mailbox_name_max_len)
+            {
+                return Ok(None);
+            }
+
             let mut batch = WriteBatch::new(account_id);
 
             for name in path {
-                if name.len() > self.config.mailbox_name_max_len {
-                    return Ok(None);
-                }
-
                 let document_id = self.assign_document_id(account_id, Collection::Mailbox)?;
                 let mut document = Document::new(Collection::Mailbox, document_id);
                 let mut orm = TinyORM::<Mailbox>::new();
@@ -490,8 +532,14 @@ where
             }
 
             Ok(Some((next_parent_id - 1, self.write(batch)?)))
-        } else {
+        } else if !self
+            .mailbox_search_folders(account_id)?
+            .contains(next_parent_id - 1)
+        {
             Ok(Some((next_parent_id - 1, None)))
+        } else {
+            // Search folders are read-only views, messages can't be filed into them
+            Ok(None)
         }
     }
 }
@@ -520,6 +568,9 @@ where
         mailbox_id: Option<DocumentId>,
         current_fields: Option<&TinyORM<Mailbox>>,
     ) -> jmap::error::set::Result<Self, Property> {
+        // Validation failures are collected and reported back as a single error
+        let mut invalid_properties: Vec<(Property, String)> = Vec::new();
+
         // Set properties
         for (property, value) in mailbox.properties {
             let value = match (property, value) {
@@ -527,9 +578,8 @@ where
                     if value.len() < helper.store.config.mailbox_name_max_len {
                         Value::Text { value }
                     } else {
-                        return Err(SetError::invalid_properties()
-                            .with_property(property)
-                            .with_description("Mailbox name is too long.".to_string()));
+                        invalid_properties.push((property, "Mailbox name is too long.".into()));
+                        continue;
                     }
                 }
                 (Property::ParentId, Value::Id { value }) => {
@@ -538,18 +588,25 @@ where
                         return Err(SetError::new(SetErrorType::WillDestroy)
                             .with_description("Parent ID will be destroyed."));
                     } else if !helper.document_ids.contains(parent_id) {
-                        return Err(SetError::invalid_properties()
-                            .with_description("Parent ID does not exist."));
+                        invalid_properties.push((property, "Parent ID does not exist.".into()));
+                        continue;
                     }
 
                     Value::Id {
                         value: (parent_id + 1).into(),
                     }
                 }
-                (Property::ParentId, Value::IdReference { value }) => Value::Id {
-                    value: (u64::from(helper.get_id_reference(Property::ParentId, &value)?) + 1)
-                        .into(),
-                },
+                (Property::ParentId, Value::IdReference { value }) => {
+                    if let Some(parent_id) = helper.map_id_reference(&value) {
+                        Value::Id {
+                            value: (u64::from(parent_id) + 1).into(),
+                        }
+                    } else {
+                        invalid_properties
+                            .push((property, format!("Could not find id '{}'.", value)));
+                        continue;
+                    }
+                }
                 (Property::IsSubscribed, Value::Bool { value: subscribe }) => {
                     let account_id = helper.acl.primary_id();
                     let mut new_value = None;
@@ -603,9 +660,8 @@ where
                         self.tag(property, Tag::Default);
                         Value::Text { value: role }
                     } else {
-                        return Err(SetError::invalid_properties()
-                            .with_property(property)
-                            .with_description("Invalid role."));
+                        invalid_properties.push((property, "Invalid role.".into()));
+                        continue;
                     }
                 }
                 (Property::Role, Value::Null) => {
@@ -613,31 +669,76 @@ where
                     Value::Null
                 }
                 (Property::SortOrder, value @ Value::Number { .. }) => value,
+                (Property::RetentionDays, Value::Number { value }) => {
+                    if value > 0 {
+                        Value::Number { value }
+                    } else {
+                        invalid_properties.push((
+                            property,
+                            "Retention period must be at least one day.".into(),
+                        ));
+                        continue;
+                    }
+                }
+                (Property::RetentionDays, Value::Null) => Value::Null,
+                (Property::Color, Value::Text { value }) => {
+                    if is_valid_color(&value) {
+                        Value::Text {
+                            value: value.to_lowercase(),
+                        }
+                    } else {
+                        invalid_properties
+                            .push((property, "Color must be in #rgb or #rrggbb format.".into()));
+                        continue;
+                    }
+                }
+                (Property::Icon, Value::Text { value }) => {
+                    if !value.is_empty() && value.len() <= MAX_ICON_LEN {
+                        Value::Text { value }
+                    } else {
+                        invalid_properties.push((property, "Invalid icon name.".into()));
+                        continue;
+                    }
+                }
+                (Property::Color | Property::Icon, Value::Null) => Value::Null,
                 (Property::ACL, Value::ACLSet(value)) => {
+                    let mut principal_to_id = |account_id: &str| match helper
+                        .store
+                        .principal_to_id::<Property>(account_id)
+                    {
+                        Ok(id) => Some(id),
+                        Err(_) => {
+                            invalid_properties.push((
+                                property,
+                                format!("Principal {:?} does not exist.", account_id),
+                            ));
+                            None
+                        }
+                    };
+
                     for acl_update in &value {
                         match acl_update {
                             ACLUpdate::Replace { acls } => {
                                 self.acl_clear();
                                 for (account_id, acls) in acls {
-                                    self.acl_update(
-                                        helper.store.principal_to_id(account_id)?,
-                                        acls,
-                                    );
+                                    if let Some(account_id) = principal_to_id(account_id.as_str()) {
+                                        self.acl_update(account_id, acls);
+                                    }
                                 }
                             }
                             ACLUpdate::Update { account_id, acls } => {
-                                self.acl_update(helper.store.principal_to_id(account_id)?, acls);
+                                if let Some(account_id) = principal_to_id(account_id.as_str()) {
+                                    self.acl_update(account_id, acls);
+                                }
                             }
                             ACLUpdate::Set {
                                 account_id,
                                 acl,
                                 is_set,
                             } => {
-                                self.acl_set(
-                                    helper.store.principal_to_id(account_id)?,
-                                    *acl,
-                                    *is_set,
-                                );
+                                if let Some(account_id) = principal_to_id(account_id.as_str()) {
+                                    self.acl_set(account_id, *acl, *is_set);
+                                }
                             }
                         }
                     }
@@ -645,9 +746,8 @@ where
                     continue;
                 }
                 (_, _) => {
-                    return Err(SetError::invalid_properties()
-                        .with_property(property)
-                        .with_description("Unexpected value."));
+                    invalid_properties.push((property, "Unexpected value.".into()));
+                    continue;
                 }
             };
 
@@ -662,8 +762,12 @@ where
             let mut success = false;
             for _ in 0..helper.store.config.mailbox_max_depth {
                 if mailbox_parent_id == (mailbox_id as store::JMAPId) + 1 {
-                    return Err(SetError::invalid_properties()
-                        .with_description("Mailbox cannot be a parent of itself."));
+                    invalid_properties.push((
+                        Property::ParentId,
+                        "Mailbox cannot be a parent of itself.".into(),
+                    ));
+                    success = true;
+                    break;
                 } else if mailbox_parent_id == 0 {
                     success = true;
                     break;
@@ -683,14 +787,18 @@ where
                     success = true;
                     break;
                 } else {
-                    return Err(SetError::invalid_properties()
-                        .with_description("Mailbox parent does not exist."));
+                    invalid_properties
+                        .push((Property::ParentId, "Mailbox parent does not exist.".into()));
+                    success = true;
+                    break;
                 }
             }
 
             if !success {
-                return Err(SetError::invalid_properties()
-                    .with_description("Mailbox parent-child relationship is too deep."));
+                invalid_properties.push((
+                    Property::ParentId,
+                    "Mailbox parent-child relationship is too deep.".into(),
+                ));
             }
         }
 
@@ -713,10 +821,10 @@ where
                 )?
                 .is_empty()
             {
-                return Err(SetError::invalid_properties().with_description(format!(
-                    "A mailbox with role '{}' already exists.",
-                    mailbox_role
-                )));
+                invalid_properties.push((
+                    Property::Role,
+                    format!("A mailbox with role '{}' already exists.", mailbox_role),
+                ));
             }
         }
 
@@ -769,10 +877,10 @@ where
                     .into_bitmap()
                     .is_empty()
                 {
-                    return Err(SetError::invalid_properties().with_description(format!(
-                        "A mailbox with name '{}' already exists.",
-                        mailbox_name
-                    )));
+                    invalid_properties.push((
+                        Property::Name,
+                        format!("A mailbox with name '{}' already exists.", mailbox_name),
+                    ));
                 }
 
                 /*for jmap_id in helper.store.query_store::<FilterMapper>(
@@ -802,6 +910,20 @@ where
             }
         }
 
+        if !invalid_properties.is_empty() {
+            let mut properties = Vec::with_capacity(invalid_properties.len());
+            let mut descriptions = Vec::with_capacity(invalid_properties.len());
+            for (property, description) in invalid_properties {
+                if !properties.contains(&property) {
+                    properties.push(property);
+                }
+                descriptions.push(description);
+            }
+            return Err(SetError::invalid_properties()
+                .with_properties(properties)
+                .with_description(descriptions.join(" ")));
+        }
+
         // Invalidate cache for changed ACLs
         if let Some(permissions) = self.get_changed_acls(current_fields) {
             for permission in permissions {
@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::{Disposition, MDN};
+use crate::mail::get::{BlobResult, JMAPGetMail};
+use jmap::{
+    error::method::MethodError,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use mail_parser::{GetHeader, HeaderValue, Message, MessagePart, PartType, RfcHeader};
+use std::sync::Arc;
+use store::{
+    ahash::AHashSet,
+    core::{acl::ACLToken, vec_map::VecMap},
+    JMAPStore, Store,
+};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MDNParseRequest {
+    #[serde(skip)]
+    pub acl: Option<Arc<ACLToken>>,
+
+    #[serde(rename = "accountId")]
+    pub account_id: JMAPId,
+
+    #[serde(rename = "blobIds")]
+    blob_ids: AHashSet<JMAPBlob>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MDNParseResponse {
+    #[serde(rename = "accountId")]
+    account_id: JMAPId,
+
+    #[serde(rename = "parsed")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    parsed: VecMap<JMAPBlob, MDN>,
+
+    #[serde(rename = "notParsable")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    not_parsable: Vec<JMAPBlob>,
+
+    #[serde(rename = "notFound")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    not_found: Vec<JMAPBlob>,
+}
+
+pub trait JMAPMailMDNParse<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mdn_parse(&self, request: MDNParseRequest) -> jmap::Result<MDNParseResponse>;
+}
+
+impl<T> JMAPMailMDNParse<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mdn_parse(&self, request: MDNParseRequest) -> jmap::Result<MDNParseResponse> {
+        if request.blob_ids.len() > self.config.mail_parse_max_items {
+            return Err(MethodError::RequestTooLarge);
+        }
+        let mut response = MDNParseResponse {
+            account_id: request.account_id,
+            parsed: VecMap::with_capacity(request.blob_ids.len()),
+            not_parsable: Vec::new(),
+            not_found: Vec::new(),
+        };
+
+        let acl = request.acl.unwrap();
+        let account_id = request.account_id.get_document_id();
+        for blob_id in request.blob_ids {
+            if let BlobResult::Blob(blob) = self.mail_blob_get(account_id, &acl, &blob_id)? {
+                if let Some(mdn) = Message::parse(&blob).and_then(|message| MDN::parse(&message)) {
+                    response.parsed.append(blob_id, mdn);
+                } else {
+                    response.not_parsable.push(blob_id);
+                }
+            } else {
+                response.not_found.push(blob_id);
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+impl MDN {
+    pub fn parse(message: &Message) -> Option<Self> {
+        let mut mdn = MDN::default();
+        let mut has_disposition = false;
+
+        if let Some(HeaderValue::Text(subject)) =
+            message.parts.first()?.headers.get_rfc(&RfcHeader::Subject)
+        {
+            mdn.subject = subject.to_string().into();
+        }
+
+        for part in &message.parts {
+            match part.content_type() {
+                Some(("message", "disposition-notification")) => {
+                    let fields = message
+                        .raw_message
+                        .get(part.offset_body..part.offset_end)
+                        .map(String::from_utf8_lossy)?;
+                    has_disposition = mdn.parse_fields(&fields);
+                }
+                Some(("message", "rfc822" | "global"))
+                | Some(("text", "rfc822-headers" | "global-headers")) => {
+                    mdn.include_original_message = true;
+                }
+                Some(("text", "plain")) if mdn.text_body.is_none() => {
+                    if let PartType::Text(text) = &part.body {
+                        mdn.text_body = text.to_string().into();
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if has_disposition {
+            mdn.into()
+        } else {
+            None
+        }
+    }
+
+    // Parses the fields of a message/disposition-notification part,
+    // returns true if a valid Disposition field was found.
+    fn parse_fields(&mut self, fields: &str) -> bool {
+        let mut has_disposition = false;
+        let mut unfolded: Vec<String> = Vec::new();
+
+        for line in fields.lines() {
+            if line.starts_with(|ch: char| ch == ' ' || ch == '\t') {
+                if let Some(last) = unfolded.last_mut() {
+                    last.push(' ');
+                    last.push_str(line.trim());
+                }
+            } else if !line.trim().is_empty() {
+                unfolded.push(line.to_string());
+            }
+        }
+
+        for field in unfolded {
+            let (name, value) = if let Some((name, value)) = field.split_once(':') {
+                (name.trim(), value.trim().to_string())
+            } else {
+                continue;
+            };
+
+            match name.to_ascii_lowercase().as_str() {
+                "reporting-ua" => self.reporting_ua = value.into(),
+                "mdn-gateway" => self.mdn_gateway = value.into(),
+                "original-recipient" => self.original_recipient = value.into(),
+                "final-recipient" => self.final_recipient = value.into(),
+                "original-message-id" => self.original_message_id = value.into(),
+                "error" => self.error.get_or_insert_with(Vec::new).push(value),
+                "disposition" => {
+                    if let Some(disposition) = Disposition::parse(&value) {
+                        self.disposition = disposition;
+                        has_disposition = true;
+                    }
+                }
+                _ => {
+                    self.extension_fields
+                        .get_or_insert_with(VecMap::new)
+                        .append(name.to_string(), value);
+                }
+            }
+        }
+
+        has_disposition
+    }
+}
+
+impl Disposition {
+    // Parses "action-mode/sending-mode; type[/modifier]"
+    pub fn parse(value: &str) -> Option<Self> {
+        let (modes, type_) = value.split_once(';')?;
+        let (action_mode, sending_mode) = modes.split_once('/')?;
+        let type_ = type_.split('/').next()?.trim();
+
+        if type_.is_empty() {
+            return None;
+        }
+
+        Some(Disposition {
+            action_mode: action_mode.trim().to_ascii_lowercase(),
+            sending_mode: sending_mode.trim().to_ascii_lowercase(),
+            type_: type_.to_ascii_lowercase(),
+        })
+    }
+}
+
+trait ContentType {
+    fn content_type(&self) -> Option<(&str, &str)>;
+}
+
+impl ContentType for MessagePart<'_> {
+    fn content_type(&self) -> Option<(&str, &str)> {
+        if let Some(HeaderValue::ContentType(content_type)) =
+            self.headers.get_rfc(&RfcHeader::ContentType)
+        {
+            Some((
+                content_type.c_type.as_ref(),
+                content_type.c_subtype.as_deref()?,
+            ))
+        } else {
+            None
+        }
+    }
+}
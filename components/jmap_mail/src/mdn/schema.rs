@@ -0,0 +1,77 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::types::jmap::JMAPId;
+use store::core::vec_map::VecMap;
+
+// Message Disposition Notification, as defined in RFC 9007
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct MDN {
+    #[serde(rename = "forEmailId")]
+    pub for_email_id: Option<JMAPId>,
+
+    #[serde(rename = "subject")]
+    pub subject: Option<String>,
+
+    #[serde(rename = "textBody")]
+    pub text_body: Option<String>,
+
+    #[serde(rename = "includeOriginalMessage")]
+    pub include_original_message: bool,
+
+    #[serde(rename = "reportingUA")]
+    pub reporting_ua: Option<String>,
+
+    #[serde(rename = "disposition")]
+    pub disposition: Disposition,
+
+    #[serde(rename = "mdnGateway")]
+    pub mdn_gateway: Option<String>,
+
+    #[serde(rename = "originalRecipient")]
+    pub original_recipient: Option<String>,
+
+    #[serde(rename = "finalRecipient")]
+    pub final_recipient: Option<String>,
+
+    #[serde(rename = "originalMessageId")]
+    pub original_message_id: Option<String>,
+
+    #[serde(rename = "error")]
+    pub error: Option<Vec<String>>,
+
+    #[serde(rename = "extensionFields")]
+    pub extension_fields: Option<VecMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Disposition {
+    #[serde(rename = "actionMode")]
+    pub action_mode: String,
+
+    #[serde(rename = "sendingMode")]
+    pub sending_mode: String,
+
+    #[serde(rename = "type")]
+    pub type_: String,
+}
@@ -76,6 +76,9 @@ where
         } else {
             None
         };
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Mail)?
+            .unwrap_or_default();
 
         // Add Id Property
         if !helper.properties.contains(&Property::Id) {
@@ -90,7 +93,8 @@ where
                 MessageField::ThreadId.into(),
                 Tag::Id(thread_id),
             )? {
-                // Filter out messages that were not shared
+                // Filter out messages that were deleted or not shared
+                doc_ids &= &document_ids;
                 if let Some(shared_messages) = &shared_messages {
                     if let Some(shared_messages) = shared_messages.as_ref() {
                         doc_ids &= shared_messages;
@@ -99,6 +103,11 @@ where
                     }
                 }
 
+                // Threads without any visible messages do not exist
+                if doc_ids.is_empty() {
+                    return Ok(None);
+                }
+
                 Ok(Some(Thread {
                     id,
                     email_ids: self
@@ -24,12 +24,13 @@
 use std::sync::Arc;
 
 use jmap::{
-    orm::serialize::JMAPOrm,
+    orm::{serialize::JMAPOrm, TinyORM},
     principal::schema::{Principal, Property, Type, Value},
     types::jmap::JMAPId,
     SUPERUSER_ID,
 };
 use store::{
+    ahash::AHashSet,
     core::{acl::ACLToken, collection::Collection, error::StoreError, JMAPIdPrefix},
     read::{
         comparator::Comparator,
@@ -50,6 +51,26 @@ pub trait JMAPAccountStore {
     ) -> store::Result<Option<(String, String, Type)>>;
     fn get_account_secret_hash(&self, account_id: AccountId) -> store::Result<Option<String>>;
     fn expand_rcpt(&self, email: String) -> store::Result<Arc<RecipientType>>;
+    fn find_recipient(&self, email: String) -> store::Result<Arc<RecipientType>>;
+    fn expand_members(
+        &self,
+        fields: &TinyORM<Principal>,
+    ) -> store::Result<Option<Vec<(AccountId, String)>>>;
+}
+
+/// Maximum depth of nested groups and lists followed when expanding members.
+pub const MAX_MEMBERS_DEPTH: usize = 10;
+
+/// Splits a plus-addressed e-mail (`user+tag@domain`) into its base
+/// address (`user@domain`) and tag.
+pub fn split_plus_address(email: &str) -> Option<(String, &str)> {
+    let (local_part, domain) = email.rsplit_once('@')?;
+    let (user, tag) = local_part.split_once('+')?;
+    if !user.is_empty() && !tag.is_empty() {
+        Some((format!("{}@{}", user, domain), tag))
+    } else {
+        None
+    }
 }
 
 impl<T> JMAPAccountStore for JMAPStore<T>
@@ -263,6 +284,35 @@ where
     }
 
     fn expand_rcpt(&self, email: String) -> store::Result<Arc<RecipientType>> {
+        let rt = self.find_recipient(email.clone())?;
+        if !matches!(rt.as_ref(), RecipientType::NotFound) {
+            return Ok(rt);
+        }
+
+        // Try delivering user+tag@domain to user@domain
+        if self.config.lmtp_plus_addressing {
+            if let Some((address, _)) = split_plus_address(&email) {
+                let rt = self.find_recipient(address)?;
+                if !matches!(rt.as_ref(), RecipientType::NotFound) {
+                    return Ok(rt);
+                }
+            }
+        }
+
+        // Fall back to the domain's catch-all address, if any
+        if let Some(catch_all) = email
+            .rsplit_once('@')
+            .and_then(|(_, domain)| self.config.lmtp_catch_all.get(domain))
+        {
+            if catch_all != &email {
+                return self.find_recipient(catch_all.clone());
+            }
+        }
+
+        Ok(rt)
+    }
+
+    fn find_recipient(&self, email: String) -> store::Result<Arc<RecipientType>> {
         self.recipients
             .try_get_with::<_, StoreError>(email.clone(), || {
                 Ok(Arc::new(
@@ -272,7 +322,7 @@ where
                             Collection::Principal,
                             Filter::or(vec![
                                 Filter::eq(Property::Email.into(), Query::Index(email.clone())),
-                                Filter::eq(Property::Aliases.into(), Query::Index(email)),
+                                Filter::eq(Property::Aliases.into(), Query::Index(email.clone())),
                             ]),
                             Comparator::None,
                         )?
@@ -280,31 +330,24 @@ where
                         .next()
                         .map(|id| id.get_document_id())
                     {
-                        if let Some(mut fields) =
-                            self.get_orm::<Principal>(SUPERUSER_ID, account_id)?
-                        {
+                        if let Some(fields) = self.get_orm::<Principal>(SUPERUSER_ID, account_id)? {
                             match fields.get(&Property::Type) {
                                 Some(Value::Type { value: Type::List }) => {
-                                    if let Some(Value::Members { value }) =
-                                        fields.remove(&Property::Members)
-                                    {
-                                        if !value.is_empty() {
-                                            let mut list = Vec::with_capacity(value.len());
-                                            for id in value {
-                                                let account_id = id.get_document_id();
-                                                match self.get_account_details(account_id)? {
-                                                    Some((email, _, ptype))
-                                                        if ptype == Type::Individual =>
-                                                    {
-                                                        list.push((account_id, email));
-                                                    }
-                                                    _ => (),
-                                                }
-                                            }
-                                            return Ok(Arc::new(RecipientType::List(list)));
-                                        }
+                                    if let Some(list) = self.expand_members(&fields)? {
+                                        RecipientType::List(list)
+                                    } else {
+                                        RecipientType::NotFound
+                                    }
+                                }
+                                // Groups receive a single shared copy in the group's account,
+                                // unless they are configured for per-member delivery.
+                                Some(Value::Type { value: Type::Group })
+                                    if self.is_group_per_member(&email) =>
+                                {
+                                    match self.expand_members(&fields)? {
+                                        Some(list) if !list.is_empty() => RecipientType::List(list),
+                                        _ => RecipientType::Individual(account_id),
                                     }
-                                    RecipientType::NotFound
                                 }
                                 _ => RecipientType::Individual(account_id),
                             }
@@ -322,4 +365,78 @@ where
             })
             .map_err(|e| e.as_ref().clone())
     }
+
+    fn expand_members(
+        &self,
+        fields: &TinyORM<Principal>,
+    ) -> store::Result<Option<Vec<(AccountId, String)>>> {
+        if let Some(Value::Members { value }) = fields.get(&Property::Members) {
+            if !value.is_empty() {
+                // Nested groups and lists are flattened, members are only listed once
+                let mut list = Vec::with_capacity(value.len());
+                self.expand_members_recursive(value, 1, &mut AHashSet::new(), &mut list)?;
+                return Ok(Some(list));
+            }
+        }
+        Ok(None)
+    }
+}
+
+trait ExpandMembers {
+    fn expand_members_recursive(
+        &self,
+        members: &[JMAPId],
+        depth: usize,
+        seen: &mut AHashSet<AccountId>,
+        list: &mut Vec<(AccountId, String)>,
+    ) -> store::Result<()>;
+    fn is_group_per_member(&self, email: &str) -> bool;
+}
+
+impl<T> ExpandMembers for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn expand_members_recursive(
+        &self,
+        members: &[JMAPId],
+        depth: usize,
+        seen: &mut AHashSet<AccountId>,
+        list: &mut Vec<(AccountId, String)>,
+    ) -> store::Result<()> {
+        for id in members {
+            let account_id = id.get_document_id();
+            if !seen.insert(account_id) {
+                continue;
+            }
+            if let Some(fields) = self.get_orm::<Principal>(SUPERUSER_ID, account_id)? {
+                match fields.get(&Property::Type) {
+                    Some(Value::Type {
+                        value: Type::Individual,
+                    }) => {
+                        if let Some(Value::Text { value }) = fields.get(&Property::Email) {
+                            list.push((account_id, value.to_string()));
+                        }
+                    }
+                    Some(Value::Type {
+                        value: Type::Group | Type::List,
+                    }) if depth < MAX_MEMBERS_DEPTH => {
+                        if let Some(Value::Members { value }) = fields.get(&Property::Members) {
+                            self.expand_members_recursive(value, depth + 1, seen, list)?;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_group_per_member(&self, email: &str) -> bool {
+        let domain = email.rsplit_once('@').map(|(_, domain)| domain);
+        self.config
+            .lmtp_group_per_member
+            .iter()
+            .any(|entry| entry == email || Some(entry.as_str()) == domain)
+    }
 }
@@ -23,9 +23,10 @@
 
 use jmap::jmap_store::get::{default_mapper, GetHelper, SharedDocsFnc};
 use jmap::orm::serialize::JMAPOrm;
-use jmap::principal::schema::{Principal, Property, Value};
+use jmap::principal::schema::{Principal, Property, Type, Value};
 use jmap::principal::store::JMAPPrincipals;
 use jmap::request::get::{GetRequest, GetResponse};
+use jmap::types::jmap::JMAPId;
 use jmap::SUPERUSER_ID;
 use jmap_mail::mail_send::dkim::DKIM;
 use store::core::collection::Collection;
@@ -39,6 +40,8 @@ use store::read::FilterMapper;
 use store::JMAPStore;
 use store::Store;
 
+use super::account::JMAPAccountStore;
+
 pub trait JMAPGetPrincipal<T>
 where
     T: for<'x> Store<'x> + 'static,
@@ -66,6 +69,26 @@ where
                 .ok_or_else(|| StoreError::NotFound("Principal data not found".to_string()))?;
             let mut principal = VecMap::with_capacity(properties.len());
 
+            // Groups and lists include their members, with nested groups flattened
+            let expanded_members = if properties.contains(&Property::ExpandedMembers)
+                && matches!(
+                    fields.get(&Property::Type),
+                    Some(Value::Type {
+                        value: Type::Group | Type::List
+                    })
+                ) {
+                Value::Members {
+                    value: self
+                        .expand_members(&fields)?
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(account_id, _)| JMAPId::from(account_id))
+                        .collect(),
+                }
+            } else {
+                Value::Null
+            };
+
             for property in properties {
                 principal.append(
                     *property,
@@ -81,6 +104,7 @@ where
                             Value::ACL(acl_get)
                         }
 
+                        Property::ExpandedMembers => expanded_members.clone(),
                         Property::Secret => Value::Null,
                         _ => fields.remove(property).unwrap_or_default(),
                     },
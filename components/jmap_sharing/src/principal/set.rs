@@ -46,7 +46,7 @@ use store::read::filter::{self, Filter, Query};
 use store::read::FilterMapper;
 use store::write::batch::WriteBatch;
 use store::write::options::IndexOptions;
-use store::{rand, DocumentId, JMAPStore, Store};
+use store::{rand, AccountId, DocumentId, JMAPStore, Store};
 
 pub trait JMAPSetPrincipal<T>
 where
@@ -154,7 +154,12 @@ where
                     }
                 }
             }
-            if let (Some(Value::Type { value: Type::List }), Some(Value::Text { value: email })) = (
+            if let (
+                Some(Value::Type {
+                    value: Type::List | Type::Group,
+                }),
+                Some(Value::Text { value: email }),
+            ) = (
                 current_fields.get(&Property::Type),
                 current_fields.get(&Property::Email),
             ) {
@@ -403,6 +408,12 @@ where
                     value
                 }
 
+                (Property::Locale, value @ (Value::Text { .. } | Value::Null))
+                    if ![Type::Domain, Type::List].contains(&ptype) =>
+                {
+                    value
+                }
+
                 (Property::Capabilities, value @ (Value::TextList { .. } | Value::Null))
                     if ![Type::Domain, Type::List].contains(&ptype) =>
                 {
@@ -467,6 +478,12 @@ where
                 (Property::Secret, Value::Text { value })
                     if !value.is_empty() && ptype == Type::Individual =>
                 {
+                    if let Some(reason) = validate_password(&helper.store.config, &value) {
+                        return Err(SetError::invalid_properties()
+                            .with_property(property)
+                            .with_description(reason));
+                    }
+
                     Value::Text {
                         value: argon2::hash_encoded(
                             value.as_bytes(),
@@ -841,3 +858,54 @@ where
         Ok(self)
     }
 }
+
+fn validate_password(config: &JMAPConfig, password: &str) -> Option<String> {
+    if password.chars().count() < config.password_min_length {
+        return format!(
+            "Password must be at least {} characters long.",
+            config.password_min_length
+        )
+        .into();
+    }
+
+    let mut classes = [false; 4];
+    for ch in password.chars() {
+        if ch.is_lowercase() {
+            classes[0] = true;
+        } else if ch.is_uppercase() {
+            classes[1] = true;
+        } else if ch.is_numeric() {
+            classes[2] = true;
+        } else {
+            classes[3] = true;
+        }
+    }
+    if classes.iter().filter(|c| **c).count() < config.password_min_classes {
+        return format!(
+            concat!(
+                "Password must contain at least {} of the following: ",
+                "lowercase letters, uppercase letters, digits and symbols."
+            ),
+            config.password_min_classes
+        )
+        .into();
+    }
+
+    None
+}
+
+/// Returns true when the request only changes the password of the
+/// principal making the request.
+pub fn is_own_secret_update(request: &SetRequest<Principal>, account_id: AccountId) -> bool {
+    request.account_id.get_document_id() == SUPERUSER_ID
+        && request.create.is_none()
+        && request.destroy.is_none()
+        && request.update.as_ref().map_or(false, |update| {
+            update.len() == 1
+                && update.iter().all(|(id, principal)| {
+                    id.get_document_id() == account_id
+                        && !principal.properties.is_empty()
+                        && principal.properties.keys().all(|p| p == &Property::Secret)
+                })
+        })
+}
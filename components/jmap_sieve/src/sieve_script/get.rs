@@ -111,11 +111,23 @@ where
                 .ok_or_else(|| StoreError::NotFound("SieveScript data not found".to_string()))?;
             let mut sieve_script = VecMap::with_capacity(properties.len());
 
+            // Fetch the script source before its blobId is moved out of the ORM
+            let script = match fields.get(&Property::BlobId) {
+                Some(Value::BlobId { value }) if properties.contains(&Property::Script) => self
+                    .blob_get(&value.id)?
+                    .and_then(|bytes| String::from_utf8(bytes).ok()),
+                _ => None,
+            };
+
             for property in properties {
                 sieve_script.append(
                     *property,
                     if let Property::Id = property {
                         Value::Id { value: id }
+                    } else if let Property::Script = property {
+                        script
+                            .clone()
+                            .map_or(Value::Null, |value| Value::Text { value })
                     } else if let Some(value) = fields.remove(property) {
                         value
                     } else {
@@ -46,6 +46,8 @@ pub enum Property {
     IsActive = 3,
     CompiledScript = 4,
     SeenIds = 5,
+    Script = 6,
+    LastError = 7,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -73,6 +75,8 @@ impl Property {
             "blobId" => Property::BlobId,
             "isActive" => Property::IsActive,
             "seenIds" => Property::SeenIds,
+            "script" => Property::Script,
+            "lastError" => Property::LastError,
             _ => Property::CompiledScript,
         }
     }
@@ -87,6 +91,8 @@ impl Display for Property {
             Property::IsActive => write!(f, "isActive"),
             Property::CompiledScript => write!(f, "compiledScript"),
             Property::SeenIds => write!(f, "seenIds"),
+            Property::Script => write!(f, "script"),
+            Property::LastError => write!(f, "lastError"),
         }
     }
 }
@@ -105,7 +111,9 @@ impl From<FieldId> for Property {
             2 => Property::BlobId,
             3 => Property::IsActive,
             4 => Property::CompiledScript,
-            _ => Property::SeenIds,
+            5 => Property::SeenIds,
+            7 => Property::LastError,
+            _ => Property::Script,
         }
     }
 }
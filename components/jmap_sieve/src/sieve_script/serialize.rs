@@ -137,6 +137,16 @@ impl<'de> serde::de::Visitor<'de> for SieveScriptVisitor {
                         },
                     );
                 }
+                "script" => {
+                    properties.append(
+                        Property::Script,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "isActive" => {
                     properties.append(
                         Property::IsActive,
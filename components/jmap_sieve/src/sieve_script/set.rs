@@ -31,6 +31,7 @@ use jmap::request::{ACLEnforce, MaybeIdReference, ResultReference};
 use jmap::types::jmap::JMAPId;
 use jmap::SUPERUSER_ID;
 use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
+use store::blob::BlobId;
 use store::core::collection::Collection;
 use store::core::document::Document;
 use store::core::error::StoreError;
@@ -384,8 +385,9 @@ where
             ));
         }
 
+        let mut is_uploaded = false;
         for (property, value) in sieve_script.properties {
-            let value = match (property, value) {
+            let (property, value) = match (property, value) {
                 (Property::Name, Value::Text { value }) => {
                     if value.len() > helper.store.config.sieve_max_script_name {
                         return Err(SetError::invalid_properties()
@@ -423,9 +425,22 @@ where
                         }
                     }
 
-                    Value::Text { value }
+                    (property, Value::Text { value })
+                }
+                (Property::BlobId, value @ Value::BlobId { .. }) => (property, value),
+                (Property::Script, Value::Text { value }) => {
+                    // Store the script source as a blob owned by this script
+                    let blob_id = BlobId::new_external(value.as_bytes());
+                    helper.store.blob_store(&blob_id, value.into_bytes())?;
+                    is_uploaded = true;
+
+                    (
+                        Property::BlobId,
+                        Value::BlobId {
+                            value: blob_id.into(),
+                        },
+                    )
                 }
-                (Property::BlobId, value @ Value::BlobId { .. }) => value,
                 (Property::Name, Value::Null) => {
                     continue;
                 }
@@ -444,7 +459,7 @@ where
             let mut add_blob = true;
             if let Some(Value::BlobId { value: prev_value }) = fields
                 .as_ref()
-                .and_then(|fields| fields.get(&Property::Name))
+                .and_then(|fields| fields.get(&Property::BlobId))
             {
                 if value.id != prev_value.id {
                     document.blob(prev_value.id.clone(), IndexOptions::new().clear());
@@ -459,9 +474,10 @@ where
                     .blob_get(&value.id)?
                     .ok_or_else(|| SetError::new(SetErrorType::BlobNotFound))?;
 
-                if !helper
-                    .store
-                    .blob_account_has_access(&value.id, &helper.acl.member_of)?
+                if !is_uploaded
+                    && !helper
+                        .store
+                        .blob_account_has_access(&value.id, &helper.acl.member_of)?
                     && !helper.acl.is_member(SUPERUSER_ID)
                 {
                     return Err(SetError::forbidden()
@@ -498,6 +514,14 @@ where
                         },
                     },
                 );
+
+                // Errors recorded while running the previous script no longer apply
+                if fields
+                    .as_ref()
+                    .map_or(false, |fields| fields.get(&Property::LastError).is_some())
+                {
+                    self.set(Property::LastError, Value::Null);
+                }
             }
         } else if fields.is_none() {
             return Err(SetError::invalid_properties()
@@ -0,0 +1,61 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, SystemTime};
+
+use tracing::error;
+
+use crate::serialize::StoreDeserialize;
+use crate::{ColumnFamily, Direction, JMAPStore, Store};
+
+use super::{BlobId, BLOB_EXTERNAL, BLOB_HASH_LEN};
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn archive_blobs(&self) -> crate::Result<()> {
+        if self.blob_store.cold.is_none() {
+            return Ok(());
+        }
+        let cutoff = SystemTime::now() - Duration::from_secs(self.blob_store.cold_after);
+
+        for (key, _) in self
+            .db
+            .iterator(ColumnFamily::Blobs, &[], Direction::Forward)?
+        {
+            // Only external blobs are archived, links and local blobs stay in the database
+            if key.len() != BLOB_HASH_LEN + 1 || key[0] != BLOB_EXTERNAL {
+                continue;
+            }
+
+            let _blob_lock = self.blob_store.lock.lock_hash(&key[..]);
+            let blob_id = BlobId::deserialize(&key).unwrap();
+            if let Err(err) = self.blob_store.archive(&blob_id, cutoff) {
+                error!("Failed to archive blob {}: {:?}", blob_id, err);
+            }
+        }
+
+        Ok(())
+    }
+}
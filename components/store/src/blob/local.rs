@@ -28,12 +28,11 @@ use std::{
     path::PathBuf,
 };
 
-use crate::{config::env_settings::EnvSettings, write::mutex_map::MutexMap};
+use crate::config::env_settings::EnvSettings;
 
 use super::{BlobId, BlobStore};
 
 pub struct LocalBlobStore {
-    pub lock: MutexMap<()>,
     pub base_path: PathBuf,
     pub hash_levels: usize,
 }
@@ -46,11 +45,10 @@ impl BlobStore for LocalBlobStore {
                 .unwrap_or_else(|| "/usr/local/stalwart-jmap/data".to_string()),
         );
         base_path.push("blobs");
-        Ok(LocalBlobStore {
-            lock: MutexMap::with_capacity(1024),
+        Ok(LocalBlobStore::open(
             base_path,
-            hash_levels: std::cmp::min(settings.parse("blob-nested-levels").unwrap_or(2), 5),
-        })
+            settings.parse("blob-nested-levels").unwrap_or(2),
+        ))
     }
 
     fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool> {
@@ -112,7 +110,14 @@ impl BlobStore for LocalBlobStore {
 }
 
 impl LocalBlobStore {
-    fn get_path(&self, blob_id: &BlobId) -> crate::Result<PathBuf> {
+    pub fn open(base_path: PathBuf, hash_levels: usize) -> Self {
+        LocalBlobStore {
+            base_path,
+            hash_levels: std::cmp::min(hash_levels, 5),
+        }
+    }
+
+    pub fn get_path(&self, blob_id: &BlobId) -> crate::Result<PathBuf> {
         let mut path = self.base_path.clone();
         let hash = blob_id.hash();
         for byte in hash.iter().take(self.hash_levels) {
@@ -30,9 +30,11 @@ use crate::{
     serialize::{base32::Base32Writer, StoreDeserialize, StoreSerialize},
 };
 
+pub mod archive;
 pub mod local;
 pub mod purge;
 pub mod store;
+pub mod tiered;
 
 pub const BLOB_HASH_LEN: usize = 32;
 pub const BLOB_LOCAL: u8 = 0;
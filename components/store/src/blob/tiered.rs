@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, ops::Range, path::PathBuf, time::SystemTime};
+
+use crate::{config::env_settings::EnvSettings, write::mutex_map::MutexMap};
+
+use super::{local::LocalBlobStore, BlobId, BlobStore};
+
+pub struct TieredBlobStore {
+    pub lock: MutexMap<()>,
+    pub hot: LocalBlobStore,
+    pub cold: Option<LocalBlobStore>,
+    pub cold_after: u64,
+}
+
+impl BlobStore for TieredBlobStore {
+    fn new(settings: &EnvSettings) -> crate::Result<Self> {
+        let hot = LocalBlobStore::new(settings)?;
+        let cold = settings
+            .get("blob-cold-path")
+            .map(|path| LocalBlobStore::open(PathBuf::from(path), hot.hash_levels));
+
+        Ok(TieredBlobStore {
+            lock: MutexMap::with_capacity(1024),
+            hot,
+            cold,
+            cold_after: settings.parse::<u64>("blob-cold-after").unwrap_or(90) * 86400,
+        })
+    }
+
+    fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool> {
+        self.hot.put(blob_id, blob)
+    }
+
+    fn get_range(&self, blob_id: &BlobId, range: Range<u32>) -> crate::Result<Option<Vec<u8>>> {
+        match self.hot.get_range(blob_id, range.clone())? {
+            Some(blob) => Ok(Some(blob)),
+            None => {
+                // Blob might have been moved to cold storage
+                if let Some(cold) = &self.cold {
+                    cold.get_range(blob_id, range)
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    fn delete(&self, blob_id: &BlobId) -> crate::Result<bool> {
+        let mut deleted = self.hot.delete(blob_id)?;
+        if let Some(cold) = &self.cold {
+            deleted = cold.delete(blob_id)? || deleted;
+        }
+        Ok(deleted)
+    }
+}
+
+impl TieredBlobStore {
+    // Moves a blob to cold storage if its hot copy was last modified before `cutoff`.
+    // The cold copy is written before the hot one is removed, so readers always find it.
+    pub fn archive(&self, blob_id: &BlobId, cutoff: SystemTime) -> crate::Result<bool> {
+        let cold = if let Some(cold) = &self.cold {
+            cold
+        } else {
+            return Ok(false);
+        };
+        let hot_path = self.hot.get_path(blob_id)?;
+        if !hot_path.exists() || fs::metadata(&hot_path)?.modified()? >= cutoff {
+            return Ok(false);
+        }
+
+        cold.put(blob_id, &fs::read(&hot_path)?)?;
+        fs::remove_file(&hot_path)?;
+        Ok(true)
+    }
+}
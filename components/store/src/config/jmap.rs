@@ -21,9 +21,11 @@
  * for more details.
 */
 
-use crate::nlp::Language;
+use ahash::AHashMap;
 
-use super::env_settings::EnvSettings;
+use crate::{core::collection::Collection, nlp::Language, ColumnFamily};
+
+use super::{env_settings::EnvSettings, scan::VirusAction};
 
 pub struct JMAPConfig {
     pub blob_temp_ttl: u64,
@@ -31,69 +33,408 @@ pub struct JMAPConfig {
 
     pub max_size_upload: usize,
     pub max_concurrent_uploads: usize,
+    pub max_download_rate: u64,
     pub max_size_request: usize,
     pub max_concurrent_requests: usize,
+    pub max_concurrent_requests_wait: u64,
     pub max_calls_in_request: usize,
     pub max_objects_in_get: usize,
     pub max_objects_in_set: usize,
+    pub slow_method_threshold: u64,
 
     pub rate_limit_authenticated: (u64, u64),
     pub rate_limit_anonymous: (u64, u64),
     pub rate_limit_auth: (u64, u64),
     pub use_forwarded_header: bool,
 
+    pub password_min_length: usize,
+    pub password_min_classes: usize,
+
     pub query_max_results: usize,
+    pub query_max_total: usize,
     pub changes_max_results: usize,
     pub mailbox_name_max_len: usize,
     pub mailbox_max_total: usize,
     pub mailbox_max_depth: usize,
+    pub mailbox_path_separator: char,
     pub mail_max_size: usize,
+    pub mail_max_messages: usize,
+    pub mail_max_references: usize,
     pub mail_attachments_max_size: usize,
+    pub mail_build_max_size: usize,
+    pub mail_decompress_max_size: usize,
+    pub mail_decompress_max_ratio: usize,
     pub mail_import_max_items: usize,
+    pub mail_import_partial: bool,
     pub mail_parse_max_items: usize,
+    pub mail_draft_revisions: usize,
+    pub mail_sort_missing_date_epoch: bool,
+    pub mail_default_sort: Option<bool>,
+    pub mail_max_thread_size: usize,
+    pub mail_message_id_domain: Option<String>,
+    pub mail_text_encoding: Option<String>,
+    pub mail_validate_from: bool,
+    pub mail_pgp_keys_path: Option<String>,
+    pub mail_pgp_timeout: u64,
+    pub mail_inline_images_min_size: usize,
+    pub mail_preview_length: usize,
+    pub mail_max_future_date: i64,
+
+    pub submission_max_messages: usize,
+    pub submission_max_recipients: usize,
+    pub submission_rate_window: u64,
+    pub submission_max_delay: u64,
 
     pub sieve_max_scripts: usize,
     pub sieve_max_script_name: usize,
+    pub sieve_limits: SieveLimits,
+    pub sieve_account_limits: AHashMap<String, SieveLimits>,
+    pub sieve_discard_folder: Option<String>,
+
+    pub lmtp_plus_addressing: bool,
+    pub lmtp_plus_addressing_fileinto: bool,
+    pub lmtp_catch_all: AHashMap<String, String>,
+    pub lmtp_reject_duplicate_rcpt: bool,
+    pub lmtp_header_keywords: Vec<HeaderKeyword>,
+    pub lmtp_large_message_size: usize,
+    pub lmtp_large_message_folder: Option<String>,
+    pub lmtp_large_message_keyword: Option<String>,
+    pub lmtp_postmaster: Option<String>,
+    pub lmtp_group_per_member: Vec<String>,
+    pub lmtp_authserv_id: Option<String>,
+    pub lmtp_rcpt_callout_url: Option<String>,
+    pub lmtp_rcpt_callout_domains: Vec<String>,
+    pub lmtp_rcpt_callout_timeout: u64,
+    pub lmtp_rcpt_callout_ttl: u64,
+    pub lmtp_rcpt_callout_ttl_negative: u64,
+    pub lmtp_virus_action: VirusAction,
+    pub lmtp_virus_quarantine_account: Option<String>,
+    pub lmtp_virus_quarantine_folder: String,
+    pub srs_secret: Option<String>,
+    pub srs_domain: Option<String>,
 
     pub push_max_total: usize,
+    pub push_expires_max: u64,
     pub ws_heartbeat_interval: u64,
     pub ws_client_timeout: u64,
     pub ws_throttle: u64,
     pub event_source_throttle: u64,
 
     pub raft_commit_timeout: u64,
+    pub raft_follower_reads: bool,
+
+    pub compact_db_families: Vec<ColumnFamily>,
+    pub changelog_retention: Vec<(Collection, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SieveLimits {
+    pub max_redirects: usize,
+    pub max_fileinto: usize,
+    pub max_actions: usize,
+    pub max_time: u64,
+}
+
+pub struct HeaderKeyword {
+    pub scope: Option<String>,
+    pub header: String,
+    pub value: String,
+    pub keyword: String,
+}
+
+impl HeaderKeyword {
+    pub fn parse(rule: &str) -> Option<Self> {
+        let (scope, rule) = match rule.split_once('/') {
+            Some((scope, rule)) if !scope.contains(':') => {
+                (Some(scope.trim().to_lowercase()), rule)
+            }
+            _ => (None, rule),
+        };
+        let (header, rule) = rule.split_once(':')?;
+        let (value, keyword) = rule.rsplit_once(':')?;
+        let (header, value, keyword) = (header.trim(), value.trim(), keyword.trim());
+
+        if !header.is_empty() && !value.is_empty() && !keyword.is_empty() {
+            HeaderKeyword {
+                scope,
+                header: header.to_string(),
+                value: value.to_string(),
+                keyword: keyword.to_string(),
+            }
+            .into()
+        } else {
+            None
+        }
+    }
+
+    pub fn matches(&self, header: &str, value: &str) -> bool {
+        self.header.eq_ignore_ascii_case(header)
+            && (self.value == "*" || self.value.eq_ignore_ascii_case(value))
+    }
+
+    pub fn applies_to(&self, address: Option<&str>) -> bool {
+        match (&self.scope, address) {
+            (Some(scope), Some(address)) => {
+                let address = address.to_lowercase();
+                if scope.contains('@') {
+                    &address == scope
+                } else {
+                    address
+                        .rsplit_once('@')
+                        .map_or(false, |(_, domain)| domain == scope)
+                }
+            }
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+impl SieveLimits {
+    // Overrides are written as "redirects=N fileinto=N actions=N time=N"
+    pub fn with_overrides(&self, overrides: &str) -> Option<Self> {
+        let mut limits = self.clone();
+        for rule in overrides.split_ascii_whitespace() {
+            let (name, value) = rule.split_once('=')?;
+            let value = value.parse::<u64>().ok()?;
+            match name {
+                "redirects" => limits.max_redirects = value as usize,
+                "fileinto" => limits.max_fileinto = value as usize,
+                "actions" => limits.max_actions = value as usize,
+                "time" => limits.max_time = value,
+                _ => return None,
+            }
+        }
+        Some(limits)
+    }
+}
+
+impl JMAPConfig {
+    pub fn sieve_limits_for(&self, address: &str) -> &SieveLimits {
+        let address = address.to_lowercase();
+        self.sieve_account_limits
+            .get(&address)
+            .or_else(|| {
+                address
+                    .rsplit_once('@')
+                    .and_then(|(_, domain)| self.sieve_account_limits.get(domain))
+            })
+            .unwrap_or(&self.sieve_limits)
+    }
+
+    pub fn is_large_message(&self, size: usize) -> bool {
+        self.lmtp_large_message_size > 0 && size >= self.lmtp_large_message_size
+    }
 }
 
 impl From<&EnvSettings> for JMAPConfig {
     fn from(settings: &EnvSettings) -> Self {
+        let sieve_limits = SieveLimits {
+            max_redirects: settings.parse("sieve-max-redirects").unwrap_or(1),
+            max_fileinto: settings.parse("sieve-max-fileinto").unwrap_or(32),
+            max_actions: settings.parse("sieve-max-actions").unwrap_or(64),
+            max_time: settings.parse("sieve-time-limit").unwrap_or(1000),
+        };
+
         JMAPConfig {
             max_size_upload: settings.parse("max-size-upload").unwrap_or(50000000),
             max_concurrent_uploads: settings.parse("max-concurrent-uploads").unwrap_or(4),
+            max_download_rate: settings.parse("max-download-rate").unwrap_or(0),
             max_concurrent_requests: settings.parse("max-concurrent-requests").unwrap_or(4),
+            max_concurrent_requests_wait: settings
+                .parse("max-concurrent-requests-wait")
+                .unwrap_or(0),
             max_size_request: settings.parse("max-size-request").unwrap_or(10000000),
             max_calls_in_request: settings.parse("max-calls-in-request").unwrap_or(16),
             max_objects_in_get: settings.parse("max-objects-in-get").unwrap_or(500),
             max_objects_in_set: settings.parse("max-objects-in-set").unwrap_or(500),
+            slow_method_threshold: settings.parse("slow-method-threshold").unwrap_or(1000),
             blob_temp_ttl: settings.parse("blob-temp-ttl").unwrap_or(3600),
             changes_max_results: settings.parse("changes-max-results").unwrap_or(5000),
             query_max_results: settings.parse("query-max-results").unwrap_or(5000),
+            query_max_total: settings.parse("query-max-total").unwrap_or(0),
             mailbox_name_max_len: settings.parse("mailbox-name-max-len").unwrap_or(255),
             mailbox_max_total: settings.parse("mailbox-max-total").unwrap_or(1000),
             mailbox_max_depth: settings.parse("mailbox-max-depth").unwrap_or(10),
+            mailbox_path_separator: settings
+                .parse("mailbox-path-separator")
+                .filter(|ch| *ch != '\\')
+                .unwrap_or('/'),
             mail_attachments_max_size: settings
                 .parse("mail-attachments-max-size")
                 .unwrap_or(50000000),
+            mail_decompress_max_size: settings
+                .parse("mail-decompress-max-size")
+                .unwrap_or(10485760),
+            mail_decompress_max_ratio: settings.parse("mail-decompress-max-ratio").unwrap_or(100),
             mail_max_size: settings.parse("mail-max-size").unwrap_or(104857600),
+            mail_build_max_size: settings.parse("mail-build-max-size").unwrap_or(104857600),
+            mail_max_messages: settings.parse("mail-max-messages").unwrap_or(0),
+            mail_max_references: settings.parse("mail-max-references").unwrap_or(20),
             mail_import_max_items: settings.parse("mail-import-max-items").unwrap_or(5),
+            mail_import_partial: settings.parse("mail-import-partial").unwrap_or(false),
             mail_parse_max_items: settings.parse("mail-parse-max-items").unwrap_or(5),
+            mail_draft_revisions: settings.parse("mail-draft-revisions").unwrap_or(0),
+            mail_sort_missing_date_epoch: settings
+                .get("mail-sort-missing-date")
+                .map_or(false, |v| v.eq_ignore_ascii_case("epoch")),
+            mail_default_sort: match settings.get("mail-default-sort") {
+                Some(v) if v.eq_ignore_ascii_case("newest") => Some(false),
+                Some(v) if v.eq_ignore_ascii_case("oldest") => Some(true),
+                _ => None,
+            },
+            mail_max_thread_size: settings.parse("mail-max-thread-size").unwrap_or(0),
+            mail_message_id_domain: settings
+                .get("mail-message-id-domain")
+                .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("sender")),
+            mail_text_encoding: settings
+                .get("mail-text-encoding")
+                .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("auto")),
+            mail_validate_from: settings.parse("mail-validate-from").unwrap_or(false),
+            mail_pgp_keys_path: settings.get("mail-pgp-keys-path").filter(|v| !v.is_empty()),
+            mail_pgp_timeout: settings.parse("mail-pgp-timeout").unwrap_or(10000),
+            mail_inline_images_min_size: settings.parse("mail-inline-images-min-size").unwrap_or(0),
+            mail_preview_length: settings.parse("mail-preview-length").unwrap_or(256),
+            mail_max_future_date: settings.parse("mail-max-future-date").unwrap_or(86400),
+            submission_max_messages: settings.parse("submission-max-messages").unwrap_or(0),
+            submission_max_recipients: settings.parse("submission-max-recipients").unwrap_or(0),
+            submission_rate_window: settings.parse("submission-rate-window").unwrap_or(3600),
+            submission_max_delay: settings.parse("submission-max-delay").unwrap_or(30 * 86400),
             sieve_max_script_name: settings.parse("sieve-max-script-name").unwrap_or(512),
             sieve_max_scripts: settings.parse("sieve-max-scripts").unwrap_or(256),
+            sieve_account_limits: settings
+                .parse_list("sieve-account-limits")
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|entry| {
+                    let (address, overrides) = entry.split_once(':')?;
+                    Some((
+                        address.trim().to_lowercase(),
+                        sieve_limits.with_overrides(overrides)?,
+                    ))
+                })
+                .collect(),
+            sieve_limits,
+            sieve_discard_folder: settings
+                .get("sieve-discard-folder")
+                .filter(|v| !v.is_empty()),
+            lmtp_plus_addressing: settings.parse("lmtp-plus-addressing").unwrap_or(false),
+            lmtp_plus_addressing_fileinto: settings
+                .parse("lmtp-plus-addressing-fileinto")
+                .unwrap_or(false),
+            lmtp_catch_all: settings
+                .parse_list("lmtp-catch-all")
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|entry| {
+                    let (domain, address) = entry.split_once(':')?;
+                    Some((domain.trim().to_lowercase(), address.trim().to_lowercase()))
+                })
+                .collect(),
+            lmtp_reject_duplicate_rcpt: settings
+                .get("lmtp-duplicate-rcpt")
+                .map_or(false, |v| v.eq_ignore_ascii_case("reject")),
+            lmtp_header_keywords: settings
+                .parse_list("lmtp-header-keywords")
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|rule| HeaderKeyword::parse(rule))
+                .collect(),
+            lmtp_large_message_size: settings.parse("lmtp-large-message-size").unwrap_or(0),
+            lmtp_large_message_folder: settings
+                .get("lmtp-large-message-folder")
+                .filter(|v| !v.is_empty()),
+            lmtp_large_message_keyword: settings
+                .get("lmtp-large-message-keyword")
+                .filter(|v| !v.is_empty()),
+            lmtp_postmaster: settings.get("lmtp-postmaster").filter(|v| !v.is_empty()),
+            lmtp_authserv_id: settings.get("lmtp-authserv-id").filter(|v| !v.is_empty()),
+            lmtp_rcpt_callout_url: settings
+                .get("lmtp-rcpt-callout-url")
+                .filter(|v| !v.is_empty()),
+            lmtp_rcpt_callout_domains: settings
+                .parse_list("lmtp-rcpt-callout-domains")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|domain| domain.trim().to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect(),
+            lmtp_rcpt_callout_timeout: settings.parse("lmtp-rcpt-callout-timeout").unwrap_or(5000),
+            lmtp_rcpt_callout_ttl: settings.parse("lmtp-rcpt-callout-ttl").unwrap_or(3600),
+            lmtp_rcpt_callout_ttl_negative: settings
+                .parse("lmtp-rcpt-callout-ttl-negative")
+                .unwrap_or(300),
+            lmtp_virus_action: match settings.get("lmtp-virus-action") {
+                Some(v) if v.eq_ignore_ascii_case("quarantine") => VirusAction::Quarantine,
+                Some(v) if v.eq_ignore_ascii_case("strip") => VirusAction::Strip,
+                _ => VirusAction::Reject,
+            },
+            lmtp_virus_quarantine_account: settings
+                .get("lmtp-virus-quarantine-account")
+                .filter(|v| !v.is_empty()),
+            lmtp_virus_quarantine_folder: settings
+                .get("lmtp-virus-quarantine-folder")
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| "Quarantine".to_string()),
+            lmtp_group_per_member: settings
+                .parse_list("lmtp-group-per-member")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|entry| entry.trim().to_lowercase())
+                .filter(|entry| !entry.is_empty())
+                .collect(),
+            srs_secret: settings.get("srs-secret").filter(|v| !v.is_empty()),
+            srs_domain: settings.get("srs-domain").filter(|v| !v.is_empty()),
             push_max_total: settings.parse("push-max-total").unwrap_or(100),
+            push_expires_max: settings.parse("push-expires-max").unwrap_or(7 * 24 * 3600),
             ws_client_timeout: settings.parse("ws-client-timeout").unwrap_or(10 * 1000),
             ws_heartbeat_interval: settings.parse("ws-heartbeat-interval").unwrap_or(5 * 1000),
             ws_throttle: settings.parse("ws-throttle").unwrap_or(1000),
             event_source_throttle: settings.parse("event-source-throttle").unwrap_or(1000),
             raft_commit_timeout: settings.parse("raft-commit-timeout").unwrap_or(1000),
+            raft_follower_reads: settings.parse("raft-follower-reads").unwrap_or(true),
+            compact_db_families: settings
+                .parse_list("compact-db-families")
+                .unwrap_or_else(|| {
+                    vec![
+                        "bitmaps".to_string(),
+                        "values".to_string(),
+                        "indexes".to_string(),
+                        "logs".to_string(),
+                    ]
+                })
+                .iter()
+                .filter_map(|cf| match cf.trim().to_lowercase().as_str() {
+                    "bitmaps" => Some(ColumnFamily::Bitmaps),
+                    "values" => Some(ColumnFamily::Values),
+                    "indexes" => Some(ColumnFamily::Indexes),
+                    "blobs" => Some(ColumnFamily::Blobs),
+                    "logs" => Some(ColumnFamily::Logs),
+                    _ => None,
+                })
+                .collect(),
+            changelog_retention: settings
+                .parse_list("changelog-retention")
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|retention| {
+                    let (collection, max_changes) = retention.split_once(':')?;
+                    Some((
+                        match collection.trim().to_lowercase().as_str() {
+                            "principal" => Collection::Principal,
+                            "push-subscription" => Collection::PushSubscription,
+                            "email" => Collection::Mail,
+                            "mailbox" => Collection::Mailbox,
+                            "thread" => Collection::Thread,
+                            "identity" => Collection::Identity,
+                            "email-submission" => Collection::EmailSubmission,
+                            "sieve-script" => Collection::SieveScript,
+                            _ => return None,
+                        },
+                        max_changes.trim().parse().ok().filter(|max| *max > 0)?,
+                    ))
+                })
+                .collect(),
             default_language: Language::from_iso_639(
                 &settings
                     .get("default-language")
@@ -131,6 +472,8 @@ impl From<&EnvSettings> for JMAPConfig {
                 })
                 .unwrap_or((100, 60)),
             use_forwarded_header: settings.parse("use-forwarded-header").unwrap_or(false),
+            password_min_length: settings.parse("password-min-length").unwrap_or(8),
+            password_min_classes: settings.parse("password-min-classes").unwrap_or(1),
         }
     }
 }
@@ -23,3 +23,5 @@
 
 pub mod env_settings;
 pub mod jmap;
+pub mod scan;
+pub mod sieve;
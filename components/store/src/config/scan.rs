@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{process::Command, sync::Arc, time::Duration};
+
+use tracing::debug;
+
+use crate::core::process::run_with_timeout;
+
+use super::env_settings::EnvSettings;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected(String),
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirusAction {
+    Reject,
+    Quarantine,
+    Strip,
+}
+
+pub trait VirusScanner: Send + Sync {
+    fn scan(&self, contents: &[u8]) -> ScanVerdict;
+    fn timeout(&self) -> Duration;
+}
+
+/// Scans messages with an external command (such as `clamdscan -`) that reads the
+/// contents from stdin and exits with 0 when clean or 1 when a virus was found.
+/// Scanners that do not finish within the configured timeout are killed.
+pub struct CommandScanner {
+    command: String,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandScanner {
+    pub fn new(settings: &EnvSettings) -> Option<Arc<dyn VirusScanner>> {
+        let command = settings.get("lmtp-virus-scan-command")?;
+        let mut args = command.split_whitespace().map(|arg| arg.to_string());
+        Some(Arc::new(CommandScanner {
+            command: args.next()?,
+            args: args.collect(),
+            timeout: Duration::from_millis(
+                settings.parse("lmtp-virus-scan-timeout").unwrap_or(30000),
+            ),
+        }))
+    }
+}
+
+impl VirusScanner for CommandScanner {
+    fn scan(&self, contents: &[u8]) -> ScanVerdict {
+        match run_with_timeout(
+            Command::new(&self.command).args(&self.args),
+            contents,
+            self.timeout,
+        ) {
+            Ok(output) => match output.status.code() {
+                Some(0) => ScanVerdict::Clean,
+                Some(1) => ScanVerdict::Infected(
+                    String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .map(|line| line.trim())
+                        .filter(|line| !line.is_empty())
+                        .last()
+                        .unwrap_or("unknown")
+                        .to_string(),
+                ),
+                code => {
+                    debug!("Virus scanner {} exited with {:?}.", self.command, code);
+                    ScanVerdict::Failed
+                }
+            },
+            Err(err) => {
+                debug!("Failed to run virus scanner {}: {}", self.command, err);
+                ScanVerdict::Failed
+            }
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fs, path::Path, sync::Arc};
+
+use sieve::{Compiler, Sieve};
+
+use super::env_settings::EnvSettings;
+
+#[derive(Default)]
+pub struct SieveGlobalScripts {
+    pub before: Vec<(String, Arc<Sieve>)>,
+    pub after: Vec<(String, Arc<Sieve>)>,
+}
+
+impl SieveGlobalScripts {
+    pub fn new(compiler: &Compiler, settings: &EnvSettings) -> Result<Self, String> {
+        Ok(SieveGlobalScripts {
+            before: compile_scripts(compiler, settings, "sieve-global-before")?,
+            after: compile_scripts(compiler, settings, "sieve-global-after")?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Sieve>> {
+        self.before
+            .iter()
+            .chain(self.after.iter())
+            .find(|(script_name, _)| script_name == name)
+            .map(|(_, script)| script.clone())
+    }
+}
+
+// Global scripts are named after their file, without the extension.
+fn compile_scripts(
+    compiler: &Compiler,
+    settings: &EnvSettings,
+    key: &str,
+) -> Result<Vec<(String, Arc<Sieve>)>, String> {
+    settings
+        .get(key)
+        .unwrap_or_default()
+        .split(';')
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            let script = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+            let script = compiler
+                .compile(&script)
+                .map_err(|err| format!("{}: {}", path, err))?;
+            let name = Path::new(path)
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .unwrap_or(path)
+                .to_string();
+            Ok((name, Arc::new(script)))
+        })
+        .collect()
+}
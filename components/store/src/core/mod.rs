@@ -31,6 +31,7 @@ pub mod collection;
 pub mod document;
 pub mod error;
 pub mod number;
+pub mod process;
 pub mod tag;
 pub mod vec_map;
 
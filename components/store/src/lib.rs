@@ -33,19 +33,26 @@ pub mod write;
 use crate::core::acl::ACL;
 use crate::core::{acl::ACLToken, collection::Collection, error::StoreError};
 use crate::nlp::Language;
-use blob::local::LocalBlobStore;
+use blob::tiered::TieredBlobStore;
 use blob::BlobStore;
-use config::{env_settings::EnvSettings, jmap::JMAPConfig};
+use config::{
+    env_settings::EnvSettings,
+    jmap::JMAPConfig,
+    scan::{CommandScanner, VirusScanner},
+    sieve::SieveGlobalScripts,
+};
+use log::changes::ChangeId;
 use log::raft::{LogIndex, RaftId};
 use moka::sync::Cache;
 use parking_lot::{Mutex, MutexGuard};
 use roaring::RoaringBitmap;
 use serialize::StoreDeserialize;
 use sieve::{Compiler, Runtime};
+use std::collections::VecDeque;
 use std::sync::atomic::AtomicBool;
 use std::{
     sync::{atomic::AtomicU64, Arc},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use write::{
     id_assign::{IdAssigner, IdCacheKey},
@@ -130,6 +137,30 @@ pub struct SharedResource {
     pub acl: ACL,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CopyIdempotencyKey {
+    pub account_id: AccountId,
+    pub from_account_id: AccountId,
+    pub collection: Collection,
+    pub principal_id: AccountId,
+    pub idempotency_key: String,
+    pub create_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryCacheKey {
+    pub account_id: AccountId,
+    pub collection: Collection,
+    pub change_ids: Vec<ChangeId>,
+    pub query: String,
+}
+
+pub struct QueryCacheEntry {
+    pub ids: Vec<JMAPId>,
+    pub total: usize,
+    pub is_truncated: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum RecipientType {
     Individual(AccountId),
@@ -139,18 +170,26 @@ pub enum RecipientType {
 
 pub struct JMAPStore<T> {
     pub db: T,
-    pub blob_store: LocalBlobStore,
+    pub blob_store: TieredBlobStore,
     pub config: JMAPConfig,
 
     pub account_lock: MutexMap<()>,
 
     pub sieve_compiler: Compiler,
     pub sieve_runtime: Runtime,
+    pub sieve_global: SieveGlobalScripts,
+
+    pub virus_scanner: Option<Arc<dyn VirusScanner>>,
 
     pub id_assigner: Cache<IdCacheKey, Arc<Mutex<IdAssigner>>>,
     pub shared_documents: Cache<SharedResource, Arc<Option<RoaringBitmap>>>,
     pub acl_tokens: Cache<AccountId, Arc<ACLToken>>,
     pub recipients: Cache<String, Arc<RecipientType>>,
+    // Kept in memory only: limits are enforced per node and reset on restart
+    pub submission_rates: Cache<AccountId, Arc<Mutex<VecDeque<(Instant, usize)>>>>,
+    pub copied_ids: Cache<CopyIdempotencyKey, u64>,
+    pub auto_replies: Option<Cache<(String, String), ()>>,
+    pub query_cache: Option<Cache<QueryCacheKey, Arc<QueryCacheEntry>>>,
 
     pub raft_term: AtomicU64,
     pub raft_index: AtomicU64,
@@ -164,7 +203,7 @@ where
     pub fn new(db: T, config: JMAPConfig, settings: &EnvSettings) -> Self {
         let mut store = Self {
             config,
-            blob_store: LocalBlobStore::new(settings).unwrap(),
+            blob_store: TieredBlobStore::new(settings).unwrap(),
             id_assigner: Cache::builder()
                 .initial_capacity(128)
                 .max_capacity(settings.parse("cache-size-ids").unwrap_or(32 * 1024 * 1024))
@@ -190,6 +229,38 @@ where
                     settings.parse("cache-tti-recipients").unwrap_or(86400),
                 ))
                 .build(),
+            submission_rates: Cache::builder()
+                .initial_capacity(128)
+                .time_to_idle(Duration::from_secs(
+                    settings.parse("submission-rate-window").unwrap_or(3600),
+                ))
+                .build(),
+            copied_ids: Cache::builder()
+                .initial_capacity(128)
+                .time_to_live(Duration::from_secs(
+                    settings.parse("copy-idempotency-window").unwrap_or(300),
+                ))
+                .build(),
+            auto_replies: Some(settings.parse("sieve-auto-reply-window").unwrap_or(86400))
+                .filter(|window| *window > 0)
+                .map(|window| {
+                    Cache::builder()
+                        .initial_capacity(128)
+                        .time_to_live(Duration::from_secs(window))
+                        .build()
+                }),
+            query_cache: settings
+                .parse("cache-size-queries")
+                .filter(|size| *size > 0)
+                .map(|size| {
+                    Cache::builder()
+                        .initial_capacity(128)
+                        .max_capacity(size)
+                        .time_to_idle(Duration::from_secs(
+                            settings.parse("cache-tti-queries").unwrap_or(300),
+                        ))
+                        .build()
+                }),
             account_lock: MutexMap::with_capacity(1024),
             raft_index: 0.into(),
             raft_term: 0.into(),
@@ -219,7 +290,8 @@ where
                 .with_max_nested_includes(settings.parse("sieve-max-nested-includes").unwrap_or(3))
                 .with_cpu_limit(settings.parse("sieve-cpu-limit").unwrap_or(5000))
                 .with_max_variable_size(settings.parse("sieve-max-variable-size").unwrap_or(4096))
-                .with_max_redirects(settings.parse("sieve-max-redirects").unwrap_or(1))
+                // Redirects are limited per account while the script runs
+                .with_max_redirects(usize::MAX)
                 .with_max_received_headers(
                     settings.parse("sieve-max-received-headers").unwrap_or(10),
                 )
@@ -284,6 +356,8 @@ where
                 .with_env_variable("version", env!("CARGO_PKG_VERSION"))
                 .with_env_variable("location", "MS")
                 .with_env_variable("phase", "during"),
+            sieve_global: SieveGlobalScripts::default(),
+            virus_scanner: CommandScanner::new(settings),
             db,
         };
 
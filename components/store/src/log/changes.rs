@@ -220,6 +220,15 @@ where
                     break;
                 }
                 if is_first {
+                    // States older than a compacted snapshot cannot be synchronized, this
+                    // applies to both compact_log and the per-collection retention.
+                    // Ranges starting at the snapshot come from a full listing.
+                    if change_id != from_change_id
+                        && !matches!(query, Query::All)
+                        && value.first() == Some(&batch::Change::SNAPSHOT)
+                    {
+                        return Ok(None);
+                    }
                     changelog.from_change_id = change_id;
                     is_first = false;
                 }
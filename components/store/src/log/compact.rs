@@ -28,7 +28,7 @@ use crate::log::entry::Entry;
 use crate::log::raft::{RaftId, TermId};
 use crate::serialize::key::LogKey;
 use crate::serialize::leb128::{Leb128Iterator, Leb128Vec};
-use crate::serialize::{DeserializeBigEndian, StoreDeserialize};
+use crate::serialize::DeserializeBigEndian;
 use crate::write::batch;
 use crate::{
     AccountId, Collection, ColumnFamily, Direction, JMAPStore, Store, StoreError, WriteOperation,
@@ -167,9 +167,7 @@ where
             })?;
 
             if raft_id.index <= up_to {
-                match Entry::deserialize(&value).ok_or_else(|| {
-                    StoreError::InternalError(format!("Corrupted raft entry for [{:?}]", key))
-                })? {
+                match Entry::deserialize_checked(&key, &value)? {
                     Entry::Item {
                         account_id,
                         changed_collections,
@@ -219,6 +217,7 @@ where
             (total_accounts * std::mem::size_of::<AccountId>())
                 + (changed_collections.len()
                     * (std::mem::size_of::<Collection>() + std::mem::size_of::<usize>()))
+                + Entry::SEAL_LEN
                 + 1
                 + std::mem::size_of::<usize>(),
         );
@@ -234,13 +233,162 @@ where
         write_batch.push(WriteOperation::set(
             ColumnFamily::Logs,
             LogKey::serialize_raft(&RaftId::new(last_term, up_to)),
-            bytes,
+            Entry::seal(bytes),
         ));
         self.db.write(write_batch)?;
 
         Ok(())
     }
 
+    // Keeps the last `max_changes` changes of each account in the collection, older
+    // ones are merged into a snapshot that never goes past `max_change_id`.
+    pub fn compact_changes(
+        &self,
+        collection: Collection,
+        max_changes: u64,
+        max_change_id: ChangeId,
+    ) -> crate::Result<()> {
+        let max_changes = max_changes as usize;
+        if max_changes == 0 {
+            return Ok(());
+        }
+
+        let mut horizons = AHashMap::default();
+        let mut current_account_id = AccountId::MAX;
+        let mut change_ids = Vec::new();
+
+        for (key, _) in self.db.iterator(
+            ColumnFamily::Logs,
+            &[LogKey::CHANGE_KEY_PREFIX],
+            Direction::Forward,
+        )? {
+            if !key.starts_with(&[LogKey::CHANGE_KEY_PREFIX]) {
+                break;
+            }
+            let (account_id, key_collection, change_id) = deserialize_change_key(&key)?;
+            if key_collection != collection {
+                continue;
+            }
+
+            if account_id != current_account_id {
+                if let Some(horizon) = changes_horizon(&change_ids, max_changes, max_change_id) {
+                    horizons.insert(current_account_id, horizon);
+                }
+                change_ids.clear();
+                current_account_id = account_id;
+            }
+            change_ids.push(change_id);
+        }
+        if let Some(horizon) = changes_horizon(&change_ids, max_changes, max_change_id) {
+            horizons.insert(current_account_id, horizon);
+        }
+
+        if !horizons.is_empty() {
+            debug!(
+                "Compacting {:?} changes of {} accounts.",
+                collection,
+                horizons.len()
+            );
+            self.compact_changes_up_to(collection, &horizons)?;
+        }
+
+        Ok(())
+    }
+
+    // Replaces the changes of a single collection up to the given id of each account
+    // with a snapshot, stored under the id of the last replaced change so that newer
+    // states stay valid. The Raft log is left untouched, callers in a cluster must not
+    // go past its oldest entry as followers replay the changes referenced by later ones.
+    pub fn compact_changes_up_to(
+        &self,
+        collection: Collection,
+        horizons: &AHashMap<AccountId, ChangeId>,
+    ) -> crate::Result<()> {
+        let mut current_account_id = AccountId::MAX;
+        let mut last_change_id = None;
+
+        let mut inserted_ids = RoaringTreemap::new();
+        let mut write_batch = Vec::new();
+
+        for (key, value) in self.db.iterator(
+            ColumnFamily::Logs,
+            &[LogKey::CHANGE_KEY_PREFIX],
+            Direction::Forward,
+        )? {
+            if !key.starts_with(&[LogKey::CHANGE_KEY_PREFIX]) {
+                break;
+            }
+            let (account_id, key_collection, change_id) = deserialize_change_key(&key)?;
+            if key_collection != collection {
+                continue;
+            }
+
+            if account_id != current_account_id {
+                if let Some(last_change_id) = last_change_id.take() {
+                    self.write_changes_snapshot(
+                        std::mem::take(&mut write_batch),
+                        &mut inserted_ids,
+                        current_account_id,
+                        collection,
+                        last_change_id,
+                    )?;
+                }
+                current_account_id = account_id;
+            }
+
+            if !matches!(horizons.get(&account_id), Some(up_to) if change_id <= *up_to) {
+                continue;
+            } else if let Some(prev_change_id) = last_change_id.replace(change_id) {
+                write_batch.push(WriteOperation::delete(
+                    ColumnFamily::Logs,
+                    LogKey::serialize_change(account_id, collection, prev_change_id),
+                ));
+            }
+
+            deserialize_inserts(&mut inserted_ids, &value).ok_or_else(|| {
+                StoreError::InternalError(format!(
+                    "Failed to deserialize changelog value for [{}/{:?}]: [{:?}]",
+                    account_id, collection, key
+                ))
+            })?;
+        }
+
+        if let Some(last_change_id) = last_change_id {
+            self.write_changes_snapshot(
+                write_batch,
+                &mut inserted_ids,
+                current_account_id,
+                collection,
+                last_change_id,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn write_changes_snapshot(
+        &self,
+        write_batch: Vec<WriteOperation>,
+        inserted_ids: &mut RoaringTreemap,
+        account_id: AccountId,
+        collection: Collection,
+        last_change_id: ChangeId,
+    ) -> crate::Result<()> {
+        // A single change is either a snapshot already or cannot be shortened
+        if !write_batch.is_empty() {
+            self.db.write(serialize_snapshot(
+                write_batch,
+                inserted_ids,
+                account_id,
+                collection,
+                last_change_id,
+            )?)
+        } else {
+            inserted_ids.clear();
+            Ok(())
+        }
+    }
+
     /*pub fn compact_bitmaps(&self) -> crate::Result<()> {
         // Not currently used.
         for (key, value) in self
@@ -258,6 +406,46 @@ where
     }*/
 }
 
+fn deserialize_change_key(key: &[u8]) -> crate::Result<(AccountId, Collection, ChangeId)> {
+    let account_id = key.deserialize_be_u32(LogKey::ACCOUNT_POS).ok_or_else(|| {
+        StoreError::InternalError(format!(
+            "Failed to deserialize account id from changelog key: [{:?}]",
+            key
+        ))
+    })?;
+    let collection: Collection = (*key.get(LogKey::COLLECTION_POS).ok_or_else(|| {
+        StoreError::InternalError(format!(
+            "Failed to deserialize collection from changelog key: [{:?}]",
+            key
+        ))
+    })?)
+    .into();
+    let change_id = LogKey::deserialize_change_id(key).ok_or_else(|| {
+        StoreError::InternalError(format!(
+            "Failed to deserialize changelog key for [{}/{:?}]: [{:?}]",
+            account_id, collection, key
+        ))
+    })?;
+    Ok((account_id, collection, change_id))
+}
+
+// Returns the id to compact up to so that `max_changes` changes remain after it.
+fn changes_horizon(
+    change_ids: &[ChangeId],
+    max_changes: usize,
+    max_change_id: ChangeId,
+) -> Option<ChangeId> {
+    if change_ids.len() > max_changes + 1 {
+        change_ids[..change_ids.len() - max_changes]
+            .iter()
+            .rev()
+            .find(|&&change_id| change_id <= max_change_id)
+            .copied()
+    } else {
+        None
+    }
+}
+
 fn serialize_snapshot(
     mut write_batch: Vec<WriteOperation>,
     inserted_ids: &mut RoaringTreemap,
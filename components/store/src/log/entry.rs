@@ -23,6 +23,7 @@
 
 use crate::core::bitmap::Bitmap;
 use crate::core::collection::Collection;
+use crate::core::error::StoreError;
 use crate::serialize::leb128::Leb128Iterator;
 use crate::serialize::StoreDeserialize;
 use crate::write::batch;
@@ -41,6 +42,53 @@ pub enum Entry {
 }
 
 impl Entry {
+    pub const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+
+    // Leading tag of checksummed entries, entries written by earlier versions
+    // start with a change type and carry no checksum.
+    pub const SEALED: u8 = 0x80;
+    pub const SEAL_LEN: usize = Self::CHECKSUM_LEN + 1;
+
+    /// Tags a serialized raft entry and appends its CRC32 checksum.
+    pub fn seal(mut bytes: Vec<u8>) -> Vec<u8> {
+        let checksum = crc32fast::hash(&bytes);
+        bytes.insert(0, Self::SEALED);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Returns the serialized entry without its tag and checksum, if the checksum matches.
+    /// Legacy entries are returned as they are.
+    pub fn verify(bytes: &[u8]) -> Option<&[u8]> {
+        match *bytes.first()? {
+            Self::SEALED => {
+                let checksum_pos = bytes.len().checked_sub(Self::CHECKSUM_LEN)?;
+                let entry = bytes.get(1..checksum_pos)?;
+                if crc32fast::hash(entry).to_le_bytes() == bytes[checksum_pos..] {
+                    Some(entry)
+                } else {
+                    None
+                }
+            }
+            batch::Change::ENTRY | batch::Change::SNAPSHOT => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Deserializes a raft entry, reporting checksum mismatches as data corruption.
+    pub fn deserialize_checked(key: &[u8], bytes: &[u8]) -> crate::Result<Self> {
+        if Entry::verify(bytes).is_none() {
+            Err(StoreError::DataCorruption(format!(
+                "Checksum mismatch for raft entry [{:?}]",
+                key
+            )))
+        } else {
+            Entry::deserialize(bytes).ok_or_else(|| {
+                StoreError::DataCorruption(format!("Corrupted raft entry for [{:?}]", key))
+            })
+        }
+    }
+
     pub fn next_account(&mut self) -> Option<(AccountId, Bitmap<Collection>)> {
         match self {
             Entry::Item {
@@ -67,6 +115,7 @@ impl Entry {
 
 impl StoreDeserialize for Entry {
     fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let bytes = Entry::verify(bytes)?;
         match *bytes.first()? {
             batch::Change::ENTRY => Entry::Item {
                 account_id: AccountId::from_le_bytes(
@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::ops::{BitAndAssign, BitOrAssign};
+use std::{
+    borrow::Cow,
+    ops::{BitAndAssign, BitOrAssign},
+};
 
 use roaring::RoaringBitmap;
 
@@ -94,9 +97,20 @@ where
         let mut bm = RoaringBitmap::new();
         let match_prefix = &match_key[0..FIELD_PREFIX_LEN];
         let match_value = &match_key[FIELD_PREFIX_LEN..];
+
+        // Index keys are followed by a document id, so entries equal to the
+        // value sort after the match key. Start past them when they are included.
+        let seek_key = if matches!(op, ComparisonOperator::LowerEqualThan) {
+            let mut seek_key = match_key.to_vec();
+            seek_key.extend_from_slice(&DocumentId::MAX.to_be_bytes());
+            Cow::Owned(seek_key)
+        } else {
+            Cow::Borrowed(match_key)
+        };
+
         for (key, _) in self.db.iterator(
             ColumnFamily::Indexes,
-            match_key,
+            &seek_key,
             match op {
                 ComparisonOperator::GreaterThan => Direction::Forward,
                 ComparisonOperator::GreaterEqualThan => Direction::Forward,
@@ -122,7 +136,7 @@ where
                         break;
                     }
                 }
-                ComparisonOperator::LowerEqualThan if value > match_value => break,
+                ComparisonOperator::LowerEqualThan if value > match_value => continue,
                 ComparisonOperator::GreaterThan if value <= match_value => {
                     if value == match_value {
                         continue;
@@ -144,4 +158,73 @@ where
 
         Ok(Some(bm))
     }
+
+    // Same as range_to_bitmap, but only the documents in 'candidates' are returned.
+    // Ranges are scanned starting from their open end, which is where the few candidates
+    // left by a previous condition (such as unread messages) usually are, and the scan
+    // stops as soon as all of them have been found.
+    pub fn range_to_bitmap_with_candidates(
+        &self,
+        match_key: &[u8],
+        op: ComparisonOperator,
+        candidates: &RoaringBitmap,
+    ) -> crate::Result<Option<RoaringBitmap>> {
+        let mut bm = RoaringBitmap::new();
+        if candidates.is_empty() {
+            return Ok(Some(bm));
+        } else if matches!(op, ComparisonOperator::Equal) {
+            return Ok(self
+                .range_to_bitmap(match_key, op)?
+                .map(|result| result & candidates));
+        }
+        let match_prefix = &match_key[0..FIELD_PREFIX_LEN];
+        let match_value = &match_key[FIELD_PREFIX_LEN..];
+
+        let (seek_key, direction) = match op {
+            ComparisonOperator::GreaterThan | ComparisonOperator::GreaterEqualThan => {
+                let mut seek_key = match_prefix.to_vec();
+                seek_key.resize(match_key.len() + std::mem::size_of::<DocumentId>(), u8::MAX);
+                (Cow::Owned(seek_key), Direction::Backward)
+            }
+            _ => (Cow::Borrowed(match_prefix), Direction::Forward),
+        };
+
+        for (key, _) in self
+            .db
+            .iterator(ColumnFamily::Indexes, &seek_key, direction)?
+        {
+            if !key.starts_with(match_prefix) {
+                break;
+            }
+            let doc_id_pos = key.len() - std::mem::size_of::<DocumentId>();
+            let value = key.get(FIELD_PREFIX_LEN..doc_id_pos).ok_or_else(|| {
+                StoreError::InternalError(
+                    "Invalid key found in 'indexes' column family.".to_string(),
+                )
+            })?;
+
+            if !match op {
+                ComparisonOperator::LowerThan => value < match_value,
+                ComparisonOperator::LowerEqualThan => value <= match_value,
+                ComparisonOperator::GreaterThan => value > match_value,
+                _ => value >= match_value,
+            } {
+                break;
+            }
+
+            let document_id = key.as_ref().deserialize_be_u32(doc_id_pos).ok_or_else(|| {
+                StoreError::InternalError(
+                    "Invalid key found in 'indexes' column family.".to_string(),
+                )
+            })?;
+            if candidates.contains(document_id)
+                && bm.insert(document_id)
+                && bm.len() == candidates.len()
+            {
+                break;
+            }
+        }
+
+        Ok(Some(bm))
+    }
 }
@@ -131,6 +131,22 @@ impl Filter {
             conditions,
         })
     }
+
+    /// Relative cost of evaluating this filter, used to run cheap bitmap
+    /// lookups before index scans and full-text matching.
+    pub(crate) fn cost(&self) -> u32 {
+        match self {
+            Filter::None | Filter::DocumentSet(_) => 0,
+            Filter::Condition(cond) => match &cond.value {
+                Query::Tag(_) | Query::Keyword(_) => 1,
+                Query::Tokenize(_) => 2,
+                Query::Integer(_) | Query::LongInteger(_) | Query::Float(_) | Query::Index(_) => 3,
+                Query::Match(text) if !text.match_phrase => 4,
+                Query::Match(_) => 5,
+            },
+            Filter::Operator(op) => op.conditions.iter().map(|f| f.cost()).max().unwrap_or(0),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,7 +34,7 @@ use std::vec::IntoIter;
 
 use super::{
     comparator::Comparator,
-    filter::{Filter, FilterOperator, LogicalOperator, Query},
+    filter::{ComparisonOperator, Filter, FilterOperator, LogicalOperator, Query},
     iterator::StoreIterator,
 };
 
@@ -44,6 +44,21 @@ struct State {
     bm: Option<RoaringBitmap>,
 }
 
+impl State {
+    fn new(op: LogicalOperator, mut conditions: Vec<Filter>) -> Self {
+        // Evaluate the cheapest conditions first so that full-text
+        // matching only has to look at the remaining candidates.
+        if op == LogicalOperator::And {
+            conditions.sort_by_key(|filter| filter.cost());
+        }
+        State {
+            op,
+            it: conditions.into_iter(),
+            bm: None,
+        }
+    }
+}
+
 impl<T> JMAPStore<T>
 where
     T: for<'x> Store<'x> + 'static,
@@ -91,11 +106,7 @@ where
             },
         };
 
-        let mut state = State {
-            op: filter.operator,
-            it: filter.conditions.into_iter(),
-            bm: None,
-        };
+        let mut state = State::new(filter.operator, filter.conditions);
 
         let mut stack = Vec::new();
 
@@ -161,6 +172,10 @@ where
                                             })
                                             .collect(),
                                     )? {
+                                        let candidates = match (&state.op, &state.bm) {
+                                            (LogicalOperator::And, Some(bm)) => candidates & bm,
+                                            _ => candidates,
+                                        };
                                         let mut results = RoaringBitmap::new();
                                         for document_id in candidates.iter() {
                                             if let Some(term_index) = self.get_term_index(
@@ -252,7 +267,14 @@ where
                                             &document_ids,
                                         );
 
-                                        if text_bitmap.as_ref().unwrap().is_empty() {
+                                        // Narrow down to the candidates from previous conditions
+                                        let text_bitmap = text_bitmap.as_mut().unwrap();
+                                        if let (LogicalOperator::And, Some(bm)) =
+                                            (&state.op, &state.bm)
+                                        {
+                                            *text_bitmap &= bm;
+                                        }
+                                        if text_bitmap.is_empty() {
                                             break;
                                         }
                                     }
@@ -260,49 +282,43 @@ where
                                 }
                             }
                             Query::Integer(i) => {
-                                state.op.apply(
-                                    &mut state.bm,
-                                    self.range_to_bitmap(
-                                        &IndexKey::serialize_key(
-                                            account_id,
-                                            collection,
-                                            filter_cond.field,
-                                            &i.to_be_bytes(),
-                                        ),
-                                        filter_cond.op,
-                                    )?,
-                                    &document_ids,
-                                );
+                                let result = self.range_to_bitmap_candidates(
+                                    &IndexKey::serialize_key(
+                                        account_id,
+                                        collection,
+                                        filter_cond.field,
+                                        &i.to_be_bytes(),
+                                    ),
+                                    filter_cond.op,
+                                    &state,
+                                )?;
+                                state.op.apply(&mut state.bm, result, &document_ids);
                             }
                             Query::LongInteger(i) => {
-                                state.op.apply(
-                                    &mut state.bm,
-                                    self.range_to_bitmap(
-                                        &IndexKey::serialize_key(
-                                            account_id,
-                                            collection,
-                                            filter_cond.field,
-                                            &i.to_be_bytes(),
-                                        ),
-                                        filter_cond.op,
-                                    )?,
-                                    &document_ids,
-                                );
+                                let result = self.range_to_bitmap_candidates(
+                                    &IndexKey::serialize_key(
+                                        account_id,
+                                        collection,
+                                        filter_cond.field,
+                                        &i.to_be_bytes(),
+                                    ),
+                                    filter_cond.op,
+                                    &state,
+                                )?;
+                                state.op.apply(&mut state.bm, result, &document_ids);
                             }
                             Query::Float(f) => {
-                                state.op.apply(
-                                    &mut state.bm,
-                                    self.range_to_bitmap(
-                                        &IndexKey::serialize_key(
-                                            account_id,
-                                            collection,
-                                            filter_cond.field,
-                                            &f.to_be_bytes(),
-                                        ),
-                                        filter_cond.op,
-                                    )?,
-                                    &document_ids,
-                                );
+                                let result = self.range_to_bitmap_candidates(
+                                    &IndexKey::serialize_key(
+                                        account_id,
+                                        collection,
+                                        filter_cond.field,
+                                        &f.to_be_bytes(),
+                                    ),
+                                    filter_cond.op,
+                                    &state,
+                                )?;
+                                state.op.apply(&mut state.bm, result, &document_ids);
                             }
                             Query::Index(text) => {
                                 state.op.apply(
@@ -338,11 +354,7 @@ where
                     }
                     Filter::Operator(filter_op) => {
                         stack.push(state);
-                        state = State {
-                            op: filter_op.operator,
-                            it: filter_op.conditions.into_iter(),
-                            bm: None,
-                        };
+                        state = State::new(filter_op.operator, filter_op.conditions);
                         continue 'outer;
                     }
                     Filter::None => (),
@@ -371,4 +383,20 @@ where
             sort,
         ))
     }
+
+    // Ranges that are intersected with the results of cheaper conditions
+    // only need to be scanned until all the current candidates are found.
+    fn range_to_bitmap_candidates(
+        &self,
+        match_key: &[u8],
+        op: ComparisonOperator,
+        state: &State,
+    ) -> crate::Result<Option<RoaringBitmap>> {
+        match (&state.op, &state.bm) {
+            (LogicalOperator::And, Some(candidates)) => {
+                self.range_to_bitmap_with_candidates(match_key, op, candidates)
+            }
+            _ => self.range_to_bitmap(match_key, op),
+        }
+    }
 }
@@ -62,6 +62,7 @@ pub const INTERNAL_KEY_PREFIX: u8 = 0;
 
 pub const FOLLOWER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 1];
 pub const LEADER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 2];
+pub const SCHEMA_VERSIONS_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 3];
 
 pub struct ValueKey {}
 pub struct BitmapKey {}
@@ -393,6 +394,7 @@ impl LogKey {
     pub const ROLLBACK_KEY_PREFIX: u8 = 2;
     pub const PENDING_UPDATES_KEY_PREFIX: u8 = 3;
     pub const TOMBSTONE_KEY_PREFIX: u8 = 3;
+    pub const QUARANTINE_KEY_PREFIX: u8 = 4;
 
     pub const CHANGE_KEY_LEN: usize = std::mem::size_of::<AccountId>()
         + std::mem::size_of::<Collection>()
@@ -431,6 +433,14 @@ impl LogKey {
         bytes
     }
 
+    pub fn serialize_quarantine(id: &RaftId) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(LogKey::RAFT_KEY_LEN);
+        bytes.push(LogKey::QUARANTINE_KEY_PREFIX);
+        bytes.extend_from_slice(&id.index.to_be_bytes());
+        bytes.extend_from_slice(&id.term.to_be_bytes());
+        bytes
+    }
+
     pub fn serialize_change(
         account: AccountId,
         collection: Collection,
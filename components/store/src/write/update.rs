@@ -29,7 +29,7 @@ use crate::{
         bitmap::Bitmap, collection::Collection, document::MAX_TOKEN_LENGTH, error::StoreError,
         tag::Tag,
     },
-    log::changes::ChangeId,
+    log::{changes::ChangeId, entry::Entry},
     nlp::{
         lang::{LanguageDetector, MIN_LANGUAGE_SCORE},
         stemmer::Stemmer,
@@ -503,7 +503,7 @@ where
 
             // Serialize raft entry
             let mut bytes = Vec::with_capacity(
-                std::mem::size_of::<AccountId>() + std::mem::size_of::<u64>() + 1,
+                std::mem::size_of::<AccountId>() + std::mem::size_of::<u64>() + Entry::SEAL_LEN + 1,
             );
             bytes.push(Change::ENTRY);
             bytes.extend_from_slice(&batch.account_id.to_le_bytes());
@@ -511,7 +511,7 @@ where
             ops.push(WriteOperation::set(
                 ColumnFamily::Logs,
                 LogKey::serialize_raft(&raft_id),
-                bytes,
+                Entry::seal(bytes),
             ));
 
             // Serialize raft tombstones
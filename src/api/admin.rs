@@ -0,0 +1,51 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::RequestError;
+use crate::authorization::Session;
+use crate::JMAPServer;
+use actix_web::{http::StatusCode, web, HttpResponse};
+use jmap::SUPERUSER_ID;
+use store::tracing::{error, info};
+use store::Store;
+
+pub async fn handle_admin_compact<T>(
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if session.account_id() != SUPERUSER_ID {
+        return Err(RequestError::forbidden());
+    }
+
+    info!("Compacting database (requested by administrator).");
+    match core.compact_db().await {
+        Ok(_) => Ok(HttpResponse::new(StatusCode::NO_CONTENT)),
+        Err(err) => {
+            error!("Failed to compact database: {}", err);
+            Err(RequestError::internal_server_error())
+        }
+    }
+}
@@ -21,13 +21,14 @@
  * for more details.
 */
 
-use super::{RequestError, RequestLimitError};
+use super::{RequestBody, RequestError, RequestLimitError};
 use crate::authorization::auth::RemoteAddress;
 use crate::authorization::Session;
 use crate::JMAPServer;
 use actix_web::http::header::ContentType;
 use actix_web::HttpRequest;
 use actix_web::{http::StatusCode, web, HttpResponse};
+use async_stream::stream;
 use jmap::error::set::SetError;
 use jmap::request::blob::{CopyBlobRequest, CopyBlobResponse};
 use jmap::request::ACLEnforce;
@@ -45,6 +46,8 @@ use store::core::vec_map::VecMap;
 use store::JMAPStore;
 use store::{tracing::error, Store};
 
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(serde::Deserialize)]
 pub struct Params {
     accept: Option<String>,
@@ -62,6 +65,7 @@ where
     // Enforce access control
     let (id, blob_id, filename) = path.into_inner();
     let account_id = id.get_document_id();
+    let session_account_id = session.account_id();
 
     let store = core.store.clone();
     match core
@@ -74,23 +78,56 @@ where
         })
         .await
     {
-        Ok(BlobResult::Blob(bytes)) => Ok(HttpResponse::build(StatusCode::OK)
-            .insert_header((
-                "Content-Type",
-                params
-                    .into_inner()
-                    .accept
-                    .unwrap_or_else(|| "application/octet-stream".to_string()),
-            ))
-            .insert_header((
-                "Content-Disposition",
-                format!(
-                    "attachment; filename=\"{}\"",
-                    filename.replace('\"', "\\\"")
-                ),
-            ))
-            .insert_header(("Cache-Control", "private, immutable, max-age=31536000"))
-            .body(bytes)),
+        Ok(BlobResult::Blob(bytes)) => {
+            let mut response = HttpResponse::build(StatusCode::OK);
+            response
+                .insert_header((
+                    "Content-Type",
+                    params
+                        .into_inner()
+                        .accept
+                        .unwrap_or_else(|| "application/octet-stream".to_string()),
+                ))
+                .insert_header((
+                    "Content-Disposition",
+                    format!(
+                        "attachment; filename=\"{}\"",
+                        filename.replace('\"', "\\\"")
+                    ),
+                ))
+                .insert_header(("Cache-Control", "private, immutable, max-age=31536000"));
+
+            // Throttle downloads to the account's bandwidth limit
+            let max_rate = core.store.config.max_download_rate;
+            let limiter = if max_rate > 0 && session_account_id != SUPERUSER_ID {
+                core.rate_limiters
+                    .get(&RemoteAddress::AccountId(session_account_id))
+            } else {
+                None
+            };
+
+            Ok(if let Some(limiter) = limiter {
+                let bytes = web::Bytes::from(bytes);
+                let chunk_size = (max_rate as usize / 10).clamp(1, DOWNLOAD_CHUNK_SIZE);
+                response
+                    .no_chunking(bytes.len() as u64)
+                    .streaming::<_, std::io::Error>(stream! {
+                        let mut offset = 0;
+                        while offset < bytes.len() {
+                            let end = std::cmp::min(offset + chunk_size, bytes.len());
+                            let chunk = bytes.slice(offset..end);
+                            offset = end;
+                            let delay = limiter.download_delay(chunk.len(), max_rate);
+                            if !delay.is_zero() {
+                                tokio::time::sleep(delay).await;
+                            }
+                            yield Ok(chunk);
+                        }
+                    })
+            } else {
+                response.body(bytes)
+            })
+        }
         Ok(BlobResult::NotFound) => Err(RequestError::not_found()),
         Ok(BlobResult::Unauthorized) => Err(RequestError::forbidden()),
         Err(err) => {
@@ -114,7 +151,7 @@ struct UploadResponse {
 pub async fn handle_jmap_upload<T>(
     path: web::Path<(JMAPId,)>,
     request: HttpRequest,
-    bytes: web::Bytes,
+    RequestBody(bytes): RequestBody,
     core: web::Data<JMAPServer<T>>,
     session: Session,
 ) -> Result<HttpResponse, RequestError>
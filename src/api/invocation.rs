@@ -38,25 +38,33 @@ use jmap_mail::{
     identity::{changes::JMAPIdentityChanges, get::JMAPGetIdentity, set::JMAPSetIdentity},
     mail::{
         changes::JMAPMailChanges, copy::JMAPCopyMail, get::JMAPGetMail, import::JMAPMailImport,
-        parse::JMAPMailParse, query::JMAPMailQuery, search_snippet::JMAPMailSearchSnippet,
-        set::JMAPSetMail,
+        parse::JMAPMailParse, query::JMAPMailQuery, revisions::JMAPMailRevisions,
+        search_snippet::JMAPMailSearchSnippet, set::JMAPSetMail,
     },
     mailbox::{
         changes::JMAPMailboxChanges, get::JMAPGetMailbox, query::JMAPMailboxQuery,
-        set::JMAPSetMailbox,
+        search::JMAPMailboxSearch, set::JMAPSetMailbox,
     },
+    mdn::parse::JMAPMailMDNParse,
     thread::{changes::JMAPThreadChanges, get::JMAPGetThread},
     vacation_response::{get::JMAPGetVacationResponse, set::JMAPSetVacationResponse},
 };
 use jmap_sharing::principal::{
-    account::JMAPAccountStore, get::JMAPGetPrincipal, query::JMAPPrincipalQuery,
-    set::JMAPSetPrincipal,
+    account::JMAPAccountStore,
+    get::JMAPGetPrincipal,
+    query::JMAPPrincipalQuery,
+    set::{is_own_secret_update, JMAPSetPrincipal},
 };
 use jmap_sieve::sieve_script::{
     get::JMAPGetSieveScript, query::JMAPSieveScriptQuery, set::JMAPSetSieveScript,
     validate::JMAPMailSieveScriptValidate,
 };
-use store::{core::collection::Collection, tracing::error, AccountId, Store};
+use std::time::{Duration, Instant};
+use store::{
+    core::collection::Collection,
+    tracing::{error, warn},
+    AccountId, Store,
+};
 
 pub async fn handle_method_calls<T>(
     request: Request,
@@ -91,7 +99,17 @@ where
             }
 
             // Execute request
-            match handle_method_call(call_method, &core, session.account_id()).await {
+            let method_name = call_method.name();
+            let started = Instant::now();
+            let result = handle_method_call(call_method, &core, session.account_id()).await;
+            log_slow_method(
+                method_name,
+                session.account_id(),
+                started.elapsed(),
+                core.store.config.slow_method_threshold,
+            );
+
+            match result {
                 Ok(mut method_response) => {
                     let next_call_method = match method_response.changes() {
                         method::Changes::Item {
@@ -143,7 +161,21 @@ where
                                         error!("No e-mail delivery configured or something else happened: {}", err);
                                     }
                                 }
-                                method::Response::SetPrincipal(_) => {
+                                method::Response::SetPrincipal(principal_response) => {
+                                    // Sessions of updated principals have to authenticate again
+                                    let account_ids = principal_response
+                                        .updated
+                                        .keys()
+                                        .map(|id| id.get_document_id())
+                                        .collect::<Vec<_>>();
+                                    if !account_ids.is_empty() {
+                                        core.sessions
+                                            .invalidate_entries_if(move |_, session| {
+                                                account_ids.contains(&session.account_id())
+                                            })
+                                            .ok();
+                                    }
+
                                     core.notify_email_delivery(email_delivery::Event::Reload)
                                         .await
                                         .ok();
@@ -205,6 +237,25 @@ where
     response
 }
 
+pub fn log_slow_method(
+    method_name: &str,
+    account_id: AccountId,
+    elapsed: Duration,
+    threshold: u64,
+) -> bool {
+    if threshold > 0 && elapsed.as_millis() >= threshold as u128 {
+        warn!(
+            "Slow method call {} for account {} took {} ms.",
+            method_name,
+            account_id,
+            elapsed.as_millis()
+        );
+        true
+    } else {
+        false
+    }
+}
+
 pub async fn handle_method_call<T>(
     call: method::Request,
     core: &web::Data<JMAPServer<T>>,
@@ -269,6 +320,13 @@ where
                     .into();
                 method::Response::SetMailbox(store.mailbox_set(request)?)
             }
+            method::Request::SetSearchMailbox(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Mailbox)?
+                    .into();
+                method::Response::SetSearchMailbox(store.mailbox_set_search(request)?)
+            }
             method::Request::GetThread(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -340,6 +398,13 @@ where
                     .into();
                 method::Response::ParseEmail(store.mail_parse(request)?)
             }
+            method::Request::RevisionsEmail(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Mail)?
+                    .into();
+                method::Response::RevisionsEmail(store.mail_revisions(request)?)
+            }
             method::Request::GetSearchSnippet(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -347,6 +412,13 @@ where
                     .into();
                 method::Response::GetSearchSnippet(store.mail_search_snippet(request)?)
             }
+            method::Request::ParseMDN(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Mail)?
+                    .into();
+                method::Response::ParseMDN(store.mdn_parse(request)?)
+            }
             method::Request::GetIdentity(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -462,10 +534,14 @@ where
                 method::Response::QueryPrincipal(store.principal_query(request)?)
             }
             method::Request::SetPrincipal(mut request) => {
-                request.acl = store
-                    .get_acl_token(account_id)?
-                    .assert_is_member(SUPERUSER_ID)?
-                    .into();
+                // Individuals are allowed to change their own password
+                let acl = store.get_acl_token(account_id)?;
+                request.acl = if is_own_secret_update(&request, account_id) {
+                    acl
+                } else {
+                    acl.assert_is_member(SUPERUSER_ID)?
+                }
+                .into();
                 method::Response::SetPrincipal(store.principal_set(request)?)
             }
             method::Request::Echo(payload) => method::Response::Echo(payload),
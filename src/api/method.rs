@@ -47,10 +47,15 @@ use jmap_mail::{
     mail::{
         import::{EmailImportRequest, EmailImportResponse},
         parse::{EmailParseRequest, EmailParseResponse},
+        revisions::{EmailRevisionsRequest, EmailRevisionsResponse},
         schema::Email,
         search_snippet::{SearchSnippetGetRequest, SearchSnippetGetResponse},
     },
-    mailbox::schema::Mailbox,
+    mailbox::{
+        schema::Mailbox,
+        search::{MailboxSetSearchRequest, MailboxSetSearchResponse},
+    },
+    mdn::parse::{MDNParseRequest, MDNParseResponse},
     thread::schema::Thread,
     vacation_response::schema::VacationResponse,
 };
@@ -100,6 +105,7 @@ pub enum Request {
     QueryMailbox(QueryRequest<Mailbox>),
     QueryChangesMailbox(QueryChangesRequest<Mailbox>),
     SetMailbox(SetRequest<Mailbox>),
+    SetSearchMailbox(MailboxSetSearchRequest),
 
     // Thread
     GetThread(GetRequest<Thread>),
@@ -114,8 +120,12 @@ pub enum Request {
     CopyEmail(CopyRequest<Email>),
     ImportEmail(EmailImportRequest),
     ParseEmail(EmailParseRequest),
+    RevisionsEmail(EmailRevisionsRequest),
     GetSearchSnippet(SearchSnippetGetRequest),
 
+    // MDN
+    ParseMDN(MDNParseRequest),
+
     // Identity
     GetIdentity(GetRequest<Identity>),
     ChangesIdentity(ChangesRequest),
@@ -161,6 +171,7 @@ pub enum Response {
     QueryMailbox(QueryResponse),
     QueryChangesMailbox(QueryChangesResponse),
     SetMailbox(SetResponse<Mailbox>),
+    SetSearchMailbox(MailboxSetSearchResponse),
 
     // Thread
     GetThread(GetResponse<Thread>),
@@ -175,8 +186,12 @@ pub enum Response {
     CopyEmail(CopyResponse<Email>),
     ImportEmail(EmailImportResponse),
     ParseEmail(EmailParseResponse),
+    RevisionsEmail(EmailRevisionsResponse),
     GetSearchSnippet(SearchSnippetGetResponse),
 
+    // MDN
+    ParseMDN(MDNParseResponse),
+
     // Identity
     GetIdentity(GetResponse<Identity>),
     ChangesIdentity(ChangesResponse<Identity>),
@@ -225,7 +240,9 @@ impl Request {
             | Request::QueryEmail(_)
             | Request::QueryChangesEmail(_)
             | Request::ParseEmail(_)
+            | Request::RevisionsEmail(_)
             | Request::GetSearchSnippet(_)
+            | Request::ParseMDN(_)
             | Request::GetIdentity(_)
             | Request::ChangesIdentity(_)
             | Request::GetEmailSubmission(_)
@@ -243,6 +260,7 @@ impl Request {
 
             Request::SetPushSubscription(_)
             | Request::SetMailbox(_)
+            | Request::SetSearchMailbox(_)
             | Request::SetEmail(_)
             | Request::CopyEmail(_)
             | Request::ImportEmail(_)
@@ -255,6 +273,52 @@ impl Request {
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::GetPushSubscription(_) => "PushSubscription/get",
+            Request::SetPushSubscription(_) => "PushSubscription/set",
+            Request::GetMailbox(_) => "Mailbox/get",
+            Request::ChangesMailbox(_) => "Mailbox/changes",
+            Request::QueryMailbox(_) => "Mailbox/query",
+            Request::QueryChangesMailbox(_) => "Mailbox/queryChanges",
+            Request::SetMailbox(_) => "Mailbox/set",
+            Request::SetSearchMailbox(_) => "Mailbox/setSearch",
+            Request::GetThread(_) => "Thread/get",
+            Request::ChangesThread(_) => "Thread/changes",
+            Request::GetEmail(_) => "Email/get",
+            Request::ChangesEmail(_) => "Email/changes",
+            Request::QueryEmail(_) => "Email/query",
+            Request::QueryChangesEmail(_) => "Email/queryChanges",
+            Request::SetEmail(_) => "Email/set",
+            Request::CopyEmail(_) => "Email/copy",
+            Request::ImportEmail(_) => "Email/import",
+            Request::ParseEmail(_) => "Email/parse",
+            Request::RevisionsEmail(_) => "Email/revisions",
+            Request::GetSearchSnippet(_) => "SearchSnippet/get",
+            Request::ParseMDN(_) => "MDN/parse",
+            Request::GetIdentity(_) => "Identity/get",
+            Request::ChangesIdentity(_) => "Identity/changes",
+            Request::SetIdentity(_) => "Identity/set",
+            Request::GetEmailSubmission(_) => "EmailSubmission/get",
+            Request::ChangesEmailSubmission(_) => "EmailSubmission/changes",
+            Request::QueryEmailSubmission(_) => "EmailSubmission/query",
+            Request::QueryChangesEmailSubmission(_) => "EmailSubmission/queryChanges",
+            Request::SetEmailSubmission(_) => "EmailSubmission/set",
+            Request::GetVacationResponse(_) => "VacationResponse/get",
+            Request::SetVacationResponse(_) => "VacationResponse/set",
+            Request::GetSieveScript(_) => "SieveScript/get",
+            Request::QuerySieveScript(_) => "SieveScript/query",
+            Request::SetSieveScript(_) => "SieveScript/set",
+            Request::ValidateSieveScript(_) => "SieveScript/validate",
+            Request::GetPrincipal(_) => "Principal/get",
+            Request::QueryPrincipal(_) => "Principal/query",
+            Request::SetPrincipal(_) => "Principal/set",
+            Request::CopyBlob(_) => "Blob/copy",
+            Request::Echo(_) => "Core/echo",
+            Request::Error(_) => "error",
+        }
+    }
+
     pub fn prepare_request(&mut self, response: &response::Response) -> jmap::Result<()> {
         // Create JSON Pointer evaluation function
         let mut eval_result_ref = |rr: &ResultReference| -> Option<Vec<u64>> {
@@ -400,6 +464,22 @@ impl Response {
                     Changes::None
                 }
             }
+            Response::SetSearchMailbox(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: None,
+                        change_id,
+                        state_change: StateChange::new(
+                            response.account_id(),
+                            vec![(TypeState::Mailbox, change_id)],
+                        )
+                        .into(),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
             Response::SetEmail(response) => {
                 if let Some(change_id) = response.has_changes() {
                     Changes::Item {
@@ -541,7 +621,9 @@ impl Response {
             | Response::QueryEmail(_)
             | Response::QueryChangesEmail(_)
             | Response::ParseEmail(_)
+            | Response::RevisionsEmail(_)
             | Response::GetSearchSnippet(_)
+            | Response::ParseMDN(_)
             | Response::GetIdentity(_)
             | Response::ChangesIdentity(_)
             | Response::GetEmailSubmission(_)
@@ -658,6 +740,16 @@ where
                 .map_err(|err| MatchError::Parse(err.to_string()))?
                 .ok_or(MatchError::Eof)?,
         ),
+        "Email/revisions" => Request::RevisionsEmail(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "MDN/parse" => Request::ParseMDN(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
         "Mailbox/get" => Request::GetMailbox(
             seq.next_element()
                 .map_err(|err| MatchError::Parse(err.to_string()))?
@@ -683,6 +775,11 @@ where
                 .map_err(|err| MatchError::Parse(err.to_string()))?
                 .ok_or(MatchError::Eof)?,
         ),
+        "Mailbox/setSearch" => Request::SetSearchMailbox(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
         "Thread/get" => Request::GetThread(
             seq.next_element()
                 .map_err(|err| MatchError::Parse(err.to_string()))?
@@ -848,6 +945,10 @@ impl Serialize for Call<Response> {
                 seq.serialize_element("Mailbox/set")?;
                 seq.serialize_element(response)?;
             }
+            Response::SetSearchMailbox(response) => {
+                seq.serialize_element("Mailbox/setSearch")?;
+                seq.serialize_element(response)?;
+            }
             Response::GetThread(response) => {
                 seq.serialize_element("Thread/get")?;
                 seq.serialize_element(response)?;
@@ -888,10 +989,18 @@ impl Serialize for Call<Response> {
                 seq.serialize_element("Email/parse")?;
                 seq.serialize_element(response)?;
             }
+            Response::RevisionsEmail(response) => {
+                seq.serialize_element("Email/revisions")?;
+                seq.serialize_element(response)?;
+            }
             Response::GetSearchSnippet(response) => {
                 seq.serialize_element("SearchSnippet/get")?;
                 seq.serialize_element(response)?;
             }
+            Response::ParseMDN(response) => {
+                seq.serialize_element("MDN/parse")?;
+                seq.serialize_element(response)?;
+            }
             Response::GetIdentity(response) => {
                 seq.serialize_element("Identity/get")?;
                 seq.serialize_element(response)?;
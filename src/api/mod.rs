@@ -21,14 +21,19 @@
  * for more details.
 */
 
+use actix_web::dev::Payload;
 use actix_web::error;
 use actix_web::http::header;
-use actix_web::{http::StatusCode, HttpResponse};
+use actix_web::web::Bytes;
+use actix_web::{http::StatusCode, FromRequest, HttpRequest, HttpResponse};
+use futures_util::future::LocalBoxFuture;
 use jmap::types::{jmap::JMAPId, state::JMAPState, type_state::TypeState};
 use std::borrow::Cow;
 use std::fmt::Display;
+use std::time::Duration;
 use store::core::vec_map::VecMap;
 
+pub mod admin;
 pub mod blob;
 pub mod invocation;
 pub mod method;
@@ -36,6 +41,9 @@ pub mod request;
 pub mod response;
 pub mod session;
 
+// Added to responses served by a follower, points clients to the leader for writes
+pub const HEADER_LEADER: &str = "X-JMAP-Leader";
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum StateChangeType {
     StateChange,
@@ -200,6 +208,14 @@ impl RequestError {
         )
     }
 
+    pub fn request_timeout() -> Self {
+        RequestError::blank(
+            408,
+            "Request Timeout",
+            "The request body was not received in time.",
+        )
+    }
+
     pub fn unauthorized() -> Self {
         RequestError::blank(401, "Unauthorized", "You have to authenticate first.")
     }
@@ -268,6 +284,32 @@ impl error::ResponseError for RequestError {
     }
 }
 
+// Maximum time allowed to receive a request body, which prevents clients
+// from holding connections open by slowly trickling data.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyTimeout(pub Duration);
+
+pub struct RequestBody(pub Bytes);
+
+impl FromRequest for RequestBody {
+    type Error = error::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let timeout = req.app_data::<BodyTimeout>().map(|timeout| timeout.0);
+        let bytes = Bytes::from_request(req, payload);
+        Box::pin(async move {
+            Ok::<_, error::Error>(RequestBody(if let Some(timeout) = timeout {
+                tokio::time::timeout(timeout, bytes)
+                    .await
+                    .map_err(|_| RequestError::request_timeout())??
+            } else {
+                bytes.await?
+            }))
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Redirect {
     pub is_permament: bool,
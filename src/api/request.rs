@@ -29,7 +29,10 @@ use jmap::types::jmap::JMAPId;
 use store::{ahash::AHashMap, tracing::debug, Store};
 
 use crate::{
-    api::{invocation::handle_method_calls, Redirect, RequestError, RequestLimitError},
+    api::{
+        invocation::handle_method_calls, Redirect, RequestBody, RequestError, RequestLimitError,
+        HEADER_LEADER,
+    },
     authorization::Session,
     JMAPServer,
 };
@@ -48,7 +51,7 @@ pub struct Request {
 }
 
 pub async fn handle_jmap_request<T>(
-    request: web::Bytes,
+    RequestBody(request): RequestBody,
     core: web::Data<JMAPServer<T>>,
     session: Session,
 ) -> Result<HttpResponse, RequestError>
@@ -60,10 +63,12 @@ where
             Ok(request) => {
                 if request.method_calls.len() < core.store.config.max_calls_in_request {
                     // Make sure this node is still the leader
+                    let mut leader_hint = None;
                     if !core.is_leader() {
-                        // Redirect requests if at least one method requires write access
-                        // or if this node is behind on the log.
+                        // Redirect requests if at least one method requires write access,
+                        // if this node is behind on the log or if follower reads are disabled.
                         let do_redirect = !core.is_up_to_date()
+                            || !core.store.config.raft_follower_reads
                             || request
                                 .method_calls
                                 .iter()
@@ -88,13 +93,25 @@ where
                                 return Err(RequestError::unavailable());
                             }
                         }
+
+                        leader_hint = core
+                            .cluster
+                            .as_ref()
+                            .unwrap()
+                            .leader_hostname
+                            .lock()
+                            .as_ref()
+                            .map(|leader_hostname| format!("{}/jmap", leader_hostname));
                     }
 
                     let result = handle_method_calls(request, core, session).await;
 
-                    Ok(HttpResponse::build(StatusCode::OK)
-                        .insert_header(ContentType::json())
-                        .json(result))
+                    let mut response = HttpResponse::build(StatusCode::OK);
+                    response.insert_header(ContentType::json());
+                    if let Some(leader_hint) = leader_hint {
+                        response.insert_header((HEADER_LEADER, leader_hint));
+                    }
+                    Ok(response.json(result))
                 } else {
                     Err(RequestError::limit(RequestLimitError::CallsIn))
                 }
@@ -23,7 +23,9 @@
 
 use std::iter::FromIterator;
 
-use crate::{api::response::serialize_hex, authorization};
+use crate::{
+    api::response::serialize_hex, authorization, services::push_subscription_vapid::VapidKey,
+};
 use actix_web::{
     http::{header::ContentType, StatusCode},
     web, HttpResponse,
@@ -90,6 +92,8 @@ enum Capabilities {
     VacationResponse(VacationResponseCapabilities),
     WebSocket(WebSocketCapabilities),
     Sieve(SieveCapabilities),
+    MDN(MDNCapabilities),
+    WebPushVapid(WebPushVapidCapabilities),
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -165,23 +169,43 @@ struct SubmissionCapabilities {
 #[derive(Debug, Clone, serde::Serialize)]
 struct VacationResponseCapabilities {}
 
+#[derive(Debug, Clone, serde::Serialize)]
+struct MDNCapabilities {}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct WebPushVapidCapabilities {
+    #[serde(rename(serialize = "applicationServerKey"))]
+    application_server_key: String,
+}
+
 impl Session {
     pub fn new(settings: &EnvSettings, config: &JMAPConfig) -> Session {
         let base_url = settings.get("jmap-url").unwrap();
 
+        let mut capabilities = VecMap::from_iter([
+            (URI::Core, Capabilities::Core(CoreCapabilities::new(config))),
+            (URI::Mail, Capabilities::Mail(MailCapabilities::new(config))),
+            (
+                URI::WebSocket,
+                Capabilities::WebSocket(WebSocketCapabilities::new(&base_url)),
+            ),
+            (
+                URI::Sieve,
+                Capabilities::Sieve(SieveCapabilities::new(settings, config)),
+            ),
+            (URI::MDN, Capabilities::MDN(MDNCapabilities {})),
+        ]);
+        if let Some(vapid) = VapidKey::new(settings) {
+            capabilities.append(
+                URI::WebPushVapid,
+                Capabilities::WebPushVapid(WebPushVapidCapabilities {
+                    application_server_key: vapid.public_key().to_string(),
+                }),
+            );
+        }
+
         Session {
-            capabilities: VecMap::from_iter([
-                (URI::Core, Capabilities::Core(CoreCapabilities::new(config))),
-                (URI::Mail, Capabilities::Mail(MailCapabilities::new(config))),
-                (
-                    URI::WebSocket,
-                    Capabilities::WebSocket(WebSocketCapabilities::new(&base_url)),
-                ),
-                (
-                    URI::Sieve,
-                    Capabilities::Sieve(SieveCapabilities::new(settings, config)),
-                ),
-            ]),
+            capabilities,
             accounts: VecMap::new(),
             primary_accounts: VecMap::new(),
             username: "".to_string(),
@@ -215,7 +239,9 @@ impl Session {
             }
         } else {
             for capability in self.capabilities.keys() {
-                self.primary_accounts.append(capability.clone(), account_id);
+                if capability.is_account_capability() {
+                    self.primary_accounts.append(capability.clone(), account_id);
+                }
             }
         }
 
@@ -276,7 +302,12 @@ impl Account {
                 );
             }
         } else {
-            self.account_capabilities = core_capabilities.clone();
+            for (capability, value) in core_capabilities.iter() {
+                if capability.is_account_capability() {
+                    self.account_capabilities
+                        .append(capability.clone(), value.clone());
+                }
+            }
         }
         self
     }
@@ -93,6 +93,7 @@ where
 
                 // Check whether a redirect is needed
                 let do_redirect = !core.is_up_to_date()
+                    || !core.store.config.raft_follower_reads
                     || request_path.starts_with("/jmap/upload")
                     || request_path.starts_with("/jmap/ws")
                     || request_path.starts_with("/jmap/eventsource")
@@ -26,11 +26,12 @@ use std::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use jmap::SUPERUSER_ID;
 use store::{parking_lot::Mutex, AccountId, Store};
+use tokio::sync::Notify;
 
 use crate::{
     api::{RequestError, RequestLimitError},
@@ -46,9 +47,15 @@ pub struct RateLimiter {
     limiter: Arc<Mutex<(Instant, f64)>>,
 }
 
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    next_free: Arc<Mutex<Instant>>,
+}
+
 #[derive(Debug)]
 pub struct ConcurrencyLimiter {
     concurrent: Arc<AtomicUsize>,
+    released: Arc<Notify>,
 }
 
 pub struct Limiter {
@@ -63,16 +70,19 @@ pub enum LimiterType {
     Authenticated {
         concurrent_request: ConcurrencyLimiter,
         concurrent_uploads: ConcurrencyLimiter,
+        download_bandwidth: BandwidthLimiter,
     },
 }
 
 pub struct InFlightRequest {
     concurrent_requests: Arc<AtomicUsize>,
+    released: Arc<Notify>,
 }
 
 impl Drop for InFlightRequest {
     fn drop(&mut self) {
         self.concurrent_requests.fetch_sub(1, Ordering::Relaxed);
+        self.released.notify_one();
     }
 }
 
@@ -107,21 +117,73 @@ impl RateLimiter {
     }
 }
 
+impl BandwidthLimiter {
+    pub fn new() -> Self {
+        BandwidthLimiter {
+            next_free: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // Reserves the time needed to transfer the specified number of bytes at the given rate,
+    // returns how long the caller has to wait before sending them.
+    pub fn reserve(&self, bytes: usize, rate: u64) -> Duration {
+        let mut next_free = self.next_free.lock();
+        let now = Instant::now();
+        let start = if *next_free > now { *next_free } else { now };
+        *next_free = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+        start - now
+    }
+}
+
+impl Default for BandwidthLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConcurrencyLimiter {
     pub fn new(concurrent: usize) -> Self {
         ConcurrencyLimiter {
             concurrent: Arc::new(AtomicUsize::new(concurrent)),
+            released: Arc::new(Notify::new()),
         }
     }
 
     pub fn is_allowed(&self, limit: usize) -> Option<InFlightRequest> {
-        if self.concurrent.load(Ordering::Relaxed) < limit {
-            self.concurrent.fetch_add(1, Ordering::Relaxed);
-            Some(InFlightRequest {
+        // Check and increment atomically, otherwise parallel requests could exceed the limit
+        self.concurrent
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |concurrent| {
+                if concurrent < limit {
+                    Some(concurrent + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| InFlightRequest {
                 concurrent_requests: self.concurrent.clone(),
+                released: self.released.clone(),
             })
-        } else {
-            None
+    }
+
+    // Waits up to the specified duration for an in-flight request to finish
+    pub async fn is_allowed_or_wait(
+        &self,
+        limit: usize,
+        wait: Duration,
+    ) -> Option<InFlightRequest> {
+        let wait_until = Instant::now() + wait;
+        loop {
+            if let Some(in_flight_request) = self.is_allowed(limit) {
+                return Some(in_flight_request);
+            }
+            let remaining = wait_until.checked_duration_since(Instant::now())?;
+            if tokio::time::timeout(remaining, self.released.notified())
+                .await
+                .is_err()
+            {
+                return self.is_allowed(limit);
+            }
         }
     }
 }
@@ -147,6 +209,7 @@ impl Limiter {
             ltype: LimiterType::Authenticated {
                 concurrent_request: ConcurrencyLimiter::new(0),
                 concurrent_uploads: ConcurrencyLimiter::new(0),
+                download_bandwidth: BandwidthLimiter::new(),
             },
         }
     }
@@ -162,11 +225,19 @@ impl Limiter {
         }
     }
 
-    pub fn is_request_allowed(&self, max_requests: usize) -> Option<InFlightRequest> {
+    pub async fn is_request_allowed(
+        &self,
+        max_requests: usize,
+        wait: Duration,
+    ) -> Option<InFlightRequest> {
         match &self.ltype {
             LimiterType::Authenticated {
                 concurrent_request, ..
-            } => concurrent_request.is_allowed(max_requests),
+            } => {
+                concurrent_request
+                    .is_allowed_or_wait(max_requests, wait)
+                    .await
+            }
             _ => None,
         }
     }
@@ -179,6 +250,15 @@ impl Limiter {
             _ => None,
         }
     }
+
+    pub fn download_delay(&self, bytes: usize, max_rate: u64) -> Duration {
+        match &self.ltype {
+            LimiterType::Authenticated {
+                download_bandwidth, ..
+            } => download_bandwidth.reserve(bytes, max_rate),
+            _ => Duration::ZERO,
+        }
+    }
 }
 
 impl<T> JMAPServer<T>
@@ -201,8 +281,12 @@ where
                 .await;
 
             if limiter.is_rate_allowed() {
-                if let Some(in_flight_request) =
-                    limiter.is_request_allowed(self.store.config.max_concurrent_requests)
+                if let Some(in_flight_request) = limiter
+                    .is_request_allowed(
+                        self.store.config.max_concurrent_requests,
+                        Duration::from_millis(self.store.config.max_concurrent_requests_wait),
+                    )
+                    .await
                 {
                     Ok(in_flight_request)
                 } else {
@@ -214,6 +298,7 @@ where
         } else {
             Ok(InFlightRequest {
                 concurrent_requests: Arc::new(0.into()),
+                released: Arc::new(Notify::new()),
             })
         }
     }
@@ -258,3 +343,55 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{BandwidthLimiter, ConcurrencyLimiter};
+
+    #[tokio::test]
+    async fn concurrency_limiter() {
+        let limiter = ConcurrencyLimiter::new(0);
+        let in_flight = (0..2)
+            .map(|_| limiter.is_allowed(2).unwrap())
+            .collect::<Vec<_>>();
+        assert!(limiter.is_allowed(2).is_none());
+
+        // Excess requests are rejected once the wait time elapses
+        assert!(limiter
+            .is_allowed_or_wait(2, Duration::from_millis(50))
+            .await
+            .is_none());
+
+        // Queued requests proceed as soon as an in-flight request finishes
+        let mut in_flight = in_flight.into_iter();
+        let first = in_flight.next().unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(first);
+        });
+        let queued = limiter
+            .is_allowed_or_wait(2, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(limiter.is_allowed(2).is_none());
+
+        drop(queued);
+        drop(in_flight);
+        assert!(limiter.is_allowed(2).is_some());
+    }
+
+    #[test]
+    fn bandwidth_limiter() {
+        let limiter = BandwidthLimiter::new();
+
+        // The first transfer goes through immediately, the following ones
+        // have to wait until the previous transfers have used their share.
+        assert_eq!(limiter.reserve(500, 1000), Duration::ZERO);
+        let wait = limiter.reserve(500, 1000);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        let wait = limiter.reserve(1000, 1000);
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+}
@@ -225,12 +225,7 @@ where
                         StoreError::InternalError(format!("Corrupted raft key for [{:?}]", key))
                     })?;
                     if apply_up_to == LogIndex::MAX || raft_id.index > apply_up_to {
-                        match Entry::deserialize(&value).ok_or_else(|| {
-                            StoreError::InternalError(format!(
-                                "Corrupted raft entry for [{:?}]",
-                                key
-                            ))
-                        })? {
+                        match Entry::deserialize_checked(&key, &value)? {
                             Entry::Item {
                                 account_id,
                                 changed_collections,
@@ -29,9 +29,11 @@ use crate::JMAPServer;
 use store::ahash::AHashMap;
 use store::core::bitmap::Bitmap;
 use store::core::collection::Collection;
+use store::core::error::StoreError;
+use store::log::entry::Entry;
 use store::log::raft::LogIndex;
 use store::serialize::key::LogKey;
-use store::tracing::debug;
+use store::tracing::{debug, error};
 use store::write::operation::WriteOperation;
 use store::{AccountId, ColumnFamily, Store};
 
@@ -120,6 +122,23 @@ where
                                 );
                             }
 
+                            // Corrupted entries are quarantined instead of being appended
+                            if Entry::verify(&log).is_none() {
+                                store.db.set(
+                                    ColumnFamily::Logs,
+                                    &LogKey::serialize_quarantine(&raft_id),
+                                    &log,
+                                )?;
+                                error!(
+                                    "Checksum mismatch for raft entry {:?}, entry quarantined.",
+                                    raft_id
+                                );
+                                return Err(StoreError::DataCorruption(format!(
+                                    "Checksum mismatch for raft entry {:?}",
+                                    raft_id
+                                )));
+                            }
+
                             last_index = raft_id.index;
                             if merge_index == LogIndex::MAX {
                                 merge_index = raft_id.index;
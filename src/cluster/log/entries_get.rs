@@ -29,7 +29,6 @@ use store::core::error::StoreError;
 use store::log::entry::Entry;
 use store::log::raft::{LogIndex, RaftId};
 use store::serialize::key::LogKey;
-use store::serialize::StoreDeserialize;
 use store::{AccountId, ColumnFamily, Direction, JMAPStore, Store};
 
 pub trait RaftStoreEntries {
@@ -93,9 +92,7 @@ where
                         log: value.to_vec(),
                     });
 
-                    match Entry::deserialize(&value).ok_or_else(|| {
-                        StoreError::InternalError(format!("Corrupted raft entry for [{:?}]", key))
-                    })? {
+                    match Entry::deserialize_checked(&key, &value)? {
                         Entry::Item {
                             account_id,
                             changed_collections,
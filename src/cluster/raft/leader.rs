@@ -24,7 +24,7 @@
 use super::{Cluster, PeerId};
 use super::{State, RAFT_LOG_LEADER};
 use crate::cluster::Peer;
-use crate::services::{email_delivery, state_change};
+use crate::services::{email_delivery, housekeeper, state_change};
 use crate::JMAPServer;
 use std::sync::atomic::Ordering;
 use store::log::raft::TermId;
@@ -151,6 +151,11 @@ where
             .send(email_delivery::Event::Start)
             .await
             .ok();
+        self.housekeeper
+            .clone()
+            .send(housekeeper::Event::Reindex)
+            .await
+            .ok();
     }
 
     pub fn is_leader(&self) -> bool {
@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{
+    sync::{atomic::AtomicUsize, Arc},
+    time::Instant,
+};
 
 use authorization::{auth::RemoteAddress, rate_limit::Limiter};
 use cluster::ClusterIpc;
@@ -51,12 +54,14 @@ pub struct JMAPServer<T> {
     pub email_delivery: mpsc::Sender<services::email_delivery::Event>,
     pub housekeeper: mpsc::Sender<services::housekeeper::Event>,
     pub lmtp: watch::Sender<bool>,
+    pub lmtp_deliveries: Arc<AtomicUsize>,
 
     pub oauth: Box<authorization::oauth::OAuth>,
     pub oauth_codes: Cache<String, Arc<authorization::oauth::OAuthCode>>,
 
     pub sessions: Cache<String, authorization::Session>,
     pub rate_limiters: Cache<RemoteAddress, Arc<Limiter>>,
+    pub rcpt_callouts: Cache<String, (bool, Instant)>,
 
     #[cfg(test)]
     pub is_offline: std::sync::atomic::AtomicBool,
@@ -0,0 +1,95 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+use store::{tracing::debug, Store};
+
+use super::session::Session;
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Verifies with the configured backend that a recipient exists.
+    /// Returns `None` when the backend could not give an answer.
+    pub async fn rcpt_callout(&self, address: &str) -> Option<bool> {
+        let config = &self.core.store.config;
+        let url = if let Some(url) = &config.lmtp_rcpt_callout_url {
+            url
+        } else {
+            return Some(true);
+        };
+        let address = address.to_lowercase();
+        if !config.lmtp_rcpt_callout_domains.is_empty()
+            && !address.rsplit_once('@').map_or(false, |(_, domain)| {
+                config.lmtp_rcpt_callout_domains.iter().any(|d| d == domain)
+            })
+        {
+            return Some(true);
+        }
+
+        if let Some((exists, expires)) = self.core.rcpt_callouts.get(&address) {
+            if expires > Instant::now() {
+                return Some(exists);
+            }
+        }
+
+        let exists = match reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.lmtp_rcpt_callout_timeout))
+            .build()
+            .unwrap_or_default()
+            .get(url)
+            .query(&[("address", &address)])
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) if response.status() == StatusCode::NOT_FOUND => false,
+            Ok(response) => {
+                debug!(
+                    "Recipient callout for {} failed with status {}.",
+                    address,
+                    response.status()
+                );
+                return None;
+            }
+            Err(err) => {
+                debug!("Recipient callout for {} failed: {}", address, err);
+                return None;
+            }
+        };
+
+        let ttl = if exists {
+            config.lmtp_rcpt_callout_ttl
+        } else {
+            config.lmtp_rcpt_callout_ttl_negative
+        };
+        self.core
+            .rcpt_callouts
+            .insert(address, (exists, Instant::now() + Duration::from_secs(ttl)))
+            .await;
+
+        Some(exists)
+    }
+}
@@ -21,7 +21,11 @@
  * for more details.
 */
 
-use std::{borrow::Cow, sync::Arc, time::SystemTime};
+use std::{
+    borrow::Cow,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
 use jmap::{
     orm::TinyORM,
@@ -31,13 +35,25 @@ use jmap::{
 use jmap_mail::{
     mail::{
         import::JMAPMailImport,
-        schema::{Email, Keyword, Property},
+        inline_images::externalize_inline_images,
+        message_id::JMAPMailMessageId,
+        schema::{Email, EmailAuthResults, Keyword, Property},
+    },
+    mail_builder::{
+        headers::{
+            address::{Address, EmailAddress},
+            content_type::ContentType,
+            message_id::MessageId,
+            raw::Raw,
+        },
+        mime::{BodyPart, MimePart},
+        MessageBuilder,
     },
     mail_parser::Message,
-    mailbox::{get::JMAPGetMailbox, is_valid_role, set::JMAPSetMailbox},
+    mailbox::{get::JMAPGetMailbox, is_valid_role, join_mailbox_path, set::JMAPSetMailbox},
     INBOX_ID, TRASH_ID,
 };
-use jmap_sharing::principal::account::JMAPAccountStore;
+use jmap_sharing::principal::account::{split_plus_address, JMAPAccountStore};
 use jmap_sieve::{
     sieve_script::{
         get::JMAPGetSieveScript,
@@ -49,6 +65,10 @@ use serde::{Deserialize, Serialize};
 use store::{
     ahash::{AHashMap, AHashSet},
     blob::BlobId,
+    config::{
+        jmap::SieveLimits,
+        scan::{ScanVerdict, VirusAction},
+    },
     core::{collection::Collection, document::Document, tag::Tag},
     log::changes::ChangeId,
     sieve::{Compiler, Envelope, Event, Input, Mailbox, Recipient},
@@ -64,8 +84,10 @@ use crate::{
 };
 
 use super::{
+    scan::strip_infected_parts,
     session::{RcptType, Session},
-    OutgoingMessage,
+    srs::Srs,
+    InFlightDelivery, OutgoingMessage,
 };
 
 impl<T> Session<T>
@@ -73,6 +95,9 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     pub async fn ingest_message(&mut self) -> Result<(), ()> {
+        // Keep the server from shutting down until the message is delivered
+        let _in_flight = InFlightDelivery::new(&self.core.lmtp_deliveries);
+
         // Validate request
         if self.message.is_empty() {
             return self
@@ -127,8 +152,9 @@ where
         // Build response
         let mut buf = Vec::with_capacity(128);
         for rcpt in &rcpt_to {
-            let (RcptType::Mailbox { name, status, .. } | RcptType::List { name, status, .. }) =
-                rcpt;
+            let (RcptType::Mailbox { name, status, .. }
+            | RcptType::List { name, status, .. }
+            | RcptType::Forward { name, status, .. }) = rcpt;
             match status {
                 DeliveryStatus::Success => buf.extend_from_slice(b"250 2.1.5 <"),
                 DeliveryStatus::TemporaryFailure { .. } => buf.extend_from_slice(b"451 4.3.0 <"),
@@ -285,6 +311,33 @@ pub trait JMAPMailIngest {
         envelope_to: &str,
     ) -> DeliveryStatus;
 
+    fn mail_plus_address_mailbox(
+        &self,
+        account_id: AccountId,
+        envelope_to: &str,
+    ) -> Option<DocumentId>;
+
+    fn mail_large_message_mailbox(
+        &self,
+        result: &mut IngestResult,
+        account_id: AccountId,
+        size: usize,
+    ) -> Option<DocumentId>;
+
+    fn mail_create_mailbox(
+        &self,
+        result: &mut IngestResult,
+        account_id: AccountId,
+        path: &str,
+    ) -> Option<DocumentId>;
+
+    fn mail_quarantine(
+        &self,
+        result: &mut IngestResult,
+        raw_message: &[u8],
+        blob_id: &BlobId,
+    ) -> DeliveryStatus;
+
     #[allow(clippy::result_unit_err)]
     fn mail_deliver_mailbox(
         &self,
@@ -295,6 +348,17 @@ pub trait JMAPMailIngest {
         mailbox_ids: &[DocumentId],
         flags: Vec<Tag>,
     ) -> Result<(), ()>;
+
+    fn mail_header_keywords(&self, account_id: AccountId, message: &Message) -> Vec<Tag>;
+
+    fn mail_auto_reply_allowed(&self, from: &str, to: &str) -> bool;
+
+    fn mail_build_bounce<'x>(
+        &self,
+        envelope_from: &str,
+        raw_message: &[u8],
+        statuses: impl Iterator<Item = (AccountId, &'x DeliveryStatus)>,
+    ) -> Option<OutgoingMessage>;
 }
 
 impl<T> JMAPMailIngest for JMAPStore<T>
@@ -307,6 +371,43 @@ where
         rcpt_to: Vec<RcptType>,
         raw_message: Vec<u8>,
     ) -> Result<IngestResult, Option<&'static str>> {
+        // Scan the message for viruses before it is modified in any way
+        let mut is_quarantined = false;
+        let raw_message = if let Some(scanner) = &self.virus_scanner {
+            match scanner.scan(&raw_message) {
+                ScanVerdict::Clean => raw_message,
+                ScanVerdict::Infected(name) => {
+                    debug!("Found virus {} in message from {}.", name, mail_from);
+                    match self.config.lmtp_virus_action {
+                        VirusAction::Quarantine
+                            if self.config.lmtp_virus_quarantine_account.is_some() =>
+                        {
+                            is_quarantined = true;
+                            Some(raw_message)
+                        }
+                        VirusAction::Reject | VirusAction::Quarantine => None,
+                        VirusAction::Strip => strip_infected_parts(scanner.as_ref(), &raw_message),
+                    }
+                    .ok_or(Some("Message contains a virus"))?
+                }
+                ScanVerdict::Failed => {
+                    error!("Failed to scan message from {} for viruses.", mail_from);
+                    return Err(None);
+                }
+            }
+        } else {
+            raw_message
+        };
+
+        // Large images embedded in HTML bodies are stored as attachments,
+        // quarantined messages are kept as received.
+        let min_size = self.config.mail_inline_images_min_size;
+        let raw_message = if min_size > 0 && !is_quarantined {
+            externalize_inline_images(&raw_message, min_size).unwrap_or(raw_message)
+        } else {
+            raw_message
+        };
+
         // Store raw message as a blob
         let blob_id = BlobId::new_external(&raw_message);
         let raw_message = self.blob_store(&blob_id, raw_message).map_err(|err| {
@@ -321,6 +422,28 @@ where
             messages: Vec::new(),
             last_change_id: ChangeId::MAX,
         };
+
+        // Infected messages are kept in the quarantine account for review,
+        // recipients do not receive anything.
+        if is_quarantined {
+            let status = self.mail_quarantine(&mut result, &raw_message, &blob_id);
+            for mut recipient in rcpt_to {
+                match &mut recipient {
+                    RcptType::Mailbox {
+                        status: rcpt_status,
+                        ..
+                    }
+                    | RcptType::List {
+                        status: rcpt_status,
+                        ..
+                    } => *rcpt_status = status.clone(),
+                    RcptType::Forward { .. } => (),
+                }
+                result.rcpt_to.push(recipient);
+            }
+            return Ok(result);
+        }
+
         let mut prev_status = if rcpt_to.iter().any(|s| {
             matches!(
                 s,
@@ -338,6 +461,9 @@ where
             None
         };
 
+        // Failures the LMTP client is not told about, as the recipient accepted the message
+        let mut undelivered = Vec::new();
+
         for mut recipient in rcpt_to {
             match &mut recipient {
                 RcptType::Mailbox { id, name, status } => {
@@ -364,9 +490,7 @@ where
                 }
                 RcptType::List { ids, name, status } => {
                     if !matches!(status, DeliveryStatus::Duplicated) {
-                        // Count number of successes and failures
-                        let mut success = 0;
-                        let mut temp_failures = 0;
+                        let mut statuses = Vec::with_capacity(ids.len());
 
                         for &account_id in ids.iter() {
                             let status = self.mail_deliver_rcpt(
@@ -378,42 +502,51 @@ where
                                 &*name,
                             );
 
-                            match &status {
-                                DeliveryStatus::Success => {
-                                    success += 1;
-                                }
-                                DeliveryStatus::TemporaryFailure { .. } => {
-                                    temp_failures += 1;
-                                }
-                                _ => (),
-                            }
-
                             if let Some(prev_status) = &mut prev_status {
-                                prev_status.insert(account_id, status);
+                                prev_status.insert(account_id, status.clone());
                             }
+                            statuses.push(status);
                         }
 
-                        *status = if success > 0 {
-                            DeliveryStatus::Success
-                        } else if temp_failures > 0 {
-                            DeliveryStatus::TemporaryFailure {
-                                reason: "temporary failure".into(),
-                            }
-                        } else {
-                            DeliveryStatus::PermanentFailure {
-                                code: "5.5.0".into(),
-                                reason: "permanent failure".into(),
-                            }
-                        };
+                        *status = DeliveryStatus::aggregate(statuses.iter());
+
+                        // The list accepted the message, so members that could not be
+                        // delivered to are not retried and have to be reported.
+                        if matches!(status, DeliveryStatus::Success) {
+                            undelivered.extend(ids.iter().copied().zip(statuses));
+                        }
                     } else {
-                        *status = DeliveryStatus::Success;
+                        // All members were already delivered to by earlier recipients
+                        let prev_status = prev_status.as_ref().unwrap();
+                        *status = DeliveryStatus::aggregate(
+                            ids.iter()
+                                .filter_map(|account_id| prev_status.get(account_id)),
+                        );
                     }
                 }
+                RcptType::Forward { address, .. } => {
+                    result.messages.push(OutgoingMessage {
+                        mail_from: String::new(),
+                        rcpt_to: vec![address.clone()],
+                        message: raw_message.to_vec(),
+                    });
+                }
             }
 
             result.rcpt_to.push(recipient);
         }
 
+        // Failed deliveries are reported to the sender with a single bounce
+        if let Some(bounce) = self.mail_build_bounce(
+            &mail_from,
+            &raw_message,
+            undelivered
+                .iter()
+                .map(|(account_id, status)| (*account_id, status)),
+        ) {
+            result.messages.push(bounce);
+        }
+
         Ok(result)
     }
 
@@ -427,7 +560,7 @@ where
         envelope_to: &str,
     ) -> DeliveryStatus {
         // Verify that this account has an Inbox mailbox
-        let mailbox_ids = match self.get_document_ids(account_id, Collection::Mailbox) {
+        let mut mailbox_ids = match self.get_document_ids(account_id, Collection::Mailbox) {
             Ok(Some(mailbox_ids)) if mailbox_ids.contains(INBOX_ID) => mailbox_ids,
             _ => {
                 error!("Account {} does not have an Inbox configured.", account_id);
@@ -435,6 +568,20 @@ where
             }
         };
 
+        // Search folders are read-only, messages cannot be filed into them
+        match self.mailbox_search_folders(account_id) {
+            Ok(search_folders) => {
+                mailbox_ids -= search_folders;
+            }
+            Err(err) => {
+                error!(
+                    "Failed to obtain search folders for account {}: {}",
+                    account_id, err
+                );
+                return DeliveryStatus::internal_error();
+            }
+        }
+
         // Parse message
         let message = if let Some(message) = Message::parse(raw_message) {
             message
@@ -442,77 +589,93 @@ where
             return DeliveryStatus::perm_failure("Failed to parse message.");
         };
 
-        let mut active_script = match self.sieve_script_get_active(account_id) {
-            Ok(None) => {
-                return if self
-                    .mail_deliver_mailbox(
-                        result,
-                        account_id,
-                        message,
-                        blob_id,
-                        &[INBOX_ID],
-                        Vec::new(),
-                    )
-                    .is_ok()
-                {
-                    DeliveryStatus::Success
-                } else {
-                    DeliveryStatus::internal_error()
-                };
-            }
-            Ok(Some(active_script)) => active_script,
+        // File plus-addressed messages into a folder named after the tag,
+        // and large messages into the configured folder
+        let default_id = self
+            .mail_plus_address_mailbox(account_id, envelope_to)
+            .or_else(|| self.mail_large_message_mailbox(result, account_id, raw_message.len()))
+            .unwrap_or(INBOX_ID);
+
+        let active_script = match self.sieve_script_get_active(account_id) {
+            Ok(active_script) => active_script,
             Err(err) => {
                 error!("Failed to get SieveScript for {}: {}", account_id, err);
-                return if self
-                    .mail_deliver_mailbox(
-                        result,
-                        account_id,
-                        message,
-                        blob_id,
-                        &[INBOX_ID],
-                        Vec::new(),
-                    )
-                    .is_ok()
-                {
-                    DeliveryStatus::Success
-                } else {
-                    DeliveryStatus::internal_error()
-                };
+                None
             }
         };
 
-        let mut instance = self.sieve_runtime.filter_parsed(message);
+        // Global scripts run before and after the user's active script
+        let global_scripts = &self.sieve_global;
+        if active_script.is_none() && global_scripts.is_empty() {
+            return if self
+                .mail_deliver_mailbox(
+                    result,
+                    account_id,
+                    message,
+                    blob_id,
+                    &[default_id],
+                    Vec::new(),
+                )
+                .is_ok()
+            {
+                DeliveryStatus::Success
+            } else {
+                DeliveryStatus::internal_error()
+            };
+        }
+        let mut scripts = global_scripts
+            .before
+            .iter()
+            .map(|(name, script)| (name.clone(), script.clone(), true))
+            .collect::<Vec<_>>();
+        if let Some(active_script) = &active_script {
+            scripts.push((
+                if let Some(Value::Text { value }) = active_script
+                    .orm
+                    .get(&jmap_sieve::sieve_script::schema::Property::Name)
+                {
+                    value.to_string()
+                } else {
+                    account_id.to_string()
+                },
+                active_script.script.clone(),
+                false,
+            ));
+        }
+        scripts.extend(
+            global_scripts
+                .after
+                .iter()
+                .map(|(name, script)| (name.clone(), script.clone(), true)),
+        );
 
-        // Set account details
-        let mail_from = match self.get_account_details(account_id) {
-            Ok(Some((email, name, _))) => {
-                let mail_from = email.clone();
-                instance.set_user_address(email);
-                instance.set_user_full_name(&name);
-                mail_from
-            }
+        // Obtain account details
+        let account_details = match self.get_account_details(account_id) {
+            Ok(Some((email, name, _))) => Some((email, name)),
             _ => {
                 error!("Failed to obtain account details for {}.", account_id);
-                instance.set_user_address(envelope_to.to_string());
-                envelope_to.to_string()
+                None
             }
         };
-
-        // Set envelope
-        instance.set_envelope(Envelope::From, envelope_from);
-        instance.set_envelope(Envelope::To, envelope_to);
-
-        let mut input = Input::script(
-            if let Some(Value::Text { value }) = active_script
-                .orm
-                .get(&jmap_sieve::sieve_script::schema::Property::Name)
-            {
-                value.to_string()
+        let mail_from = account_details
+            .as_ref()
+            .map_or(envelope_to, |(email, _)| email.as_str())
+            .to_string();
+        let is_automated = is_automated_message(&message);
+        let new_instance = |message| {
+            let mut instance = self.sieve_runtime.filter_parsed(message);
+            if let Some((email, name)) = &account_details {
+                instance.set_user_address(email.clone());
+                instance.set_user_full_name(name);
             } else {
-                account_id.to_string()
-            },
-            active_script.script.clone(),
-        );
+                instance.set_user_address(envelope_to.to_string());
+            }
+            instance.set_envelope(Envelope::From, envelope_from);
+            instance.set_envelope(Envelope::To, envelope_to);
+            instance
+        };
+        let mut instance = new_instance(message);
+        let mut has_message_changed = false;
 
         let mut do_discard = false;
         let mut do_deliver = false;
@@ -524,258 +687,399 @@ where
             file_into: Vec::new(),
             flags: Vec::new(),
         }];
+        let mut global_flags = Vec::new();
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .map(|d| d.as_secs())
             .unwrap_or(0);
 
-        while let Some(event) = instance.run(input) {
-            match event {
-                Ok(event) => match event {
-                    Event::IncludeScript { name, .. } => {
-                        if let Ok(Some(script)) =
-                            self.sieve_script_get_by_name(account_id, name.as_str().to_string())
-                        {
-                            input = Input::script(name, script);
-                        } else {
-                            input = false.into();
-                        }
+        // Enforce the runtime limits configured for this account
+        let limits = self.config.sieve_limits_for(&mail_from);
+        let started = Instant::now();
+        let num_outgoing = result.messages.len();
+        let mut usage = SieveUsage::default();
+        let mut script_error = None;
+        let mut is_aborted = false;
+
+        for (script_num, (script_name, script, is_global)) in scripts.into_iter().enumerate() {
+            // Each script runs on its own copy of the original message
+            if script_num > 0 {
+                has_message_changed |= instance.has_message_changed();
+                instance = if let Some(message) = Message::parse(raw_message) {
+                    new_instance(message)
+                } else {
+                    break;
+                };
+            }
+
+            // Message ids are local to each script, the ones it creates follow the existing ones
+            let message_offset = messages.len() - 1;
+            let message_pos = |message_id: usize| {
+                if message_id > 0 {
+                    message_id + message_offset
+                } else {
+                    0
+                }
+            };
+            let mut input = Input::script(script_name, script);
+            while let Some(event) = instance.run(input) {
+                if let Ok(event) = &event {
+                    if let Some(reason) = usage.track(event, limits, started) {
+                        debug!("Sieve script of account {} aborted: {}", account_id, reason);
+                        script_error = reason.into();
+                        is_aborted = true;
+                        break;
                     }
-                    Event::MailboxExists {
-                        mailboxes,
-                        special_use,
-                    } => {
-                        if !mailboxes.is_empty() {
-                            let special_use = special_use
-                                .into_iter()
-                                .map(|role| {
-                                    if role.eq_ignore_ascii_case("inbox") {
-                                        INBOX_ID
-                                    } else if role.eq_ignore_ascii_case("trash") {
-                                        TRASH_ID
-                                    } else {
-                                        let mut mailbox_id = DocumentId::MAX;
-                                        let role = role.to_ascii_lowercase();
-                                        if is_valid_role(&role) {
-                                            if let Ok(Some(mailbox_id_)) =
-                                                self.mailbox_get_by_role(account_id, &role)
-                                            {
-                                                mailbox_id = mailbox_id_;
+                }
+
+                match event {
+                    Ok(event) => match event {
+                        Event::IncludeScript { name, .. } => {
+                            if is_global {
+                                // Global scripts can only include other global scripts
+                                if let Some(script) = global_scripts.get(name.as_str()) {
+                                    input = Input::script(name, script);
+                                } else {
+                                    input = false.into();
+                                }
+                            } else if let Ok(Some(script)) =
+                                self.sieve_script_get_by_name(account_id, name.as_str().to_string())
+                            {
+                                input = Input::script(name, script);
+                            } else {
+                                input = false.into();
+                            }
+                        }
+                        Event::MailboxExists {
+                            mailboxes,
+                            special_use,
+                        } => {
+                            if !mailboxes.is_empty() {
+                                let special_use = special_use
+                                    .into_iter()
+                                    .map(|role| {
+                                        if role.eq_ignore_ascii_case("inbox") {
+                                            INBOX_ID
+                                        } else if role.eq_ignore_ascii_case("trash") {
+                                            TRASH_ID
+                                        } else {
+                                            let mut mailbox_id = DocumentId::MAX;
+                                            let role = role.to_ascii_lowercase();
+                                            if is_valid_role(&role) {
+                                                if let Ok(Some(mailbox_id_)) =
+                                                    self.mailbox_get_by_role(account_id, &role)
+                                                {
+                                                    mailbox_id = mailbox_id_;
+                                                }
                                             }
+                                            mailbox_id
                                         }
-                                        mailbox_id
-                                    }
-                                })
-                                .collect::<Vec<_>>();
-
-                            let mut result = true;
-                            for mailbox in mailboxes {
-                                match mailbox {
-                                    Mailbox::Name(name) => {
-                                        if !matches!(
-                                            self.mailbox_get_by_name(account_id, &name),
-                                            Ok(Some(document_id)) if special_use.is_empty() ||
-                                                        special_use.contains(&document_id)
-                                        ) {
-                                            result = false;
-                                            break;
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                let mut result = true;
+                                for mailbox in mailboxes {
+                                    match mailbox {
+                                        Mailbox::Name(name) => {
+                                            if !matches!(
+                                                self.mailbox_get_by_name(account_id, &name),
+                                                Ok(Some(document_id)) if special_use.is_empty() ||
+                                                            special_use.contains(&document_id)
+                                            ) {
+                                                result = false;
+                                                break;
+                                            }
+                                        }
+                                        Mailbox::Id(id) => {
+                                            if !matches!(JMAPId::parse(&id), Some(id) if
+                                                                mailbox_ids.contains(id.get_document_id()) &&
+                                                                (special_use.is_empty() ||
+                                                                 special_use.contains(&id.get_document_id())))
+                                            {
+                                                result = false;
+                                                break;
+                                            }
                                         }
                                     }
-                                    Mailbox::Id(id) => {
-                                        if !matches!(JMAPId::parse(&id), Some(id) if
-                                                            mailbox_ids.contains(id.get_document_id()) &&
-                                                            (special_use.is_empty() ||
-                                                             special_use.contains(&id.get_document_id())))
+                                }
+                                input = result.into();
+                            } else if !special_use.is_empty() {
+                                let mut result = true;
+
+                                for role in special_use {
+                                    if !role.eq_ignore_ascii_case("inbox")
+                                        && !role.eq_ignore_ascii_case("trash")
+                                    {
+                                        let role = role.to_ascii_lowercase();
+                                        if !is_valid_role(&role)
+                                            || !matches!(
+                                                self.mailbox_get_by_role(account_id, &role),
+                                                Ok(Some(_))
+                                            )
                                         {
                                             result = false;
                                             break;
                                         }
                                     }
                                 }
+                                input = result.into();
+                            } else {
+                                input = false.into();
                             }
-                            input = result.into();
-                        } else if !special_use.is_empty() {
-                            let mut result = true;
-
-                            for role in special_use {
-                                if !role.eq_ignore_ascii_case("inbox")
-                                    && !role.eq_ignore_ascii_case("trash")
-                                {
-                                    let role = role.to_ascii_lowercase();
-                                    if !is_valid_role(&role)
-                                        || !matches!(
-                                            self.mailbox_get_by_role(account_id, &role),
-                                            Ok(Some(_))
-                                        )
-                                    {
-                                        result = false;
-                                        break;
-                                    }
+                        }
+                        Event::DuplicateId { id, expiry, last } => {
+                            // Seen ids are stored in the active script, global scripts have none
+                            if let (Some(active_script), false) = (&active_script, is_global) {
+                                let id_hash = SeenIdHash::new(&id, expiry + now);
+                                let seen_id = active_script.seen_ids.contains(&id_hash);
+                                if !seen_id || last {
+                                    new_ids.insert(id_hash);
                                 }
+
+                                input = seen_id.into();
+                            } else {
+                                input = false.into();
                             }
-                            input = result.into();
-                        } else {
-                            input = false.into();
                         }
-                    }
-                    Event::DuplicateId { id, expiry, last } => {
-                        let id_hash = SeenIdHash::new(&id, expiry + now);
-                        let seen_id = active_script.seen_ids.contains(&id_hash);
-                        if !seen_id || last {
-                            new_ids.insert(id_hash);
+                        Event::Discard => {
+                            do_discard = true;
+                            input = true.into();
                         }
-
-                        input = seen_id.into();
-                    }
-                    Event::Discard => {
-                        do_discard = true;
-                        input = true.into();
-                    }
-                    Event::Reject { reason, .. } => {
-                        reject_reason = reason.into();
-                        do_discard = true;
-                        input = true.into();
-                    }
-                    Event::Keep { flags, message_id } => {
-                        if let Some(message) = messages.get_mut(message_id) {
-                            message.flags =
-                                flags.into_iter().map(|f| Keyword::parse(&f).tag).collect();
-                            if !message.file_into.contains(&INBOX_ID) {
-                                message.file_into.push(INBOX_ID);
-                            }
-                            do_deliver = true;
-                        } else {
-                            error!("Sieve filter failed: Unknown message id {}.", message_id);
+                        Event::Reject { reason, .. } => {
+                            reject_reason = reason.into();
+                            do_discard = true;
+                            input = true.into();
                         }
-                        input = true.into();
-                    }
-                    Event::FileInto {
-                        folder,
-                        flags,
-                        mailbox_id,
-                        special_use,
-                        create,
-                        message_id,
-                    } => {
-                        let mut target_id = DocumentId::MAX;
-
-                        // Find mailbox by Id
-                        if let Some(mailbox_id) = mailbox_id.and_then(|m| JMAPId::parse(&m)) {
-                            let mailbox_id = mailbox_id.get_document_id();
-                            if mailbox_ids.contains(mailbox_id) {
-                                target_id = mailbox_id;
+                        Event::Keep { flags, message_id } => {
+                            if is_global {
+                                // Keeps from global scripts only contribute their flags, the message
+                                // is filed by the user's script or by the fail-safe keep below.
+                                global_flags
+                                    .extend(flags.into_iter().map(|f| Keyword::parse(&f).tag));
+                            } else if let Some(message) = messages.get_mut(message_pos(message_id))
+                            {
+                                message.flags =
+                                    flags.into_iter().map(|f| Keyword::parse(&f).tag).collect();
+                                if !message.file_into.contains(&default_id) {
+                                    message.file_into.push(default_id);
+                                }
+                                do_deliver = true;
+                            } else {
+                                error!("Sieve filter failed: Unknown message id {}.", message_id);
                             }
+                            input = true.into();
                         }
+                        Event::FileInto {
+                            folder,
+                            flags,
+                            mailbox_id,
+                            special_use,
+                            create,
+                            message_id,
+                        } => {
+                            let mut target_id = DocumentId::MAX;
+
+                            // Find mailbox by Id
+                            if let Some(mailbox_id) = mailbox_id.and_then(|m| JMAPId::parse(&m)) {
+                                let mailbox_id = mailbox_id.get_document_id();
+                                if mailbox_ids.contains(mailbox_id) {
+                                    target_id = mailbox_id;
+                                }
+                            }
 
-                        // Find mailbox by role
-                        if let Some(special_use) = special_use {
-                            if target_id == DocumentId::MAX {
-                                if special_use.eq_ignore_ascii_case("inbox") {
-                                    target_id = INBOX_ID;
-                                } else if special_use.eq_ignore_ascii_case("trash") {
-                                    target_id = TRASH_ID;
-                                } else {
-                                    let role = special_use.to_ascii_lowercase();
-                                    if is_valid_role(&role) {
-                                        if let Ok(Some(mailbox_id_)) =
-                                            self.mailbox_get_by_role(account_id, &role)
-                                        {
-                                            target_id = mailbox_id_;
+                            // Find mailbox by role
+                            if let Some(special_use) = special_use {
+                                if target_id == DocumentId::MAX {
+                                    if special_use.eq_ignore_ascii_case("inbox") {
+                                        target_id = INBOX_ID;
+                                    } else if special_use.eq_ignore_ascii_case("trash") {
+                                        target_id = TRASH_ID;
+                                    } else {
+                                        let role = special_use.to_ascii_lowercase();
+                                        if is_valid_role(&role) {
+                                            if let Ok(Some(mailbox_id_)) =
+                                                self.mailbox_get_by_role(account_id, &role)
+                                            {
+                                                if mailbox_ids.contains(mailbox_id_) {
+                                                    target_id = mailbox_id_;
+                                                }
+                                            }
                                         }
                                     }
                                 }
                             }
-                        }
 
-                        // Find mailbox by name
-                        if target_id == DocumentId::MAX {
-                            if !create {
-                                if let Ok(Some(document_id)) =
-                                    self.mailbox_get_by_name(account_id, &folder)
+                            // Find mailbox by name
+                            if target_id == DocumentId::MAX {
+                                if !create {
+                                    if let Ok(Some(document_id)) =
+                                        self.mailbox_get_by_name(account_id, &folder)
+                                    {
+                                        target_id = document_id;
+                                    }
+                                } else if let Ok(Some((document_id, changes))) =
+                                    self.mailbox_create_path(account_id, &folder)
                                 {
                                     target_id = document_id;
-                                }
-                            } else if let Ok(Some((document_id, changes))) =
-                                self.mailbox_create_path(account_id, &folder)
-                            {
-                                target_id = document_id;
-                                if let Some(changes) = changes {
-                                    result.last_change_id = changes.change_id;
-                                    result.changes.insert(account_id, changes);
+                                    if let Some(changes) = changes {
+                                        result.last_change_id = changes.change_id;
+                                        result.changes.insert(account_id, changes);
+                                    }
                                 }
                             }
-                        }
 
-                        // Default to Inbox
-                        if target_id == DocumentId::MAX {
-                            target_id = INBOX_ID;
-                        }
+                            // Default to Inbox
+                            if target_id == DocumentId::MAX {
+                                target_id = INBOX_ID;
+                            }
 
-                        if let Some(message) = messages.get_mut(message_id) {
-                            message.flags =
-                                flags.into_iter().map(|f| Keyword::parse(&f).tag).collect();
-                            if !message.file_into.contains(&target_id) {
-                                message.file_into.push(target_id);
+                            if let Some(message) = messages.get_mut(message_pos(message_id)) {
+                                let flags = flags.into_iter().map(|f| Keyword::parse(&f).tag);
+                                if is_global {
+                                    global_flags.extend(flags);
+                                } else {
+                                    message.flags = flags.collect();
+                                }
+                                if !message.file_into.contains(&target_id) {
+                                    message.file_into.push(target_id);
+                                }
+                                do_deliver = true;
+                            } else {
+                                error!("Sieve filter failed: Unknown message id {}.", message_id);
                             }
-                            do_deliver = true;
-                        } else {
-                            error!("Sieve filter failed: Unknown message id {}.", message_id);
+                            input = true.into();
                         }
-                        input = true.into();
-                    }
-                    Event::SendMessage {
-                        recipient,
-                        message_id,
-                        ..
-                    } => {
-                        input = true.into();
-
-                        result.messages.push(OutgoingMessage {
-                            mail_from: mail_from.clone(),
-                            rcpt_to: match recipient {
+                        Event::SendMessage {
+                            recipient,
+                            message_id,
+                            ..
+                        } => {
+                            input = true.into();
+
+                            let mut rcpt_to = match recipient {
                                 Recipient::Address(rcpt) => vec![rcpt],
                                 Recipient::Group(rcpts) => rcpts,
                                 Recipient::List(_) => {
                                     // Not yet implemented
                                     continue;
                                 }
-                            },
-                            message: if let Some(message) = messages.get(message_id) {
+                            };
+                            let message = if let Some(message) =
+                                messages.get(message_pos(message_id))
+                            {
                                 message.raw_message.to_vec()
                             } else {
                                 error!("Sieve filter failed: Unknown message id {}.", message_id);
                                 continue;
-                            },
-                        });
-                    }
-                    Event::ListContains { .. } | Event::Execute { .. } | Event::Notify { .. } => {
-                        // Not allowed
-                        input = false.into();
+                            };
+
+                            // Auto-replies are never sent to automated messages, and only once
+                            // per window between the same addresses to break reply loops.
+                            if message_id > 0
+                                && Message::parse(&message)
+                                    .map_or(false, |message| is_automated_message(&message))
+                            {
+                                if is_automated {
+                                    debug!(
+                                        "Suppressed auto-reply from {} to automated message.",
+                                        mail_from
+                                    );
+                                    continue;
+                                }
+                                rcpt_to
+                                    .retain(|rcpt| self.mail_auto_reply_allowed(&mail_from, rcpt));
+                                if rcpt_to.is_empty() {
+                                    debug!("Suppressed repeated auto-reply from {}.", mail_from);
+                                    continue;
+                                }
+                            }
+
+                            // Rewrite the envelope sender of redirected messages using SRS
+                            let mail_from = match &self.config.srs_secret {
+                                Some(secret) if message_id == 0 => Srs::new(secret)
+                                    .encode(
+                                        envelope_from,
+                                        self.config.srs_domain.as_deref().unwrap_or_else(|| {
+                                            mail_from.rsplit_once('@').map_or("localhost", |r| r.1)
+                                        }),
+                                        now,
+                                    )
+                                    .unwrap_or_else(|| mail_from.clone()),
+                                _ => mail_from.clone(),
+                            };
+
+                            result.messages.push(OutgoingMessage {
+                                mail_from,
+                                rcpt_to,
+                                message,
+                            });
+                        }
+                        Event::ListContains { .. }
+                        | Event::Execute { .. }
+                        | Event::Notify { .. } => {
+                            // Not allowed
+                            input = false.into();
+                        }
+                        Event::CreatedMessage { message, .. } => {
+                            // Messages generated by the script use the same Message-ID policy
+                            // as the ones created through JMAP.
+                            let message = if Message::parse(&message)
+                                .map_or(false, |message| message.get_message_id().is_none())
+                            {
+                                let mut raw_message = format!(
+                                    "Message-ID: <{}>\r\n",
+                                    self.mail_message_id(mail_from.as_str().into())
+                                )
+                                .into_bytes();
+                                raw_message.extend_from_slice(&message);
+                                raw_message
+                            } else {
+                                message
+                            };
+                            messages.push(SieveMessage {
+                                raw_message: message.into(),
+                                file_into: Vec::new(),
+                                flags: Vec::new(),
+                            });
+                            input = true.into();
+                        }
+                        #[allow(unreachable_patterns)]
+                        _ => unreachable!(),
+                    },
+
+                    #[cfg(test)]
+                    Err(store::sieve::runtime::RuntimeError::ScriptErrorMessage(err)) => {
+                        panic!("Sieve test failed: {}", err);
                     }
-                    Event::CreatedMessage { message, .. } => {
-                        messages.push(SieveMessage {
-                            raw_message: message.into(),
-                            file_into: Vec::new(),
-                            flags: Vec::new(),
-                        });
+
+                    Err(err) => {
+                        debug!("Sieve script runtime error: {}", err);
+                        if !is_global {
+                            script_error = err.to_string().into();
+                        }
                         input = true.into();
                     }
-                    #[allow(unreachable_patterns)]
-                    _ => unreachable!(),
-                },
-
-                #[cfg(test)]
-                Err(store::sieve::runtime::RuntimeError::ScriptErrorMessage(err)) => {
-                    panic!("Sieve test failed: {}", err);
                 }
+            }
 
-                Err(err) => {
-                    debug!("Sieve script runtime error: {}", err);
-                    input = true.into();
-                }
+            if is_aborted {
+                break;
             }
         }
 
+        // Undo the actions of aborted scripts and fall back to an implicit keep
+        if is_aborted {
+            result.messages.truncate(num_outgoing);
+            messages.truncate(1);
+            messages[0].file_into = vec![default_id];
+            messages[0].flags.clear();
+            new_ids.clear();
+            global_flags.clear();
+            reject_reason = None;
+            do_discard = false;
+            do_deliver = true;
+        }
+
         for (pos, message) in messages.iter().enumerate() {
             println!(
                 "----- message {} {:?} {:?}",
@@ -785,7 +1089,28 @@ where
 
         // Fail-safe, no discard and no keep seen, assume that something went wrong and file anyway.
         if !do_deliver && !do_discard {
-            messages[0].file_into.push(INBOX_ID);
+            messages[0].file_into.push(default_id);
+        }
+
+        // Discarded messages are filed into the fallback folder, when one is configured
+        if do_discard && reject_reason.is_none() && messages[0].file_into.is_empty() {
+            if let Some(discard_id) = self
+                .config
+                .sieve_discard_folder
+                .as_ref()
+                .and_then(|folder| self.mail_create_mailbox(result, account_id, folder))
+            {
+                messages[0].file_into.push(discard_id);
+            }
+        }
+
+        // Flags set by global scripts apply to every delivered message
+        for message in &mut messages {
+            for flag in &global_flags {
+                if !message.flags.contains(flag) {
+                    message.flags.push(flag.clone());
+                }
+            }
         }
 
         // Deliver messages
@@ -809,14 +1134,15 @@ where
                 };
 
                 // Parse message if needed
-                let message = if message_id == 0 && !instance.has_message_changed() {
-                    instance.take_message()
-                } else if let Some(message) = Message::parse(raw_message.as_ref()) {
-                    message
-                } else {
-                    debug!("Failed to parse Sieve generated message.");
-                    continue;
-                };
+                let message =
+                    if message_id == 0 && !has_message_changed && !instance.has_message_changed() {
+                        instance.take_message()
+                    } else if let Some(message) = Message::parse(raw_message.as_ref()) {
+                        message
+                    } else {
+                        debug!("Failed to parse Sieve generated message.");
+                        continue;
+                    };
 
                 // Deliver message
                 if self
@@ -838,7 +1164,9 @@ where
         }
 
         // Save Sieve script changes
-        if active_script.has_changes || !new_ids.is_empty() {
+        if let Some(mut active_script) = active_script.filter(|active_script| {
+            active_script.has_changes || !new_ids.is_empty() || script_error.is_some()
+        }) {
             drop(instance);
             active_script.seen_ids.extend(new_ids);
             let mut changes = TinyORM::track_changes(&active_script.orm);
@@ -869,6 +1197,14 @@ where
                     },
                 },
             );
+            if let Some(script_error) = script_error {
+                changes.set(
+                    jmap_sieve::sieve_script::schema::Property::LastError,
+                    jmap_sieve::sieve_script::schema::Value::Text {
+                        value: script_error,
+                    },
+                );
+            }
             let mut document = Document::new(Collection::SieveScript, active_script.document_id);
             active_script.orm.merge(&mut document, changes).ok();
             let mut batch = WriteBatch::new(account_id);
@@ -898,6 +1234,124 @@ where
         }
     }
 
+    fn mail_plus_address_mailbox(
+        &self,
+        account_id: AccountId,
+        envelope_to: &str,
+    ) -> Option<DocumentId> {
+        if !self.config.lmtp_plus_addressing || !self.config.lmtp_plus_addressing_fileinto {
+            return None;
+        }
+        let email = sanitize_email(envelope_to)?;
+        let (_, tag) = split_plus_address(&email)?;
+
+        // Addresses containing a '+' that are registered as-is are not tagged
+        if !matches!(
+            self.find_recipient(email.clone()).ok()?.as_ref(),
+            RecipientType::NotFound
+        ) {
+            return None;
+        }
+
+        // Tags are chosen by the sender, so only existing top-level mailboxes are used
+        self.mailbox_get_by_name(
+            account_id,
+            &join_mailbox_path([tag], self.config.mailbox_path_separator),
+        )
+        .ok()?
+    }
+
+    fn mail_large_message_mailbox(
+        &self,
+        result: &mut IngestResult,
+        account_id: AccountId,
+        size: usize,
+    ) -> Option<DocumentId> {
+        if !self.config.is_large_message(size) {
+            return None;
+        }
+        let folder = self.config.lmtp_large_message_folder.as_ref()?;
+
+        self.mail_create_mailbox(result, account_id, folder)
+    }
+
+    fn mail_create_mailbox(
+        &self,
+        result: &mut IngestResult,
+        account_id: AccountId,
+        path: &str,
+    ) -> Option<DocumentId> {
+        match self.mailbox_create_path(account_id, path) {
+            Ok(Some((document_id, changes))) => {
+                if let Some(changes) = changes {
+                    result.last_change_id = changes.change_id;
+                    result.changes.insert(account_id, changes);
+                }
+                Some(document_id)
+            }
+            Ok(None) => None,
+            Err(err) => {
+                error!(
+                    "Failed to create mailbox '{}' for account {}: {}",
+                    path, account_id, err
+                );
+                None
+            }
+        }
+    }
+
+    fn mail_quarantine(
+        &self,
+        result: &mut IngestResult,
+        raw_message: &[u8],
+        blob_id: &BlobId,
+    ) -> DeliveryStatus {
+        let account = if let Some(account) = &self.config.lmtp_virus_quarantine_account {
+            account
+        } else {
+            return DeliveryStatus::internal_error();
+        };
+        let account_id = match self.find_individual(account) {
+            Ok(Some(account_id)) => account_id,
+            Ok(None) => {
+                error!("Quarantine account {} does not exist.", account);
+                return DeliveryStatus::internal_error();
+            }
+            Err(err) => {
+                error!("Failed to lookup quarantine account {}: {}", account, err);
+                return DeliveryStatus::internal_error();
+            }
+        };
+        let message = if let Some(message) = Message::parse(raw_message) {
+            message
+        } else {
+            return DeliveryStatus::perm_failure("Failed to parse message.");
+        };
+
+        // Sieve scripts are not run on quarantined messages
+        match self.mail_create_mailbox(
+            result,
+            account_id,
+            &self.config.lmtp_virus_quarantine_folder,
+        ) {
+            Some(mailbox_id)
+                if self
+                    .mail_deliver_mailbox(
+                        result,
+                        account_id,
+                        message,
+                        blob_id,
+                        &[mailbox_id],
+                        Vec::new(),
+                    )
+                    .is_ok() =>
+            {
+                DeliveryStatus::Success
+            }
+            _ => DeliveryStatus::internal_error(),
+        }
+    }
+
     fn mail_deliver_mailbox(
         &self,
         result: &mut IngestResult,
@@ -905,8 +1359,51 @@ where
         message: Message,
         blob_id: &BlobId,
         mailbox_ids: &[DocumentId],
-        flags: Vec<Tag>,
+        mut flags: Vec<Tag>,
     ) -> Result<(), ()> {
+        // Add keywords based on the configured header rules
+        if !self.config.lmtp_header_keywords.is_empty() {
+            for keyword in self.mail_header_keywords(account_id, &message) {
+                if !flags.contains(&keyword) {
+                    flags.push(keyword);
+                }
+            }
+        }
+
+        // Flag large messages
+        if let Some(keyword) = &self.config.lmtp_large_message_keyword {
+            if self.config.is_large_message(message.raw_message.len()) {
+                let keyword = Keyword::parse(keyword).tag;
+                if !flags.contains(&keyword) {
+                    flags.push(keyword);
+                }
+            }
+        }
+
+        // Authentication results are taken from the topmost header, which is the
+        // one added by the receiving MTA, unless a trusted authserv-id is configured.
+        let auth_results = message.parts.first().and_then(|root_part| {
+            root_part
+                .headers
+                .iter()
+                .filter(|header| {
+                    header
+                        .name
+                        .as_str()
+                        .eq_ignore_ascii_case("Authentication-Results")
+                })
+                .find_map(|header| {
+                    EmailAuthResults::parse(
+                        &String::from_utf8_lossy(
+                            message
+                                .raw_message
+                                .get(header.offset_start..header.offset_end)?,
+                        ),
+                        self.config.lmtp_authserv_id.as_deref(),
+                    )
+                })
+        });
+
         // Prepare batch
         let mut batch = WriteBatch::new(account_id);
 
@@ -929,6 +1426,9 @@ where
         for flag in flags {
             orm.tag(Property::Keywords, flag);
         }
+        for tag in auth_results.map(|r| r.into_tags()).unwrap_or_default() {
+            orm.tag(Property::AuthenticationResults, tag);
+        }
 
         // Serialize ORM
         if let Err(err) = orm.insert(&mut document) {
@@ -973,6 +1473,290 @@ where
             }
         }
     }
+
+    fn mail_header_keywords(&self, account_id: AccountId, message: &Message) -> Vec<Tag> {
+        let mut keywords = Vec::new();
+        let headers = if let Some(root_part) = message.parts.first() {
+            &root_part.headers
+        } else {
+            return keywords;
+        };
+
+        // Rules scoped to an address or domain need the account's e-mail address
+        let address = if self
+            .config
+            .lmtp_header_keywords
+            .iter()
+            .any(|rule| rule.scope.is_some())
+        {
+            match self.get_account_details(account_id) {
+                Ok(Some((email, _, _))) => Some(email),
+                Ok(None) => None,
+                Err(err) => {
+                    error!(
+                        "Failed to obtain account details for {}: {}",
+                        account_id, err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        for header in headers {
+            let value = if let Some(value) = message
+                .raw_message
+                .get(header.offset_start..header.offset_end)
+            {
+                String::from_utf8_lossy(value)
+            } else {
+                continue;
+            };
+            let value = value.trim();
+
+            for rule in &self.config.lmtp_header_keywords {
+                if rule.matches(header.name.as_str(), value) && rule.applies_to(address.as_deref())
+                {
+                    let keyword = Keyword::parse(&rule.keyword).tag;
+                    if !keywords.contains(&keyword) {
+                        keywords.push(keyword);
+                    }
+                }
+            }
+        }
+
+        keywords
+    }
+
+    fn mail_auto_reply_allowed(&self, from: &str, to: &str) -> bool {
+        if let Some(auto_replies) = &self.auto_replies {
+            let key = (from.to_lowercase(), to.trim().to_lowercase());
+            if auto_replies.contains_key(&key) {
+                false
+            } else {
+                auto_replies.insert(key, ());
+                true
+            }
+        } else {
+            true
+        }
+    }
+
+    fn mail_build_bounce<'x>(
+        &self,
+        envelope_from: &str,
+        raw_message: &[u8],
+        statuses: impl Iterator<Item = (AccountId, &'x DeliveryStatus)>,
+    ) -> Option<OutgoingMessage> {
+        let postmaster = self.config.lmtp_postmaster.as_ref()?;
+
+        // Never bounce messages sent by the null sender or by the postmaster itself
+        if envelope_from.is_empty()
+            || envelope_from == "<>"
+            || envelope_from.eq_ignore_ascii_case(postmaster)
+        {
+            return None;
+        }
+
+        let reporting_mta = postmaster.rsplit_once('@').map_or("localhost", |r| r.1);
+        let mut text = String::from(concat!(
+            "Your message could not be delivered to one or more recipients.\r\n",
+            "\r\n"
+        ));
+        let mut report = format!("Reporting-MTA: dns; {}\r\n", reporting_mta);
+        let mut has_failures = false;
+
+        for (account_id, status) in statuses {
+            // Temporary failures are not retried once the message was accepted
+            let (reply, code, reason): (&str, &str, _) = match status {
+                DeliveryStatus::PermanentFailure { code, reason } => ("550", code.as_ref(), reason),
+                DeliveryStatus::TemporaryFailure { reason } => ("451", "4.3.0", reason),
+                DeliveryStatus::Success | DeliveryStatus::Duplicated => continue,
+            };
+            let rcpt = match self.get_account_details(account_id) {
+                Ok(Some((email, _, _))) => email,
+                Ok(None) => continue,
+                Err(err) => {
+                    error!(
+                        "Failed to obtain account details for {}: {}",
+                        account_id, err
+                    );
+                    continue;
+                }
+            };
+
+            text.push_str(&format!("<{}>: {}\r\n", rcpt, reason));
+            report.push_str(&format!(
+                concat!(
+                    "\r\n",
+                    "Final-Recipient: rfc822; {}\r\n",
+                    "Action: failed\r\n",
+                    "Status: {}\r\n",
+                    "Diagnostic-Code: smtp; {} {} {}\r\n"
+                ),
+                rcpt, code, reply, code, reason
+            ));
+            has_failures = true;
+        }
+
+        if !has_failures {
+            return None;
+        }
+
+        // Include the headers of the original message
+        let headers = Message::parse(raw_message)
+            .and_then(|message| raw_message.get(..message.parts.first()?.offset_body))
+            .unwrap_or(raw_message);
+
+        let mut builder = MessageBuilder::new()
+            .header(
+                "From",
+                Address::Address(EmailAddress {
+                    name: Some("Mail Delivery System".into()),
+                    email: postmaster.into(),
+                }),
+            )
+            .header(
+                "To",
+                Address::Address(EmailAddress {
+                    name: None,
+                    email: envelope_from.into(),
+                }),
+            )
+            .header(
+                "Message-ID",
+                MessageId::new(self.mail_message_id(postmaster.as_str().into())),
+            )
+            .header("Auto-Submitted", Raw::new("auto-generated"))
+            .subject("Undelivered Mail Returned to Sender");
+        builder.body = MimePart {
+            headers: vec![(
+                "Content-Type".into(),
+                ContentType::new("multipart/report")
+                    .attribute("report-type", "delivery-status")
+                    .into(),
+            )],
+            contents: BodyPart::Multipart(vec![
+                MimePart {
+                    headers: vec![(
+                        "Content-Type".into(),
+                        ContentType::new("text/plain")
+                            .attribute("charset", "utf-8")
+                            .into(),
+                    )],
+                    contents: BodyPart::Text(text.into()),
+                },
+                MimePart {
+                    headers: vec![(
+                        "Content-Type".into(),
+                        ContentType::new("message/delivery-status").into(),
+                    )],
+                    contents: BodyPart::Text(report.into()),
+                },
+                MimePart {
+                    headers: vec![(
+                        "Content-Type".into(),
+                        ContentType::new("text/rfc822-headers").into(),
+                    )],
+                    contents: BodyPart::Text(String::from_utf8_lossy(headers)),
+                },
+            ]),
+        }
+        .into();
+
+        let mut message = Vec::with_capacity(headers.len() + 1024);
+        builder.write_to(&mut message).ok()?;
+
+        Some(OutgoingMessage {
+            mail_from: String::new(),
+            rcpt_to: vec![envelope_from.to_string()],
+            message,
+        })
+    }
+}
+
+// Messages marked as automatically generated (RFC 3834) or sent to
+// bulk recipients, which must not receive auto-replies.
+fn is_automated_message(message: &Message) -> bool {
+    message.parts.first().map_or(false, |root_part| {
+        root_part.headers.iter().any(|header| {
+            let value = if let Some(value) = message
+                .raw_message
+                .get(header.offset_start..header.offset_end)
+            {
+                String::from_utf8_lossy(value)
+            } else {
+                return false;
+            };
+            let value = value.split(';').next().unwrap_or_default().trim();
+
+            match header.name.as_str() {
+                name if name.eq_ignore_ascii_case("Auto-Submitted") => {
+                    !value.eq_ignore_ascii_case("no")
+                }
+                name if name.eq_ignore_ascii_case("Precedence") => ["bulk", "list", "junk"]
+                    .iter()
+                    .any(|precedence| value.eq_ignore_ascii_case(precedence)),
+                _ => false,
+            }
+        })
+    })
+}
+
+#[derive(Default)]
+struct SieveUsage {
+    redirects: usize,
+    fileinto: usize,
+    actions: usize,
+}
+
+impl SieveUsage {
+    // Returns the reason for aborting the script when an event exceeds the limits
+    fn track(&mut self, event: &Event, limits: &SieveLimits, started: Instant) -> Option<String> {
+        match event {
+            Event::SendMessage { message_id, .. } => {
+                if *message_id == 0 {
+                    self.redirects += 1;
+                    if self.redirects > limits.max_redirects {
+                        return format!(
+                            "Script exceeded the maximum of {} redirects.",
+                            limits.max_redirects
+                        )
+                        .into();
+                    }
+                }
+                self.actions += 1;
+            }
+            Event::FileInto { .. } => {
+                self.fileinto += 1;
+                if self.fileinto > limits.max_fileinto {
+                    return format!(
+                        "Script exceeded the maximum of {} fileinto actions.",
+                        limits.max_fileinto
+                    )
+                    .into();
+                }
+                self.actions += 1;
+            }
+            Event::Keep { .. } | Event::Discard | Event::Reject { .. } => {
+                self.actions += 1;
+            }
+            _ => (),
+        }
+
+        if self.actions > limits.max_actions {
+            format!(
+                "Script exceeded the maximum of {} actions.",
+                limits.max_actions
+            )
+            .into()
+        } else if started.elapsed().as_millis() as u64 > limits.max_time {
+            format!("Script exceeded the time limit of {} ms.", limits.max_time).into()
+        } else {
+            None
+        }
+    }
 }
 
 struct SieveMessage<'x> {
@@ -1008,6 +1792,39 @@ impl DeliveryStatus {
         }
     }
 
+    /// Combines the statuses of the members of a list into a single status,
+    /// which is successful if the message was delivered to at least one member.
+    pub fn aggregate<'x>(statuses: impl Iterator<Item = &'x DeliveryStatus>) -> Self {
+        // Count number of successes and failures
+        let mut success = 0;
+        let mut temp_failures = 0;
+
+        for status in statuses {
+            match status {
+                DeliveryStatus::Success | DeliveryStatus::Duplicated => {
+                    success += 1;
+                }
+                DeliveryStatus::TemporaryFailure { .. } => {
+                    temp_failures += 1;
+                }
+                DeliveryStatus::PermanentFailure { .. } => (),
+            }
+        }
+
+        if success > 0 {
+            DeliveryStatus::Success
+        } else if temp_failures > 0 {
+            DeliveryStatus::TemporaryFailure {
+                reason: "temporary failure".into(),
+            }
+        } else {
+            DeliveryStatus::PermanentFailure {
+                code: "5.5.0".into(),
+                reason: "permanent failure".into(),
+            }
+        }
+    }
+
     pub fn perm_failure(reason: impl Into<Cow<'static, str>>) -> Self {
         DeliveryStatus::PermanentFailure {
             code: "5.5.0".into(),
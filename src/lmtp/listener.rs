@@ -37,11 +37,14 @@ use tokio::{io::AsyncWriteExt, net::TcpListener, sync::watch};
 use tokio_rustls::TlsAcceptor;
 
 use crate::{
-    cluster::rpc::tls::load_tls_server_config, lmtp::session::Session, server::failed_to,
+    cluster::rpc::tls::load_tls_server_config,
+    lmtp::{proxy::read_proxy_header, session::Session},
+    server::failed_to,
     JMAPServer,
 };
 
 const TIMEOUT: Duration = Duration::from_secs(5 * 60); // 5 minutes
+const PROXY_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_LMTP_PORT: u16 = 11200;
 
 pub fn init_lmtp() -> (watch::Sender<bool>, watch::Receiver<bool>) {
@@ -63,21 +66,10 @@ pub fn spawn_lmtp<T>(
     info!("Starting LMTP service at {}...", bind_addr);
 
     // Parse allowed IPs
-    let trusted_ips = if let Some(trusted_ips_) = settings.get("lmtp-trusted-ips") {
-        let mut trusted_ips = Vec::new();
-        for ip in trusted_ips_.split(';') {
-            trusted_ips.push(ip.parse::<IpAddr>().unwrap_or_else(|_| {
-                failed_to(&format!("parse 'lmtp-trusted-ips', invalid ip {}.", ip));
-            }));
-        }
-        if !trusted_ips.is_empty() {
-            trusted_ips.into()
-        } else {
-            failed_to("parse 'lmtp-trusted-ips', no entries found.");
-        }
-    } else {
-        None
-    };
+    let trusted_ips = parse_ip_list(settings, "lmtp-trusted-ips");
+
+    // Parse upstreams allowed to send PROXY protocol headers
+    let proxy_ips = parse_ip_list(settings, "lmtp-proxy-trusted-ips");
 
     // Build TLS acceptor
     let tls_acceptor = if let (Some(cert_path), Some(key_path)) = (
@@ -97,6 +89,19 @@ pub fn spawn_lmtp<T>(
         tls_only = false;
     }
 
+    // Greeting and HELP texts, "{hostname}" is replaced by the server's hostname
+    let greeting = settings.get("lmtp-greeting").unwrap_or_else(|| {
+        concat!(
+            "{hostname} Stalwart LMTP v",
+            env!("CARGO_PKG_VERSION"),
+            " at your service."
+        )
+        .to_string()
+    });
+    let help = settings
+        .get("lmtp-help")
+        .unwrap_or_else(|| "Help can be found at https://stalw.art/jmap/".to_string());
+
     tokio::spawn(async move {
         // Start listening for LMTP connections.
         let listener = match TcpListener::bind(bind_addr).await {
@@ -113,16 +118,10 @@ pub fn spawn_lmtp<T>(
                 .unwrap_or("localhost")
                 .to_string(),
         );
-        let greeting = Arc::new(
-            format!(
-                concat!(
-                    "220 {} Stalwart LMTP v",
-                    env!("CARGO_PKG_VERSION"),
-                    " at your service.\r\n"
-                ),
-                &hostname
-            )
-            .into_bytes(),
+        let greeting =
+            Arc::new(format!("220 {}\r\n", greeting.replace("{hostname}", &hostname)).into_bytes());
+        let help = Arc::new(
+            format!("250 2.0.0 {}\r\n", help.replace("{hostname}", &hostname)).into_bytes(),
         );
 
         loop {
@@ -130,11 +129,11 @@ pub fn spawn_lmtp<T>(
                 stream = listener.accept() => {
                     match stream {
                         Ok((mut stream, peer_addr)) => {
-                            if let Some(trusted_ips) = &trusted_ips {
-                                if !trusted_ips.contains(&peer_addr.ip()) {
-                                    debug!("Dropping LMTP connection from unknow address {}.", peer_addr.ip());
-                                    continue;
-                                }
+                            let is_proxy = proxy_ips
+                                .as_ref()
+                                .map_or(false, |proxy_ips| proxy_ips.contains(&peer_addr.ip()));
+                            if !is_proxy && !is_trusted(&trusted_ips, &peer_addr) {
+                                continue;
                             }
 
                             let shutdown_rx = shutdown_rx.clone();
@@ -142,8 +141,35 @@ pub fn spawn_lmtp<T>(
                             let greeting = greeting.clone();
                             let tls_acceptor = tls_acceptor.clone();
                             let hostname = hostname.clone();
+                            let help = help.clone();
+                            let trusted_ips = trusted_ips.clone();
 
                             tokio::spawn(async move {
+                                // Obtain the real remote address from the PROXY header
+                                let peer_addr = if is_proxy {
+                                    match tokio::time::timeout(
+                                        PROXY_TIMEOUT,
+                                        read_proxy_header(&mut stream),
+                                    )
+                                    .await
+                                    {
+                                        // UNKNOWN and LOCAL connections keep the address of the proxy
+                                        Ok(Ok(remote_addr)) => {
+                                            let remote_addr = remote_addr.unwrap_or(peer_addr);
+                                            if !is_trusted(&trusted_ips, &remote_addr) {
+                                                return;
+                                            }
+                                            remote_addr
+                                        }
+                                        _ => {
+                                            debug!("Invalid PROXY header received from {}.", peer_addr);
+                                            return;
+                                        }
+                                    }
+                                } else {
+                                    peer_addr
+                                };
+
                                 if tls_only {
                                     let mut stream = match tls_acceptor.as_ref().unwrap().accept(stream).await {
                                         Ok(stream) => stream,
@@ -160,7 +186,7 @@ pub fn spawn_lmtp<T>(
                                     }
 
                                     handle_conn(
-                                        Session::new(core, peer_addr, stream.into(), None, hostname),
+                                        Session::new(core, peer_addr, stream.into(), None, hostname, help),
                                         shutdown_rx
                                     ).await;
                                 } else {
@@ -171,7 +197,7 @@ pub fn spawn_lmtp<T>(
                                     }
 
                                     handle_conn(
-                                        Session::new(core, peer_addr, stream.into(), tls_acceptor, hostname),
+                                        Session::new(core, peer_addr, stream.into(), tls_acceptor, hostname, help),
                                         shutdown_rx
                                     ).await;
                                 }
@@ -191,6 +217,37 @@ pub fn spawn_lmtp<T>(
     });
 }
 
+fn parse_ip_list(settings: &EnvSettings, key: &str) -> Option<Arc<Vec<IpAddr>>> {
+    if let Some(ips_) = settings.get(key) {
+        let mut ips = Vec::new();
+        for ip in ips_.split(';') {
+            ips.push(ip.parse::<IpAddr>().unwrap_or_else(|_| {
+                failed_to(&format!("parse '{}', invalid ip {}.", key, ip));
+            }));
+        }
+        if !ips.is_empty() {
+            Arc::new(ips).into()
+        } else {
+            failed_to(&format!("parse '{}', no entries found.", key));
+        }
+    } else {
+        None
+    }
+}
+
+fn is_trusted(trusted_ips: &Option<Arc<Vec<IpAddr>>>, peer_addr: &SocketAddr) -> bool {
+    if let Some(trusted_ips) = trusted_ips {
+        if !trusted_ips.contains(&peer_addr.ip()) {
+            debug!(
+                "Dropping LMTP connection from unknow address {}.",
+                peer_addr.ip()
+            );
+            return false;
+        }
+    }
+    true
+}
+
 pub async fn handle_conn<T>(mut session: Session<T>, mut shutdown_rx: watch::Receiver<bool>)
 where
     T: for<'x> Store<'x> + 'static,
@@ -21,14 +21,43 @@
  * for more details.
 */
 
+pub mod callout;
 pub mod ingest;
 pub mod listener;
+pub mod proxy;
 pub mod request;
 pub mod response;
+pub mod scan;
 pub mod session;
+pub mod srs;
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 pub struct OutgoingMessage {
     pub mail_from: String,
     pub rcpt_to: Vec<String>,
     pub message: Vec<u8>,
 }
+
+/// Tracks a message delivery in progress, used to drain deliveries on shutdown.
+pub struct InFlightDelivery {
+    deliveries: Arc<AtomicUsize>,
+}
+
+impl InFlightDelivery {
+    pub fn new(deliveries: &Arc<AtomicUsize>) -> Self {
+        deliveries.fetch_add(1, Ordering::Relaxed);
+        InFlightDelivery {
+            deliveries: deliveries.clone(),
+        }
+    }
+}
+
+impl Drop for InFlightDelivery {
+    fn drop(&mut self) {
+        self.deliveries.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,184 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads a PROXY protocol (v1 or v2) header from the stream and returns the
+/// address of the original client. `None` is returned when the upstream
+/// does not provide an address (v1 UNKNOWN or v2 LOCAL), in which case the
+/// address of the connecting peer should be used.
+pub async fn read_proxy_header<R>(stream: &mut R) -> Result<Option<SocketAddr>, ()>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header[..5]).await.map_err(|_| ())?;
+
+    if &header[..5] == b"PROXY" {
+        let mut line = Vec::with_capacity(V1_MAX_LENGTH);
+        line.extend_from_slice(b"PROXY");
+        loop {
+            let byte = stream.read_u8().await.map_err(|_| ())?;
+            line.push(byte);
+            if byte == b'\n' {
+                break;
+            } else if line.len() >= V1_MAX_LENGTH {
+                return Err(());
+            }
+        }
+        parse_v1(&line)
+    } else if header[..5] == V2_SIGNATURE[..5] {
+        stream.read_exact(&mut header[5..]).await.map_err(|_| ())?;
+        if &header[..12] != V2_SIGNATURE || header[12] >> 4 != 2 {
+            return Err(());
+        }
+        let mut payload = vec![0u8; u16::from_be_bytes([header[14], header[15]]) as usize];
+        stream.read_exact(&mut payload).await.map_err(|_| ())?;
+        parse_v2(header[12] & 0x0f, header[13] >> 4, &payload)
+    } else {
+        Err(())
+    }
+}
+
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>, ()> {
+    let line = std::str::from_utf8(line)
+        .ok()
+        .and_then(|line| line.strip_suffix("\r\n"))
+        .ok_or(())?;
+    let mut parts = line.split(' ').skip(1);
+
+    let is_ipv4 = match parts.next() {
+        Some("TCP4") => true,
+        Some("TCP6") => false,
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(()),
+    };
+    let mut addresses = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let ip = parts
+            .next()
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+            .ok_or(())?;
+        if ip.is_ipv4() != is_ipv4 {
+            return Err(());
+        }
+        addresses.push(ip);
+    }
+    let mut ports = Vec::with_capacity(2);
+    for _ in 0..2 {
+        ports.push(
+            parts
+                .next()
+                .and_then(|port| port.parse::<u16>().ok())
+                .ok_or(())?,
+        );
+    }
+
+    if parts.next().is_none() {
+        Ok(Some(SocketAddr::new(addresses[0], ports[0])))
+    } else {
+        Err(())
+    }
+}
+
+fn parse_v2(command: u8, family: u8, payload: &[u8]) -> Result<Option<SocketAddr>, ()> {
+    match command {
+        0x00 => return Ok(None),
+        0x01 => (),
+        _ => return Err(()),
+    }
+
+    match family {
+        0x01 => {
+            let payload = payload.get(..12).ok_or(())?;
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&payload[..4]).unwrap());
+            let port = u16::from_be_bytes([payload[8], payload[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        0x02 => {
+            let payload = payload.get(..36).ok_or(())?;
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&payload[..16]).unwrap());
+            let port = u16::from_be_bytes([payload[32], payload[33]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::read_proxy_header;
+
+    #[tokio::test]
+    async fn proxy_header() {
+        for (header, expected_result) in [
+            (
+                b"PROXY TCP4 192.0.2.1 127.0.0.1 56324 11200\r\nLHLO".to_vec(),
+                Ok(Some("192.0.2.1:56324".parse::<SocketAddr>().unwrap())),
+            ),
+            (
+                b"PROXY TCP6 2001:db8::1 ::1 4000 11200\r\n".to_vec(),
+                Ok(Some("[2001:db8::1]:4000".parse::<SocketAddr>().unwrap())),
+            ),
+            (b"PROXY UNKNOWN\r\n".to_vec(), Ok(None)),
+            (
+                b"PROXY TCP4 2001:db8::1 ::1 4000 11200\r\n".to_vec(),
+                Err(()),
+            ),
+            (
+                b"PROXY TCP4 192.0.2.1 127.0.0.1 56324\r\n".to_vec(),
+                Err(()),
+            ),
+            (b"LHLO localhost\r\n".to_vec(), Err(())),
+            (
+                [
+                    &b"\r\n\r\n\0\r\nQUIT\n"[..],
+                    &[0x21, 0x11, 0x00, 0x0c],
+                    &[192, 0, 2, 1, 127, 0, 0, 1],
+                    &56324u16.to_be_bytes(),
+                    &11200u16.to_be_bytes(),
+                ]
+                .concat(),
+                Ok(Some("192.0.2.1:56324".parse::<SocketAddr>().unwrap())),
+            ),
+            (
+                [&b"\r\n\r\n\0\r\nQUIT\n"[..], &[0x20, 0x00, 0x00, 0x00]].concat(),
+                Ok(None),
+            ),
+        ] {
+            assert_eq!(
+                read_proxy_header(&mut &header[..]).await,
+                expected_result,
+                "{:?}",
+                String::from_utf8_lossy(&header)
+            );
+        }
+    }
+}
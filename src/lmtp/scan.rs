@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Instant;
+
+use jmap_mail::{
+    mail_builder::{
+        headers::content_type::ContentType,
+        mime::{BodyPart, MimePart},
+    },
+    mail_parser::{Message, PartType},
+};
+use store::config::scan::{ScanVerdict, VirusScanner};
+
+// Scans each part of a message and replaces the infected ones with a text notice.
+// Returns `None` when the virus could not be located in any of the parts, or when
+// scanning all parts takes longer than the scanner timeout, in which case the
+// message cannot be delivered.
+pub fn strip_infected_parts(scanner: &dyn VirusScanner, raw_message: &[u8]) -> Option<Vec<u8>> {
+    let message = Message::parse(raw_message)?;
+    let deadline = Instant::now() + scanner.timeout();
+    let mut output = Vec::with_capacity(raw_message.len());
+    let mut last_offset = 0;
+
+    // The root part holds the message headers, only its subparts can be removed
+    for part in message.parts.iter().skip(1) {
+        if matches!(part.body, PartType::Multipart(_)) {
+            continue;
+        } else if Instant::now() >= deadline {
+            return None;
+        }
+        let name = match scanner.scan(part.get_contents()) {
+            ScanVerdict::Clean => continue,
+            ScanVerdict::Infected(name) => name,
+            ScanVerdict::Failed => return None,
+        };
+
+        output.extend_from_slice(raw_message.get(last_offset..part.offset_header)?);
+        MimePart {
+            headers: vec![(
+                "Content-Type".into(),
+                ContentType::new("text/plain")
+                    .attribute("charset", "utf-8")
+                    .into(),
+            )],
+            contents: BodyPart::Text(
+                format!(
+                    "This part was removed because it contained a virus ({}).\r\n",
+                    name
+                )
+                .into(),
+            ),
+        }
+        .write_part(&mut output)
+        .ok()?;
+        last_offset = part.offset_end;
+    }
+
+    if last_offset > 0 {
+        output.extend_from_slice(raw_message.get(last_offset..)?);
+        Some(output)
+    } else {
+        None
+    }
+}
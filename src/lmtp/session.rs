@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::SystemTime};
 
 use actix_web::web;
 use serde::{Deserialize, Serialize};
@@ -38,6 +38,7 @@ use super::{
     ingest::DeliveryStatus,
     request::{Event, Param, Request, RequestParser},
     response::{Extension, Response},
+    srs::{is_srs_address, Srs},
 };
 
 const MAX_COMMAND_LENGTH: usize = 1024;
@@ -49,6 +50,7 @@ where
     pub core: web::Data<JMAPServer<T>>,
     pub tls_acceptor: Option<Arc<TlsAcceptor>>,
     pub hostname: Arc<String>,
+    pub help: Arc<Vec<u8>>,
     pub parser: RequestParser,
     pub peer_addr: SocketAddr,
     pub stream: Stream,
@@ -74,6 +76,11 @@ pub enum RcptType {
         name: String,
         status: DeliveryStatus,
     },
+    Forward {
+        address: String,
+        name: String,
+        status: DeliveryStatus,
+    },
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -93,6 +100,7 @@ where
         stream: Stream,
         tls_acceptor: Option<Arc<TlsAcceptor>>,
         hostname: Arc<String>,
+        help: Arc<Vec<u8>>,
     ) -> Self {
         Self {
             parser: RequestParser::new(MAX_COMMAND_LENGTH, core.store.config.mail_max_size),
@@ -107,6 +115,7 @@ where
             rcpt_to_dup: AHashSet::new(),
             message: Vec::new(),
             hostname,
+            help,
         }
     }
 
@@ -156,55 +165,89 @@ where
                             }
                         });
                     }
-                    Request::Rcpt { recipient, .. } => match self.expand_rcpt(&recipient).await {
-                        Some(recipient_) => match recipient_.as_ref() {
-                            RecipientType::Individual(account_id) => {
-                                self.write_bytes(
-                                    format!("250 2.1.5 Recipient <{}> accepted.\r\n", recipient)
-                                        .as_bytes(),
-                                )
+                    Request::Rcpt { recipient, .. }
+                        if self.core.store.config.srs_secret.is_some()
+                            && is_srs_address(&recipient) =>
+                    {
+                        // Bounces to SRS addresses are relayed to the original sender
+                        if let Some(address) = self.srs_decode(&recipient) {
+                            self.write_bytes(self.rcpt_accepted(&recipient, false).as_bytes())
                                 .await?;
-
-                                self.rcpt_to.push(RcptType::Mailbox {
-                                    id: *account_id,
-                                    name: recipient,
-                                    status: if self.rcpt_to_dup.insert(*account_id) {
-                                        DeliveryStatus::Success
-                                    } else {
-                                        DeliveryStatus::Duplicated
-                                    },
-                                });
-                            }
-                            RecipientType::List(account_ids) => {
-                                self.write_bytes(
-                                    format!("250 2.1.5 Recipient <{}> accepted.\r\n", recipient)
-                                        .as_bytes(),
-                                )
+                            self.rcpt_to.push(RcptType::Forward {
+                                address,
+                                name: recipient,
+                                status: DeliveryStatus::Success,
+                            });
+                        } else {
+                            self.write_bytes(b"550 5.1.1 Invalid or expired SRS address.\r\n")
                                 .await?;
+                        }
+                    }
+                    Request::Rcpt { recipient, .. } => match self.rcpt_callout(&recipient).await {
+                        Some(true) => match self.expand_rcpt(&recipient).await {
+                            Some(recipient_) => match recipient_.as_ref() {
+                                RecipientType::Individual(account_id) => {
+                                    let is_duplicate = !self.rcpt_to_dup.insert(*account_id);
+                                    self.write_bytes(
+                                        self.rcpt_accepted(&recipient, is_duplicate).as_bytes(),
+                                    )
+                                    .await?;
 
-                                let mut ids = Vec::with_capacity(account_ids.len());
-                                for (account_id, _) in account_ids {
-                                    if self.rcpt_to_dup.insert(*account_id) {
-                                        ids.push(*account_id);
-                                    }
+                                    self.rcpt_to.push(RcptType::Mailbox {
+                                        id: *account_id,
+                                        name: recipient,
+                                        status: if !is_duplicate {
+                                            DeliveryStatus::Success
+                                        } else {
+                                            DeliveryStatus::Duplicated
+                                        },
+                                    });
                                 }
-                                self.rcpt_to.push(RcptType::List {
-                                    status: if !ids.is_empty() {
-                                        DeliveryStatus::Success
+                                RecipientType::List(account_ids) => {
+                                    let mut ids = Vec::with_capacity(account_ids.len());
+                                    for (account_id, _) in account_ids {
+                                        if self.rcpt_to_dup.insert(*account_id) {
+                                            ids.push(*account_id);
+                                        }
+                                    }
+                                    let is_duplicate = ids.is_empty();
+                                    self.write_bytes(
+                                        self.rcpt_accepted(&recipient, is_duplicate).as_bytes(),
+                                    )
+                                    .await?;
+
+                                    self.rcpt_to.push(if !is_duplicate {
+                                        RcptType::List {
+                                            ids,
+                                            name: recipient,
+                                            status: DeliveryStatus::Success,
+                                        }
                                     } else {
-                                        DeliveryStatus::Duplicated
-                                    },
-                                    ids,
-                                    name: recipient,
-                                });
-                            }
-                            RecipientType::NotFound => {
-                                self.write_bytes(b"550 5.1.1 Mailbox not found.\r\n")
+                                        // Keep all members so the status of their earlier
+                                        // deliveries can be reported for this recipient.
+                                        RcptType::List {
+                                            ids: account_ids.iter().map(|(id, _)| *id).collect(),
+                                            name: recipient,
+                                            status: DeliveryStatus::Duplicated,
+                                        }
+                                    });
+                                }
+                                RecipientType::NotFound => {
+                                    self.write_bytes(b"550 5.1.1 Mailbox not found.\r\n")
+                                        .await?;
+                                }
+                            },
+                            None => {
+                                self.write_bytes(b"450 4.3.2 Temporary server failure.\r\n")
                                     .await?;
                             }
                         },
+                        Some(false) => {
+                            self.write_bytes(b"550 5.1.1 Mailbox not found.\r\n")
+                                .await?;
+                        }
                         None => {
-                            self.write_bytes(b"450 4.3.2 Temporary server failure.\r\n")
+                            self.write_bytes(b"450 4.4.3 Recipient verification failed.\r\n")
                                 .await?;
                         }
                     },
@@ -292,10 +335,8 @@ where
                         }
                     },
                     Request::Help { .. } => {
-                        self.write_bytes(
-                            b"250 2.0.0 Help can be found at https://stalw.art/jmap/\r\n",
-                        )
-                        .await?;
+                        let help = self.help.clone();
+                        self.write_bytes(&help).await?;
                     }
                     Request::StartTls => match (&self.stream, &self.tls_acceptor) {
                         (Stream::Clear(_), Some(_)) => {
@@ -393,6 +434,27 @@ where
         }
     }
 
+    fn rcpt_accepted(&self, recipient: &str, is_duplicate: bool) -> String {
+        if is_duplicate && self.core.store.config.lmtp_reject_duplicate_rcpt {
+            format!(
+                "250 2.1.5 Duplicate recipient <{}> ignored, message will be delivered once.\r\n",
+                recipient
+            )
+        } else {
+            format!("250 2.1.5 Recipient <{}> accepted.\r\n", recipient)
+        }
+    }
+
+    fn srs_decode(&self, recipient: &str) -> Option<String> {
+        Srs::new(self.core.store.config.srs_secret.as_ref()?).decode(
+            recipient,
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        )
+    }
+
     fn build_return_path(&self) -> String {
         format!(
             concat!(
@@ -401,8 +463,8 @@ where
                 "\t{}\r\n"
             ),
             self.remote_hostname.as_deref().unwrap_or("unknown"),
-            self.hostname.as_ref(),
             self.peer_addr.ip(),
+            self.hostname.as_ref(),
             Local::now().to_rfc2822()
         )
     }
@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::blake3;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const HASH_BYTES: usize = 5; // 40 bits, eight base32 characters
+const TIMESTAMP_PRECISION: u64 = 86400;
+const TIMESTAMP_SLOTS: u64 = 1024;
+const MAX_AGE_DAYS: u64 = 21;
+
+/// Sender Rewriting Scheme, used to rewrite the envelope sender of forwarded
+/// messages so they pass SPF checks at the next hop.
+pub struct Srs {
+    key: [u8; 32],
+}
+
+impl Srs {
+    pub fn new(secret: &str) -> Self {
+        Srs {
+            key: blake3::derive_key("stalwart-jmap srs", secret.as_bytes()),
+        }
+    }
+
+    /// Rewrites `sender` as an SRS0 address at `domain`. Null senders are not
+    /// rewritten and addresses that are already SRS encoded are returned as is.
+    pub fn encode(&self, sender: &str, domain: &str, now: u64) -> Option<String> {
+        let (local_part, sender_domain) = sender.rsplit_once('@')?;
+        if local_part.is_empty() || sender_domain.is_empty() {
+            return None;
+        } else if is_srs(local_part) {
+            return Some(sender.to_string());
+        }
+
+        let timestamp = encode_timestamp(now);
+        Some(format!(
+            "SRS0={}={}={}={}@{}",
+            self.hash(&timestamp, sender_domain, local_part),
+            timestamp,
+            sender_domain,
+            local_part,
+            domain
+        ))
+    }
+
+    /// Decodes an SRS0 address back to the original sender, provided that its
+    /// hash is valid and it has not expired.
+    pub fn decode(&self, address: &str, now: u64) -> Option<String> {
+        let (local_part, _) = address.rsplit_once('@')?;
+        if !is_srs(local_part) || !local_part[..5].eq_ignore_ascii_case("SRS0=") {
+            return None;
+        }
+        let mut parts = local_part[5..].splitn(4, '=');
+        let hash = parts.next()?;
+        let timestamp = parts.next()?;
+        let sender_domain = parts.next()?;
+        let sender_local_part = parts.next()?;
+
+        if !sender_domain.is_empty()
+            && !sender_local_part.is_empty()
+            && hash.eq_ignore_ascii_case(&self.hash(timestamp, sender_domain, sender_local_part))
+            && decode_timestamp(timestamp).map_or(false, |timestamp| {
+                timestamp_age(timestamp, now) <= MAX_AGE_DAYS
+            })
+        {
+            Some(format!("{}@{}", sender_local_part, sender_domain))
+        } else {
+            None
+        }
+    }
+
+    fn hash(&self, timestamp: &str, domain: &str, local_part: &str) -> String {
+        // Hashed case-insensitively, as relays may change the case of the address
+        let mut hasher = blake3::Hasher::new_keyed(&self.key);
+        hasher.update(timestamp.to_ascii_uppercase().as_bytes());
+        hasher.update(domain.to_lowercase().as_bytes());
+        hasher.update(local_part.to_lowercase().as_bytes());
+        let hash = hasher.finalize().as_bytes()[..HASH_BYTES]
+            .iter()
+            .fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+        (0..HASH_BYTES * 8 / 5)
+            .rev()
+            .map(|pos| BASE32_ALPHABET[((hash >> (pos * 5)) & 0x1f) as usize] as char)
+            .collect()
+    }
+}
+
+/// Returns true if the local part of `address` looks like an SRS address.
+pub fn is_srs_address(address: &str) -> bool {
+    address
+        .rsplit_once('@')
+        .map_or(false, |(local_part, _)| is_srs(local_part))
+}
+
+fn is_srs(local_part: &str) -> bool {
+    local_part.len() > 5
+        && local_part.get(..5).map_or(false, |prefix| {
+            prefix.eq_ignore_ascii_case("SRS0=") || prefix.eq_ignore_ascii_case("SRS1=")
+        })
+}
+
+fn encode_timestamp(now: u64) -> String {
+    let timestamp = (now / TIMESTAMP_PRECISION) % TIMESTAMP_SLOTS;
+    [timestamp >> 5, timestamp & 0x1f]
+        .iter()
+        .map(|&value| BASE32_ALPHABET[value as usize] as char)
+        .collect()
+}
+
+fn decode_timestamp(timestamp: &str) -> Option<u64> {
+    if timestamp.len() != 2 {
+        return None;
+    }
+    timestamp.bytes().try_fold(0, |acc, ch| {
+        let ch = ch.to_ascii_uppercase();
+        let value = BASE32_ALPHABET.iter().position(|&c| c == ch)? as u64;
+        Some((acc << 5) | value)
+    })
+}
+
+fn timestamp_age(timestamp: u64, now: u64) -> u64 {
+    let today = (now / TIMESTAMP_PRECISION) % TIMESTAMP_SLOTS;
+    (today + TIMESTAMP_SLOTS - timestamp) % TIMESTAMP_SLOTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Srs;
+
+    #[test]
+    fn srs_round_trip() {
+        let srs = Srs::new("secret");
+        let now = 1660000000;
+
+        let address = srs
+            .encode("john@example.org", "forwarder.net", now)
+            .unwrap();
+        assert!(address.starts_with("SRS0="), "{}", address);
+        assert!(
+            address.ends_with("=example.org=john@forwarder.net"),
+            "{}",
+            address
+        );
+        assert_eq!(
+            srs.decode(&address, now + 86400).as_deref(),
+            Some("john@example.org")
+        );
+        assert_eq!(
+            srs.decode(&address.to_lowercase(), now).as_deref(),
+            Some("john@example.org")
+        );
+        assert_eq!(
+            srs.decode(&address.to_uppercase(), now).as_deref(),
+            Some("JOHN@EXAMPLE.ORG")
+        );
+        assert_eq!(address.split('=').nth(1).map(|hash| hash.len()), Some(8));
+
+        // Expired, tampered or foreign addresses are rejected
+        assert_eq!(srs.decode(&address, now + 30 * 86400), None);
+        assert_eq!(srs.decode(&address.replace("=john@", "=jane@"), now), None);
+        assert_eq!(Srs::new("other secret").decode(&address, now), None);
+        assert_eq!(srs.decode("john@example.org", now), None);
+
+        // Null senders and SRS addresses are not rewritten
+        assert_eq!(srs.encode("", "forwarder.net", now), None);
+        assert_eq!(
+            srs.encode(&address, "other.net", now).as_deref(),
+            Some(address.as_str())
+        );
+    }
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use actix_cors::Cors;
 use actix_web::{
@@ -37,7 +37,7 @@ use jmap::{
 };
 use jmap_sharing::principal::CreateAccount;
 use store::{
-    config::{env_settings::EnvSettings, jmap::JMAPConfig},
+    config::{env_settings::EnvSettings, jmap::JMAPConfig, sieve::SieveGlobalScripts},
     core::{collection::Collection, document::Document},
     moka::future::Cache,
     rand::{distributions::Alphanumeric, thread_rng, Rng},
@@ -48,9 +48,11 @@ use store::{
 
 use crate::{
     api::{
+        admin::handle_admin_compact,
         blob::{handle_jmap_download, handle_jmap_upload},
         request::handle_jmap_request,
         session::{handle_jmap_session, Session},
+        BodyTimeout,
     },
     authorization::{
         auth::SessionFactory,
@@ -91,6 +93,8 @@ where
         config,
         settings,
     );
+    store.sieve_global = SieveGlobalScripts::new(&store.sieve_compiler, settings)
+        .failed_to("load global Sieve scripts");
     store.sieve_runtime.set_env_variable(
         "host",
         gethostname::gethostname()
@@ -185,6 +189,12 @@ where
         ));
     }
 
+    // Cached callout results expire individually, entries are kept for the longest TTL
+    let rcpt_callout_ttl = Duration::from_secs(std::cmp::max(
+        store.config.lmtp_rcpt_callout_ttl,
+        store.config.lmtp_rcpt_callout_ttl_negative,
+    ));
+
     let server = web::Data::new(JMAPServer {
         store: store.into(),
         worker_pool: rayon::ThreadPoolBuilder::new()
@@ -200,15 +210,21 @@ where
         email_delivery: email_tx.clone(),
         housekeeper: housekeeper_tx,
         lmtp: lmtp_tx,
+        lmtp_deliveries: Arc::new(0.into()),
         sessions: Cache::builder()
             .initial_capacity(128)
             .time_to_live(HALF_HOUR_EXPIRY)
+            .support_invalidation_closures()
             .build(),
         rate_limiters: Cache::builder()
             .initial_capacity(128)
             .time_to_idle(ONE_HOUR_EXPIRY)
             .build(),
         oauth_codes: Cache::builder().time_to_live(ONE_HOUR_EXPIRY).build(),
+        rcpt_callouts: Cache::builder()
+            .initial_capacity(128)
+            .time_to_live(rcpt_callout_ttl)
+            .build(),
         oauth,
         cluster,
         base_session,
@@ -269,6 +285,15 @@ where
     );
 
     let strict_cors = settings.parse("strict-cors").unwrap_or(false);
+
+    // Connections that take too long to send their request are closed
+    let header_timeout =
+        Duration::from_millis(settings.parse("http-header-timeout").unwrap_or(5000));
+    let body_timeout = BodyTimeout(Duration::from_millis(
+        settings.parse("http-body-timeout").unwrap_or(60000),
+    ));
+    let max_connections = settings.parse("http-max-connections").unwrap_or(25000);
+
     let server = HttpServer::new(move || {
         App::new()
             .wrap(SessionFactory::new(jmap_server.clone()))
@@ -285,6 +310,7 @@ where
                 jmap_server.store.config.max_size_upload,
                 jmap_server.store.config.max_size_request,
             )))
+            .app_data(body_timeout)
             .app_data(jmap_server.clone())
             .route("/.well-known/jmap", web::get().to(handle_jmap_session::<T>))
             .route("/jmap", web::post().to(handle_jmap_request::<T>))
@@ -301,6 +327,7 @@ where
                 web::get().to(handle_jmap_event_source::<T>),
             )
             .route("/jmap/ws", web::get().to(handle_ws::<T>))
+            .route("/admin/compact", web::post().to(handle_admin_compact::<T>))
             .route("/auth", web::get().to(handle_user_device_auth::<T>))
             .route("/auth", web::post().to(handle_user_device_auth_post::<T>))
             .route("/auth/code", web::get().to(handle_user_code_auth::<T>))
@@ -314,7 +341,9 @@ where
                 "/.well-known/oauth-authorization-server",
                 web::get().to(handle_oauth_metadata::<T>),
             )
-    });
+    })
+    .client_request_timeout(header_timeout)
+    .max_connections(max_connections);
     if let Some(tls_config) = tls_config {
         server.bind_rustls(http_addr, tls_config)
     } else {
@@ -25,10 +25,13 @@ pub mod event_source;
 pub mod http;
 pub mod websocket;
 
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
 use crate::services::{email_delivery, housekeeper, state_change};
 use crate::{cluster, JMAPServer};
 use store::core::error::StoreError;
-use store::tracing::{debug, error};
+use store::tracing::{debug, error, warn};
 use store::ColumnFamily;
 use store::{
     serialize::{StoreDeserialize, StoreSerialize},
@@ -36,6 +39,8 @@ use store::{
 };
 use tokio::sync::oneshot;
 
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl<T> JMAPServer<T>
 where
     T: for<'x> Store<'x> + 'static,
@@ -128,31 +133,48 @@ where
             error!("Failed to send shutdown event to LMTP service.");
         }
 
+        // Wait for in-flight LMTP deliveries to complete
+        let started = Instant::now();
+        while self.lmtp_deliveries.load(Ordering::Relaxed) > 0 {
+            if started.elapsed() >= SHUTDOWN_TIMEOUT {
+                warn!(
+                    "Shutting down with {} LMTP deliveries in progress.",
+                    self.lmtp_deliveries.load(Ordering::Relaxed)
+                );
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // Persist queued submissions and wait for the current one to be relayed
+        let (tx, rx) = oneshot::channel();
         if self
-            .state_change
-            .send(state_change::Event::Stop)
+            .email_delivery
+            .send(email_delivery::Event::Shutdown { tx })
             .await
             .is_err()
         {
-            error!("Failed to send shutdown event to state manager.");
+            debug!("Failed to send shutdown event to e-mail delivery task.");
+        } else if tokio::time::timeout(SHUTDOWN_TIMEOUT, rx).await.is_err() {
+            warn!("Timed out waiting for e-mail delivery task to shut down.");
         }
 
         if self
-            .housekeeper
-            .send(housekeeper::Event::Exit)
+            .state_change
+            .send(state_change::Event::Stop)
             .await
             .is_err()
         {
-            error!("Failed to send shutdown event to housekeeper task.");
+            error!("Failed to send shutdown event to state manager.");
         }
 
         if self
-            .email_delivery
-            .send(email_delivery::Event::Stop)
+            .housekeeper
+            .send(housekeeper::Event::Exit)
             .await
             .is_err()
         {
-            debug!("Failed to send shutdown event to e-mail delivery task.");
+            error!("Failed to send shutdown event to housekeeper task.");
         }
     }
 
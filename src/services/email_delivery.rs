@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::{collections::VecDeque, time::Duration};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
 
 use actix_web::web;
 use jmap::{
@@ -31,24 +34,37 @@ use jmap::{
 use jmap_mail::email_submission::schema::{
     Delivered, DeliveryStatus, Displayed, EmailSubmission, Property, UndoStatus, Value,
 };
-use jmap_mail::mail_send::{smtp::message::Message, Transport};
-use jmap_sharing::principal::get::JMAPGetPrincipal;
+use jmap_mail::mail_send::{self, smtp::message::Message, Transport};
+use jmap_mail::{mail_parser::Message as MessageParser, mailbox::set::JMAPSetMailbox, INBOX_ID};
+use jmap_sharing::principal::{account::JMAPAccountStore, get::JMAPGetPrincipal};
+use serde::{Deserialize, Serialize};
 use store::{
-    ahash::AHashMap,
+    ahash::{AHashMap, AHashSet},
+    bincode,
     blob::BlobId,
     config::env_settings::EnvSettings,
-    core::{collection::Collection, document::Document},
-    tracing::{debug, log::error},
+    core::{collection::Collection, document::Document, error::StoreError},
+    log::changes::ChangeId,
+    serialize::{StoreDeserialize, StoreSerialize},
+    tracing::{debug, info, log::error},
     write::batch::WriteBatch,
     AccountId, DocumentId, Store,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 
-use crate::{cluster::IPC_CHANNEL_BUFFER, JMAPServer};
+use crate::{
+    cluster::IPC_CHANNEL_BUFFER,
+    lmtp::ingest::{IngestResult, JMAPMailIngest},
+    JMAPServer,
+};
 
 use super::state_change::StateChange;
 
 const DEFAULT_SMTP_TIMEOUT_MS: u64 = 60000;
+const DEFAULT_SMTP_RETRIES: u32 = 3;
+const DEFAULT_SMTP_RETRY_INTERVAL: u64 = 300;
+const TLS_REQUIRED_ERROR: &str = "TLS is required for this recipient but could not be negotiated.";
+pub const DELIVERY_QUEUE_KEY: &str = "email_delivery_queue";
 
 pub enum Event {
     EmailSubmission {
@@ -60,11 +76,26 @@ pub enum Event {
         from: String,
         to: Vec<String>,
         message: Vec<u8>,
+        attempt: u32,
+    },
+    Retry {
+        due: Instant,
+        event: Box<Event>,
     },
     RelayReady,
     Reload,
     Start,
     Stop,
+    Shutdown {
+        tx: oneshot::Sender<()>,
+    },
+}
+
+/// Deliveries that were still queued when the server was shut down.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeliveryQueue {
+    pub submissions: Vec<(AccountId, Vec<DocumentId>)>,
+    pub messages: Vec<(String, Vec<String>, Vec<u8>)>,
 }
 
 impl Event {
@@ -81,7 +112,55 @@ impl Event {
     }
 
     pub fn outgoing_message(from: String, to: Vec<String>, message: Vec<u8>) -> Self {
-        Event::OutgoingMessage { from, to, message }
+        Event::OutgoingMessage {
+            from,
+            to,
+            message,
+            attempt: 0,
+        }
+    }
+}
+
+impl DeliveryQueue {
+    pub fn push(&mut self, event: Event) {
+        match event {
+            Event::EmailSubmission {
+                account_id,
+                created_ids,
+                ..
+            } => self.submissions.push((account_id, created_ids)),
+            Event::OutgoingMessage {
+                from, to, message, ..
+            } => self.messages.push((from, to, message)),
+            _ => (),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submissions.is_empty() && self.messages.is_empty()
+    }
+
+    pub fn into_events(self) -> impl Iterator<Item = Event> {
+        self.submissions
+            .into_iter()
+            .map(|(account_id, created_ids)| Event::new_submission(account_id, created_ids, vec![]))
+            .chain(
+                self.messages
+                    .into_iter()
+                    .map(|(from, to, message)| Event::outgoing_message(from, to, message)),
+            )
+    }
+}
+
+impl StoreSerialize for DeliveryQueue {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for DeliveryQueue {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
     }
 }
 
@@ -99,19 +178,85 @@ pub fn spawn_email_delivery<T>(
 {
     // Parse SMTP relay
     let relay_tx = if let Some(smtp_relay) = parse_smtp_settings(settings) {
-        spawn_email_relay(core, smtp_relay, tx)
+        spawn_email_relay(core.clone(), smtp_relay, tx)
     } else {
         return;
     };
 
     tokio::spawn(async move {
         let mut queue = VecDeque::new();
+        let mut retries: Vec<(Instant, Event)> = Vec::new();
         let mut is_ready = true;
+        let mut shutdown_tx = None;
+
+        // Restore any deliveries that were queued during the last shutdown
+        match core.get_key::<DeliveryQueue>(DELIVERY_QUEUE_KEY).await {
+            Ok(Some(pending)) if !pending.is_empty() => {
+                info!(
+                    "Restoring {} queued submissions and {} outgoing messages.",
+                    pending.submissions.len(),
+                    pending.messages.len()
+                );
+                queue.extend(pending.into_events());
+                if let Err(err) = core
+                    .set_key(DELIVERY_QUEUE_KEY, DeliveryQueue::default())
+                    .await
+                {
+                    error!("Failed to clear delivery queue: {}", err);
+                }
+                if let Some(event) = queue.pop_front() {
+                    if let Err(err) = relay_tx.send(event).await {
+                        error!("Error sending event to relay: {}", err);
+                    }
+                    is_ready = false;
+                }
+            }
+            Ok(_) => (),
+            Err(err) => {
+                error!("Failed to read delivery queue: {}", err);
+            }
+        }
+
+        loop {
+            let event = if let Some(due) = retries.iter().map(|(due, _)| *due).min() {
+                tokio::select! {
+                    event = rx.recv() => event,
+                    _ = tokio::time::sleep_until(due.into()) => {
+                        // Send the messages whose retry interval elapsed
+                        let now = Instant::now();
+                        let (due, pending): (Vec<_>, Vec<_>) =
+                            retries.drain(..).partition(|(due, _)| *due <= now);
+                        retries = pending;
+                        for (_, event) in due {
+                            if is_ready {
+                                if let Err(err) = relay_tx.send(event).await {
+                                    error!("Error sending event to relay: {}", err);
+                                }
+                                is_ready = false;
+                            } else {
+                                queue.push_back(event);
+                            }
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                rx.recv().await
+            };
+            let event = if let Some(event) = event {
+                event
+            } else {
+                break;
+            };
 
-        while let Some(event) = rx.recv().await {
             match event {
                 Event::RelayReady => {
-                    if let Some(event) = queue.pop_front() {
+                    // Finish shutting down once the message in flight was relayed
+                    if let Some(tx) = shutdown_tx.take() {
+                        persist_delivery_queue(&core, &mut queue, &mut retries).await;
+                        tx.send(()).ok();
+                        break;
+                    } else if let Some(event) = queue.pop_front() {
                         if let Err(err) = relay_tx.send(event).await {
                             error!("Error sending event to relay: {}", err);
                         }
@@ -124,6 +269,19 @@ pub fn spawn_email_delivery<T>(
                         error!("Error sending event to relay: {}", err);
                     }
                     queue.clear();
+                    retries.clear();
+                }
+                Event::Retry { due, event } => {
+                    retries.push((due, *event));
+                }
+                Event::Shutdown { tx } => {
+                    if is_ready {
+                        persist_delivery_queue(&core, &mut queue, &mut retries).await;
+                        tx.send(()).ok();
+                        break;
+                    } else {
+                        shutdown_tx = tx.into();
+                    }
                 }
                 Event::Start => (),
                 event => {
@@ -131,6 +289,7 @@ pub fn spawn_email_delivery<T>(
                         if let Err(err) = relay_tx.send(event).await {
                             error!("Error sending event to relay: {}", err);
                         }
+                        is_ready = false;
                     } else {
                         queue.push_back(event);
                     }
@@ -140,6 +299,33 @@ pub fn spawn_email_delivery<T>(
     });
 }
 
+/// Persists queued deliveries and pending retries so they are sent after a restart.
+async fn persist_delivery_queue<T>(
+    core: &JMAPServer<T>,
+    queue: &mut VecDeque<Event>,
+    retries: &mut Vec<(Instant, Event)>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut pending = DeliveryQueue::default();
+    for event in queue
+        .drain(..)
+        .chain(retries.drain(..).map(|(_, event)| event))
+    {
+        pending.push(event);
+    }
+    if !pending.is_empty() {
+        info!(
+            "Persisting {} queued submissions and {} outgoing messages.",
+            pending.submissions.len(),
+            pending.messages.len()
+        );
+        if let Err(err) = core.set_key(DELIVERY_QUEUE_KEY, pending).await {
+            error!("Failed to persist delivery queue: {}", err);
+        }
+    }
+}
+
 fn spawn_email_relay<T>(
     core: web::Data<JMAPServer<T>>,
     smtp_relay: SMTPRelay,
@@ -158,7 +344,9 @@ where
         if let Some((username, secret)) = &smtp_relay.credentials {
             client = client.credentials(username, secret);
         }
-        let is_tls = smtp_relay.tls;
+        if smtp_relay.allow_invalid_certs {
+            client = client.allow_invalid_certs();
+        }
         let mut dkim_map = AHashMap::new();
 
         while let Some(event) = rx.recv().await {
@@ -196,24 +384,34 @@ where
                         })
                         .await
                     {
-                        Ok(messages) => {
-                            if messages.is_empty() {
-                                continue;
+                        Ok(messages) if !messages.is_empty() => messages,
+                        result => {
+                            if let Err(err) = result {
+                                error!("Error getting email submissions: {}", err);
+                            }
+
+                            // Nothing to relay, notify the queue right away
+                            if let Err(err) = queue_tx.send(Event::RelayReady).await {
+                                error!("Error sending event to relay: {}", err);
                             }
-                            messages
-                        }
-                        Err(err) => {
-                            error!("Error getting email submissions: {}", err);
                             continue;
                         }
                     };
 
                     // Connect to relay server
                     let mut results = Vec::with_capacity(messages.len());
-                    match if is_tls {
+                    let mut is_tls = smtp_relay.tls != RelayTls::Disabled;
+                    match match if is_tls {
                         client.clone().connect_tls().await
                     } else {
                         client.clone().connect().await
+                    } {
+                        Err(err) if smtp_relay.tls == RelayTls::Opportunistic => {
+                            debug!("STARTTLS failed, falling back to cleartext: {}", err);
+                            is_tls = false;
+                            client.clone().connect().await
+                        }
+                        result => result,
                     } {
                         Ok(mut client) => {
                             for (email_submission_id, current_email_submission, raw_message) in
@@ -313,6 +511,19 @@ where
                                     // Send recipients
                                     let mut accepted_rcpt = false;
                                     for rcpt in &envelope.rcpt_to {
+                                        // Never deliver in cleartext when TLS is enforced
+                                        if !is_tls && smtp_relay.requires_tls(&rcpt.email) {
+                                            delivery_status.insert(
+                                                rcpt.email.to_string(),
+                                                DeliveryStatus::new(
+                                                    TLS_REQUIRED_ERROR,
+                                                    Delivered::No,
+                                                    Displayed::Unknown,
+                                                ),
+                                            );
+                                            continue;
+                                        }
+
                                         match client
                                             .cmd(format!("RCPT TO:{}\r\n", &rcpt).as_bytes())
                                             .await
@@ -347,6 +558,13 @@ where
 
                                     // Do not submit message if no recipients were accepted
                                     if accepted_rcpt {
+                                        // Remove headers that could leak private information
+                                        let raw_message = if !smtp_relay.strip_headers.is_empty() {
+                                            strip_headers(&raw_message, &smtp_relay.strip_headers)
+                                        } else {
+                                            raw_message
+                                        };
+
                                         // Sign message
                                         let mut headers = None;
                                         if let Some(dkim) = dkim {
@@ -523,20 +741,82 @@ where
                         }
                     }
                 }
-                Event::OutgoingMessage { from, to, message } => {
-                    match if is_tls {
+                Event::OutgoingMessage {
+                    from,
+                    to,
+                    message,
+                    attempt,
+                } => {
+                    let mut is_tls = smtp_relay.tls != RelayTls::Disabled;
+                    let result = match match if is_tls {
                         client.clone().connect_tls().await
                     } else {
                         client.clone().connect().await
+                    } {
+                        Err(err) if smtp_relay.tls == RelayTls::Opportunistic => {
+                            debug!("STARTTLS failed, falling back to cleartext: {}", err);
+                            is_tls = false;
+                            client.clone().connect().await
+                        }
+                        result => result,
                     } {
                         Ok(mut client) => {
-                            if let Err(err) = client.send(Message::new(from, to, message)).await {
-                                debug!("Failed to send vacation response: {}", err);
-                            }
+                            let result =
+                                if !is_tls && to.iter().any(|rcpt| smtp_relay.requires_tls(rcpt)) {
+                                    Err((TLS_REQUIRED_ERROR.to_string(), false))
+                                } else {
+                                    client
+                                        .send(Message::new(
+                                            from.as_str(),
+                                            to.iter().map(|rcpt| rcpt.as_str()),
+                                            message.as_slice(),
+                                        ))
+                                        .await
+                                        .map_err(|err| {
+                                            let is_transient = is_transient_error(&err);
+                                            (err.to_string(), is_transient)
+                                        })
+                                };
                             client.quit().await.ok();
+                            result
                         }
                         Err(err) => {
                             error!("Failed to connect to relay server: {}", err);
+                            Err((err.to_string(), true))
+                        }
+                    };
+
+                    if let Err((err, is_transient)) = result {
+                        if is_transient && attempt < smtp_relay.retries {
+                            debug!(
+                                "Failed to send outgoing message (attempt {}), retrying: {}",
+                                attempt + 1,
+                                err
+                            );
+
+                            // Hand the retry back to the queue so it is persisted on shutdown
+                            if let Err(err) = queue_tx
+                                .send(Event::Retry {
+                                    due: Instant::now() + smtp_relay.retry_interval,
+                                    event: Box::new(Event::OutgoingMessage {
+                                        from,
+                                        to,
+                                        message,
+                                        attempt: attempt + 1,
+                                    }),
+                                })
+                                .await
+                            {
+                                error!("Failed to requeue outgoing message: {}", err);
+                            }
+                        } else {
+                            debug!("Failed to send outgoing message: {}", err);
+
+                            // Permanent failure or retries exhausted, keep a copy for inspection
+                            if let Some(dead_letter) = &smtp_relay.dead_letter {
+                                core.store_dead_letter(dead_letter.clone(), from, to, message, err)
+                                    .await;
+                            }
                         }
                     }
                 }
@@ -559,8 +839,76 @@ struct SMTPRelay {
     hostname: String,
     port: u16,
     credentials: Option<(String, String)>,
-    tls: bool,
+    tls: RelayTls,
+    allow_invalid_certs: bool,
+    require_tls: AHashSet<String>,
+    strip_headers: AHashSet<String>,
     timeout: Duration,
+    retries: u32,
+    retry_interval: Duration,
+    dead_letter: Option<DeadLetter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayTls {
+    Disabled,
+    Opportunistic,
+    Required,
+}
+
+impl SMTPRelay {
+    /// Returns true if messages to the recipient must not be relayed in cleartext.
+    fn requires_tls(&self, rcpt: &str) -> bool {
+        self.tls == RelayTls::Required
+            || rcpt.rsplit_once('@').map_or(false, |(_, domain)| {
+                self.require_tls.contains(&domain.trim().to_lowercase())
+            })
+    }
+}
+
+/// Returns true if a relay error is worth retrying (4xx replies and I/O failures).
+fn is_transient_error(err: &mail_send::Error) -> bool {
+    match err {
+        mail_send::Error::UnexpectedReply(reply) => (400..500).contains(&reply.code()),
+        mail_send::Error::Io(_) | mail_send::Error::Timeout => true,
+        _ => false,
+    }
+}
+
+/// Returns a copy of the message without the headers listed in `headers` (lowercase names).
+fn strip_headers(raw_message: &[u8], headers: &AHashSet<String>) -> Vec<u8> {
+    let mut message = Vec::with_capacity(raw_message.len());
+    let mut is_stripped = false;
+    let mut lines = raw_message.split_inclusive(|&ch| ch == b'\n');
+
+    for line in lines.by_ref() {
+        if line.iter().all(|ch| ch.is_ascii_whitespace()) {
+            message.extend_from_slice(line);
+            break;
+        } else if !matches!(line.first(), Some(b' ' | b'\t')) {
+            is_stripped = line
+                .iter()
+                .position(|&ch| ch == b':')
+                .and_then(|pos| std::str::from_utf8(&line[..pos]).ok())
+                .map_or(false, |name| {
+                    headers.contains(&name.trim().to_ascii_lowercase())
+                });
+        }
+        if !is_stripped {
+            message.extend_from_slice(line);
+        }
+    }
+    for line in lines {
+        message.extend_from_slice(line);
+    }
+
+    message
+}
+
+#[derive(Clone)]
+pub struct DeadLetter {
+    pub account: String,
+    pub mailbox: String,
 }
 
 fn parse_smtp_settings(settings: &EnvSettings) -> Option<SMTPRelay> {
@@ -575,12 +923,47 @@ fn parse_smtp_settings(settings: &EnvSettings) -> Option<SMTPRelay> {
         } else {
             None
         },
-        tls: settings.parse("smtp-relay-tls").unwrap_or(false),
+        tls: match settings.get("smtp-relay-tls").as_deref() {
+            Some("true" | "require") => RelayTls::Required,
+            Some("opportunistic") => RelayTls::Opportunistic,
+            _ => RelayTls::Disabled,
+        },
+        allow_invalid_certs: settings
+            .parse("smtp-relay-allow-invalid-certs")
+            .unwrap_or(false),
+        require_tls: settings
+            .parse_list("smtp-relay-require-tls")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|domain| domain.trim().to_lowercase())
+            .collect(),
+        strip_headers: settings
+            .parse_list("smtp-relay-strip-headers")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|header| header.trim().to_lowercase())
+            .collect(),
         timeout: Duration::from_millis(
             settings
                 .parse("smtp-relay-timeout")
                 .unwrap_or(DEFAULT_SMTP_TIMEOUT_MS),
         ),
+        retries: settings
+            .parse("smtp-relay-retries")
+            .unwrap_or(DEFAULT_SMTP_RETRIES),
+        retry_interval: Duration::from_secs(
+            settings
+                .parse("smtp-relay-retry-interval")
+                .unwrap_or(DEFAULT_SMTP_RETRY_INTERVAL),
+        ),
+        dead_letter: settings
+            .get("dead-letter-account")
+            .map(|account| DeadLetter {
+                account,
+                mailbox: settings
+                    .get("dead-letter-mailbox")
+                    .unwrap_or_else(|| "Dead Letters".to_string()),
+            }),
     })
 }
 
@@ -595,4 +978,112 @@ where
         }
         Ok(())
     }
+
+    pub async fn store_dead_letter(
+        &self,
+        dead_letter: DeadLetter,
+        from: String,
+        to: Vec<String>,
+        message: Vec<u8>,
+        reason: String,
+    ) {
+        let store = self.store.clone();
+        let result = match self
+            .spawn_worker(move || {
+                let account_id =
+                    if let Some(account_id) = store.find_individual(&dead_letter.account)? {
+                        account_id
+                    } else {
+                        debug!(
+                            "Dead letter account {} does not exist, discarding message.",
+                            dead_letter.account
+                        );
+                        return Ok(None);
+                    };
+                let mut result = IngestResult {
+                    rcpt_to: Vec::new(),
+                    changes: AHashMap::new(),
+                    last_change_id: ChangeId::MAX,
+                    messages: Vec::new(),
+                };
+
+                let mailbox_id =
+                    match store.mailbox_create_path(account_id, &dead_letter.mailbox)? {
+                        Some((mailbox_id, changes)) => {
+                            if let Some(changes) = changes {
+                                result.last_change_id = changes.change_id;
+                                result.changes.insert(account_id, changes);
+                            }
+                            mailbox_id
+                        }
+                        None => INBOX_ID,
+                    };
+
+                // Record the failure reason and envelope in the message headers
+                let mut raw_message = format!(
+                    "X-Dead-Letter-Reason: {}\r\nX-Dead-Letter-Envelope: from=<{}> to=<{}>\r\n",
+                    reason.split_whitespace().collect::<Vec<_>>().join(" "),
+                    from,
+                    to.join(">, <")
+                )
+                .into_bytes();
+                raw_message.extend_from_slice(&message);
+
+                let blob_id = BlobId::new_external(&raw_message);
+                let raw_message = store.blob_store(&blob_id, raw_message)?;
+                let message = MessageParser::parse(&raw_message).ok_or_else(|| {
+                    StoreError::InvalidArguments("Failed to parse dead letter.".to_string())
+                })?;
+
+                store
+                    .mail_deliver_mailbox(
+                        &mut result,
+                        account_id,
+                        message,
+                        &blob_id,
+                        &[mailbox_id],
+                        Vec::new(),
+                    )
+                    .map_err(|_| {
+                        StoreError::InternalError("Failed to store dead letter.".to_string())
+                    })?;
+
+                Ok(Some(result))
+            })
+            .await
+        {
+            Ok(Some(result)) => result,
+            Ok(None) => return,
+            Err(err) => {
+                error!("Failed to store dead letter: {}", err);
+                return;
+            }
+        };
+
+        // Wait for message to be committed
+        if result.last_change_id != ChangeId::MAX
+            && self.is_in_cluster()
+            && !self.commit_index(result.last_change_id).await
+        {
+            error!("Failed to commit dead letter.");
+            return;
+        }
+
+        // Publish state changes
+        for (account_id, changes) in result.changes {
+            if let Err(err) = self
+                .publish_state_change(StateChange::new(
+                    account_id,
+                    changes
+                        .collections
+                        .into_iter()
+                        .filter_map(|c| Some((TypeState::try_from(c).ok()?, changes.change_id)))
+                        .collect(),
+                ))
+                .await
+            {
+                error!("Failed to publish state change: {}", err);
+            }
+        }
+    }
 }
@@ -21,21 +21,38 @@
  * for more details.
 */
 
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use actix_web::web;
+use jmap::{
+    orm::reindex::JMAPReindex,
+    principal::schema::Principal,
+    push_subscription::{schema::PushSubscription, set::JMAPSetPushSubscription},
+    types::type_state::TypeState,
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    email_submission::schema::EmailSubmission,
+    identity::schema::Identity,
+    mail::snooze::JMAPMailSnooze,
+    mailbox::{retention::JMAPMailboxRetention, schema::Mailbox},
+};
 use jmap_sharing::principal::set::JMAPSetPrincipal;
+use jmap_sieve::sieve_script::schema::SieveScript;
 use store::{
-    chrono::{self, Datelike, TimeZone},
+    chrono::{self, Datelike, TimeZone, Timelike},
     config::env_settings::EnvSettings,
+    core::collection::Collection,
+    log::{changes::ChangeId, raft::RaftId},
     tracing::{debug, error, info},
-    ColumnFamily, Store,
+    Store,
 };
 use tokio::sync::mpsc;
 
 use crate::{
     cluster::IPC_CHANNEL_BUFFER,
     server::{failed_to, UnwrapFailure},
+    services::state_change::StateChange,
     JMAPServer,
 };
 
@@ -44,10 +61,16 @@ pub enum Event {
     PurgeBlobs,
     SnapshotLog,
     CompactDb,
+    PurgeRetention,
+    PurgePushSubscriptions,
+    WakeSnoozed,
+    ArchiveBlobs,
+    Reindex,
     Exit,
 }
 
 enum SimpleCron {
+    EveryHour { minute: u32 },
     EveryDay { hour: u32, minute: u32 },
     EveryWeek { day: u32, hour: u32, minute: u32 },
 }
@@ -56,6 +79,10 @@ const TASK_PURGE_ACCOUNTS: usize = 0;
 const TASK_PURGE_BLOBS: usize = 1;
 const TASK_SNAPSHOT_LOG: usize = 2;
 const TASK_COMPACT_DB: usize = 3;
+const TASK_PURGE_RETENTION: usize = 4;
+const TASK_PURGE_PUSH_SUBSCRIPTIONS: usize = 5;
+const TASK_WAKE_SNOOZED: usize = 6;
+const TASK_ARCHIVE_BLOBS: usize = 7;
 
 pub fn spawn_housekeeper<T>(
     core: web::Data<JMAPServer<T>>,
@@ -84,8 +111,35 @@ pub fn spawn_housekeeper<T>(
             .get("schedule-compact-db")
             .unwrap_or_else(|| "0 4 *".to_string()),
     );
+    let purge_retention_at = SimpleCron::parse(
+        &settings
+            .get("schedule-purge-retention")
+            .unwrap_or_else(|| "15 3 *".to_string()),
+    );
+    let purge_push_subscriptions_at = SimpleCron::parse(
+        &settings
+            .get("schedule-purge-push")
+            .unwrap_or_else(|| "20 3 *".to_string()),
+    );
+    // Snoozed messages are due at any minute, so they are checked at a fixed interval
+    let wake_snoozed_interval =
+        Duration::from_secs(settings.parse("snooze-wake-interval").unwrap_or(60));
+    let mut wake_snoozed_at = Instant::now() + wake_snoozed_interval;
+    let archive_blobs_at = SimpleCron::parse(
+        &settings
+            .get("schedule-archive-blobs")
+            .unwrap_or_else(|| "0 2 *".to_string()),
+    );
     let max_log_entries: u64 = settings.parse("max-changelog-entries").unwrap_or(10000);
 
+    // Rebuild the indexes of collections whose indexing schema changed since the last run
+    let reindex_core = core.clone();
+    tokio::spawn(async move {
+        if let Err(err) = reindex_core.reindex_collections().await {
+            error!("Error while reindexing collections: {}", err);
+        }
+    });
+
     tokio::spawn(async move {
         debug!("Housekeeper task started.");
         loop {
@@ -94,8 +148,12 @@ pub fn spawn_housekeeper<T>(
                 purge_blobs_at.time_to_next(),
                 snapshot_log_at.time_to_next(),
                 compact_db_at.time_to_next(),
+                purge_retention_at.time_to_next(),
+                purge_push_subscriptions_at.time_to_next(),
+                wake_snoozed_at.saturating_duration_since(Instant::now()),
+                archive_blobs_at.time_to_next(),
             ];
-            let mut tasks_to_run = [false, false, false, false];
+            let mut tasks_to_run = [false, false, false, false, false, false, false, false];
             let start_time = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
@@ -108,6 +166,20 @@ pub fn spawn_housekeeper<T>(
                     Event::PurgeBlobs => tasks_to_run[TASK_PURGE_BLOBS] = true,
                     Event::SnapshotLog => tasks_to_run[TASK_SNAPSHOT_LOG] = true,
                     Event::CompactDb => tasks_to_run[TASK_COMPACT_DB] = true,
+                    Event::PurgeRetention => tasks_to_run[TASK_PURGE_RETENTION] = true,
+                    Event::PurgePushSubscriptions => {
+                        tasks_to_run[TASK_PURGE_PUSH_SUBSCRIPTIONS] = true
+                    }
+                    Event::WakeSnoozed => tasks_to_run[TASK_WAKE_SNOOZED] = true,
+                    Event::ArchiveBlobs => tasks_to_run[TASK_ARCHIVE_BLOBS] = true,
+                    Event::Reindex => {
+                        let core = core.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = core.reindex_collections().await {
+                                error!("Error while reindexing collections: {}", err);
+                            }
+                        });
+                    }
                     Event::Exit => {
                         debug!("Housekeeper task exiting.");
                         return;
@@ -130,6 +202,9 @@ pub fn spawn_housekeeper<T>(
                     tasks_to_run[pos] = true;
                 }
             }
+            if tasks_to_run[TASK_WAKE_SNOOZED] {
+                wake_snoozed_at = Instant::now() + wake_snoozed_interval;
+            }
 
             // Spawn tasks
             for (task_id, do_run) in tasks_to_run.into_iter().enumerate() {
@@ -152,13 +227,27 @@ pub fn spawn_housekeeper<T>(
                         }
                         TASK_SNAPSHOT_LOG => {
                             info!("Compacting changes and Raft logs.");
-                            core.spawn_worker(move || store.compact_log(max_log_entries))
-                                .await
+                            core.snapshot_log(max_log_entries).await
                         }
                         TASK_COMPACT_DB => {
                             info!("Compacting database.");
-                            core.spawn_worker(move || store.db.compact(ColumnFamily::Bitmaps))
-                                .await
+                            core.compact_db().await
+                        }
+                        TASK_PURGE_RETENTION => {
+                            info!("Purging messages past their mailbox retention period.");
+                            core.purge_retention().await
+                        }
+                        TASK_PURGE_PUSH_SUBSCRIPTIONS => {
+                            info!("Purging expired push subscriptions.");
+                            core.purge_push_subscriptions().await
+                        }
+                        TASK_WAKE_SNOOZED => {
+                            debug!("Moving snoozed messages back to the Inbox.");
+                            core.wake_snoozed().await
+                        }
+                        TASK_ARCHIVE_BLOBS => {
+                            info!("Moving aged blobs to cold storage.");
+                            core.spawn_worker(move || store.archive_blobs()).await
                         }
                         _ => unreachable!(),
                     };
@@ -172,6 +261,189 @@ pub fn spawn_housekeeper<T>(
     });
 }
 
+impl<T> JMAPServer<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn snapshot_log(&self, max_log_entries: u64) -> store::Result<()> {
+        let store = self.store.clone();
+        self.spawn_worker(move || store.compact_log(max_log_entries))
+            .await?;
+
+        // Followers replay the changes referenced by the Raft log one entry at a time,
+        // in a cluster only the changes older than its first entry can be pruned.
+        let max_change_id = if self.is_in_cluster() {
+            let store = self.store.clone();
+            self.spawn_worker(move || store.get_next_raft_id(RaftId::new(0, 0)))
+                .await?
+                .map_or(0, |raft_id| raft_id.index)
+        } else {
+            ChangeId::MAX
+        };
+        for (collection, max_changes) in self.store.config.changelog_retention.iter().copied() {
+            let store = self.store.clone();
+            self.spawn_worker(move || {
+                store.compact_changes(collection, max_changes, max_change_id)
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn reindex_collections(&self) -> store::Result<()> {
+        // Only the leader is allowed to modify the store
+        if !self.is_leader() {
+            return Ok(());
+        }
+
+        let store = self.store.clone();
+        self.spawn_worker(move || {
+            let mut versions = store.get_schema_versions()?;
+            store.reindex_if_changed::<Principal>(&mut versions)?;
+            store.reindex_if_changed::<PushSubscription>(&mut versions)?;
+            store.reindex_if_changed::<Mailbox>(&mut versions)?;
+            store.reindex_if_changed::<Identity>(&mut versions)?;
+            store.reindex_if_changed::<EmailSubmission>(&mut versions)?;
+            store.reindex_if_changed::<SieveScript>(&mut versions)?;
+            store.set_schema_versions(&versions)
+        })
+        .await
+    }
+
+    pub async fn compact_db(&self) -> store::Result<()> {
+        for cf in self.store.config.compact_db_families.iter().copied() {
+            let store = self.store.clone();
+            self.spawn_worker(move || store.db.compact(cf)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn purge_retention(&self) -> store::Result<()> {
+        // Only the leader is allowed to modify the store
+        if !self.is_leader() {
+            return Ok(());
+        }
+
+        let store = self.store.clone();
+        let account_ids = self
+            .spawn_worker(move || store.get_document_ids(SUPERUSER_ID, Collection::Principal))
+            .await?
+            .unwrap_or_default();
+
+        for account_id in account_ids {
+            let store = self.store.clone();
+            if let Some(changes) = self
+                .spawn_worker(move || store.mailbox_purge_expired(account_id))
+                .await?
+            {
+                if self.is_in_cluster() && !self.commit_index(changes.change_id).await {
+                    error!(
+                        "Failed to commit retention changes for account {}.",
+                        account_id
+                    );
+                    continue;
+                }
+
+                if let Err(err) = self
+                    .publish_state_change(StateChange::new(
+                        account_id,
+                        changes
+                            .collections
+                            .into_iter()
+                            .filter_map(|c| Some((TypeState::try_from(c).ok()?, changes.change_id)))
+                            .collect(),
+                    ))
+                    .await
+                {
+                    error!("Failed to publish state change: {}", err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn wake_snoozed(&self) -> store::Result<()> {
+        // Only the leader is allowed to modify the store
+        if !self.is_leader() {
+            return Ok(());
+        }
+
+        let store = self.store.clone();
+        let account_ids = self
+            .spawn_worker(move || store.mail_snoozed_accounts())
+            .await?
+            .unwrap_or_default();
+
+        for account_id in account_ids {
+            let store = self.store.clone();
+            if let Some(changes) = self
+                .spawn_worker(move || store.mail_wake_snoozed(account_id))
+                .await?
+            {
+                if self.is_in_cluster() && !self.commit_index(changes.change_id).await {
+                    error!(
+                        "Failed to commit snoozed message changes for account {}.",
+                        account_id
+                    );
+                    continue;
+                }
+
+                if let Err(err) = self
+                    .publish_state_change(StateChange::new(
+                        account_id,
+                        changes
+                            .collections
+                            .into_iter()
+                            .filter_map(|c| Some((TypeState::try_from(c).ok()?, changes.change_id)))
+                            .collect(),
+                    ))
+                    .await
+                {
+                    error!("Failed to publish state change: {}", err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn purge_push_subscriptions(&self) -> store::Result<()> {
+        // Only the leader is allowed to modify the store
+        if !self.is_leader() {
+            return Ok(());
+        }
+
+        let store = self.store.clone();
+        let account_ids = self
+            .spawn_worker(move || store.get_document_ids(SUPERUSER_ID, Collection::Principal))
+            .await?
+            .unwrap_or_default();
+
+        for account_id in account_ids {
+            let store = self.store.clone();
+            if let Some(changes) = self
+                .spawn_worker(move || store.push_subscription_purge_expired(account_id))
+                .await?
+            {
+                if self.is_in_cluster() && !self.commit_index(changes.change_id).await {
+                    error!(
+                        "Failed to commit push subscription changes for account {}.",
+                        account_id
+                    );
+                    continue;
+                }
+
+                if let Err(err) = self.update_push_subscriptions(account_id).await {
+                    error!("Failed to update push subscriptions: {}", err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub fn init_housekeeper() -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
     mpsc::channel::<Event>(IPC_CHANNEL_BUFFER)
 }
@@ -180,6 +452,7 @@ impl SimpleCron {
     pub fn parse(value: &str) -> Self {
         let mut hour = 0;
         let mut minute = 0;
+        let mut every_hour = false;
 
         for (pos, value) in value.split(' ').enumerate() {
             if pos == 0 {
@@ -188,13 +461,23 @@ impl SimpleCron {
                     failed_to(&format!("parse minute, invalid value: {}", minute));
                 }
             } else if pos == 1 {
-                hour = value.parse::<u32>().failed_to("parse hour.");
-                if !(0..=23).contains(&hour) {
-                    failed_to(&format!("parse hour, invalid value: {}", hour));
+                if value == "*" {
+                    every_hour = true;
+                } else {
+                    hour = value.parse::<u32>().failed_to("parse hour.");
+                    if !(0..=23).contains(&hour) {
+                        failed_to(&format!("parse hour, invalid value: {}", hour));
+                    }
                 }
             } else if pos == 2 {
                 if value.as_bytes().first().failed_to("parse weekday") == &b'*' {
-                    return SimpleCron::EveryDay { hour, minute };
+                    return if every_hour {
+                        SimpleCron::EveryHour { minute }
+                    } else {
+                        SimpleCron::EveryDay { hour, minute }
+                    };
+                } else if every_hour {
+                    failed_to("parse cron expression, weekday has to be '*' when hour is '*'.");
                 } else {
                     let day = value.parse::<u32>().failed_to("parse weekday.");
                     if !(1..=7).contains(&hour) {
@@ -215,6 +498,16 @@ impl SimpleCron {
     pub fn time_to_next(&self) -> Duration {
         let now = chrono::Local::now();
         let next = match self {
+            SimpleCron::EveryHour { minute } => {
+                let next = chrono::Local
+                    .ymd(now.year(), now.month(), now.day())
+                    .and_hms(now.hour(), *minute, 0);
+                if next < now {
+                    next + chrono::Duration::hours(1)
+                } else {
+                    next
+                }
+            }
             SimpleCron::EveryDay { hour, minute } => {
                 let next = chrono::Local
                     .ymd(now.year(), now.month(), now.day())
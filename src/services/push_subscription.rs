@@ -21,16 +21,20 @@
  * for more details.
 */
 
-use super::{push_subscription_ece::ece_encrypt, state_change::StateChange, LONG_SLUMBER_MS};
+use super::{
+    push_subscription_ece::ece_encrypt, push_subscription_vapid::VapidKey,
+    state_change::StateChange, LONG_SLUMBER_MS,
+};
 use crate::{api::StateChangeResponse, cluster::IPC_CHANNEL_BUFFER, JMAPServer};
 use jmap::{
     orm::serialize::JMAPOrm,
     push_subscription::schema::{self, Property, Value},
     types::{jmap::JMAPId, type_state::TypeState},
 };
-use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
 use std::{
     collections::hash_map::Entry,
+    sync::Arc,
     time::{Duration, Instant, SystemTime},
 };
 use store::{
@@ -126,6 +130,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
     let push_timeout: u64 = settings.parse("push-timeout").unwrap_or(10 * 1000);
     let push_verify_timeout: u64 = settings.parse("push-verify-timeout").unwrap_or(60 * 1000);
     let push_throttle: u64 = settings.parse("push-throttle").unwrap_or(1000);
+    let vapid = VapidKey::new(settings).map(Arc::new);
 
     tokio::spawn(async move {
         let mut subscriptions = AHashMap::default();
@@ -167,6 +172,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                         })
                                         .unwrap_or(true)
                                     {
+                                        let vapid = vapid.clone();
                                         tokio::spawn(async move {
                                             http_request(
                                                 url,
@@ -180,6 +186,7 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                                     code
                                                 ),
                                                 keys,
+                                                vapid,
                                                 push_timeout,
                                             )
                                             .await;
@@ -230,7 +237,12 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                             .contains(&subscription.num_attempts)
                                             && last_request > push_attempt_interval))
                                 {
-                                    subscription.send(id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        id,
+                                        push_tx.clone(),
+                                        vapid.clone(),
+                                        push_timeout,
+                                    );
                                     retry_ids.remove(&id);
                                 } else {
                                     retry_ids.insert(id);
@@ -284,7 +296,12 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                                         && last_request >= push_attempt_interval))
                             {
                                 if subscription.num_attempts < push_attempts_max {
-                                    subscription.send(*retry_id, push_tx.clone(), push_timeout);
+                                    subscription.send(
+                                        *retry_id,
+                                        push_tx.clone(),
+                                        vapid.clone(),
+                                        push_timeout,
+                                    );
                                 } else {
                                     debug!(
                                         concat!(
@@ -326,7 +343,13 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
 }
 
 impl PushServer {
-    fn send(&mut self, id: store::JMAPId, push_tx: mpsc::Sender<Event>, push_timeout: u64) {
+    fn send(
+        &mut self,
+        id: store::JMAPId,
+        push_tx: mpsc::Sender<Event>,
+        vapid: Option<Arc<VapidKey>>,
+        push_timeout: u64,
+    ) {
         let url = self.url.clone();
         let keys = self.keys.clone();
         let state_changes = std::mem::take(&mut self.state_changes);
@@ -351,6 +374,7 @@ impl PushServer {
                         url,
                         serde_json::to_string(&response).unwrap(),
                         keys,
+                        vapid,
                         push_timeout,
                     )
                     .await
@@ -368,8 +392,9 @@ impl PushServer {
 
 async fn http_request(
     url: String,
-    mut body: String,
+    body: String,
     keys: Option<EncryptionKeys>,
+    vapid: Option<Arc<VapidKey>>,
     push_timeout: u64,
 ) -> bool {
     let client_builder = reqwest::Client::builder().timeout(Duration::from_millis(push_timeout));
@@ -381,26 +406,30 @@ async fn http_request(
         .build()
         .unwrap_or_default()
         .post(&url)
-        .header(CONTENT_TYPE, "application/json")
         .header("TTL", "86400");
 
-    if let Some(keys) = keys {
-        match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes())
-            .map(|b| base64::encode_config(b, base64::URL_SAFE))
-        {
-            Ok(body_) => {
-                body = body_;
-                client = client.header(CONTENT_ENCODING, "aes128gcm");
-            }
+    if let Some(authorization) = vapid.and_then(|vapid| vapid.authorization(&url)) {
+        client = client.header(AUTHORIZATION, authorization);
+    }
+
+    // Encrypted payloads are sent as an aes128gcm binary body (RFC 8291)
+    let client = if let Some(keys) = keys {
+        match ece_encrypt(&keys.p256dh, &keys.auth, body.as_bytes()) {
+            Ok(body) => client
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header(CONTENT_ENCODING, "aes128gcm")
+                .body(body),
             Err(err) => {
                 // Do not reattempt if encryption fails.
                 debug!("Failed to encrypt push subscription to {}: {}", url, err);
                 return true;
             }
         }
-    }
+    } else {
+        client.header(CONTENT_TYPE, "application/json").body(body)
+    };
 
-    match client.body(body).send().await {
+    match client.send().await {
         Ok(response) => response.status().is_success(),
         Err(err) => {
             debug!("HTTP post to {} failed with: {}", url, err);
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use p256::{
+    ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey},
+    elliptic_curve::sec1::ToEncodedPoint,
+};
+use store::{config::env_settings::EnvSettings, tracing::error};
+
+// JWTs are valid for 12 hours, RFC 8292 forbids expiration times over 24 hours.
+const VAPID_JWT_EXPIRY: u64 = 12 * 3600;
+
+/*
+
+ Voluntary Application Server Identification (RFC 8292).
+ Push requests are signed with the server's P-256 key so that push
+ services can restrict subscriptions to this application server.
+
+*/
+
+#[derive(Debug)]
+pub struct VapidKey {
+    signing_key: SigningKey,
+    public_key: String,
+    subject: Option<String>,
+}
+
+impl VapidKey {
+    pub fn new(settings: &EnvSettings) -> Option<Self> {
+        let signing_key = settings.get("push-vapid-key").and_then(|key| {
+            match base64::decode_config(key.trim(), base64::URL_SAFE_NO_PAD)
+                .map_err(|e| e.to_string())
+                .and_then(|key| SigningKey::from_bytes(&key).map_err(|e| e.to_string()))
+            {
+                Ok(signing_key) => Some(signing_key),
+                Err(err) => {
+                    error!("Invalid push-vapid-key: {}", err);
+                    None
+                }
+            }
+        })?;
+        let public_key = base64::encode_config(
+            VerifyingKey::from(&signing_key)
+                .to_encoded_point(false)
+                .as_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        );
+
+        Some(VapidKey {
+            signing_key,
+            public_key,
+            subject: settings.get("push-vapid-subject").filter(|v| !v.is_empty()),
+        })
+    }
+
+    // Base64url encoded public key, advertised to clients as the applicationServerKey.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    // Returns the value of the Authorization header for a push to the endpoint at 'url'.
+    pub fn authorization(&self, url: &str) -> Option<String> {
+        let audience = reqwest::Url::parse(url)
+            .ok()?
+            .origin()
+            .ascii_serialization();
+        let expires = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+            + VAPID_JWT_EXPIRY;
+
+        let mut claims = serde_json::json!({
+            "aud": audience,
+            "exp": expires,
+        });
+        if let Some(subject) = &self.subject {
+            claims["sub"] = subject.clone().into();
+        }
+
+        let mut token = format!(
+            "{}.{}",
+            base64::encode_config(
+                b"{\"typ\":\"JWT\",\"alg\":\"ES256\"}",
+                base64::URL_SAFE_NO_PAD
+            ),
+            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+        );
+        let signature: Signature = self.signing_key.sign(token.as_bytes());
+        token.push('.');
+        token.push_str(&base64::encode_config(
+            signature.as_ref(),
+            base64::URL_SAFE_NO_PAD,
+        ));
+
+        Some(format!("vapid t={}, k={}", token, self.public_key))
+    }
+}
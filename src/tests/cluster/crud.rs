@@ -21,16 +21,18 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use actix_web::web;
 use jmap_client::{
     client::{Client, Credentials},
     email::{query::Filter, Property},
 };
+use reqwest::{header, redirect::Policy, StatusCode};
 use store::{ahash::AHashMap, parking_lot::Mutex, Store};
 
 use crate::{
+    api::HEADER_LEADER,
     tests::{
         cluster::utils::{
             activate_all_peers, assert_cluster_updated, assert_leader_elected,
@@ -102,6 +104,54 @@ where
         "jdoe@example.com"
     );
 
+    // Followers serve read-only requests with a hint pointing to the leader,
+    // requests containing writes are redirected to the leader.
+    let http_client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .redirect(Policy::none())
+        .build()
+        .unwrap_or_default();
+    let mut leader_urls = Vec::new();
+    for (method_call, expect_redirect) in [
+        (
+            serde_json::json!(["Core/echo", {"hello": "world"}, "0"]),
+            false,
+        ),
+        (
+            serde_json::json!([
+                "Mailbox/set",
+                {"accountId": &account_id_1, "create": {"a": {"name": "Follower"}}},
+                "0"
+            ]),
+            true,
+        ),
+    ] {
+        let response = http_client
+            .post(format!("http://127.0.0.1:{}/jmap", 8000 + follower_id))
+            .bearer_auth("DO_NOT_ATTEMPT_THIS_AT_HOME")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::json!({
+                    "using": ["urn:ietf:params:jmap:core", "urn:ietf:params:jmap:mail"],
+                    "methodCalls": [method_call]
+                })
+                .to_string(),
+            )
+            .send()
+            .await
+            .unwrap();
+        let leader_url = if expect_redirect {
+            assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+            response.headers().get(header::LOCATION)
+        } else {
+            assert_eq!(response.status(), StatusCode::OK);
+            response.headers().get(HEADER_LEADER)
+        };
+        leader_urls.push(leader_url.unwrap().to_str().unwrap().to_string());
+    }
+    assert_eq!(leader_urls[0], leader_urls[1]);
+    assert!(leader_urls[0].ends_with("/jmap"), "{:?}", leader_urls);
+
     // LMTP requests should be forwarded to the leader over RPC
     let mut lmtp = SmtpConnection::connect_peer(follower_id).await;
     lmtp.expn("members@example.com", 2)
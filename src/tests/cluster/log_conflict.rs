@@ -56,9 +56,9 @@ where
 
         {
             let (peer1, client1, tmp_path_1, handle1) =
-                init_jmap_tests_opts::<T>(&base_dir_2, 1, 1, true).await;
+                init_jmap_tests_opts::<T>(&base_dir_2, 1, 1, true, &[]).await;
             let (peer2, client2, tmp_path_2, handle2) =
-                init_jmap_tests_opts::<T>(&base_dir_2, 2, 1, true).await;
+                init_jmap_tests_opts::<T>(&base_dir_2, 2, 1, true, &[]).await;
 
             let clients1 = Clients {
                 clients: vec![client1],
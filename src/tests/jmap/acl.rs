@@ -22,15 +22,27 @@
 */
 
 use actix_web::web;
-use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap::{
+    jmap_store::Object,
+    principal::schema::Principal,
+    request::{get::GetRequest, set::SetRequest},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
 use jmap_client::{
     client::{Client, Credentials},
     email::{import::EmailImportResponse, query::Filter, Property},
     mailbox::{self, Role},
     principal::ACL,
 };
-use jmap_mail::{INBOX_ID, TRASH_ID};
-use jmap_sharing::principal::set::JMAPSetPrincipal;
+use jmap_mail::{
+    mail::{schema::Email, set::JMAPSetMail},
+    mailbox::{schema::Mailbox, set::JMAPSetMailbox},
+    INBOX_ID, TRASH_ID,
+};
+use jmap_sharing::principal::{
+    account::JMAPAccountStore, get::JMAPGetPrincipal, set::JMAPSetPrincipal,
+};
 use store::{ahash::AHashMap, Store};
 
 use crate::{
@@ -262,6 +274,62 @@ where
             .await,
     );
 
+    // John should only be able to forward messages he has access to as attachments
+    let forward = |blob_id: &str| {
+        let mut request = serde_json::from_value::<SetRequest<Email>>(serde_json::json!({
+            "accountId": &john_id,
+            "create": {
+                "a": {
+                    "mailboxIds": {&inbox_id: true},
+                    "subject": "Fwd: Owned by jane",
+                    "bodyStructure": {
+                        "type": "multipart/mixed",
+                        "subParts": [
+                            {"partId": "1", "type": "text/plain"},
+                            {
+                                "blobId": blob_id,
+                                "type": "message/rfc822",
+                                "disposition": "attachment"
+                            }
+                        ]
+                    },
+                    "bodyValues": {"1": {"value": "Forwarded message attached."}}
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server
+            .store
+            .get_acl_token(JMAPId::parse(&john_id).unwrap().get_document_id())
+            .unwrap()
+            .into();
+        serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap()
+    };
+    let response = forward(&blob_id);
+    assert_eq!(
+        response["notCreated"]["a"]["type"], "forbidden",
+        "{:?}",
+        response
+    );
+    let shared_blob_id = jane_client
+        .email_get(
+            email_ids.get("jane").unwrap().first().unwrap(),
+            [Property::BlobId].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+        .take_blob_id();
+    let response = forward(&shared_blob_id);
+    let forwarded_id = response["created"]["a"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response));
+    john_client
+        .set_default_account_id(&john_id)
+        .email_destroy(forwarded_id)
+        .await
+        .unwrap();
+
     // John only has ReadItems access to Inbox but no Read access
     assert_forbidden(
         john_client
@@ -658,6 +726,84 @@ where
         "Owned by jane in inbox"
     );
 
+    // Create a fully-specified shared mailbox in a single request
+    let mut request = serde_json::from_str::<SetRequest<Mailbox>>(&format!(
+        r#"{{
+            "accountId": "{}",
+            "create": {{
+                "a": {{
+                    "name": "Shared Archive",
+                    "parentId": "{}",
+                    "role": "archive",
+                    "isSubscribed": true,
+                    "acl": {{
+                        "jdoe@example.com": ["read", "readItems"]
+                    }}
+                }},
+                "b": {{
+                    "name": "Invalid Folder",
+                    "parentId": "{}",
+                    "role": "not-a-role",
+                    "acl": {{
+                        "nobody@example.com": ["read"]
+                    }}
+                }}
+            }}
+        }}"#,
+        bill_id,
+        inbox_id,
+        JMAPId::new(1234)
+    ))
+    .unwrap();
+    let bill_account_id = JMAPId::parse(&bill_id).unwrap().get_document_id();
+    request.acl = server.store.get_acl_token(bill_account_id).unwrap().into();
+    let response = server.store.mailbox_set(request).unwrap();
+    let shared_id = response.created.get("a").unwrap().id().unwrap().to_string();
+    let response = serde_json::to_value(&response).unwrap();
+    assert_eq!(
+        response["notCreated"]["b"]["type"].as_str().unwrap(),
+        "invalidProperties"
+    );
+    let mut properties = response["notCreated"]["b"]["properties"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p.as_str().unwrap())
+        .collect::<Vec<_>>();
+    properties.sort_unstable();
+    assert_eq!(properties, ["acl", "parentId", "role"]);
+    let shared_mailbox = bill_client
+        .set_default_account_id(&bill_id)
+        .mailbox_get(
+            &shared_id,
+            [
+                mailbox::Property::Role,
+                mailbox::Property::ParentId,
+                mailbox::Property::IsSubscribed,
+            ]
+            .into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shared_mailbox.role(), Role::Archive);
+    assert_eq!(shared_mailbox.parent_id(), Some(inbox_id.as_str()));
+    assert!(shared_mailbox.is_subscribed());
+    john_client.refresh_session().await.unwrap();
+    assert_eq!(
+        john_client
+            .set_default_account_id(&bill_id)
+            .mailbox_get(&shared_id, [mailbox::Property::MyRights].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .my_rights()
+            .unwrap()
+            .acl_list(),
+        vec![ACL::ReadItems]
+    );
+    bill_client.mailbox_destroy(&shared_id, true).await.unwrap();
+
     // Add John and Jane to the Sales group
     admin_client
         .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64).to_string())
@@ -745,6 +891,48 @@ where
             .await,
     );
 
+    // Nested group members are flattened, following cycles only once
+    let team_id = admin_client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64).to_string())
+        .group_create("team@example.com", "Team", [&sales_id, &bill_id, &jane_id])
+        .await
+        .unwrap()
+        .take_id();
+    let staff_id = admin_client
+        .group_create("staff@example.com", "Staff", [&team_id, &john_id])
+        .await
+        .unwrap()
+        .take_id();
+    admin_client
+        .principal_set_members(&team_id, [&sales_id, &bill_id, &jane_id, &staff_id].into())
+        .await
+        .unwrap();
+    let mut request = serde_json::from_value::<GetRequest<Principal>>(serde_json::json!({
+        "accountId": JMAPId::new(SUPERUSER_ID as u64),
+        "ids": [&staff_id],
+        "properties": ["members", "expandedMembers"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    let response = serde_json::to_value(&server.store.principal_get(request).unwrap()).unwrap();
+    let mut members = response["list"][0]["expandedMembers"]
+        .as_array()
+        .unwrap_or_else(|| panic!("{:?}", response))
+        .iter()
+        .map(|id| id.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    members.sort_unstable();
+    let mut expected_members = vec![john_id.clone(), jane_id.clone(), bill_id.clone()];
+    expected_members.sort_unstable();
+    assert_eq!(members, expected_members);
+    assert_eq!(
+        response["list"][0]["members"],
+        serde_json::json!([&team_id, &john_id])
+    );
+    for id in [&staff_id, &team_id] {
+        admin_client.principal_destroy(id).await.unwrap();
+    }
+
     // Remove John from the sales group
     admin_client
         .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64).to_string())
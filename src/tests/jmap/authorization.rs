@@ -21,7 +21,11 @@
  * for more details.
 */
 
-use std::{fmt::Debug, sync::Arc, time::Duration};
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use actix_web::web;
 use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
@@ -219,6 +223,78 @@ where
         }))
     ));
 
+    // Concurrent downloads share the account's bandwidth limit (1MB/s)
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let blob = vec![b'A'; 500000];
+    let blob_id = client
+        .upload(None, blob.clone(), None)
+        .await
+        .unwrap()
+        .take_blob_id();
+    let time = Instant::now();
+    let mut downloads = Vec::new();
+    for _ in 0..2 {
+        let client_ = client.clone();
+        let blob_id = blob_id.clone();
+        downloads.push(tokio::spawn(async move {
+            client_.download(&blob_id).await.unwrap()
+        }));
+    }
+    for download in downloads {
+        assert_eq!(download.await.unwrap(), blob);
+    }
+    assert!(
+        time.elapsed() >= Duration::from_millis(900),
+        "{:?}",
+        time.elapsed()
+    );
+
+    // Users should only be allowed to change their own password
+    let mut client = Client::new()
+        .credentials(Credentials::basic("jdoe@example.com", "12345"))
+        .connect(server.base_session.base_url())
+        .await
+        .unwrap();
+    client.set_default_account_id(JMAPId::new(SUPERUSER_ID as u64));
+    assert_forbidden(client.principal_set_secret(&domain_id, "Secret99").await);
+    assert!(matches!(
+        client.principal_set_secret(&account_id, "abc").await,
+        Err(jmap_client::Error::Set(SetError {
+            type_: SetErrorType::InvalidProperties,
+            ..
+        }))
+    ));
+    client
+        .principal_set_secret(&account_id, "Secret99")
+        .await
+        .unwrap();
+
+    // The old password should no longer authenticate
+    assert!(matches!(
+        client
+            .mailbox_query(None::<mailbox::query::Filter>, None::<Vec<_>>)
+            .await,
+        Err(jmap_client::Error::Problem(ProblemDetails {
+            status: Some(401),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        Client::new()
+            .credentials(Credentials::basic("jdoe@example.com", "12345"))
+            .connect(server.base_session.base_url())
+            .await,
+        Err(jmap_client::Error::Problem(ProblemDetails {
+            status: Some(401),
+            ..
+        }))
+    ));
+    Client::new()
+        .credentials(Credentials::basic("jdoe@example.com", "Secret99"))
+        .connect(server.base_session.base_url())
+        .await
+        .unwrap();
+
     // Destroy test accounts
     admin_client
         .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
@@ -0,0 +1,86 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, SystemTime};
+
+use actix_web::web;
+use jmap::types::{blob::JMAPBlob, jmap::JMAPId};
+use jmap_client::client::Client;
+use store::{
+    serialize::{key::BlobKey, StoreSerialize},
+    ColumnFamily, Store,
+};
+
+use crate::{services::housekeeper, tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running blob expiration tests...");
+
+    // Upload a blob without referencing it
+    let blob_id = client
+        .set_default_account_id(JMAPId::new(1))
+        .upload(None, b"This blob is never referenced.".to_vec(), None)
+        .await
+        .unwrap()
+        .take_blob_id();
+
+    // Purging should not remove uploads before their TTL expires
+    server
+        .housekeeper
+        .send(housekeeper::Event::PurgeBlobs)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        client.download(&blob_id).await.unwrap(),
+        b"This blob is never referenced."
+    );
+
+    // Expire the upload and make sure it is removed by the housekeeper
+    let expired_timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        - (server.store.config.blob_temp_ttl + 2);
+    server
+        .store
+        .db
+        .set(
+            ColumnFamily::Blobs,
+            &BlobKey::serialize_prefix(&JMAPBlob::parse(&blob_id).unwrap().id, 1),
+            &expired_timestamp.serialize().unwrap(),
+        )
+        .unwrap();
+    server
+        .housekeeper
+        .send(housekeeper::Event::PurgeBlobs)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(client.download(&blob_id).await.is_err());
+
+    server.store.assert_is_empty();
+}
@@ -40,10 +40,14 @@ use super::store::utils::{destroy_temp_dir, init_settings};
 
 pub mod acl;
 pub mod authorization;
+pub mod blobs;
+pub mod compaction;
 pub mod event_source;
+pub mod http_limits;
 pub mod oauth;
 pub mod push_subscription;
 pub mod references;
+pub mod slow_methods;
 pub mod stress_test;
 pub mod websocket;
 
@@ -52,11 +56,16 @@ pub async fn init_jmap_tests_opts<T>(
     peer_num: u32,
     total_peers: u32,
     delete_if_exists: bool,
+    extra_settings: &[(&str, &str)],
 ) -> (web::Data<JMAPServer<T>>, Client, PathBuf, ServerHandle)
 where
     T: for<'x> Store<'x> + 'static,
 {
-    let (settings, temp_dir) = init_settings(test_name, peer_num, total_peers, delete_if_exists);
+    let (mut settings, temp_dir) =
+        init_settings(test_name, peer_num, total_peers, delete_if_exists);
+    for (key, value) in extra_settings {
+        settings.set_value(key.to_string(), value.to_string());
+    }
     let server = init_jmap_server::<T>(&settings, None);
 
     // Start web server
@@ -86,7 +95,10 @@ where
     (server, client, temp_dir, handle)
 }
 
-pub async fn init_jmap_tests<T>(test_name: &str) -> (web::Data<JMAPServer<T>>, Client, PathBuf)
+pub async fn init_jmap_tests<T>(
+    test_name: &str,
+    extra_settings: &[(&str, &str)],
+) -> (web::Data<JMAPServer<T>>, Client, PathBuf)
 where
     T: for<'x> Store<'x> + 'static,
 {
@@ -102,7 +114,8 @@ where
     )
     .expect("Setting default subscriber failed.");
 
-    let (server, client, tmp_dir, _) = init_jmap_tests_opts::<T>(test_name, 1, 1, true).await;
+    let (server, client, tmp_dir, _) =
+        init_jmap_tests_opts::<T>(test_name, 1, 1, true, extra_settings).await;
     (server, client, tmp_dir)
 }
 
@@ -134,15 +147,18 @@ where
 #[actix_web::test]
 #[ignore]
 async fn jmap_core_tests() {
-    let (server, mut client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_tests").await;
+    let (server, mut client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_tests", &[]).await;
 
     // Run tests
     oauth::test(server.clone(), &mut client).await;
     acl::test(server.clone(), &mut client).await;
     authorization::test(server.clone(), &mut client).await;
+    blobs::test(server.clone(), &mut client).await;
+    compaction::test(server.clone(), &mut client, &temp_dir).await;
     event_source::test(server.clone(), &mut client).await;
     push_subscription::test(server.clone(), &mut client).await;
     websocket::test(server.clone(), &mut client).await;
+    http_limits::test(server.clone(), &mut client).await;
 
     destroy_temp_dir(&temp_dir);
 }
@@ -29,10 +29,20 @@ use std::{
 
 use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer};
 use ece::EcKeyComponents;
-use jmap::types::{jmap::JMAPId, type_state::TypeState};
+use jmap::{
+    orm::serialize::JMAPOrm,
+    push_subscription::{
+        schema::{Property, PushSubscription as PushSubscriptionObject},
+        set::JMAPSetPushSubscription,
+    },
+    request::set::SetRequest,
+    types::{jmap::JMAPId, type_state::TypeState},
+    SUPERUSER_ID,
+};
 use jmap_client::{client::Client, mailbox::Role, push_subscription::Keys};
-use reqwest::header::CONTENT_ENCODING;
-use store::{ahash::AHashSet, Store};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use reqwest::header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE};
+use store::{ahash::AHashSet, chrono::Utc, core::collection::Collection, Store};
 use tokio::sync::mpsc;
 
 use crate::{
@@ -46,6 +56,15 @@ where
 {
     println!("Running Push Subscription tests...");
 
+    // The VAPID public key is advertised to clients in the session
+    let session = serde_json::to_value(&server.base_session).unwrap();
+    assert_eq!(
+        session["capabilities"]["urn:ietf:params:jmap:webpush-vapid"]["applicationServerKey"],
+        VAPID_PUBLIC_KEY,
+        "{:?}",
+        session
+    );
+
     // Create channels
     let (event_tx, mut event_rx) = mpsc::channel::<PushMessage>(100);
 
@@ -182,14 +201,83 @@ where
     assert_state(&mut event_rx, &[TypeState::Mailbox]).await;
     expect_nothing(&mut event_rx).await;
 
+    // Expiration times beyond the configured maximum are clamped
+    set_expires(&server, &push_id, "2099-01-01T00:00:00Z");
+    let expires = get_expires(&server, &push_id);
+    let expires_max = Utc::now().timestamp() + server.store.config.push_expires_max as i64;
+    assert!(
+        expires <= expires_max && expires > expires_max - 60,
+        "{} {}",
+        expires,
+        expires_max
+    );
+
+    // Expired subscriptions no longer receive state changes
+    set_expires(&server, &push_id, "2000-01-01T00:00:00Z");
+    server
+        .update_push_subscriptions(SUPERUSER_ID)
+        .await
+        .unwrap();
+    client
+        .mailbox_update_sort_order(&mailbox_id, 100)
+        .await
+        .unwrap();
+    expect_nothing(&mut event_rx).await;
+
+    // Expired subscriptions are removed by the housekeeper
+    server.purge_push_subscriptions().await.unwrap();
+    assert!(server
+        .store
+        .get_document_ids(SUPERUSER_ID, Collection::PushSubscription)
+        .unwrap()
+        .map_or(true, |ids| ids.is_empty()));
+
     // Destroy mailbox
-    client.push_subscription_destroy(&push_id).await.unwrap();
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
     expect_nothing(&mut event_rx).await;
 
     server.store.assert_is_empty();
 }
 
+fn set_expires<T>(server: &JMAPServer<T>, push_id: &str, expires: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request =
+        serde_json::from_value::<SetRequest<PushSubscriptionObject>>(serde_json::json!({
+            "accountId": JMAPId::from(SUPERUSER_ID).to_string(),
+            "update": {
+                push_id: {
+                    "expires": expires
+                }
+            }
+        }))
+        .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    let response = server.store.push_subscription_set(request).unwrap();
+    assert!(response.not_updated.is_empty(), "{:?}", response);
+}
+
+fn get_expires<T>(server: &JMAPServer<T>, push_id: &str) -> i64
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    server
+        .store
+        .get_orm::<PushSubscriptionObject>(
+            SUPERUSER_ID,
+            JMAPId::parse(push_id).unwrap().get_document_id(),
+        )
+        .unwrap()
+        .unwrap()
+        .get(&Property::Expires)
+        .and_then(|p| p.as_timestamp())
+        .unwrap()
+}
+
+const VAPID_PUBLIC_KEY: &str =
+    "BKUwscACvK0b3dXCt9VDHaSHDpCEAer-ejrWKv-PX5O26cpS_KpbiXxgDUj3tH_7BBE3qQZBQ_Ytp9R954SBpeg";
+
 struct PushServer {
     keypair: EcKeyComponents,
     auth_secret: Vec<u8>,
@@ -244,18 +332,29 @@ async fn handle_push(
         return HttpResponse::InternalServerError().finish();
     }
 
+    assert_vapid(&request);
+
     let is_encrypted = request
         .headers()
         .get(CONTENT_ENCODING)
         .map_or(false, |encoding| encoding.to_str().unwrap() == "aes128gcm");
 
     let message = serde_json::from_slice::<PushMessage>(&if is_encrypted {
-        ece::decrypt(
-            &data.keypair,
-            &data.auth_secret,
-            &base64::decode_config(payload, base64::URL_SAFE).unwrap(),
-        )
-        .unwrap()
+        // Binary aes128gcm body: salt, record size, key id length and the sender's public key
+        assert_eq!(
+            request.headers().get(CONTENT_TYPE).unwrap(),
+            "application/octet-stream"
+        );
+        assert!(payload.len() > 86, "{:?}", payload);
+        assert_eq!(
+            u32::from_be_bytes(payload[16..20].try_into().unwrap()),
+            4096
+        );
+        assert_eq!(payload[20], 65);
+        assert_eq!(payload[21], 4);
+        assert_eq!((payload.len() - 86) % 16, 0);
+
+        ece::decrypt(&data.keypair, &data.auth_secret, &payload).unwrap()
     } else {
         payload.to_vec()
     })
@@ -268,6 +367,51 @@ async fn handle_push(
     HttpResponse::Ok().body("")
 }
 
+fn assert_vapid(request: &HttpRequest) {
+    let authorization = request
+        .headers()
+        .get(AUTHORIZATION)
+        .expect("Missing VAPID authorization")
+        .to_str()
+        .unwrap();
+    let (token, public_key) = authorization
+        .strip_prefix("vapid t=")
+        .and_then(|value| value.split_once(", k="))
+        .unwrap();
+    assert_eq!(public_key, VAPID_PUBLIC_KEY);
+
+    // Verify the ES256 signature and the claims of the JWT
+    let (message, signature) = token.rsplit_once('.').unwrap();
+    VerifyingKey::from_sec1_bytes(
+        &base64::decode_config(public_key, base64::URL_SAFE_NO_PAD).unwrap(),
+    )
+    .unwrap()
+    .verify(
+        message.as_bytes(),
+        &Signature::try_from(
+            &base64::decode_config(signature, base64::URL_SAFE_NO_PAD).unwrap()[..],
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let (header, claims) = message.split_once('.').unwrap();
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(
+            &base64::decode_config(header, base64::URL_SAFE_NO_PAD).unwrap()
+        )
+        .unwrap(),
+        serde_json::json!({"typ": "JWT", "alg": "ES256"})
+    );
+    let claims = serde_json::from_slice::<serde_json::Value>(
+        &base64::decode_config(claims, base64::URL_SAFE_NO_PAD).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims["aud"], "https://127.0.0.1:9000");
+    assert_eq!(claims["sub"], "mailto:admin@example.com");
+    let expires = claims["exp"].as_i64().unwrap() - Utc::now().timestamp();
+    assert!(expires > 0 && expires <= 86400, "{:?}", claims);
+}
+
 async fn expect_push(event_rx: &mut mpsc::Receiver<PushMessage>) -> PushMessage {
     match tokio::time::timeout(Duration::from_millis(1500), event_rx.recv()).await {
         Ok(Some(push)) => push,
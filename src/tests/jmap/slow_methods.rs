@@ -0,0 +1,85 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::api::invocation::log_slow_method;
+
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn slow_method_log() {
+    let buffer = LogBuffer::default();
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(store::tracing::Level::WARN)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+
+    store::tracing::subscriber::with_default(subscriber, || {
+        // Fast calls and disabled thresholds are not logged
+        assert!(!log_slow_method(
+            "Mailbox/get",
+            1,
+            Duration::from_millis(5),
+            1000
+        ));
+        assert!(!log_slow_method(
+            "Mailbox/get",
+            1,
+            Duration::from_secs(60),
+            0
+        ));
+
+        // Artificially slow method call
+        let started = Instant::now();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(log_slow_method("Email/query", 2, started.elapsed(), 20));
+    });
+
+    let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(log.lines().count(), 1, "{}", log);
+    assert!(
+        log.contains("Slow method call Email/query for account 2 took"),
+        "{}",
+        log
+    );
+    assert!(!log.contains("Mailbox/get"), "{}", log);
+}
@@ -57,7 +57,7 @@ const NUM_PASSES: usize = 1;
 #[actix_web::test]
 #[ignore]
 async fn jmap_stress_tests() {
-    let (server, client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_stress_tests").await;
+    let (server, client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_stress_tests", &[]).await;
 
     let client = Arc::new(client);
 
@@ -287,6 +287,105 @@ where
     );
     assert_eq!(changes.updated(), Vec::<String>::new());
     assert_eq!(changes.destroyed(), Vec::<String>::new());
+
+    // Large change sets are returned in pages
+    let initial_state = JMAPState::parse(changes.new_state()).unwrap();
+    let mut documents = WriteBatch::new(1);
+    for id in 1000..2000 {
+        documents.log_insert(Collection::Mail, id);
+    }
+    server.store.write(documents).unwrap();
+    let inserted_state = JMAPState::parse(
+        client
+            .email_changes(initial_state.to_string(), None)
+            .await
+            .unwrap()
+            .new_state(),
+    )
+    .unwrap();
+
+    let mut documents = WriteBatch::new(1);
+    for id in 1000..1300 {
+        documents.log_update(Collection::Mail, id);
+    }
+    for id in 1300..1400 {
+        documents.log_delete(Collection::Mail, id);
+    }
+    for id in 2000..2200 {
+        documents.log_insert(Collection::Mail, id);
+    }
+    server.store.write(documents).unwrap();
+
+    for (state, expected_changes) in [
+        (
+            &initial_state,
+            [
+                (1000..1300).chain(1400..2200).collect::<Vec<u64>>(),
+                vec![],
+                vec![],
+            ],
+        ),
+        (
+            &inserted_state,
+            [
+                (2000..2200).collect::<Vec<u64>>(),
+                (1000..1300).collect(),
+                (1300..1400).collect(),
+            ],
+        ),
+    ] {
+        for max_changes in [7, 100, 250, 5000] {
+            let mut changes_found: [Vec<u64>; 3] = [vec![], vec![], vec![]];
+            let mut int_state = state.clone();
+
+            for _ in 0..1000 {
+                let changes = client
+                    .email_changes(int_state.to_string(), max_changes.into())
+                    .await
+                    .unwrap();
+                let total_changes =
+                    changes.created().len() + changes.updated().len() + changes.destroyed().len();
+                assert!(
+                    total_changes <= max_changes,
+                    "{} > {}",
+                    total_changes,
+                    max_changes
+                );
+
+                for (list, found) in [changes.created(), changes.updated(), changes.destroyed()]
+                    .into_iter()
+                    .zip(changes_found.iter_mut())
+                {
+                    found.extend(list.iter().map(|i| u64::from(JMAPId::parse(i).unwrap())));
+                }
+
+                int_state = JMAPState::parse(changes.new_state()).unwrap();
+                if !changes.has_more_changes() {
+                    break;
+                }
+                assert_eq!(total_changes, max_changes);
+            }
+
+            for (found, expected) in changes_found.iter_mut().zip(expected_changes.iter()) {
+                found.sort_unstable();
+                assert_eq!(found, expected, "max_changes: {}", max_changes);
+            }
+
+            // The final cursor points to the latest state
+            let changes = client
+                .email_changes(int_state.to_string(), max_changes.into())
+                .await
+                .unwrap();
+            assert!(
+                changes.created().is_empty()
+                    && changes.updated().is_empty()
+                    && changes.destroyed().is_empty()
+                    && !changes.has_more_changes(),
+                "max_changes: {}",
+                max_changes
+            );
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -22,8 +22,10 @@
 */
 
 use actix_web::web;
-use jmap::types::jmap::JMAPId;
-use jmap_client::{client::Client, mailbox::Role};
+use jmap::{request::copy::CopyRequest, types::jmap::JMAPId};
+use jmap_client::{client::Client, email, mailbox::Role};
+use jmap_mail::mail::{copy::JMAPCopyMail, schema};
+use jmap_sharing::principal::account::JMAPAccountStore;
 use store::Store;
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
@@ -114,6 +116,132 @@ where
         .unwrap()
         .is_none());
 
+    // Import a thread and a message without references on account 1
+    let mut thread_email_ids = Vec::new();
+    for raw_message in [
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Message-ID: <tps-1@example.com>\r\n",
+            "Subject: TPS Report cover sheets\r\n",
+            "\r\n",
+            "Did you get the memo?"
+        ),
+        concat!(
+            "From: jdoe@example.com\r\n",
+            "To: bill@example.com\r\n",
+            "Message-ID: <tps-2@example.com>\r\n",
+            "In-Reply-To: <tps-1@example.com>\r\n",
+            "Subject: Re: TPS Report cover sheets\r\n",
+            "\r\n",
+            "Yeah, I got the memo."
+        ),
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Saturday\r\n",
+            "\r\n",
+            "I'm going to need you to go ahead and come in tomorrow."
+        ),
+    ] {
+        thread_email_ids.push(
+            client
+                .email_import(
+                    raw_message.as_bytes().to_vec(),
+                    [&ac1_mailbox_id],
+                    None::<Vec<&str>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+
+    // Copy the messages to another mailbox within the same account
+    let ac1_archive_id = client
+        .mailbox_create("Copy Test Archive", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut request = client.build();
+    let copy_request = request.copy_email(JMAPId::new(1).to_string());
+    for email_id in &thread_email_ids[1..] {
+        copy_request
+            .create(email_id)
+            .mailbox_id(&ac1_archive_id, true);
+    }
+    let mut response = request
+        .send()
+        .await
+        .unwrap()
+        .method_response_by_pos(0)
+        .unwrap_copy_email()
+        .unwrap();
+
+    // Copies join the thread of the original message
+    let original_thread_id = get_thread_id(client, &thread_email_ids[0]).await;
+    for email_id in &thread_email_ids[1..] {
+        let copy_id = response.created(email_id).unwrap().take_id();
+        assert_ne!(&copy_id, email_id);
+        assert_eq!(
+            get_thread_id(client, &copy_id).await,
+            get_thread_id(client, email_id).await
+        );
+        let email = client
+            .email_get(&copy_id, None::<Vec<_>>)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(email.mailbox_ids(), &[&ac1_archive_id]);
+    }
+    assert_eq!(
+        get_thread_id(client, &thread_email_ids[1]).await,
+        original_thread_id
+    );
+    assert_eq!(
+        client
+            .thread_get(&original_thread_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .email_ids()
+            .len(),
+        3
+    );
+
+    // Copies retried with the same idempotency key return the original copies,
+    // while repeating a copy without it creates new messages
+    let archived_ids = copy_with_key(
+        &server,
+        &thread_email_ids[1..],
+        &ac1_archive_id,
+        "archive-retry",
+    );
+    assert_eq!(archived_ids.len(), 2);
+    assert_eq!(
+        copy_with_key(
+            &server,
+            &thread_email_ids[1..],
+            &ac1_archive_id,
+            "archive-retry",
+        ),
+        archived_ids
+    );
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox(&ac1_archive_id).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        4
+    );
+    client.mailbox_destroy(&ac1_archive_id, true).await.unwrap();
+
     // Empty store
     client.mailbox_destroy(&ac1_mailbox_id, true).await.unwrap();
     client
@@ -123,3 +251,48 @@ where
         .unwrap();
     server.store.assert_is_empty();
 }
+
+fn copy_with_key<T>(
+    server: &JMAPServer<T>,
+    email_ids: &[String],
+    mailbox_id: &str,
+    idempotency_key: &str,
+) -> Vec<String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request = serde_json::from_value::<CopyRequest<schema::Email>>(serde_json::json!({
+        "fromAccountId": JMAPId::new(1).to_string(),
+        "accountId": JMAPId::new(1).to_string(),
+        "create": email_ids
+            .iter()
+            .map(|email_id| (email_id.to_string(), serde_json::json!({
+                "mailboxIds": {mailbox_id: true}
+            })))
+            .collect::<serde_json::Map<_, _>>(),
+        "idempotencyKey": idempotency_key
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_copy(request).unwrap()).unwrap();
+    email_ids
+        .iter()
+        .map(|email_id| {
+            response["created"][email_id]["id"]
+                .as_str()
+                .unwrap_or_else(|| panic!("{:?}", response))
+                .to_string()
+        })
+        .collect()
+}
+
+async fn get_thread_id(client: &mut Client, email_id: &str) -> String {
+    client
+        .email_get(email_id, [email::Property::ThreadId].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .thread_id()
+        .unwrap()
+        .to_string()
+}
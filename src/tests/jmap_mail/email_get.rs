@@ -24,14 +24,25 @@
 use std::{fs, path::PathBuf};
 
 use actix_web::web;
-use jmap::types::jmap::JMAPId;
+use jmap::{request::get::GetRequest, types::jmap::JMAPId};
 use jmap_client::{
     client::Client,
     email::{self, Header, HeaderForm},
     mailbox::Role,
 };
-use jmap_mail::mail_parser::RfcHeader;
-use store::Store;
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        parse::{EmailParseRequest, JMAPMailParse},
+        schema, MessageField, MessagePreview,
+    },
+    mail_parser::RfcHeader,
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{
+    core::collection::Collection, serialize::key::ValueKey, serialize::StoreSerialize,
+    ColumnFamily, Store,
+};
 
 use crate::{
     tests::{jmap_mail::replace_blob_ids, store::utils::StoreCompareWith},
@@ -189,6 +200,323 @@ where
         }
     }
 
+    // Inline images referenced by Content-ID are not attachments
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: bill@example.com\r\n",
+                "Subject: Inline image\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/related; boundary=\"rel\"\r\n",
+                "\r\n",
+                "--rel\r\n",
+                "Content-Type: text/html\r\n",
+                "\r\n",
+                "<p>Look at this: <img src=\"cid:logo@example.com\"></p>\r\n",
+                "--rel\r\n",
+                "Content-Type: image/png; name=\"logo.png\"\r\n",
+                "Content-ID: <logo@example.com>\r\n",
+                "Content-Disposition: inline; filename=\"logo.png\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "iVBORw0KGgo=\r\n",
+                "--rel--\r\n",
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    assert!(!client
+        .email_get(&email_id, [email::Property::HasAttachment].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .has_attachment());
+    for has_attachment in [true, false] {
+        assert_eq!(
+            client
+                .email_query(
+                    email::query::Filter::and(vec![
+                        email::query::Filter::in_mailbox(&mailbox_id),
+                        email::query::Filter::has_attachment(has_attachment),
+                    ])
+                    .into(),
+                    None::<Vec<_>>,
+                )
+                .await
+                .unwrap()
+                .ids()
+                .contains(&email_id),
+            !has_attachment
+        );
+    }
+
+    // List-Unsubscribe headers are returned as structured unsubscribe targets
+    let mut email_ids = Vec::new();
+    for headers in [
+        concat!(
+            "List-Unsubscribe: <mailto:unsubscribe@lists.example.com?subject=unsubscribe>,\r\n",
+            "  <https://lists.example.com/unsubscribe/1234>\r\n",
+            "List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n",
+        ),
+        "List-Unsubscribe: <mailto:unsubscribe@lists.example.com>\r\n",
+        "",
+    ] {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        "From: list@lists.example.com\r\nSubject: Newsletter\r\n{}\r\nHello!\r\n",
+                        headers
+                    )
+                    .into_bytes(),
+                    [&mailbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": email_ids,
+        "properties": ["unsubscribe"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["unsubscribe"],
+        serde_json::json!({
+            "mailto": ["mailto:unsubscribe@lists.example.com?subject=unsubscribe"],
+            "http": ["https://lists.example.com/unsubscribe/1234"],
+            "oneClick": true
+        }),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        response["list"][1]["unsubscribe"],
+        serde_json::json!({
+            "mailto": ["mailto:unsubscribe@lists.example.com"],
+            "http": [],
+            "oneClick": false
+        }),
+        "{:?}",
+        response
+    );
+    assert!(
+        response["list"][2]["unsubscribe"].is_null(),
+        "{:?}",
+        response
+    );
+
+    // Autocrypt headers are only returned when valid and matching the sender's address
+    let mut email_ids = Vec::new();
+    for headers in [
+        concat!(
+            "Autocrypt: addr=jdoe@example.com; prefer-encrypt=mutual; keydata=\r\n",
+            " bWFpbiBrZXkgZGF0YQ==\r\n",
+        ),
+        "Autocrypt: addr=jdoe@example.com; _custom=1; keydata=bWFpbiBrZXkgZGF0YQ==\r\n",
+        "Autocrypt: addr=other@example.com; keydata=bWFpbiBrZXkgZGF0YQ==\r\n",
+        "Autocrypt: addr=jdoe@example.com; critical=yes; keydata=bWFpbiBrZXkgZGF0YQ==\r\n",
+        "Autocrypt: addr=jdoe@example.com; keydata=!!invalid!!\r\n",
+        concat!(
+            "Autocrypt: addr=jdoe@example.com; keydata=bWFpbiBrZXkgZGF0YQ==\r\n",
+            "Autocrypt: addr=jdoe@example.com; keydata=c2Vjb25kIGtleQ==\r\n",
+        ),
+        "",
+    ] {
+        email_ids.push(
+            client
+                .email_import(
+                    format!(
+                        "From: jdoe@example.com\r\nSubject: Keys\r\n{}\r\nHello!\r\n",
+                        headers
+                    )
+                    .into_bytes(),
+                    [&mailbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": email_ids,
+        "properties": ["autocrypt"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["autocrypt"],
+        serde_json::json!({
+            "addr": "jdoe@example.com",
+            "preferEncrypt": "mutual",
+            "keyData": "bWFpbiBrZXkgZGF0YQ=="
+        }),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        response["list"][1]["autocrypt"],
+        serde_json::json!({
+            "addr": "jdoe@example.com",
+            "preferEncrypt": "nopreference",
+            "keyData": "bWFpbiBrZXkgZGF0YQ=="
+        }),
+        "{:?}",
+        response
+    );
+    for pos in 2..=6 {
+        assert!(
+            response["list"][pos]["autocrypt"].is_null(),
+            "{:?}",
+            response
+        );
+    }
+
+    // Previews are stored at ingest and only recomputed when the preview length changes
+    let email_id = client
+        .email_import(
+            b"Subject: Preview\r\n\r\nThe preview is obtained from this text.\r\n".to_vec(),
+            [&mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let get_preview = || {
+        let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "ids": [&email_id],
+            "properties": ["preview"]
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap()["list"][0]
+            ["preview"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    let set_preview = |max_len: usize| {
+        server
+            .store
+            .db
+            .set(
+                ColumnFamily::Values,
+                &ValueKey::serialize_value(
+                    1,
+                    Collection::Mail,
+                    JMAPId::parse(&email_id).unwrap().get_document_id(),
+                    MessageField::Preview.into(),
+                ),
+                &MessagePreview {
+                    max_len,
+                    preview: "Stored preview".to_string(),
+                }
+                .serialize()
+                .unwrap(),
+            )
+            .unwrap();
+    };
+    let preview = get_preview();
+    assert!(
+        preview.starts_with("The preview is obtained from this text."),
+        "{:?}",
+        preview
+    );
+    set_preview(server.store.config.mail_preview_length);
+    assert_eq!(get_preview(), "Stored preview");
+    set_preview(server.store.config.mail_preview_length + 1);
+    assert_eq!(get_preview(), preview);
+
+    // Remote resources referenced by HTML bodies are reported by Email/get and Email/parse
+    let email = client
+        .email_import(
+            concat!(
+                "Subject: Remote resources
+",
+                "Content-Type: text/html; charset=utf-8
+",
+                "\r\n",
+                "<html><body background=\"https://cdn.example.com/bg.png\">\r\n",
+                "<img src=\"cid:logo@example.com\">\r\n",
+                "<img src='https://example.com/banner.png' width=600>\r\n",
+                "<img width=\"1\" height=\"1\" src=\"http://t.example.com/o.gif?u=1&amp;m=2\">\r\n",
+                "</body></html>\r\n",
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap();
+    let expected_resources = serde_json::json!([
+        {
+            "url": "https://cdn.example.com/bg.png",
+            "element": "body",
+            "attribute": "background",
+            "isTrackingPixel": false
+        },
+        {
+            "url": "https://example.com/banner.png",
+            "element": "img",
+            "attribute": "src",
+            "isTrackingPixel": false
+        },
+        {
+            "url": "http://t.example.com/o.gif?u=1&m=2",
+            "element": "img",
+            "attribute": "src",
+            "isTrackingPixel": true
+        }
+    ]);
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [email.id().unwrap()],
+        "properties": ["remoteResources"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["remoteResources"], expected_resources,
+        "{:?}",
+        response
+    );
+
+    let mut request = serde_json::from_value::<EmailParseRequest>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "blobIds": [email.blob_id().unwrap()],
+        "properties": ["remoteResources"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_parse(request).unwrap()).unwrap();
+    assert_eq!(
+        response["parsed"][email.blob_id().unwrap()]["remoteResources"],
+        expected_resources,
+        "{:?}",
+        response
+    );
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
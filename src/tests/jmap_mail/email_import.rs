@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+
+use jmap::types::jmap::JMAPId;
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_mail::mail::import::{EmailImportRequest, JMAPMailImport};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Email Import tests...");
+
+    let account_id = JMAPId::new(1).to_string();
+    let mailbox_id = client
+        .set_default_account_id(&account_id)
+        .mailbox_create("JMAP Import", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Requests over the limit import the first items and reject the rest
+    let max_items = server.store.config.mail_import_max_items;
+    let mut emails = serde_json::Map::new();
+    for num in 0..max_items + 2 {
+        let blob_id = client
+            .upload(
+                Some(account_id.as_str()),
+                format!("Subject: import {}\r\n\r\nMessage {}", num, num).into_bytes(),
+                None,
+            )
+            .await
+            .unwrap()
+            .take_blob_id();
+        emails.insert(
+            format!("m{}", num),
+            serde_json::json!({
+                "blobId": blob_id,
+                "mailboxIds": {&mailbox_id: true}
+            }),
+        );
+    }
+    let mut request = serde_json::from_value::<EmailImportRequest>(serde_json::json!({
+        "accountId": &account_id,
+        "emails": emails
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(server.store.mail_import(request).unwrap()).unwrap();
+
+    for num in 0..max_items + 2 {
+        let id = format!("m{}", num);
+        if num < max_items {
+            assert!(
+                response["created"][&id]["id"].is_string(),
+                "{} {:?}",
+                id,
+                response
+            );
+        } else {
+            assert_eq!(
+                response["notCreated"][&id]["type"], "tooManyItems",
+                "{} {:?}",
+                id, response
+            );
+        }
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    server.store.assert_is_empty();
+}
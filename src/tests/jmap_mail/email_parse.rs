@@ -31,6 +31,11 @@ use jmap_client::{
     email::{self, Header, HeaderForm},
     mailbox::Role,
 };
+use jmap_mail::{
+    mail::parse::{EmailParseRequest, JMAPMailParse},
+    mdn::parse::{JMAPMailMDNParse, MDNParseRequest},
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
 use store::Store;
 
 use crate::{
@@ -254,6 +259,154 @@ where
         panic!("Test failed, output saved to {}", test_file.display());
     }
 
+    // Lightweight parsing returns only the envelope fields
+    let blob_size = fs::read(test_file.with_extension("eml")).unwrap().len();
+    let parse = |arguments: serde_json::Value| {
+        let mut request = serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "blobIds": [&blob_id],
+        });
+        for (key, value) in arguments.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+        let mut request = serde_json::from_value::<EmailParseRequest>(request).unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let mut response =
+            serde_json::to_value(&server.store.mail_parse(request).unwrap()).unwrap();
+        response["parsed"][&blob_id].take()
+    };
+
+    let email = parse(serde_json::json!({"lightweight": true}));
+    let mut properties = email
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(|k| k.as_str())
+        .collect::<Vec<_>>();
+    properties.sort_unstable();
+    assert_eq!(
+        properties,
+        [
+            "blobId",
+            "from",
+            "hasAttachment",
+            "sentAt",
+            "size",
+            "subject",
+            "to"
+        ]
+    );
+    assert_eq!(email["size"], blob_size);
+    assert_eq!(email["blobId"], blob_id.as_str());
+
+    // Body properties are ignored in lightweight mode
+    let email = parse(serde_json::json!({
+        "lightweight": true,
+        "properties": ["subject", "bodyStructure", "textBody", "size"]
+    }));
+    assert_eq!(email.as_object().unwrap().len(), 2, "{:?}", email);
+    assert_eq!(email["size"], blob_size);
+
+    // Envelope fields match the ones obtained from a full parse
+    let mut full_email = parse(serde_json::json!({
+        "properties": ["from", "to", "subject", "sentAt", "size", "hasAttachment", "preview"]
+    }));
+    full_email.as_object_mut().unwrap().remove("preview");
+    assert_eq!(
+        parse(serde_json::json!({
+            "properties": ["from", "to", "subject", "sentAt", "size", "hasAttachment"]
+        })),
+        full_email
+    );
+
+    // Parse a disposition notification
+    let mdn_blob_id = client
+        .upload(
+            None,
+            concat!(
+                "From: Joe Recipient <joe@example.com>\r\n",
+                "To: Jane Sender <jane@example.org>\r\n",
+                "Subject: Read: Kitchen remodel\r\n",
+                "Message-ID: <mdn-1@example.com>\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/report; report-type=disposition-notification;\r\n",
+                "\tboundary=\"RAA14128.773615765/example.com\"\r\n",
+                "\r\n",
+                "--RAA14128.773615765/example.com\r\n",
+                "Content-Type: text/plain; charset=utf-8\r\n",
+                "\r\n",
+                "The message sent on 1995 Sep 19 at 13:30:00 (EDT) -0400 to Joe\r\n",
+                "has been displayed.\r\n",
+                "--RAA14128.773615765/example.com\r\n",
+                "Content-Type: message/disposition-notification\r\n",
+                "\r\n",
+                "Reporting-UA: joes-pc.cs.example.com; Foomail 97.1\r\n",
+                "Original-Recipient: rfc822;Joe_Recipient@example.com\r\n",
+                "Final-Recipient: rfc822;Joe_Recipient@example.com\r\n",
+                "Original-Message-ID: <199509192301.23456@example.org>\r\n",
+                "Disposition: manual-action/MDN-sent-manually; displayed\r\n",
+                "X-Foomail-Id: 1234\r\n",
+                "\r\n",
+                "--RAA14128.773615765/example.com\r\n",
+                "Content-Type: text/rfc822-headers\r\n",
+                "\r\n",
+                "From: Jane Sender <jane@example.org>\r\n",
+                "Subject: Kitchen remodel\r\n",
+                "Message-ID: <199509192301.23456@example.org>\r\n",
+                "\r\n",
+                "--RAA14128.773615765/example.com--\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_blob_id();
+    let mut request = serde_json::from_value::<MDNParseRequest>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "blobIds": [&mdn_blob_id],
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let mut response = serde_json::to_value(&server.store.mdn_parse(request).unwrap()).unwrap();
+    assert_eq!(
+        response["parsed"][&mdn_blob_id].take(),
+        serde_json::json!({
+            "forEmailId": null,
+            "subject": "Read: Kitchen remodel",
+            "textBody": concat!(
+                "The message sent on 1995 Sep 19 at 13:30:00 (EDT) -0400 to Joe\r\n",
+                "has been displayed."
+            ),
+            "includeOriginalMessage": true,
+            "reportingUA": "joes-pc.cs.example.com; Foomail 97.1",
+            "disposition": {
+                "actionMode": "manual-action",
+                "sendingMode": "mdn-sent-manually",
+                "type": "displayed"
+            },
+            "mdnGateway": null,
+            "originalRecipient": "rfc822;Joe_Recipient@example.com",
+            "finalRecipient": "rfc822;Joe_Recipient@example.com",
+            "originalMessageId": "<199509192301.23456@example.org>",
+            "error": null,
+            "extensionFields": {
+                "X-Foomail-Id": "1234"
+            }
+        })
+    );
+
+    // Regular messages are not parsable as MDNs
+    let mut request = serde_json::from_value::<MDNParseRequest>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "blobIds": [&blob_id],
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mdn_parse(request).unwrap()).unwrap();
+    assert_eq!(response["notParsable"], serde_json::json!([&blob_id]));
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
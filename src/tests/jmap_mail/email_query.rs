@@ -21,17 +21,23 @@
  * for more details.
 */
 
-use std::{collections::hash_map::Entry, time::Instant};
+use std::{collections::hash_map::Entry, io::Write, sync::Arc, time::Instant};
 
 use actix_web::web;
 
-use jmap::types::jmap::JMAPId;
+use flate2::{write::GzEncoder, Compression};
+use jmap::{request::query::QueryRequest, types::jmap::JMAPId};
 use jmap_client::{
     client::Client,
     core::query::{Comparator, Filter},
     email,
+    mailbox::Role,
 };
-use jmap_mail::mail_parser::RfcHeader;
+use jmap_mail::{
+    mail::{query::JMAPMailQuery, schema},
+    mail_parser::RfcHeader,
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
 use store::{
     ahash::AHashMap,
     core::collection::Collection,
@@ -50,6 +56,11 @@ use crate::{
     JMAPServer,
 };
 
+pub const SETTINGS: &[(&str, &str)] = &[
+    ("cache-size-queries", "100"),
+    ("mail-default-sort", "newest"),
+];
+
 const MAX_THREADS: usize = 100;
 const MAX_MESSAGES: usize = 1000;
 const MAX_MESSAGES_PER_THREAD: usize = 100;
@@ -136,9 +147,584 @@ where
         .unwrap_set_email()
         .unwrap();
 
+    println!("Running JMAP Mail sentAt sort tests...");
+    sent_at_sort(client).await;
+
+    println!("Running JMAP Mail sentAt filter tests...");
+    sent_at_filter(&server, client).await;
+
+    println!("Running JMAP Mail inMailboxOtherThan tests...");
+    in_mailbox_other_than(client).await;
+
+    println!("Running JMAP Mail compressed attachment tests...");
+    compressed_attachments(client).await;
+
+    println!("Running JMAP Mail attachment filename tests...");
+    attachment_filenames(client).await;
+
+    println!("Running JMAP Mail size boundary tests...");
+    size_boundaries(client).await;
+
+    println!("Running JMAP Mail sender collation tests...");
+    from_collation(client).await;
+
+    println!("Running JMAP Mail system flag alias tests...");
+    system_flag_aliases(client).await;
+
+    println!("Running JMAP Mail query cache tests...");
+    query_cache(&server, client).await;
+
     server.store.assert_is_empty();
 }
 
+pub async fn sent_at_sort(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("Sent At", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut ids = AHashMap::new();
+    for (name, date, received_at) in [
+        ("a", Some("Sat, 20 Nov 2021 14:22:01 -0800"), 3000i64),
+        ("b", None, 1000i64),
+        ("c", Some("Mon, 1 Nov 2021 10:00:00 +0000"), 2000i64),
+    ] {
+        let message = if let Some(date) = date {
+            format!("Date: {}\nSubject: {}\n\ntest", date, name)
+        } else {
+            format!("Subject: {}\n\ntest", name)
+        };
+        let id = client
+            .email_import(
+                message.into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                Some(received_at),
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    for (sort, expected_results) in [
+        (email::query::Comparator::sent_at(), ["c", "a", "b"]),
+        (
+            email::query::Comparator::sent_at().descending(),
+            ["a", "c", "b"],
+        ),
+        (email::query::Comparator::received_at(), ["b", "c", "a"]),
+        (
+            email::query::Comparator::received_at().descending(),
+            ["a", "c", "b"],
+        ),
+    ] {
+        assert_eq!(
+            client
+                .email_query(
+                    email::query::Filter::in_mailbox(&mailbox_id).into(),
+                    vec![sort].into(),
+                )
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected_results
+        );
+    }
+
+    // Queries without a sort default to newest first
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox(&mailbox_id).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .iter()
+            .map(|id| *ids.get(id).unwrap())
+            .collect::<Vec<_>>(),
+        ["a", "c", "b"]
+    );
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn sent_at_filter<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox_id = client
+        .mailbox_create("Sent At Filter", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Received and sent dates are deliberately far apart
+    let mut ids = AHashMap::new();
+    for (name, date, received_at) in [
+        ("a", Some("Sat, 20 Nov 2021 14:22:01 -0800"), 3000i64),
+        ("b", None, 2000000000i64),
+        ("c", Some("Mon, 1 Nov 2021 10:00:00 +0000"), 2000000000i64),
+    ] {
+        let message = if let Some(date) = date {
+            format!("Date: {}\nSubject: {}\n\ntest", date, name)
+        } else {
+            format!("Subject: {}\n\ntest", name)
+        };
+        let id = client
+            .email_import(
+                message.into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                Some(received_at),
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    let account_id = JMAPId::parse(client.default_account_id()).unwrap();
+    for (condition, expected_results) in [
+        (
+            serde_json::json!({"sentAfter": "2021-11-10T00:00:00Z"}),
+            vec!["a"],
+        ),
+        (
+            serde_json::json!({"sentBefore": "2021-11-10T00:00:00Z"}),
+            vec!["c"],
+        ),
+        (
+            serde_json::json!({"after": "2021-11-10T00:00:00Z"}),
+            vec!["b", "c"],
+        ),
+        (
+            serde_json::json!({"before": "2021-11-10T00:00:00Z"}),
+            vec!["a"],
+        ),
+    ] {
+        let mut request =
+            serde_json::from_value::<QueryRequest<schema::Email>>(serde_json::json!({
+                "accountId": account_id,
+                "filter": {
+                    "operator": "AND",
+                    "conditions": [{"inMailbox": &mailbox_id}, &condition]
+                }
+            }))
+            .unwrap();
+        request.acl = server
+            .store
+            .get_acl_token(account_id.get_document_id())
+            .unwrap()
+            .into();
+        let response = serde_json::to_value(server.store.mail_query(request).unwrap()).unwrap();
+        let mut results = response["ids"]
+            .as_array()
+            .unwrap_or_else(|| panic!("{:?}", response))
+            .iter()
+            .map(|id| *ids.get(id.as_str().unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        results.sort_unstable();
+        assert_eq!(results, expected_results, "{}", condition);
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn in_mailbox_other_than(client: &mut Client) {
+    let mut mailbox_ids = Vec::new();
+    for name in ["All Mail Inbox", "All Mail Trash", "All Mail Spam"] {
+        mailbox_ids.push(
+            client
+                .mailbox_create(name, None::<String>, Role::None)
+                .await
+                .unwrap()
+                .take_id(),
+        );
+    }
+    let (inbox_id, trash_id, spam_id) = (&mailbox_ids[0], &mailbox_ids[1], &mailbox_ids[2]);
+
+    let mut ids = AHashMap::new();
+    for (name, mailbox_id, received_at) in [
+        ("a", inbox_id, 1000i64),
+        ("b", trash_id, 2000i64),
+        ("c", inbox_id, 3000i64),
+        ("d", spam_id, 4000i64),
+    ] {
+        let id = client
+            .email_import(
+                format!("Subject: {}\n\ntest", name).into_bytes(),
+                [mailbox_id],
+                None::<Vec<String>>,
+                Some(received_at),
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    for (excluded_ids, expected_results) in [
+        (vec![trash_id, spam_id], vec!["a", "c"]),
+        (vec![trash_id], vec!["a", "c", "d"]),
+        (vec![inbox_id, trash_id, spam_id], vec![]),
+    ] {
+        assert_eq!(
+            client
+                .email_query(
+                    email::query::Filter::in_mailbox_other_than(excluded_ids).into(),
+                    vec![email::query::Comparator::received_at()].into(),
+                )
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected_results
+        );
+    }
+
+    for mailbox_id in mailbox_ids {
+        client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    }
+}
+
+pub async fn compressed_attachments(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("Compressed Attachments", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut ids = AHashMap::new();
+    for (name, contents) in [
+        ("small", "A zebracorn report.".to_string()),
+        (
+            "bomb",
+            format!("{}A unicornfish report.", " ".repeat(2 * 1024 * 1024)),
+        ),
+    ] {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        let id = client
+            .email_import(
+                format!(
+                    concat!(
+                        "Subject: {}\r\n",
+                        "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+                        "\r\n",
+                        "--b\r\n",
+                        "Content-Type: text/plain\r\n",
+                        "\r\n",
+                        "See attached.\r\n",
+                        "--b\r\n",
+                        "Content-Type: application/gzip; name=\"report.txt.gz\"\r\n",
+                        "Content-Transfer-Encoding: base64\r\n",
+                        "\r\n",
+                        "{}\r\n",
+                        "--b--\r\n"
+                    ),
+                    name,
+                    base64::encode(encoder.finish().unwrap())
+                )
+                .into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                None,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    // Attachments exceeding the decompression ratio are not indexed
+    for (text, expected_results) in [("zebracorn", vec!["small"]), ("unicornfish", vec![])] {
+        assert_eq!(
+            client
+                .email_query(email::query::Filter::text(text).into(), None::<Vec<_>>)
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected_results
+        );
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn attachment_filenames(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("Attachment Filenames", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut ids = AHashMap::new();
+    for (name, filename) in [("invoice", "invoice.pdf"), ("photo", "holidays.jpg")] {
+        let id = client
+            .email_import(
+                format!(
+                    concat!(
+                        "Subject: {}\r\n",
+                        "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+                        "\r\n",
+                        "--b\r\n",
+                        "Content-Type: text/plain\r\n",
+                        "\r\n",
+                        "See attached.\r\n",
+                        "--b\r\n",
+                        "Content-Type: application/octet-stream\r\n",
+                        "Content-Disposition: attachment; filename=\"{}\"\r\n",
+                        "Content-Transfer-Encoding: base64\r\n",
+                        "\r\n",
+                        "AAECAwQFBgc=\r\n",
+                        "--b--\r\n"
+                    ),
+                    name, filename
+                )
+                .into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                None,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    for (text, expected_results) in [
+        ("invoice.pdf", vec!["invoice"]),
+        ("holidays", vec!["photo"]),
+        ("spreadsheet.xls", vec![]),
+    ] {
+        assert_eq!(
+            client
+                .email_query(email::query::Filter::text(text).into(), None::<Vec<_>>)
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected_results
+        );
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn size_boundaries(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("Size Boundaries", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Pad each message so that its size is exactly the requested number of octets.
+    let mut ids = AHashMap::new();
+    for (name, size) in [("small", 1000), ("medium", 2000), ("large", 3000)] {
+        let mut message = format!("Subject: {}\r\n\r\n", name).into_bytes();
+        message.resize(size, b'x');
+        let mut email = client
+            .email_import(message, [&mailbox_id], None::<Vec<String>>, None)
+            .await
+            .unwrap();
+        assert_eq!(email.size(), size);
+        ids.insert(email.take_id(), name);
+    }
+
+    for (filter, expected_results) in [
+        (
+            email::query::Filter::min_size(2000).into(),
+            vec!["medium", "large"],
+        ),
+        (email::query::Filter::min_size(2001).into(), vec!["large"]),
+        (email::query::Filter::max_size(2000).into(), vec!["small"]),
+        (
+            email::query::Filter::max_size(2001).into(),
+            vec!["small", "medium"],
+        ),
+        (
+            Filter::and(vec![
+                email::query::Filter::min_size(2000),
+                email::query::Filter::max_size(2001),
+            ]),
+            vec!["medium"],
+        ),
+        (
+            Filter::and(vec![
+                email::query::Filter::min_size(2000),
+                email::query::Filter::max_size(2000),
+            ]),
+            vec![],
+        ),
+        (email::query::Filter::min_size(3001).into(), vec![]),
+        (email::query::Filter::max_size(1000).into(), vec![]),
+    ] {
+        assert_eq!(
+            client
+                .email_query(Some(filter), Some(vec![email::query::Comparator::size()]))
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected_results
+        );
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn system_flag_aliases(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("System Flags", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut ids = AHashMap::new();
+    for (name, keywords) in [
+        ("read", vec!["$seen"]),
+        ("unread", vec![]),
+        ("read_flagged", vec!["$seen", "$flagged"]),
+        ("imap_flag", vec!["\\Seen"]),
+    ] {
+        let id = client
+            .email_import(
+                format!("Subject: {}\r\n\r\ntest", name).into_bytes(),
+                [&mailbox_id],
+                Some(keywords),
+                None,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    // IMAP, bare and JMAP forms of a system flag return the same results,
+    // while stored keywords are never aliased
+    for (filter, expected_results) in [
+        (
+            email::query::Filter::has_keyword("$seen"),
+            vec!["read", "read_flagged"],
+        ),
+        (
+            email::query::Filter::has_keyword("\\Seen"),
+            vec!["read", "read_flagged"],
+        ),
+        (
+            email::query::Filter::has_keyword("Seen"),
+            vec!["read", "read_flagged"],
+        ),
+        (
+            email::query::Filter::not_keyword("$seen"),
+            vec!["imap_flag", "unread"],
+        ),
+        (
+            email::query::Filter::not_keyword("\\Seen"),
+            vec!["imap_flag", "unread"],
+        ),
+        (
+            email::query::Filter::not_keyword("seen"),
+            vec!["imap_flag", "unread"],
+        ),
+        (
+            email::query::Filter::has_keyword("\\Flagged"),
+            vec!["read_flagged"],
+        ),
+    ] {
+        let mut results = client
+            .email_query(
+                Filter::and(vec![email::query::Filter::in_mailbox(&mailbox_id), filter]).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .iter()
+            .map(|id| *ids.get(id).unwrap())
+            .collect::<Vec<_>>();
+        results.sort_unstable();
+        assert_eq!(results, expected_results);
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+pub async fn from_collation(client: &mut Client) {
+    let mailbox_id = client
+        .mailbox_create("From Collation", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Senders are sorted by display name, falling back to the address,
+    // ignoring case and diacritics.
+    let mut ids = AHashMap::new();
+    for (name, from) in [
+        ("zoe", "\"Zo\u{eb} Quinn\" <zoe@example.com>"),
+        ("emile", "\"\u{c9}mile Zola\" <emile@example.com>"),
+        ("bob", "bob@example.com"),
+        ("eve", "\"eve Adams\" <eve@example.com>"),
+        ("angel", "\"\u{c1}ngel Ruiz\" <angel@example.com>"),
+        ("adam", "\"ADAM Smith\" <adam@example.com>"),
+        ("orjan", "\"\u{f6}rjan Berg\" <orjan@example.com>"),
+        ("carol", "Carol <carol@example.com>"),
+    ] {
+        let mut email = client
+            .email_import(
+                format!("From: {}\r\nSubject: collation\r\n\r\nTest", from).into_bytes(),
+                [&mailbox_id],
+                None::<Vec<String>>,
+                None,
+            )
+            .await
+            .unwrap();
+        ids.insert(email.take_id(), name);
+    }
+
+    let mut expected = vec![
+        "adam", "angel", "bob", "carol", "emile", "eve", "orjan", "zoe",
+    ];
+    for comparator in [
+        email::query::Comparator::from(),
+        email::query::Comparator::from().descending(),
+    ] {
+        assert_eq!(
+            client
+                .email_query(
+                    email::query::Filter::in_mailbox(&mailbox_id).into(),
+                    Some(vec![comparator])
+                )
+                .await
+                .unwrap()
+                .take_ids()
+                .iter()
+                .map(|id| *ids.get(id).unwrap())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        expected.reverse();
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
 pub async fn query(client: &mut Client) {
     for (filter, sort, expected_results) in [
         (
@@ -813,3 +1399,101 @@ pub struct EmailQuery {
     pub anchor_offset: i32,
     pub limit: usize,
 }
+
+pub async fn query_cache<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox_id = client
+        .mailbox_create("Query Cache", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut ids = AHashMap::new();
+    for name in ["b", "a"] {
+        ids.insert(import_message(client, &mailbox_id, name).await, name);
+    }
+
+    let account_id = JMAPId::parse(client.default_account_id()).unwrap();
+    let mailbox_key = format!("{:?}", JMAPId::parse(&mailbox_id).unwrap());
+    let query = |position: i32| {
+        let mut request =
+            serde_json::from_value::<QueryRequest<schema::Email>>(serde_json::json!({
+                "accountId": account_id,
+                "filter": {"inMailbox": &mailbox_id},
+                "sort": [{"property": "subject"}],
+                "position": position,
+                "calculateTotal": true
+            }))
+            .unwrap();
+        request.acl = server
+            .store
+            .get_acl_token(account_id.get_document_id())
+            .unwrap()
+            .into();
+        let response = serde_json::to_value(server.store.mail_query(request).unwrap()).unwrap();
+        (
+            response["ids"]
+                .as_array()
+                .unwrap_or_else(|| panic!("{:?}", response))
+                .iter()
+                .map(|id| id.as_str().unwrap().to_string())
+                .collect::<Vec<_>>(),
+            response["total"].as_u64().unwrap(),
+        )
+    };
+    let cache_entries = || {
+        server
+            .store
+            .query_cache
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.query.contains(&mailbox_key))
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>()
+    };
+
+    // Identical queries are answered from the cache, whatever page is requested
+    let (results, total) = query(0);
+    assert_eq!(
+        results.iter().map(|id| ids[id]).collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert_eq!(total, 2);
+    let entries = cache_entries();
+    assert_eq!(entries.len(), 1);
+
+    assert_eq!(query(0), (results.clone(), 2));
+    assert_eq!(query(1), (vec![results[1].clone()], 2));
+    let cached_entries = cache_entries();
+    assert_eq!(cached_entries.len(), 1);
+    assert!(Arc::ptr_eq(&entries[0], &cached_entries[0]));
+
+    // Changes to the collection invalidate the cached results
+    ids.insert(import_message(client, &mailbox_id, "c").await, "c");
+    let (results, total) = query(0);
+    assert_eq!(
+        results.iter().map(|id| ids[id]).collect::<Vec<_>>(),
+        vec!["a", "b", "c"]
+    );
+    assert_eq!(total, 3);
+    assert!(cache_entries()
+        .iter()
+        .any(|entry| !Arc::ptr_eq(entry, &entries[0]) && entry.total == 3));
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+async fn import_message(client: &mut Client, mailbox_id: &str, subject: &str) -> String {
+    client
+        .email_import(
+            format!("Subject: {}\n\ntest", subject).into_bytes(),
+            [mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id()
+}
@@ -21,11 +21,20 @@
  * for more details.
 */
 
-use std::{fs, path::PathBuf};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use actix_web::web;
 
-use jmap::types::jmap::JMAPId;
+use jmap::{
+    request::{get::GetRequest, set::SetRequest},
+    types::{date::JMAPDate, jmap::JMAPId, type_state::TypeState},
+    SUPERUSER_ID,
+};
 use jmap_client::{
     client::Client,
     core::set::{SetError, SetErrorType},
@@ -33,12 +42,26 @@ use jmap_client::{
     mailbox::Role,
     Error, Set,
 };
-use store::Store;
+use jmap_mail::{
+    mail::{
+        get::JMAPGetMail,
+        revisions::{EmailRevisionsRequest, JMAPMailRevisions},
+        schema,
+        set::JMAPSetMail,
+        snooze::JMAPMailSnooze,
+    },
+    mail_parser::Message,
+    mailbox::get::JMAPGetMailbox,
+};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use store::{chrono::Utc, core::collection::Collection, Store};
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
 
 use super::{find_values, replace_blob_ids, replace_boundaries, replace_values};
 
+pub const SETTINGS: &[(&str, &str)] = &[("mail-draft-revisions", "2")];
+
 pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
 where
     T: for<'x> Store<'x> + 'static,
@@ -53,7 +76,22 @@ where
         .take_id();
 
     create(client, &mailbox_id).await;
+    update_immutable(&server, client, &mailbox_id).await;
     update(client, &mailbox_id).await;
+    part_id_round_trip(&server, client, &mailbox_id).await;
+    message_id_generation(&server, &mailbox_id);
+    references_trimming(&server, &mailbox_id);
+    default_disposition(&server, &mailbox_id);
+    require_recipients(&server, &mailbox_id);
+    transfer_encoding(&server, &mailbox_id);
+    header_encoding(&server, &mailbox_id);
+    oversized_attachment(&server, client, &mailbox_id).await;
+    pgp_encryption(&server, client, &mailbox_id).await;
+    draft_revisions(&server, client, &mailbox_id).await;
+    conditional_keywords(&server, client, &mailbox_id).await;
+    forwarded_message(&server, client, &mailbox_id).await;
+    historical_dates(&server, client).await;
+    snooze(&server, client).await;
 
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
@@ -321,6 +359,748 @@ async fn update(client: &mut Client, root_mailbox_id: &str) {
         .unwrap();
 }
 
+async fn update_immutable<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email_id = client
+        .email_query(
+            email::query::Filter::in_mailbox(mailbox_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+
+    let mut keywords = client
+        .email_get(&email_id, [email::Property::Keywords].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .keywords()
+        .into_iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<_>>();
+    keywords.sort_unstable();
+
+    // Immutable properties cannot be updated
+    for (property, value) in [
+        ("threadId", serde_json::json!(JMAPId::new(1234).to_string())),
+        ("size", serde_json::json!(1234)),
+    ] {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "update": {
+                &email_id: {
+                    property: value,
+                    "keywords/$seen": true
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+        assert_eq!(
+            response["notUpdated"][&email_id]["type"].as_str().unwrap(),
+            "invalidProperties",
+            "{:?}",
+            response
+        );
+        assert_eq!(
+            response["notUpdated"][&email_id]["properties"],
+            serde_json::json!([property])
+        );
+    }
+
+    // The message should not have been modified
+    let mut updated_keywords = client
+        .email_get(&email_id, [email::Property::Keywords].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .keywords()
+        .into_iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<_>>();
+    updated_keywords.sort_unstable();
+    assert_eq!(updated_keywords, keywords);
+}
+
+async fn part_id_round_trip<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: bill@example.com\r\n",
+                "Subject: Part ids\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: multipart/mixed; boundary=\"mixed\"\r\n",
+                "\r\n",
+                "--mixed\r\n",
+                "Content-Type: multipart/alternative; boundary=\"alt\"\r\n",
+                "\r\n",
+                "--alt\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "Hello, world.\r\n",
+                "--alt\r\n",
+                "Content-Type: text/html\r\n",
+                "\r\n",
+                "<p>Hello, world.</p>\r\n",
+                "--alt--\r\n",
+                "--mixed\r\n",
+                "Content-Type: application/octet-stream\r\n",
+                "Content-Disposition: attachment; filename=\"data.bin\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "SGVsbG8sIHdvcmxkLg==\r\n",
+                "--mixed--\r\n",
+            )
+            .as_bytes()
+            .to_vec(),
+            [mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+
+    let get_email = |email_id: &str| {
+        let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "ids": [email_id],
+            "properties": ["bodyStructure", "bodyValues"],
+            "bodyProperties": ["partId", "blobId", "type", "disposition", "name"],
+            "fetchAllBodyValues": true
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let mut response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+        response["list"][0].take()
+    };
+
+    fn part_ids(part: &serde_json::Value, ids: &mut Vec<Option<String>>) {
+        ids.push(part["partId"].as_str().map(|id| id.to_string()));
+        for subpart in part["subParts"].as_array().into_iter().flatten() {
+            part_ids(subpart, ids);
+        }
+    }
+
+    // Keep partId for parts with a body value and blobId for everything else
+    fn strip_part(part: &mut serde_json::Value, body_values: &serde_json::Value) {
+        let part = part.as_object_mut().unwrap();
+        for subpart in part
+            .get_mut("subParts")
+            .and_then(|subparts| subparts.as_array_mut())
+            .into_iter()
+            .flatten()
+        {
+            strip_part(subpart, body_values);
+        }
+        if part
+            .get("partId")
+            .and_then(|id| id.as_str())
+            .map_or(false, |part_id| body_values.get(part_id).is_some())
+        {
+            part.remove("blobId");
+        } else {
+            part.remove("partId");
+        }
+    }
+
+    // Part ids are assigned in MIME tree order and match the keys in bodyValues
+    let email = get_email(&email_id);
+    let mut ids = Vec::new();
+    part_ids(&email["bodyStructure"], &mut ids);
+    assert_eq!(
+        ids,
+        [None, None, Some("2"), Some("3"), Some("4")]
+            .into_iter()
+            .map(|id| id.map(|id| id.to_string()))
+            .collect::<Vec<_>>()
+    );
+    let mut body_values = email["bodyValues"].clone();
+    assert_eq!(
+        body_values.as_object().unwrap().keys().collect::<Vec<_>>(),
+        ["2", "3"]
+    );
+
+    // Edit the text part by its partId and create a new message from the result
+    body_values["2"] = serde_json::json!({"value": "This message was edited."});
+    let mut body_structure = email["bodyStructure"].clone();
+    strip_part(&mut body_structure, &body_values);
+
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "a": {
+                "mailboxIds": {mailbox_id: true},
+                "subject": "Part ids (edited)",
+                "bodyStructure": body_structure,
+                "bodyValues": body_values
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    let new_email_id = response["created"]["a"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response));
+
+    // The new message has the same structure and the edited body value
+    let new_email = get_email(new_email_id);
+    let mut new_ids = Vec::new();
+    part_ids(&new_email["bodyStructure"], &mut new_ids);
+    assert_eq!(new_ids, ids);
+    assert_eq!(
+        new_email["bodyValues"]["2"]["value"],
+        "This message was edited."
+    );
+    assert_eq!(
+        new_email["bodyValues"]["3"]["value"],
+        email["bodyValues"]["3"]["value"]
+    );
+
+    // Truncated body values cannot be set
+    body_values["2"]["isTruncated"] = true.into();
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "b": {
+                "mailboxIds": {mailbox_id: true},
+                "bodyStructure": body_structure,
+                "bodyValues": body_values
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    assert_eq!(
+        response["notCreated"]["b"]["properties"],
+        serde_json::json!(["bodyValues"]),
+        "{:?}",
+        response
+    );
+}
+
+fn message_id_generation<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "a": {
+                "mailboxIds": {mailbox_id: true},
+                "from": [{"email": "jane@Example.org"}],
+                "subject": "No Message-ID"
+            },
+            "b": {
+                "mailboxIds": {mailbox_id: true},
+                "from": [{"email": "jane@example.org"}],
+                "messageId": ["my-own-id@example.net"],
+                "subject": "Explicit Message-ID"
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [response["created"]["a"]["id"], response["created"]["b"]["id"]],
+        "properties": ["messageId"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+
+    // Messages without a Message-ID get one using the sender's domain
+    let message_id = response["list"][0]["messageId"][0]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response));
+    assert!(message_id.ends_with("@example.org"), "{}", message_id);
+    assert_eq!(
+        response["list"][1]["messageId"],
+        serde_json::json!(["my-own-id@example.net"])
+    );
+}
+
+fn references_trimming<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let create = |message_id: String, references: Vec<String>| {
+        let mut email = serde_json::json!({
+            "mailboxIds": {mailbox_id: true},
+            "from": [{"email": "jane@example.org"}],
+            "messageId": [message_id],
+            "subject": "Long thread"
+        });
+        if let Some(in_reply_to) = references.last() {
+            email["inReplyTo"] = serde_json::json!([in_reply_to]);
+            email["references"] = serde_json::json!(references);
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {"a": email}
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+        response["created"]["a"]["id"]
+            .as_str()
+            .unwrap_or_else(|| panic!("{:?}", response))
+            .to_string()
+    };
+
+    // Build a thread where the parent of the reply is the most recent message
+    let thread = (0..50)
+        .map(|num| format!("thread-{}@example.org", num))
+        .collect::<Vec<_>>();
+    let root_id = create(thread[0].clone(), Vec::new());
+    let parent_id = create(thread[49].clone(), thread[..49].to_vec());
+    let reply_id = create("reply@example.org".to_string(), thread.clone());
+
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [&root_id, &parent_id, &reply_id],
+        "properties": ["references", "threadId"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+
+    // The References header keeps the thread root and the most recent message ids
+    let mut expected_references = vec![thread[0].clone()];
+    expected_references.extend_from_slice(&thread[31..]);
+    assert_eq!(
+        response["list"][2]["references"],
+        serde_json::json!(expected_references),
+        "{:?}",
+        response
+    );
+
+    // Trimmed messages are still threaded with the rest of the conversation
+    let thread_id = &response["list"][0]["threadId"];
+    assert!(thread_id.is_string(), "{:?}", response);
+    assert_eq!(&response["list"][1]["threadId"], thread_id);
+    assert_eq!(&response["list"][2]["threadId"], thread_id);
+}
+
+fn default_disposition<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "a": {
+                "mailboxIds": {mailbox_id: true},
+                "from": [{"email": "jane@example.org"}],
+                "subject": "Default disposition",
+                "textBody": [{"partId": "text", "type": "text/plain"}],
+                "attachments": [{"partId": "file", "type": "text/csv", "name": "data.csv"}],
+                "bodyValues": {
+                    "text": {"value": "See attached."},
+                    "file": {"value": "a,b,c"}
+                }
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [response["created"]["a"]["id"]],
+        "properties": ["textBody", "attachments"],
+        "bodyProperties": ["type", "disposition", "name", "header:Content-Disposition"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    let email = &response["list"][0];
+
+    // Attachments default to "attachment" and body parts to "inline"
+    assert_eq!(
+        email["attachments"],
+        serde_json::json!([{
+            "type": "text/csv",
+            "disposition": "attachment",
+            "name": "data.csv",
+            "header:Content-Disposition": " attachment; filename=\"data.csv\""
+        }]),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        email["textBody"][0]["disposition"], "inline",
+        "{:?}",
+        response
+    );
+}
+
+fn require_recipients<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for (require_recipients, recipients, expect_created) in [
+        (None, serde_json::json!({}), true),
+        (Some(false), serde_json::json!({}), true),
+        (Some(true), serde_json::json!({}), false),
+        (Some(true), serde_json::json!({"to": []}), false),
+        (
+            Some(true),
+            serde_json::json!({"to": [{"email": "john@example.org"}]}),
+            true,
+        ),
+        (
+            Some(true),
+            serde_json::json!({"bcc": [{"email": "john@example.org"}]}),
+            true,
+        ),
+        (
+            Some(true),
+            serde_json::json!({"header:Cc:asAddresses": [{"email": "john@example.org"}]}),
+            true,
+        ),
+    ] {
+        let mut email = serde_json::json!({
+            "mailboxIds": {mailbox_id: true},
+            "from": [{"email": "jane@example.org"}],
+            "subject": "Recipients"
+        });
+        for (property, value) in recipients.as_object().unwrap() {
+            email[property] = value.clone();
+        }
+        let mut request = serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {"a": email}
+        });
+        if let Some(require_recipients) = require_recipients {
+            request["requireRecipients"] = require_recipients.into();
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(request).unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+        if expect_created {
+            assert!(
+                response["created"]["a"]["id"].is_string(),
+                "{:?} {:?}",
+                recipients,
+                response
+            );
+        } else {
+            assert_eq!(
+                response["notCreated"]["a"]["type"], "invalidProperties",
+                "{:?} {:?}",
+                recipients, response
+            );
+            assert_eq!(
+                response["notCreated"]["a"]["properties"],
+                serde_json::json!(["to", "cc", "bcc"]),
+                "{:?}",
+                response
+            );
+        }
+    }
+}
+
+async fn oversized_attachment<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // The first attachment alone exceeds the 5MB limit set for the tests
+    let blob_id = client
+        .upload(None, vec![b'A'; 6_000_000], None)
+        .await
+        .unwrap()
+        .take_blob_id();
+
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "a": {
+                "mailboxIds": {mailbox_id: true},
+                "from": [{"email": "jane@example.org"}],
+                "subject": "Oversized attachment",
+                "attachments": [
+                    {"blobId": blob_id, "type": "application/octet-stream", "name": "big.bin"},
+                    {"partId": "file", "type": "text/plain", "name": "small.txt"}
+                ],
+                "bodyValues": {
+                    "file": {"value": "small file"}
+                }
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+    assert_eq!(
+        response["notCreated"]["a"]["type"], "invalidProperties",
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        response["notCreated"]["a"]["properties"],
+        serde_json::json!(["attachments"]),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        response["notCreated"]["a"]["description"],
+        "Message exceeds maximum size of 5000000 bytes.",
+        "{:?}",
+        response
+    );
+
+    // Parts within the attachments limit can still exceed the 6MB message limit
+    // once they are encoded
+    let mut attachments = Vec::new();
+    let mut body_values = serde_json::Map::new();
+    for num in 0..10 {
+        let part_id = format!("file{}", num);
+        attachments.push(serde_json::json!({
+            "partId": &part_id,
+            "type": "text/plain",
+            "name": format!("{}.txt", part_id),
+            "header:Content-Transfer-Encoding": "base64"
+        }));
+        body_values.insert(part_id, serde_json::json!({ "value": "A".repeat(480_000) }));
+    }
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {
+            "a": {
+                "mailboxIds": {mailbox_id: true},
+                "from": [{"email": "jane@example.org"}],
+                "subject": "Oversized message",
+                "attachments": attachments,
+                "bodyValues": body_values
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+    assert_eq!(
+        response["notCreated"]["a"]["type"], "tooLarge",
+        "{:?}",
+        response
+    );
+    assert!(
+        response["notCreated"]["a"]["description"]
+            .as_str()
+            .unwrap()
+            .ends_with("exceeds maximum size of 6000000 bytes."),
+        "{:?}",
+        response
+    );
+}
+
+fn transfer_encoding<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for (text, hint, expected_encoding) in [
+        (
+            "Caf\u{e9} cr\u{e8}me, na\u{ef}ve fa\u{e7}ade.",
+            None,
+            Some(" quoted-printable"),
+        ),
+        (
+            "\u{65e5}\u{672c}\u{8a9e}\u{306e}\u{30c6}\u{30ad}\u{30b9}\u{30c8}",
+            None,
+            Some(" base64"),
+        ),
+        ("Plain ASCII text.", None, None),
+        ("Plain ASCII text.", Some("base64"), Some(" base64")),
+        (
+            "Caf\u{e9}",
+            Some("Quoted-Printable"),
+            Some(" quoted-printable"),
+        ),
+    ] {
+        let mut text_part = serde_json::json!({"partId": "text", "type": "text/plain"});
+        if let Some(hint) = hint {
+            text_part["header:Content-Transfer-Encoding"] = hint.into();
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {
+                "a": {
+                    "mailboxIds": {mailbox_id: true},
+                    "from": [{"email": "jane@example.org"}],
+                    "subject": "Transfer encoding",
+                    "textBody": [text_part],
+                    "bodyValues": {"text": {"value": text}}
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+        let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "ids": [response["created"]["a"]["id"]],
+            "properties": ["textBody", "bodyValues"],
+            "bodyProperties": ["partId", "header:Content-Transfer-Encoding"],
+            "fetchTextBodyValues": true
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+        let email = &response["list"][0];
+        let part = &email["textBody"][0];
+
+        assert_eq!(
+            part["header:Content-Transfer-Encoding"].as_str(),
+            expected_encoding,
+            "{:?}",
+            response
+        );
+        assert_eq!(
+            email["bodyValues"][part["partId"].as_str().unwrap()]["value"]
+                .as_str()
+                .map(|value| value.trim_end()),
+            Some(text),
+            "{:?}",
+            response
+        );
+    }
+
+    // Encoding hints are only accepted on text parts
+    for body in [
+        serde_json::json!({
+            "textBody": [{
+                "partId": "text",
+                "type": "text/plain",
+                "header:Content-Transfer-Encoding": "7bit"
+            }],
+            "bodyValues": {"text": {"value": "Hello"}}
+        }),
+        serde_json::json!({
+            "attachments": [{
+                "partId": "file",
+                "type": "application/octet-stream",
+                "header:Content-Transfer-Encoding": "base64"
+            }],
+            "bodyValues": {"file": {"value": "Hello"}}
+        }),
+    ] {
+        let mut email = serde_json::json!({
+            "mailboxIds": {mailbox_id: true},
+            "from": [{"email": "jane@example.org"}],
+            "subject": "Invalid transfer encoding"
+        });
+        for (property, value) in body.as_object().unwrap() {
+            email[property] = value.clone();
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {"a": email}
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+        assert_eq!(
+            response["notCreated"]["a"]["type"], "invalidProperties",
+            "{:?}",
+            response
+        );
+    }
+}
+
+fn header_encoding<T>(server: &JMAPServer<T>, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    for (subject, name, expected_encoding) in [
+        (
+            "Caf\u{e9} cr\u{e8}me, na\u{ef}ve fa\u{e7}ade.",
+            "Ren\u{e9}e Fran\u{e7}ois",
+            "?q?",
+        ),
+        (
+            concat!(
+                "\u{1f389}\u{1f382} \u{65e5}\u{672c}\u{8a9e}\u{306e}\u{4ef6}\u{540d} ",
+                "\u{1f680}\u{1f31f}\u{1f525} \u{65e5}\u{672c}\u{8a9e}\u{306e}\u{4ef6}\u{540d} ",
+                "\u{1f389}\u{1f382}\u{1f680}\u{1f31f}\u{1f525}\u{1f389}\u{1f382}\u{1f680}"
+            ),
+            "\u{5c71}\u{7530} \u{592a}\u{90ce} \u{1f600}",
+            "?b?",
+        ),
+        ("Plain ASCII subject", "Jane Doe", ""),
+    ] {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {
+                "a": {
+                    "mailboxIds": {mailbox_id: true},
+                    "from": [{"name": name, "email": "jane@example.org"}],
+                    "subject": subject
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+        let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "ids": [response["created"]["a"]["id"]],
+            "properties": ["subject", "from", "header:Subject", "header:From"]
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+        let email = &response["list"][0];
+
+        // Decoded values round-trip
+        assert_eq!(email["subject"].as_str(), Some(subject), "{:?}", response);
+        assert_eq!(
+            email["from"][0]["name"].as_str(),
+            Some(name),
+            "{:?}",
+            response
+        );
+
+        // Raw headers only contain ASCII encoded-words folded at 78 characters
+        for (header, raw_value) in [
+            ("Subject:", email["header:Subject"].as_str().unwrap()),
+            ("From:", email["header:From"].as_str().unwrap()),
+        ] {
+            assert!(raw_value.is_ascii(), "{}", raw_value);
+            for (line_num, line) in raw_value.split("\r\n").enumerate() {
+                let line_len = line.len() + if line_num == 0 { header.len() } else { 0 };
+                assert!(line_len <= 78, "{} {}", header, raw_value);
+            }
+            if expected_encoding.is_empty() {
+                assert!(!raw_value.contains("=?"), "{}", raw_value);
+            } else {
+                let raw_value = raw_value.to_lowercase();
+                assert!(raw_value.contains("=?utf-8?"), "{}", raw_value);
+                assert!(raw_value.contains(expected_encoding), "{}", raw_value);
+            }
+        }
+    }
+}
+
 pub async fn assert_email_properties(
     client: &mut Client,
     message_id: &str,
@@ -344,3 +1124,771 @@ pub async fn assert_email_properties(
     assert_eq!(mailbox_ids_, mailbox_ids);
     assert_eq!(keywords_, keywords);
 }
+
+async fn draft_revisions<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut draft_ids = vec![client
+        .email_import(
+            b"Subject: Draft v1\r\n\r\nv1".to_vec(),
+            [mailbox_id],
+            ["$draft"].into(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id()];
+
+    // Replaced drafts have to be destroyed explicitly
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {"a": {
+            "mailboxIds": {mailbox_id: true},
+            "keywords": {"$draft": true},
+            "subject": "Draft v2"
+        }},
+        "replaceDrafts": {"a": &draft_ids[0]}
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    assert_eq!(
+        response["notCreated"]["a"]["type"], "invalidProperties",
+        "{:?}",
+        response
+    );
+    assert!(response["destroyed"].is_null(), "{:?}", response);
+
+    // Replace the draft a few times, each replaced version is destroyed
+    for version in 2..=4 {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {"a": {
+                "mailboxIds": {mailbox_id: true},
+                "keywords": {"$draft": true},
+                "subject": format!("Draft v{}", version),
+                "textBody": [{"partId": "text", "type": "text/plain"}],
+                "bodyValues": {"text": {"value": format!("v{}", version)}}
+            }},
+            "replaceDrafts": {"a": draft_ids.last().unwrap()},
+            "destroy": [draft_ids.last().unwrap()]
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+        assert_eq!(
+            response["destroyed"],
+            serde_json::json!([draft_ids.last().unwrap()]),
+            "{:?}",
+            response
+        );
+        draft_ids.push(
+            response["created"]["a"]["id"]
+                .as_str()
+                .unwrap_or_else(|| panic!("{:?}", response))
+                .to_string(),
+        );
+    }
+
+    // Only the two most recent revisions are kept
+    let draft_id = draft_ids.last().unwrap();
+    let mut request = serde_json::from_value::<EmailRevisionsRequest>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [draft_id, &draft_ids[0]]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_revisions(request).unwrap()).unwrap();
+    assert_eq!(response["notFound"], serde_json::json!([&draft_ids[0]]));
+    let revisions = response["revisions"][draft_id]
+        .as_array()
+        .unwrap_or_else(|| panic!("{:?}", response));
+    assert_eq!(revisions.len(), 2, "{:?}", response);
+
+    // Recover the overwritten versions
+    for (blob_id, subject) in revisions.iter().zip(["Draft v3", "Draft v2"]) {
+        let raw_message =
+            String::from_utf8(client.download(blob_id.as_str().unwrap()).await.unwrap()).unwrap();
+        assert!(
+            raw_message.contains(&format!("Subject: {}", subject)),
+            "{}",
+            raw_message
+        );
+    }
+
+    // Only drafts can be replaced
+    let email_id = client
+        .email_import(
+            b"Subject: Sent\r\n\r\nsent".to_vec(),
+            [mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {"a": {
+            "mailboxIds": {mailbox_id: true},
+            "subject": "Not a draft"
+        }},
+        "replaceDrafts": {"a": &email_id}
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    assert_eq!(
+        response["notCreated"]["a"]["type"], "invalidProperties",
+        "{:?}",
+        response
+    );
+    client.email_destroy(&email_id).await.unwrap();
+    client.email_destroy(draft_id).await.unwrap();
+}
+
+async fn conditional_keywords<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let archive_id = client
+        .mailbox_create("Archive", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let email_id = client
+        .email_import(
+            b"Subject: Unread\r\n\r\nunread".to_vec(),
+            [mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let archive = |id: &str, target_id: &str| {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "update": {id: {
+                "mailboxIds": {target_id: true}
+            }},
+            "ifKeywords": {id: {"$seen": false}}
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        server.store.mail_set(request).unwrap()
+    };
+
+    // Archive only if unread
+    let response = archive(&email_id, &archive_id);
+    assert!(
+        response["updated"]
+            .as_object()
+            .map_or(false, |updated| updated.contains_key(&email_id)),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        client
+            .email_get(&email_id, [email::Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .mailbox_ids(),
+        vec![archive_id.as_str()]
+    );
+
+    // Once read, the precondition fails and the message is left untouched
+    client
+        .email_set_keyword(&email_id, "$seen", true)
+        .await
+        .unwrap();
+    let response = archive(&email_id, mailbox_id);
+    assert_eq!(
+        response["notUpdated"][&email_id]["type"], "conflict",
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        client
+            .email_get(&email_id, [email::Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .mailbox_ids(),
+        vec![archive_id.as_str()]
+    );
+
+    client.email_destroy(&email_id).await.unwrap();
+    client.mailbox_destroy(&archive_id, true).await.unwrap();
+}
+
+async fn forwarded_message<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let email_id = client
+        .email_import(
+            b"From: bill@example.com\r\nSubject: Quarterly figures\r\n\r\nSee the numbers.\r\n"
+                .to_vec(),
+            [mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let forward = |forwarded_id: &str| {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {
+                "f": {
+                    "mailboxIds": {mailbox_id: true},
+                    "from": [{"email": "jdoe@example.com"}],
+                    "to": [{"email": "jane@example.org"}],
+                    "subject": "Fwd: Quarterly figures",
+                    "textBody": [{"partId": "text", "type": "text/plain"}],
+                    "bodyValues": {"text": {"value": "Have a look."}},
+                    "forwardedEmailId": forwarded_id
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap()
+    };
+
+    // Unknown messages cannot be forwarded
+    let response = forward(&JMAPId::new(999_999).to_string());
+    assert_eq!(
+        response["notCreated"]["f"]["type"], "invalidProperties",
+        "{:?}",
+        response
+    );
+
+    // The original message is attached as message/rfc822
+    let response = forward(&email_id);
+    let forward_id = response["created"]["f"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response))
+        .to_string();
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [&forward_id],
+        "properties": ["hasAttachment", "attachments"],
+        "bodyProperties": ["type", "disposition", "blobId"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    let email = &response["list"][0];
+    assert_eq!(email["hasAttachment"], true, "{:?}", response);
+    assert_eq!(
+        email["attachments"].as_array().map(|parts| parts.len()),
+        Some(1),
+        "{:?}",
+        response
+    );
+    assert_eq!(email["attachments"][0]["type"], "message/rfc822");
+    assert_eq!(email["attachments"][0]["disposition"], "attachment");
+
+    let attachment = client
+        .download(email["attachments"][0]["blobId"].as_str().unwrap())
+        .await
+        .unwrap();
+    let attachment = Message::parse(&attachment).unwrap();
+    assert_eq!(attachment.get_subject(), Some("Quarterly figures"));
+    assert_eq!(
+        attachment
+            .get_text_body(0)
+            .map(|text| text.trim_end().to_string()),
+        Some("See the numbers.".to_string())
+    );
+
+    client.email_destroy(&forward_id).await.unwrap();
+    client.email_destroy(&email_id).await.unwrap();
+}
+
+async fn historical_dates<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox_id = client
+        .mailbox_create("Migrated", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let now = Utc::now().timestamp();
+    let far_future = JMAPDate::from_timestamp(now + 10 * 365 * 86400).to_string();
+
+    let mut create = serde_json::Map::new();
+    for (name, sent_at, received_at) in [
+        (
+            "recent",
+            "2004-03-14T09:30:00+01:00".to_string(),
+            "2004-03-14T08:31:00Z".to_string(),
+        ),
+        (
+            "old",
+            "1999-12-31T23:59:00Z".to_string(),
+            "2000-01-01T00:00:00Z".to_string(),
+        ),
+        ("bogus", far_future.clone(), far_future),
+    ] {
+        create.insert(
+            name.to_string(),
+            serde_json::json!({
+                "mailboxIds": {&mailbox_id: true},
+                "subject": name,
+                "sentAt": sent_at,
+                "receivedAt": received_at,
+                "textBody": [{"partId": "1", "type": "text/plain"}],
+                "bodyValues": {"1": {"value": "Migrated message"}}
+            }),
+        );
+    }
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": create
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    let id = |name: &str| {
+        response["created"][name]["id"]
+            .as_str()
+            .unwrap_or_else(|| panic!("{} not created: {:?}", name, response))
+            .to_string()
+    };
+
+    // Past dates are kept as set, future ones are replaced by the current time
+    for (name, expected_sent_at, expected_received_at) in [
+        ("recent", Some(1079253000), Some(1079253060)),
+        ("old", Some(946684740), Some(946684800)),
+        ("bogus", None, None),
+    ] {
+        let email = client
+            .email_get(
+                &id(name),
+                [email::Property::SentAt, email::Property::ReceivedAt].into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        for (value, expected) in [
+            (email.sent_at(), expected_sent_at),
+            (email.received_at(), expected_received_at),
+        ] {
+            let value = value.unwrap();
+            if let Some(expected) = expected {
+                assert_eq!(value, expected, "{}", name);
+            } else {
+                assert!(
+                    (now..=Utc::now().timestamp()).contains(&value),
+                    "{} {}",
+                    name,
+                    value
+                );
+            }
+        }
+    }
+
+    // Migrated messages sort by their original dates
+    for sort in [
+        email::query::Comparator::sent_at(),
+        email::query::Comparator::received_at(),
+    ] {
+        assert_eq!(
+            client
+                .email_query(
+                    email::query::Filter::in_mailbox(&mailbox_id).into(),
+                    vec![sort].into(),
+                )
+                .await
+                .unwrap()
+                .take_ids(),
+            vec![id("old"), id("recent"), id("bogus")]
+        );
+    }
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
+async fn snooze<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let inbox_id = client
+        .mailbox_create("Inbox", None::<String>, Role::Inbox)
+        .await
+        .unwrap()
+        .take_id();
+    let email_id = client
+        .email_import(
+            b"Subject: Snoozed\r\n\r\nsnoozed".to_vec(),
+            [&inbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let set_snoozed = |snoozed: serde_json::Value| {
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "update": {&email_id: {
+                "snoozed": snoozed
+            }}
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap()
+    };
+    let get_snoozed = || {
+        let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "ids": [&email_id],
+            "properties": ["mailboxIds", "snoozed"]
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap()["list"][0].clone()
+    };
+
+    // The snoozed mailbox is not created until a message is snoozed
+    set_snoozed(serde_json::Value::Null);
+    assert!(server
+        .store
+        .mailbox_get_by_role(1, "snoozed")
+        .unwrap()
+        .is_none());
+
+    // Snoozing moves the message from the Inbox to the snoozed mailbox
+    let until = JMAPDate::from_timestamp(Utc::now().timestamp() + 86400).to_string();
+    let mut response = set_snoozed(serde_json::json!({ "until": &until }));
+    assert!(response
+        .state_changes()
+        .unwrap_or_default()
+        .iter()
+        .any(|(type_state, _)| *type_state == TypeState::Mailbox));
+    let response = serde_json::to_value(&response).unwrap();
+    assert!(
+        response["updated"]
+            .as_object()
+            .map_or(false, |updated| updated.contains_key(&email_id)),
+        "{:?}",
+        response
+    );
+    let snoozed_id = JMAPId::from(
+        server
+            .store
+            .mailbox_get_by_role(1, "snoozed")
+            .unwrap()
+            .unwrap(),
+    )
+    .to_string();
+    let email = get_snoozed();
+    assert_eq!(email["snoozed"]["until"], until, "{:?}", email);
+    assert_eq!(
+        email["mailboxIds"],
+        serde_json::json!({ &snoozed_id: true }),
+        "{:?}",
+        email
+    );
+
+    // Accounts with snoozed messages are indexed for the housekeeper
+    let is_indexed = || {
+        server
+            .store
+            .mail_snoozed_accounts()
+            .unwrap()
+            .map_or(false, |account_ids| account_ids.contains(1))
+    };
+    assert!(is_indexed());
+
+    // Messages are not woken up before their time
+    assert!(server.store.mail_wake_snoozed(1).unwrap().is_none());
+    assert!(is_indexed());
+    assert_eq!(
+        get_snoozed()["mailboxIds"],
+        serde_json::json!({ &snoozed_id: true })
+    );
+
+    // Once the time passes the message reappears in the Inbox
+    let until = JMAPDate::from_timestamp(Utc::now().timestamp() - 60).to_string();
+    set_snoozed(serde_json::json!({ "until": &until }));
+    let changes = server.store.mail_wake_snoozed(1).unwrap().unwrap();
+    assert!(changes.collections.contains(Collection::Mail));
+    assert!(changes.collections.contains(Collection::Mailbox));
+    let email = get_snoozed();
+    assert_eq!(email["snoozed"], serde_json::Value::Null, "{:?}", email);
+    assert_eq!(
+        email["mailboxIds"],
+        serde_json::json!({ &inbox_id: true }),
+        "{:?}",
+        email
+    );
+    assert!(!is_indexed());
+    assert!(server.store.mail_wake_snoozed(1).unwrap().is_none());
+
+    // Unsnoozing a message moves it back to the Inbox right away
+    set_snoozed(serde_json::json!({ "until": "2099-01-01T00:00:00Z" }));
+    assert_eq!(
+        get_snoozed()["mailboxIds"],
+        serde_json::json!({ &snoozed_id: true })
+    );
+    set_snoozed(serde_json::Value::Null);
+    let email = get_snoozed();
+    assert_eq!(email["snoozed"], serde_json::Value::Null, "{:?}", email);
+    assert_eq!(
+        email["mailboxIds"],
+        serde_json::json!({ &inbox_id: true }),
+        "{:?}",
+        email
+    );
+
+    // Accounts left without snoozed messages are removed from the index
+    assert!(is_indexed());
+    assert!(server.store.mail_wake_snoozed(1).unwrap().is_none());
+    assert!(!is_indexed());
+
+    client.email_destroy(&email_id).await.unwrap();
+    client.mailbox_destroy(&snoozed_id, true).await.unwrap();
+    client.mailbox_destroy(&inbox_id, true).await.unwrap();
+}
+
+async fn pgp_encryption<T>(server: &JMAPServer<T>, client: &mut Client, mailbox_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut key_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    key_dir.push("src");
+    key_dir.push("tests");
+    key_dir.push("resources");
+    key_dir.push("pgp");
+
+    let email = serde_json::json!({
+        "mailboxIds": {mailbox_id: true},
+        "from": [{"email": "jane@example.org"}],
+        "to": [{"email": "john@example.org"}],
+        "subject": "Top secret",
+        "textBody": [{"partId": "text", "type": "text/plain"}],
+        "bodyValues": {"text": {"value": "The eagle has landed."}}
+    });
+
+    // Encryption requires a public key for every recipient, signing requires encryption
+    for (arguments, recipient, expected_error) in [
+        (
+            serde_json::json!({"encrypt": true}),
+            "bill@example.org",
+            Some("No public key found for recipient bill@example.org."),
+        ),
+        (serde_json::json!({"sign": true}), "john@example.org", None),
+    ] {
+        let mut email = email.clone();
+        email["to"] = serde_json::json!([{ "email": recipient }]);
+        let mut request = serde_json::json!({
+            "accountId": JMAPId::new(1).to_string(),
+            "create": {"a": email}
+        });
+        for (argument, value) in arguments.as_object().unwrap() {
+            request[argument] = value.clone();
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(request).unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+
+        if let Some(expected_error) = expected_error {
+            let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+            assert_eq!(
+                response["notCreated"]["a"]["description"], expected_error,
+                "{:?}",
+                response
+            );
+        } else {
+            assert!(server.store.mail_set(request).is_err());
+        }
+    }
+
+    // Signing keys can only be used by the accounts owning the sender address
+    let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "create": {"a": email},
+        "encrypt": true,
+        "sign": true
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+    assert_eq!(
+        response["notCreated"]["a"]["description"],
+        "Sender jane@example.org does not match any identity.",
+        "{:?}",
+        response
+    );
+
+    // Create a signed and encrypted message from the sender's account
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.org")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jane@example.org", "12345", "Jane Smith")
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let inbox_id = JMAPId::from(
+        server
+            .store
+            .mailbox_get_by_role(document_id, "inbox")
+            .unwrap()
+            .unwrap(),
+    )
+    .to_string();
+    let create_encrypted = |mut email: serde_json::Value| {
+        email["mailboxIds"] = serde_json::json!({ &inbox_id: true });
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": &account_id,
+            "create": {"a": email},
+            "encrypt": true,
+            "sign": true
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(document_id).unwrap().into();
+        serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap()
+    };
+    let email_template = email.clone();
+    let response = create_encrypted(email);
+    let blob_id = response["created"]["a"]["blobId"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response))
+        .to_string();
+
+    // Verify the PGP/MIME structure
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": &account_id,
+        "ids": [response["created"]["a"]["id"]],
+        "properties": ["subject", "bodyStructure"],
+        "bodyProperties": ["type", "header:Content-Type:asRaw", "subParts"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(document_id).unwrap().into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    let email = &response["list"][0];
+    assert_eq!(email["subject"], "Top secret", "{:?}", response);
+    assert_eq!(
+        email["bodyStructure"]["type"], "multipart/encrypted",
+        "{:?}",
+        response
+    );
+    assert!(
+        email["bodyStructure"]["header:Content-Type:asRaw"]
+            .as_str()
+            .unwrap()
+            .contains("protocol=\"application/pgp-encrypted\""),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        email["bodyStructure"]["subParts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|part| part["type"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        ["application/pgp-encrypted", "application/octet-stream"],
+        "{:?}",
+        response
+    );
+
+    // The recipient should be able to decrypt the message and verify its signature
+    let raw_message = client
+        .set_default_account_id(&account_id)
+        .download(&blob_id)
+        .await
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&raw_message).contains("The eagle has landed."));
+    let (entity, status) = gpg_decrypt(&key_dir, &raw_message);
+    assert!(status.contains("Good signature"), "{}", status);
+    assert!(status.contains("\"john <john@example.org>\""), "{}", status);
+    let entity = Message::parse(&entity).unwrap();
+    assert_eq!(entity.get_subject(), None);
+    assert_eq!(
+        entity
+            .get_text_body(0)
+            .map(|text| text.trim_end().to_string()),
+        Some("The eagle has landed.".to_string())
+    );
+
+    // Bcc recipients can decrypt the message without being listed in it
+    let mut email = email_template;
+    email["to"] = serde_json::json!([{"email": "jane@example.org"}]);
+    email["bcc"] = serde_json::json!([{"email": "john@example.org"}]);
+    let response = create_encrypted(email);
+    let blob_id = response["created"]["a"]["blobId"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{:?}", response))
+        .to_string();
+    let (_, status) = gpg_decrypt(&key_dir, &client.download(&blob_id).await.unwrap());
+    assert!(status.contains("anonymous recipient"), "{}", status);
+    assert!(!status.contains("<john@example.org>"), "{}", status);
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    client.set_default_account_id(JMAPId::new(1));
+}
+
+// Decrypts a PGP/MIME message as john@example.org, returning the decrypted MIME entity
+// and the status reported by GnuPG.
+fn gpg_decrypt(key_dir: &Path, raw_message: &[u8]) -> (Vec<u8>, String) {
+    let message = Message::parse(raw_message).unwrap();
+    let mut home_dir = std::env::temp_dir();
+    home_dir.push(format!("jmap-test-gnupg-{}", std::process::id()));
+    fs::create_dir_all(&home_dir).unwrap();
+    let mut encrypted = home_dir.clone();
+    encrypted.push("encrypted.asc");
+    fs::write(&encrypted, message.parts[2].get_contents()).unwrap();
+
+    let gpg = |args: &[&OsStr]| {
+        Command::new("gpg")
+            .arg("--homedir")
+            .arg(&home_dir)
+            .args(["--batch", "--no-tty", "--no-permission-warning"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let import = gpg(&[
+        OsStr::new("--import"),
+        key_dir.join("john@example.org.key").as_os_str(),
+        key_dir.join("jane@example.org.asc").as_os_str(),
+    ]);
+    assert!(import.status.success(), "{:?}", import);
+    let output = gpg(&[
+        OsStr::new("--trust-model"),
+        OsStr::new("always"),
+        OsStr::new("--decrypt"),
+        encrypted.as_os_str(),
+    ]);
+
+    Command::new("gpgconf")
+        .arg("--homedir")
+        .arg(&home_dir)
+        .args(["--kill", "gpg-agent"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&home_dir).unwrap();
+
+    let status = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", status);
+    (output.stdout, status)
+}
@@ -21,30 +21,53 @@
  * for more details.
 */
 
-use std::{sync::Arc, time::Duration};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use actix_web::web;
-use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap::{
+    request::{get::GetRequest, set::SetRequest},
+    types::{date::JMAPDate, jmap::JMAPId},
+    SUPERUSER_ID,
+};
 use jmap_client::{
     client::Client,
     core::set::{SetError, SetErrorType, SetObject},
-    email_submission::{Address, Delivered, DeliveryStatus, Displayed, UndoStatus},
+    email,
+    email_submission::{self, Address, Delivered, DeliveryStatus, Displayed, UndoStatus},
     mailbox::Role,
     Error,
 };
-use jmap_sharing::principal::set::JMAPSetPrincipal;
-use store::{ahash::AHashMap, chrono::DateTime, parking_lot::Mutex, Store};
+use jmap_mail::{
+    email_submission::{
+        get::JMAPGetEmailSubmission, schema::EmailSubmission, set::JMAPSetEmailSubmission,
+    },
+    mail::{schema, set::JMAPSetMail},
+};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use store::{
+    ahash::AHashMap,
+    chrono::{DateTime, Utc},
+    parking_lot::Mutex,
+    Store,
+};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
     net::TcpListener,
     sync::mpsc,
 };
+use tokio_rustls::TlsAcceptor;
 
 use crate::{
+    cluster::rpc::tls::load_tls_server_config,
     tests::{jmap_mail::email_set::assert_email_properties, store::utils::StoreCompareWith},
     JMAPServer,
 };
 
+pub const SETTINGS: &[(&str, &str)] = &[
+    ("submission-max-messages", "10"),
+    ("submission-max-recipients", "5"),
+];
+
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct MockMessage {
     pub mail_from: String,
@@ -71,6 +94,7 @@ pub struct MockSMTPSettings {
     pub fail_mail_from: bool,
     pub fail_rcpt_to: bool,
     pub fail_message: bool,
+    pub offer_starttls: bool,
     pub do_stop: bool,
 }
 
@@ -141,6 +165,61 @@ where
         .unwrap()
         .take_id();
 
+    // From addresses not matching any identity are rejected when validation is requested
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    for (from, expect_created) in [
+        (
+            serde_json::json!({"from": [{"email": "bill@example.com"}]}),
+            false,
+        ),
+        (
+            serde_json::json!({"header:From": " John Doe <JDoe@Example.com>"}),
+            true,
+        ),
+        (
+            serde_json::json!({"header:From:asAddresses": [
+                {"email": "jdoe@example.com"},
+                {"email": "jane@example.com"}
+            ]}),
+            false,
+        ),
+    ] {
+        let mut email = serde_json::json!({
+            "mailboxIds": {&mailbox_id: true},
+            "subject": "Spoofed?"
+        });
+        for (property, value) in from.as_object().unwrap() {
+            email[property] = value.clone();
+        }
+        let mut request = serde_json::from_value::<SetRequest<schema::Email>>(serde_json::json!({
+            "accountId": &account_id,
+            "create": {"a": email},
+            "validateFrom": true
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(document_id).unwrap().into();
+        let response = serde_json::to_value(&server.store.mail_set(request).unwrap()).unwrap();
+
+        if expect_created {
+            let email_id = response["created"]["a"]["id"]
+                .as_str()
+                .unwrap_or_else(|| panic!("{:?} {:?}", from, response));
+            client.email_destroy(email_id).await.unwrap();
+        } else {
+            assert_eq!(
+                response["notCreated"]["a"]["type"], "invalidProperties",
+                "{:?} {:?}",
+                from, response
+            );
+            assert_eq!(
+                response["notCreated"]["a"]["properties"],
+                serde_json::json!(["from"]),
+                "{:?}",
+                response
+            );
+        }
+    }
+
     // Import an email without any recipients
     let email_id = client
         .email_import(
@@ -231,6 +310,51 @@ where
     )
     .await;
 
+    // Headers leaking private information are stripped from outbound messages
+    let email_id_2 = client
+        .email_import(
+            concat!(
+                "Received: from [10.0.0.5] (unknown [10.0.0.5])\r\n",
+                "\tby mail.example.com; Mon, 1 Aug 2022 10:00:00 +0000\r\n",
+                "From: jdoe@example.com\r\n",
+                "X-Originating-IP: [10.0.0.5]\r\n",
+                "To: jane_smith@example.com\r\n",
+                "User-Agent: Internal Mailer 1.0\r\n",
+                "Subject: private\r\n",
+                "\r\n",
+                "User-Agent: kept in the body\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    client
+        .email_submission_create(&email_id_2, &identity_id)
+        .await
+        .unwrap();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane_smith@example.com>"],
+            concat!(
+                "From: jdoe@example.com\r\n",
+                "To: jane_smith@example.com\r\n",
+                "Subject: private\r\n",
+                "\r\n",
+                "User-Agent: kept in the body\r\n"
+            ),
+        ),
+        false,
+    )
+    .await;
+    client.email_destroy(&email_id_2).await.unwrap();
+
     // Manually add recipients to the envelope and confirm submission
     let email_submission_id = client
         .email_submission_create_envelope(
@@ -342,6 +466,51 @@ where
             ),
         ])
     );
+
+    // The status of each recipient is returned by EmailSubmission/get
+    let email_submission_id = client
+        .email_submission_create_envelope(
+            &email_id,
+            &identity_id,
+            "jdoe@example.com",
+            ["tim@foobar.com", "jane@test.com"],
+        )
+        .await
+        .unwrap()
+        .take_id();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new("<jdoe@example.com>", ["<tim@foobar.com>"], email_body),
+        false,
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let mut request = serde_json::from_value::<GetRequest<EmailSubmission>>(serde_json::json!({
+        "accountId": &account_id,
+        "ids": [&email_submission_id],
+        "properties": ["deliveryStatus"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(document_id).unwrap().into();
+    let response =
+        serde_json::to_value(&server.store.email_submission_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["deliveryStatus"],
+        serde_json::json!({
+            "tim@foobar.com": {
+                "smtpReply": "250 OK",
+                "delivered": "queued",
+                "displayed": "unknown"
+            },
+            "jane@test.com": {
+                "smtpReply": "550 I refuse to accept that recipient.",
+                "delivered": "no",
+                "displayed": "unknown"
+            }
+        }),
+        "{:?}",
+        response
+    );
     smtp_settings.lock().fail_rcpt_to = false;
 
     // SMTP rejects the message
@@ -400,6 +569,91 @@ where
     );
     smtp_settings.lock().fail_message = false;
 
+    // Domains that enforce TLS are not delivered to in cleartext
+    let email_submission_id = client
+        .email_submission_create_envelope(
+            &email_id,
+            &identity_id,
+            "jdoe@example.com",
+            ["tim@foobar.com", "jane@tls-required.org"],
+        )
+        .await
+        .unwrap()
+        .take_id();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new("<jdoe@example.com>", ["<tim@foobar.com>"], email_body),
+        false,
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let email_submission = client
+        .email_submission_get(&email_submission_id, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email_submission.undo_status().unwrap(), &UndoStatus::Final);
+    assert_eq!(
+        email_submission.delivery_status().unwrap(),
+        &AHashMap::from_iter([
+            (
+                "jane@tls-required.org".to_string(),
+                DeliveryStatus::new(
+                    "TLS is required for this recipient but could not be negotiated.",
+                    Delivered::No,
+                    Displayed::Unknown
+                )
+            ),
+            (
+                "tim@foobar.com".to_string(),
+                DeliveryStatus::new("250 OK", Delivered::Queued, Displayed::Unknown)
+            ),
+        ])
+    );
+
+    // Once the relay offers STARTTLS all recipients are delivered
+    smtp_settings.lock().offer_starttls = true;
+    let email_submission_id = client
+        .email_submission_create_envelope(
+            &email_id,
+            &identity_id,
+            "jdoe@example.com",
+            ["tim@foobar.com", "jane@tls-required.org"],
+        )
+        .await
+        .unwrap()
+        .take_id();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane@tls-required.org>", "<tim@foobar.com>"],
+            email_body,
+        ),
+        false,
+    )
+    .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let email_submission = client
+        .email_submission_get(&email_submission_id, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        email_submission.delivery_status().unwrap(),
+        &AHashMap::from_iter([
+            (
+                "jane@tls-required.org".to_string(),
+                DeliveryStatus::new("250 OK", Delivered::Queued, Displayed::Unknown)
+            ),
+            (
+                "tim@foobar.com".to_string(),
+                DeliveryStatus::new("250 OK", Delivered::Queued, Displayed::Unknown)
+            ),
+        ])
+    );
+    smtp_settings.lock().offer_starttls = false;
+
     // Enable DKIM for the domain
     client
         .set_default_account_id(JMAPId::from(SUPERUSER_ID))
@@ -442,6 +696,172 @@ where
             .timestamp()
     );
 
+    // Holds past the maximum delay are shortened, which is 100 years in the test settings
+    let max_send_at = Utc::now().timestamp() + 100 * 365 * 86400;
+    let email_submission_id = client
+        .email_submission_create_envelope(
+            &email_id,
+            &identity_id,
+            Address::new("jdoe@example.com").parameter("HOLDUNTIL", Some("2999-01-01T00:00:00Z")),
+            ["jane_smith@example.com"],
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let send_at = client
+        .email_submission_get(&email_submission_id, None)
+        .await
+        .unwrap()
+        .unwrap()
+        .send_at()
+        .unwrap();
+    assert!(
+        (max_send_at..max_send_at + 5).contains(&send_at),
+        "{} != {}",
+        send_at,
+        max_send_at
+    );
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            format!(
+                "<jdoe@example.com> HOLDUNTIL={}",
+                JMAPDate::from_timestamp(send_at)
+            ),
+            ["<jane_smith@example.com>".to_string()],
+            email_body.to_string(),
+        ),
+        true,
+    )
+    .await;
+
+    // Holds in the past are sent immediately and negative holds are rejected
+    client
+        .email_submission_create_envelope(
+            &email_id,
+            &identity_id,
+            Address::new("jdoe@example.com").parameter("HOLDUNTIL", Some("2000-01-01T00:00:00Z")),
+            ["jane_smith@example.com"],
+        )
+        .await
+        .unwrap();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane_smith@example.com>"],
+            email_body,
+        ),
+        true,
+    )
+    .await;
+    assert!(matches!(
+        client
+            .email_submission_create_envelope(
+                &email_id,
+                &identity_id,
+                Address::new("jdoe@example.com").parameter("HOLDFOR", Some("-3600")),
+                ["jane_smith@example.com"],
+            )
+            .await,
+        Err(Error::Set(SetError {
+            type_: SetErrorType::InvalidProperties,
+            ..
+        }))
+    ));
+    expect_nothing(&mut smtp_rx).await;
+
+    // Non-admin accounts are rate limited
+    let account_document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let submit = |rcpt_to: &[&str], is_admin: bool| {
+        let rcpt_to = rcpt_to
+            .iter()
+            .map(|email| serde_json::json!({"email": email, "parameters": null}))
+            .collect::<Vec<_>>();
+        let mut request =
+            serde_json::from_value::<SetRequest<EmailSubmission>>(serde_json::json!({
+                "accountId": &account_id,
+                "create": {
+                    "a": {
+                        "emailId": &email_id,
+                        "identityId": &identity_id,
+                        "envelope": {
+                            "mailFrom": {"email": "jdoe@example.com", "parameters": null},
+                            "rcptTo": rcpt_to
+                        }
+                    }
+                }
+            }))
+            .unwrap();
+        request.acl = server
+            .store
+            .get_acl_token(if is_admin {
+                SUPERUSER_ID
+            } else {
+                account_document_id
+            })
+            .unwrap()
+            .into();
+        let response =
+            serde_json::to_value(&server.store.email_submission_set(request).unwrap()).unwrap();
+        response["notCreated"]["a"]["type"]
+            .as_str()
+            .map(|error| error.to_string())
+    };
+    assert_eq!(
+        submit(&["a@foobar.com", "b@foobar.com", "c@foobar.com"], false),
+        None
+    );
+    assert_eq!(
+        submit(&["d@foobar.com", "e@foobar.com", "f@foobar.com"], false),
+        Some("rateLimit".to_string())
+    );
+    assert_eq!(submit(&["d@foobar.com", "e@foobar.com"], false), None);
+    assert_eq!(
+        submit(&["f@foobar.com"], false),
+        Some("rateLimit".to_string())
+    );
+    assert_eq!(submit(&["f@foobar.com"], true), None);
+
+    // Query submissions by the thread of the submitted email
+    let thread_id = client
+        .email_get(&email_id, [email::Property::ThreadId].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .thread_id()
+        .unwrap()
+        .to_string();
+    let mut submission_ids = client
+        .email_submission_query(
+            email_submission::query::Filter::email_ids([&email_id]).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert!(!submission_ids.is_empty());
+    let mut thread_submission_ids = client
+        .email_submission_query(
+            email_submission::query::Filter::thread_ids([&thread_id]).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    submission_ids.sort_unstable();
+    thread_submission_ids.sort_unstable();
+    assert_eq!(thread_submission_ids, submission_ids);
+    assert!(client
+        .email_submission_query(
+            email_submission::query::Filter::thread_ids([JMAPId::new(123456).to_string()]).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+
     // Verify onSuccessUpdateEmail action
     let mut request = client.build();
     let set_request = request.set_email_submission();
@@ -497,6 +917,17 @@ pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockS
     let _settings = Arc::new(Mutex::new(MockSMTPSettings::default()));
     let settings = _settings.clone();
 
+    // Build TLS acceptor for STARTTLS
+    let mut pem_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    pem_dir.push("src");
+    pem_dir.push("tests");
+    pem_dir.push("resources");
+    pem_dir.push("cert.pem");
+    let cert = pem_dir.to_str().unwrap().to_string();
+    pem_dir.set_file_name("key.pem");
+    let key = pem_dir.to_str().unwrap().to_string();
+    let tls_acceptor = TlsAcceptor::from(Arc::new(load_tls_server_config(&cert, &key)));
+
     // Start mock SMTP server
     tokio::spawn(async move {
         let listener = TcpListener::bind("127.0.0.1:9999")
@@ -505,89 +936,16 @@ pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockS
                 panic!("Failed to bind mock SMTP server to 127.0.0.1:9999: {}", e);
             });
 
-        while let Ok((mut stream, _)) = listener.accept().await {
-            let (rx, mut tx) = stream.split();
-            let mut rx = BufReader::new(rx);
-            let mut buf = String::with_capacity(128);
-            let mut message = MockMessage::default();
-
-            tx.write_all(b"220 [127.0.0.1] Clueless host service ready\r\n")
-                .await
-                .unwrap();
-
-            while rx.read_line(&mut buf).await.is_ok() {
-                print!("-> {}", buf);
-                if buf.starts_with("EHLO") {
-                    tx.write_all(b"250 Hi there, but I have no extensions to offer :-(\r\n")
-                        .await
-                        .unwrap();
-                } else if buf.starts_with("MAIL FROM") {
-                    if settings.lock().fail_mail_from {
-                        tx.write_all("552-I do not\r\n552 like that MAIL FROM.\r\n".as_bytes())
-                            .await
-                            .unwrap();
-                    } else {
-                        message.mail_from = buf.split_once(':').unwrap().1.trim().to_string();
-                        tx.write_all(b"250 OK\r\n").await.unwrap();
-                    }
-                } else if buf.starts_with("RCPT TO") {
-                    if settings.lock().fail_rcpt_to && !buf.contains("foobar.com") {
-                        tx.write_all(
-                            "550-I refuse to\r\n550 accept that recipient.\r\n".as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    } else {
-                        message
-                            .rcpt_to
-                            .push(buf.split(':').nth(1).unwrap().trim().to_string());
-                        tx.write_all(b"250 OK\r\n").await.unwrap();
+        while let Ok((stream, _)) = listener.accept().await {
+            if let Some(stream) = mock_smtp_session(stream, &settings, &event_tx, false).await {
+                match tls_acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        mock_smtp_session(stream, &settings, &event_tx, true).await;
                     }
-                } else if buf.starts_with("DATA") {
-                    if settings.lock().fail_message {
-                        tx.write_all(
-                            "503-Thank you but I am\r\n503 saving myself for dessert.\r\n"
-                                .as_bytes(),
-                        )
-                        .await
-                        .unwrap();
-                    } else if !message.mail_from.is_empty() && !message.rcpt_to.is_empty() {
-                        tx.write_all(b"354 Start feeding me now some quality content please\r\n")
-                            .await
-                            .unwrap();
-                        buf.clear();
-                        while rx.read_line(&mut buf).await.is_ok() {
-                            if buf.starts_with('.') {
-                                message.message = message.message.trim().to_string();
-                                break;
-                            } else {
-                                message.message += &buf;
-                                buf.clear();
-                            }
-                        }
-                        tx.write_all(b"250 Great success!\r\n").await.unwrap();
-                        message.rcpt_to.sort_unstable();
-                        event_tx.send(message).await.unwrap();
-                        message = MockMessage::default();
-                    } else {
-                        tx.write_all("554 You forgot to tell me a few things.\r\n".as_bytes())
-                            .await
-                            .unwrap();
+                    Err(err) => {
+                        println!("TLS handshake failed: {}", err);
                     }
-                } else if buf.starts_with("QUIT") {
-                    tx.write_all("250 Arrivederci!\r\n".as_bytes())
-                        .await
-                        .unwrap();
-                    break;
-                } else if buf.starts_with("RSET") {
-                    tx.write_all("250 Your wish is my command.\r\n".as_bytes())
-                        .await
-                        .unwrap();
-                    message = MockMessage::default();
-                } else {
-                    println!("Unknown command: {}", buf.trim());
                 }
-                buf.clear();
             }
 
             if settings.lock().do_stop {
@@ -600,6 +958,130 @@ pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockS
     (event_rx, _settings)
 }
 
+// Returns the stream when the client requested STARTTLS
+async fn mock_smtp_session<S>(
+    stream: S,
+    settings: &Arc<Mutex<MockSMTPSettings>>,
+    event_tx: &mpsc::Sender<MockMessage>,
+    is_tls: bool,
+) -> Option<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut buf = String::with_capacity(128);
+    let mut message = MockMessage::default();
+
+    if !is_tls {
+        stream
+            .write_all(b"220 [127.0.0.1] Clueless host service ready\r\n")
+            .await
+            .unwrap();
+    }
+
+    while matches!(stream.read_line(&mut buf).await, Ok(bytes_read) if bytes_read > 0) {
+        print!("-> {}", buf);
+        if buf.starts_with("EHLO") {
+            if settings.lock().offer_starttls && !is_tls {
+                stream
+                    .write_all(b"250-Hi there, I can offer you\r\n250 STARTTLS\r\n")
+                    .await
+                    .unwrap();
+            } else {
+                stream
+                    .write_all(b"250 Hi there, but I have no extensions to offer :-(\r\n")
+                    .await
+                    .unwrap();
+            }
+        } else if buf.starts_with("STARTTLS") {
+            if settings.lock().offer_starttls && !is_tls {
+                stream
+                    .write_all(b"220 Ready to start TLS\r\n")
+                    .await
+                    .unwrap();
+                return Some(stream.into_inner());
+            } else {
+                stream
+                    .write_all(b"502 I do not speak that language\r\n")
+                    .await
+                    .unwrap();
+            }
+        } else if buf.starts_with("MAIL FROM") {
+            if settings.lock().fail_mail_from {
+                stream
+                    .write_all("552-I do not\r\n552 like that MAIL FROM.\r\n".as_bytes())
+                    .await
+                    .unwrap();
+            } else {
+                message.mail_from = buf.split_once(':').unwrap().1.trim().to_string();
+                stream.write_all(b"250 OK\r\n").await.unwrap();
+            }
+        } else if buf.starts_with("RCPT TO") {
+            if settings.lock().fail_rcpt_to && !buf.contains("foobar.com") {
+                stream
+                    .write_all("550-I refuse to\r\n550 accept that recipient.\r\n".as_bytes())
+                    .await
+                    .unwrap();
+            } else {
+                message
+                    .rcpt_to
+                    .push(buf.split(':').nth(1).unwrap().trim().to_string());
+                stream.write_all(b"250 OK\r\n").await.unwrap();
+            }
+        } else if buf.starts_with("DATA") {
+            if settings.lock().fail_message {
+                stream
+                    .write_all(
+                        "503-Thank you but I am\r\n503 saving myself for dessert.\r\n".as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            } else if !message.mail_from.is_empty() && !message.rcpt_to.is_empty() {
+                stream
+                    .write_all(b"354 Start feeding me now some quality content please\r\n")
+                    .await
+                    .unwrap();
+                buf.clear();
+                while stream.read_line(&mut buf).await.is_ok() {
+                    if buf.starts_with('.') {
+                        message.message = message.message.trim().to_string();
+                        break;
+                    } else {
+                        message.message += &buf;
+                        buf.clear();
+                    }
+                }
+                stream.write_all(b"250 Great success!\r\n").await.unwrap();
+                message.rcpt_to.sort_unstable();
+                event_tx.send(message).await.unwrap();
+                message = MockMessage::default();
+            } else {
+                stream
+                    .write_all("554 You forgot to tell me a few things.\r\n".as_bytes())
+                    .await
+                    .unwrap();
+            }
+        } else if buf.starts_with("QUIT") {
+            stream
+                .write_all("250 Arrivederci!\r\n".as_bytes())
+                .await
+                .unwrap();
+            break;
+        } else if buf.starts_with("RSET") {
+            stream
+                .write_all("250 Your wish is my command.\r\n".as_bytes())
+                .await
+                .unwrap();
+            message = MockMessage::default();
+        } else {
+            println!("Unknown command: {}", buf.trim());
+        }
+        buf.clear();
+    }
+
+    None
+}
+
 pub async fn assert_message_delivery(
     event_rx: &mut mpsc::Receiver<MockMessage>,
     expected_message: MockMessage,
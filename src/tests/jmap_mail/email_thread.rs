@@ -23,9 +23,14 @@
 
 use actix_web::web;
 
-use jmap::types::jmap::JMAPId;
+use jmap::{
+    jmap_store::changes::JMAPChanges,
+    request::changes::{ChangesRequest, ChangesResponse},
+    types::{jmap::JMAPId, state::JMAPState},
+};
 use jmap_client::{client::Client, mailbox::Role};
-use store::Store;
+use jmap_mail::thread::{changes::JMAPThreadChanges, schema::Thread};
+use store::{core::collection::Collection, Store};
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
 
@@ -69,7 +74,62 @@ where
         expected_result
     );
 
+    // Adding a message to an existing thread reports the thread as updated
+    let state = server.store.get_state(1, Collection::Thread).unwrap();
+    let email_id = client
+        .email_import(
+            b"Subject: test\nReferences: <1234>\n\n6".to_vec(),
+            [&mailbox_id],
+            None::<Vec<String>>,
+            Some(10006i64),
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let changes = thread_changes(&server, state.clone());
+    assert_eq!(changes.created, vec![]);
+    assert_eq!(changes.updated, vec![JMAPId::parse(&thread_id).unwrap()]);
+    assert_eq!(changes.destroyed, vec![]);
+
+    // Removing it reports the thread as updated again
+    client.email_destroy(&email_id).await.unwrap();
+    let changes = thread_changes(&server, state);
+    assert_eq!(changes.created, vec![]);
+    assert_eq!(changes.updated, vec![JMAPId::parse(&thread_id).unwrap()]);
+    assert_eq!(changes.destroyed, vec![]);
+
+    // Once all its messages are deleted, the thread no longer exists
+    let state = server.store.get_state(1, Collection::Thread).unwrap();
+    let mut request = client.build();
+    request.set_email().destroy(&expected_result);
+    request.send_set_email().await.unwrap();
+    let changes = thread_changes(&server, state);
+    assert_eq!(changes.updated, vec![]);
+    assert_eq!(changes.destroyed, vec![JMAPId::parse(&thread_id).unwrap()]);
+
+    let unknown_id = JMAPId::new(u32::MAX as u64).to_string();
+    let mut request = client.build();
+    request.get_thread().ids([&thread_id, &unknown_id]);
+    let response = request.send_get_thread().await.unwrap();
+    assert!(response.list().is_empty());
+    assert_eq!(response.not_found().len(), 2);
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
 }
+
+fn thread_changes<T>(server: &JMAPServer<T>, since_state: JMAPState) -> ChangesResponse<Thread>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    server
+        .store
+        .thread_changes(ChangesRequest {
+            acl: None,
+            account_id: JMAPId::new(1),
+            since_state,
+            max_changes: None,
+        })
+        .unwrap()
+}
@@ -21,12 +21,16 @@
  * for more details.
 */
 
+use std::sync::Arc;
+
 use actix_web::web;
 
-use jmap::types::jmap::JMAPId;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
 use jmap_client::{client::Client, email, mailbox::Role};
+use jmap_mail::mail::import::{EmailImportRequest, JMAPMailImport};
 use store::{
     ahash::{AHashMap, AHashSet},
+    core::acl::ACLToken,
     Store, ThreadId,
 };
 
@@ -212,9 +216,94 @@ where
         }
     }
 
+    merged_thread_info(&server, client).await;
+
     server.store.assert_is_empty();
 }
 
+async fn merged_thread_info<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = JMAPId::new(50).to_string();
+    let mailbox_id = client
+        .set_default_account_id(&account_id)
+        .mailbox_create("Thread merge", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Create two unrelated threads
+    let mut thread_ids = Vec::new();
+    for message_id in ["a", "b"] {
+        thread_ids.push(
+            client
+                .email_import(
+                    format!("Message-ID: <{}>\nSubject: merge\n\nmsg\n", message_id).into_bytes(),
+                    [&mailbox_id],
+                    None::<Vec<String>>,
+                    None,
+                )
+                .await
+                .unwrap()
+                .thread_id()
+                .unwrap()
+                .to_string(),
+        );
+    }
+    assert_ne!(thread_ids[0], thread_ids[1]);
+
+    // Importing a reply to both messages merges the threads
+    let blob_id = client
+        .upload(
+            Some(account_id.as_str()),
+            b"Message-ID: <c>\nReferences: <a> <b>\nSubject: re: merge\n\nreply\n".to_vec(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_blob_id();
+    let mut request = serde_json::from_value::<EmailImportRequest>(serde_json::json!({
+        "accountId": &account_id,
+        "emails": {
+            "c": {
+                "blobId": blob_id,
+                "mailboxIds": {
+                    &mailbox_id: true
+                }
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![SUPERUSER_ID, 50],
+        access_to: vec![],
+    })
+    .into();
+    let response = serde_json::to_value(server.store.mail_import(request).unwrap()).unwrap();
+    let thread_id = response["created"]["c"]["threadId"].as_str().unwrap();
+    let merged_thread_id = response["mergedThreadIds"]["c"][0].as_str().unwrap();
+    assert!(thread_ids.contains(&thread_id.to_string()));
+    assert!(thread_ids.contains(&merged_thread_id.to_string()));
+    assert_ne!(thread_id, merged_thread_id);
+    assert_eq!(
+        response["mergedThreadIds"]["c"].as_array().unwrap().len(),
+        1
+    );
+    assert_eq!(
+        client
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .email_ids()
+            .len(),
+        3
+    );
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+}
+
 fn build_message(message: usize, in_reply_to: Option<usize>, thread_num: usize) -> String {
     if let Some(in_reply_to) = in_reply_to {
         format!(
@@ -21,22 +21,57 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use actix_web::web;
-use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use jmap::{request::get::GetRequest, types::jmap::JMAPId, SUPERUSER_ID};
 use jmap_client::{
     client::Client,
     core::set::{SetError, SetErrorType},
+    email, mailbox,
+};
+use jmap_mail::{
+    mail::{get::JMAPGetMail, schema},
+    mail_parser::Message,
 };
-use jmap_sharing::principal::set::JMAPSetPrincipal;
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
 use store::{core::collection::Collection, Store};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
-    net::TcpStream,
+    net::{TcpSocket, TcpStream},
 };
 
-use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+use crate::{
+    tests::{
+        jmap::init_jmap_tests_opts,
+        store::utils::{destroy_temp_dir, StoreCompareWith},
+    },
+    JMAPServer,
+};
+
+pub const SETTINGS: &[(&str, &str)] = &[
+    ("lmtp-plus-addressing", "true"),
+    ("lmtp-plus-addressing-fileinto", "true"),
+    (
+        "lmtp-catch-all",
+        "example.org:bill@example.com;example.edu:bill@example.com",
+    ),
+    ("lmtp-large-message-size", "1000000"),
+    ("lmtp-large-message-folder", "Large Messages"),
+    ("lmtp-large-message-keyword", "$large"),
+    ("lmtp-virus-scan-command", VIRUS_SCAN_COMMAND),
+    ("lmtp-virus-action", "strip"),
+];
+
+const VIRUS_SCAN_COMMAND: &str = concat!(
+    "sh ",
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/tests/resources/lmtp/virus_scan.sh"
+);
 
 pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
 where
@@ -128,8 +163,18 @@ where
         .unwrap()
         .take_id();
 
-    // Delivering to individuals
+    // The configured greeting and HELP texts are used
     let mut lmtp = SmtpConnection::connect().await;
+    lmtp.greeting
+        .clone()
+        .assert_contains("Test LMTP ready")
+        .assert_count("{hostname}", 0);
+    lmtp.help()
+        .await
+        .assert_contains("Ask the ")
+        .assert_count("{hostname}", 0);
+
+    // Delivering to individuals
     lmtp.ingest(
         "bill@example.com",
         &["jdoe@example.com"],
@@ -300,12 +345,556 @@ where
         );
     }
 
+    // Duplicate RCPT TO commands are acknowledged but only delivered once
+    lmtp.mail_from("bill@example.com", 2).await;
+    lmtp.rcpt_to("jane@example.com", 2)
+        .await
+        .assert_contains("Recipient <jane@example.com> accepted");
+    lmtp.rcpt_to("jane@example.com", 2)
+        .await
+        .assert_contains("Duplicate recipient <jane@example.com> ignored");
+    lmtp.data(3).await;
+    lmtp.data_bytes(
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jane@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "Did you get the memo?"
+        ),
+        2,
+        2,
+    )
+    .await
+    .assert_count("<jane@example.com> delivered", 2);
+    assert_eq!(
+        server
+            .store
+            .get_document_ids(
+                JMAPId::parse(&account_id_2).unwrap().get_document_id(),
+                Collection::Mail
+            )
+            .unwrap()
+            .unwrap()
+            .len(),
+        4
+    );
+
     // Size checks
     lmtp.send("MAIL FROM:<hello@world> SIZE=943718400").await;
     lmtp.read(1, 5).await;
     lmtp.send("BDAT 943718400").await;
     lmtp.read(1, 5).await;
 
+    // Real client address is obtained from the PROXY header
+    let mut lmtp =
+        SmtpConnection::connect_proxied("127.0.0.2", "PROXY TCP4 192.0.2.1 127.0.0.1 56324 11201")
+            .await;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report (proxied)\r\n",
+            "\r\n",
+            "Did you get the memo?"
+        ),
+    )
+    .await;
+    let email_id = client
+        .set_default_account_id(&account_id_1)
+        .email_query(
+            email::query::Filter::subject("proxied").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email = client
+        .email_get(&email_id, [email::Property::BlobId].into())
+        .await
+        .unwrap()
+        .unwrap();
+    let message =
+        String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+    assert!(
+        message.starts_with("Received: from unknown ([192.0.2.1])\r\n"),
+        "{}",
+        message
+    );
+
+    // Plus-addressed messages are filed into an existing folder named after the tag
+    let mailbox_id = client
+        .set_default_account_id(&account_id_1)
+        .mailbox_create("newsletter", None::<String>, mailbox::Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe+newsletter@example.com", "nobody@example.org"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe+newsletter@example.com\r\n",
+            "Cc: nobody@example.org\r\n",
+            "Subject: Weekly newsletter\r\n",
+            "\r\n",
+            "This week's TPS report cover sheets."
+        ),
+    )
+    .await;
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox(&mailbox_id).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        1
+    );
+
+    // Senders can't create folders using unknown tags, these messages go to the Inbox
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe+unsolicited@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe+unsolicited@example.com\r\n",
+            "Subject: Unsolicited offer\r\n",
+            "\r\n",
+            "Buy now."
+        ),
+    )
+    .await;
+    assert!(client
+        .mailbox_query(
+            mailbox::query::Filter::name("unsolicited").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+
+    // Unknown recipients are delivered to the catch-all address of the domain
+    lmtp.mail_from("bill@example.com", 2).await;
+    lmtp.rcpt_to("jdoe+newsletter@example.net", 5).await;
+    lmtp.rset().await;
+    for (account_id, num_messages) in [(&account_id_1, 7), (&account_id_2, 3), (&account_id_3, 4)] {
+        assert_eq!(
+            server
+                .store
+                .get_document_ids(
+                    JMAPId::parse(account_id).unwrap().get_document_id(),
+                    Collection::Mail
+                )
+                .unwrap()
+                .unwrap()
+                .len(),
+            num_messages,
+            "for {}",
+            account_id
+        );
+    }
+
+    // Keywords are assigned based on the message headers
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com", "jane@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com, jane@example.com\r\n",
+            "Subject: Urgent TPS Report\r\n",
+            "Importance: High\r\n",
+            "Precedence: bulk\r\n",
+            "\r\n",
+            "Please read this."
+        ),
+    )
+    .await;
+    for (account_id, expected_keywords) in [
+        (&account_id_1, vec!["$important"]),
+        (&account_id_2, vec!["$bulk", "$important"]),
+    ] {
+        let email_id = client
+            .set_default_account_id(account_id)
+            .email_query(
+                email::query::Filter::subject("Urgent").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        let mut keywords = client
+            .email_get(&email_id, [email::Property::Keywords].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .keywords()
+            .into_iter()
+            .map(|k| k.to_string())
+            .collect::<Vec<_>>();
+        keywords.sort_unstable();
+        assert_eq!(keywords, expected_keywords, "for {}", account_id);
+    }
+
+    // Authentication results added by the receiving MTA are stored with the message
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "Authentication-Results: mx.example.com;\r\n",
+            "  dkim=pass (good signature) header.d=example.com;\r\n",
+            "  spf=softfail smtp.mailfrom=bill@example.com;\r\n",
+            "  dmarc=pass header.from=example.com\r\n",
+            "Authentication-Results: forged.example.net; dkim=fail; spf=fail\r\n",
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Authenticated TPS Report\r\n",
+            "\r\n",
+            "This message was authenticated."
+        ),
+    )
+    .await;
+    let mut email_ids = Vec::new();
+    for subject in ["Authenticated TPS Report", "Urgent TPS Report"] {
+        email_ids.push(
+            client
+                .set_default_account_id(&account_id_1)
+                .email_query(
+                    email::query::Filter::subject(subject).into(),
+                    None::<Vec<_>>,
+                )
+                .await
+                .unwrap()
+                .take_ids()
+                .pop()
+                .unwrap(),
+        );
+    }
+    let account_document_id = JMAPId::parse(&account_id_1).unwrap().get_document_id();
+    let mut request = serde_json::from_value::<GetRequest<schema::Email>>(serde_json::json!({
+        "accountId": &account_id_1,
+        "ids": email_ids,
+        "properties": ["authenticationResults"]
+    }))
+    .unwrap();
+    request.acl = server
+        .store
+        .get_acl_token(account_document_id)
+        .unwrap()
+        .into();
+    let response = serde_json::to_value(&server.store.mail_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["authenticationResults"],
+        serde_json::json!({
+            "dkim": "pass",
+            "spf": "softfail",
+            "dmarc": "pass"
+        }),
+        "{:?}",
+        response
+    );
+    assert!(
+        response["list"][1]["authenticationResults"].is_null(),
+        "{:?}",
+        response
+    );
+
+    // Groups receive a single shared copy unless configured for per-member delivery
+    let mut group_ids = Vec::new();
+    for group_email in ["staff@example.com", "team@example.com"] {
+        group_ids.push(
+            client
+                .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+                .group_create(group_email, "Group", [&account_id_2, &account_id_3])
+                .await
+                .unwrap()
+                .take_id(),
+        );
+        lmtp.ingest(
+            "bill@example.com",
+            &[group_email],
+            &format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: {}\r\n",
+                    "Subject: Group delivery to {}\r\n",
+                    "\r\n",
+                    "TPS reports are due on Friday."
+                ),
+                group_email, group_email
+            ),
+        )
+        .await;
+    }
+    for (account_id, num_messages) in [
+        (&account_id_2, 5),
+        (&account_id_3, 5),
+        (&group_ids[0], 1),
+        (&group_ids[1], 0),
+    ] {
+        assert_eq!(
+            server
+                .store
+                .get_document_ids(
+                    JMAPId::parse(account_id).unwrap().get_document_id(),
+                    Collection::Mail
+                )
+                .unwrap()
+                .map_or(0, |ids| ids.len()),
+            num_messages,
+            "for {}",
+            account_id
+        );
+    }
+
+    // Per-member copies share the same blob
+    let mut blob_ids = Vec::new();
+    for account_id in [&account_id_2, &account_id_3] {
+        let email_id = client
+            .set_default_account_id(account_id)
+            .email_query(
+                email::query::Filter::subject("team@example.com").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        blob_ids.push(
+            client
+                .email_get(&email_id, [email::Property::BlobId].into())
+                .await
+                .unwrap()
+                .unwrap()
+                .blob_id()
+                .unwrap()
+                .to_string(),
+        );
+    }
+    assert_eq!(blob_ids[0], blob_ids[1]);
+
+    // Recipients on verified domains are checked with the callout before being accepted
+    let callout_hits = web::Data::new(AtomicUsize::new(0));
+    let data = callout_hits.clone();
+    actix_web::rt::spawn(async move {
+        HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/verify", web::get().to(handle_callout))
+        })
+        .bind("127.0.0.1:9010")?
+        .run()
+        .await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.mail_from("bill@example.com", 2).await;
+    lmtp.rcpt_to("unknown@example.edu", 5).await;
+    lmtp.rcpt_to("known@example.edu", 2).await;
+    assert_eq!(callout_hits.load(Ordering::Relaxed), 2);
+
+    // Callout results are cached
+    lmtp.rcpt_to("Unknown@example.edu", 5).await;
+    lmtp.rcpt_to("known@example.edu", 2).await;
+    assert_eq!(callout_hits.load(Ordering::Relaxed), 2);
+    lmtp.rset().await;
+
+    // Large inline images in HTML bodies are stored as attachments
+    let image = (0..2000).map(|n| (n % 251) as u8).collect::<Vec<_>>();
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        &format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Inline images\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: text/html; charset=utf-8\r\n",
+                "\r\n",
+                "<html><body><img src=\"data:image/png;base64,{}\">",
+                "<img src=\"data:image/gif;base64,R0lGODlhAQABAAAAACw=\"></body></html>\r\n"
+            ),
+            base64::encode(&image)
+        ),
+    )
+    .await;
+    let email_id = client
+        .set_default_account_id(&account_id_1)
+        .email_query(
+            email::query::Filter::subject("Inline images").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let raw_message = client
+        .download(
+            client
+                .email_get(&email_id, [email::Property::BlobId].into())
+                .await
+                .unwrap()
+                .unwrap()
+                .blob_id()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let message = Message::parse(&raw_message).unwrap();
+    assert_eq!(message.get_subject(), Some("Inline images"));
+    let html = message.get_html_body(0).unwrap();
+    assert!(html.contains("src=\"cid:"), "{}", html);
+    assert!(!html.contains("data:image/png"), "{}", html);
+    assert!(html.contains("data:image/gif"), "{}", html);
+    assert!(
+        message
+            .parts
+            .iter()
+            .any(|part| part.get_contents() == image.as_slice()),
+        "{}",
+        String::from_utf8_lossy(&raw_message)
+    );
+
+    // Large messages are filed into the configured folder and flagged
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        &format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Large TPS Report\r\n",
+                "\r\n",
+                "{}\r\n"
+            ),
+            "TPS report line\r\n".repeat(70000)
+        ),
+    )
+    .await;
+    let mailbox_id = client
+        .set_default_account_id(&account_id_1)
+        .mailbox_query(
+            mailbox::query::Filter::name("Large Messages").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email_id = client
+        .email_query(
+            email::query::Filter::in_mailbox(&mailbox_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email = client
+        .email_get(
+            &email_id,
+            [email::Property::Subject, email::Property::Keywords].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.subject(), Some("Large TPS Report"));
+    assert_eq!(email.keywords(), ["$large"]);
+
+    // Infected attachments are replaced with a notice, messages infected elsewhere
+    // are rejected as no part can be removed
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Infected TPS Report\r\n",
+            "Content-Type: multipart/mixed; boundary=\"bound\"\r\n",
+            "\r\n",
+            "--bound\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "Hello.\r\n",
+            "--bound\r\n",
+            "Content-Type: application/octet-stream\r\n",
+            "Content-Disposition: attachment; filename=\"tps.exe\"\r\n",
+            "\r\n",
+            "EICAR test file\r\n",
+            "--bound--\r\n"
+        ),
+    )
+    .await;
+    lmtp.ingest_with_code(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Single part\r\n",
+            "\r\n",
+            "EICAR test file\r\n"
+        ),
+        5,
+    )
+    .await
+    .assert_contains("Message contains a virus");
+    let email_id = client
+        .set_default_account_id(&account_id_1)
+        .email_query(
+            email::query::Filter::subject("Infected TPS Report").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email = client
+        .email_get(
+            &email_id,
+            [email::Property::BlobId, email::Property::HasAttachment].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!email.has_attachment());
+    let raw_message =
+        String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+    assert!(
+        raw_message
+            .contains("This part was removed because it contained a virus (Eicar-Test-Signature)."),
+        "{}",
+        raw_message
+    );
+    assert!(!raw_message.contains("EICAR"), "{}", raw_message);
+    assert!(client
+        .email_query(
+            email::query::Filter::subject("Single part").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+
     // Remove test data
     for account_id in [&account_id_1, &account_id_2, &account_id_3] {
         client
@@ -314,15 +903,145 @@ where
             .await
             .unwrap();
     }
-    client.principal_destroy(&list_id).await.unwrap();
+    for principal_id in group_ids.iter().chain([&list_id]) {
+        client.principal_destroy(principal_id).await.unwrap();
+    }
     client.principal_destroy(&domain_id).await.unwrap();
     server.store.principal_purge().unwrap();
     server.store.assert_is_empty();
+
+    // Virus actions other than strip are tested on their own servers
+    virus_action::<T>("reject", 2).await;
+    virus_action::<T>("quarantine", 3).await;
+}
+
+async fn virus_action<T>(action: &str, peer_num: u32)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (_server, mut client, temp_dir, handle) = init_jmap_tests_opts::<T>(
+        &format!("jmap_mail_virus_{}", action),
+        peer_num,
+        1,
+        true,
+        &[
+            ("lmtp-virus-scan-command", VIRUS_SCAN_COMMAND),
+            ("lmtp-virus-action", action),
+            ("lmtp-virus-quarantine-account", "postmaster@example.com"),
+        ],
+    )
+    .await;
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let postmaster_id = client
+        .individual_create("postmaster@example.com", "12345", "Postmaster")
+        .await
+        .unwrap()
+        .take_id();
+
+    let message = concat!(
+        "From: bill@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: Infected TPS Report\r\n",
+        "Content-Type: multipart/mixed; boundary=\"bound\"\r\n",
+        "\r\n",
+        "--bound\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Hello.\r\n",
+        "--bound\r\n",
+        "Content-Type: application/octet-stream\r\n",
+        "Content-Disposition: attachment; filename=\"tps.exe\"\r\n",
+        "\r\n",
+        "EICAR test file\r\n",
+        "--bound--\r\n"
+    );
+    let mut lmtp = SmtpConnection::connect_peer(peer_num as usize).await;
+    if action == "reject" {
+        lmtp.ingest_with_code("bill@example.com", &["jdoe@example.com"], message, 5)
+            .await
+            .assert_contains("554 5.7.7 Message contains a virus");
+    } else {
+        lmtp.ingest("bill@example.com", &["jdoe@example.com"], message)
+            .await;
+    }
+
+    // The recipient never gets the infected message
+    assert!(client
+        .set_default_account_id(&account_id)
+        .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+
+    // Quarantined messages are stored unmodified in the quarantine account
+    let email_ids = client
+        .set_default_account_id(&postmaster_id)
+        .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids();
+    if action == "reject" {
+        assert!(email_ids.is_empty());
+    } else {
+        assert_eq!(email_ids.len(), 1);
+        let mailbox_id = client
+            .mailbox_query(
+                mailbox::query::Filter::name("Quarantine").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        let email = client
+            .email_get(
+                &email_ids[0],
+                [email::Property::MailboxIds, email::Property::BlobId].into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(email.mailbox_ids(), [mailbox_id.as_str()]);
+        let raw_message =
+            String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+        assert!(raw_message.ends_with(message), "{}", raw_message);
+    }
+
+    lmtp.quit().await;
+    handle.stop(true).await;
+    destroy_temp_dir(&temp_dir);
+}
+
+async fn handle_callout(
+    query: web::Query<HashMap<String, String>>,
+    hits: web::Data<AtomicUsize>,
+) -> HttpResponse {
+    hits.fetch_add(1, Ordering::Relaxed);
+    if query
+        .get("address")
+        .map_or(false, |a| a == "known@example.edu")
+    {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
 }
 
 pub struct SmtpConnection {
     reader: Lines<BufReader<ReadHalf<TcpStream>>>,
     writer: WriteHalf<TcpStream>,
+    pub greeting: Vec<String>,
 }
 
 impl SmtpConnection {
@@ -375,8 +1094,30 @@ impl SmtpConnection {
         let mut conn = SmtpConnection {
             reader: BufReader::new(reader).lines(),
             writer,
+            greeting: Vec::new(),
         };
-        conn.read(1, 2).await;
+        conn.greeting = conn.read(1, 2).await;
+        conn
+    }
+
+    pub async fn connect_proxied(source_ip: &str, proxy_header: &str) -> Self {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket
+            .bind(format!("{}:0", source_ip).parse().unwrap())
+            .unwrap();
+        let (reader, writer) = tokio::io::split(
+            socket
+                .connect("127.0.0.1:11201".parse().unwrap())
+                .await
+                .unwrap(),
+        );
+        let mut conn = SmtpConnection {
+            reader: BufReader::new(reader).lines(),
+            writer,
+            greeting: Vec::new(),
+        };
+        conn.send(proxy_header).await;
+        conn.greeting = conn.read(1, 2).await;
         conn
     }
 
@@ -438,6 +1179,11 @@ impl SmtpConnection {
         self.read(1, 2).await
     }
 
+    pub async fn help(&mut self) -> Vec<String> {
+        self.send("HELP").await;
+        self.read(1, 2).await
+    }
+
     pub async fn noop(&mut self) -> Vec<String> {
         self.send("NOOP").await;
         self.read(1, 2).await
@@ -22,19 +22,32 @@
 */
 
 use actix_web::web;
-use jmap::types::{jmap::JMAPId, state::JMAPState};
+use jmap::{
+    error::method::MethodError,
+    orm::reindex::{schema_version, JMAPReindex},
+    principal::schema::Principal,
+    request::{get::GetRequest, query::QueryRequest, set::SetRequest as JMAPSetRequest},
+    types::{jmap::JMAPId, state::JMAPState},
+    SUPERUSER_ID,
+};
 use jmap_client::{
     client::Client,
     core::{
         query::Filter,
         set::{SetError, SetErrorType, SetObject, SetRequest},
     },
+    email,
     mailbox::{self, Mailbox, Role},
     Error, Set,
 };
+use jmap_mail::mailbox::{
+    get::JMAPGetMailbox, query::JMAPMailboxQuery, retention::JMAPMailboxRetention, schema,
+    set::JMAPSetMailbox,
+};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
 use serde::{Deserialize, Serialize};
 
-use store::{ahash::AHashMap, Store};
+use store::{ahash::AHashMap, chrono::Utc, core::collection::Collection, Store};
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
 
@@ -319,6 +332,28 @@ where
         .updated(&id_map["1.1.1.1.1"])
         .is_ok());
 
+    // Updates with a stale ifInState are rejected
+    let account_id = JMAPId::parse(client.default_account_id())
+        .unwrap()
+        .get_document_id();
+    let mailbox_id = &id_map["1.1.1.1.1"];
+    let mut request: jmap::request::set::SetRequest<schema::Mailbox> =
+        serde_json::from_value(serde_json::json!({
+            "accountId": client.default_account_id(),
+            "ifInState": &state,
+            "update": {
+                mailbox_id: {
+                    "name": "Stale rename"
+                }
+            }
+        }))
+        .unwrap();
+    request.acl = server.store.get_acl_token(account_id).unwrap().into();
+    assert!(matches!(
+        server.store.mailbox_set(request),
+        Err(MethodError::StateMismatch)
+    ));
+
     // Verify changes
     let state = client.mailbox_changes(state, 0).await.unwrap();
     assert_eq!(state.created().len(), 0);
@@ -607,6 +642,45 @@ where
         ["inbox", "sent", "spam"]
     );
 
+    // Changing the schema version should trigger a reindex
+    type MailboxObject = jmap_mail::mailbox::schema::Mailbox;
+    let mut versions = server.store.get_schema_versions().unwrap();
+    versions.set(Collection::Mailbox, schema_version::<MailboxObject>() ^ 1);
+    assert!(matches!(
+        server
+            .store
+            .reindex_if_changed::<MailboxObject>(&mut versions)
+            .unwrap(),
+        Some(n) if n >= id_map.len()
+    ));
+    server.store.set_schema_versions(&versions).unwrap();
+    let versions = server.store.get_schema_versions().unwrap();
+    assert_eq!(
+        versions.get(Collection::Mailbox),
+        Some(schema_version::<MailboxObject>())
+    );
+    assert_eq!(
+        server
+            .store
+            .reindex_if_changed::<MailboxObject>(&mut versions.clone())
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        client
+            .mailbox_query(
+                mailbox::query::Filter::has_any_role(true).into(),
+                [mailbox::query::Comparator::name()].into()
+            )
+            .await
+            .unwrap()
+            .ids()
+            .iter()
+            .map(|id| id_map.get(id).unwrap())
+            .collect::<Vec<_>>(),
+        ["inbox", "sent", "spam"]
+    );
+
     let mut request = client.build();
     request.query_mailbox().arguments().sort_as_tree(true);
     let mut ids = request.send_query_mailbox().await.unwrap().take_ids();
@@ -615,6 +689,505 @@ where
         client.mailbox_destroy(&id, true).await.unwrap();
     }
     server.store.assert_is_empty();
+
+    localized_names(&server, client).await;
+    retention_policy(&server, client).await;
+    color_and_icon(&server, client).await;
+    rename_cascade(&server, client).await;
+    sort_by_unread(&server, client).await;
+}
+
+async fn retention_policy<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox_id = client
+        .mailbox_create("Ephemeral", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let other_mailbox_id = client
+        .mailbox_create("Permanent", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Set a 7-day retention policy
+    let set_retention = |retention_days: serde_json::Value| {
+        let mut request =
+            serde_json::from_value::<JMAPSetRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": JMAPId::new(1).to_string(),
+                "update": {
+                    &mailbox_id: {
+                        "retentionDays": retention_days
+                    }
+                }
+            }))
+            .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mailbox_set(request).unwrap()).unwrap()
+    };
+    let response = set_retention(serde_json::json!(0));
+    assert_eq!(
+        response["notUpdated"][&mailbox_id]["type"], "invalidProperties",
+        "{:?}",
+        response
+    );
+    let response = set_retention(serde_json::json!(7));
+    assert!(
+        response["updated"].get(&mailbox_id).is_some(),
+        "{:?}",
+        response
+    );
+
+    let mut request = serde_json::from_value::<GetRequest<schema::Mailbox>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "ids": [&mailbox_id, &other_mailbox_id],
+        "properties": ["retentionDays"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mailbox_get(request).unwrap()).unwrap();
+    assert_eq!(response["list"][0]["retentionDays"], 7, "{:?}", response);
+    assert_eq!(
+        response["list"][1]["retentionDays"],
+        serde_json::Value::Null,
+        "{:?}",
+        response
+    );
+
+    // Import messages received at different times
+    let now = Utc::now().timestamp();
+    let mut ids = AHashMap::new();
+    for (name, mailbox_ids, days_ago) in [
+        ("expired", vec![&mailbox_id], 10),
+        ("recent", vec![&mailbox_id], 1),
+        ("expired_shared", vec![&mailbox_id, &other_mailbox_id], 10),
+    ] {
+        let id = client
+            .email_import(
+                format!("Subject: {}\r\n\r\ntest", name).into_bytes(),
+                mailbox_ids,
+                None::<Vec<String>>,
+                Some(now - days_ago * 86400),
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(name, id);
+    }
+
+    // Messages older than 7 days are purged
+    assert!(server.store.mailbox_purge_expired(1).unwrap().is_some());
+    assert!(client
+        .email_get(&ids["expired"], None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox(&mailbox_id).into(),
+                None::<Vec<_>>
+            )
+            .await
+            .unwrap()
+            .take_ids(),
+        [ids["recent"].clone()]
+    );
+
+    // Messages in other mailboxes are only removed from the expired mailbox
+    assert_eq!(
+        client
+            .email_get(&ids["expired_shared"], [email::Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .mailbox_ids(),
+        [other_mailbox_id.as_str()]
+    );
+
+    // Nothing else is left to purge
+    assert!(server.store.mailbox_purge_expired(1).unwrap().is_none());
+
+    for mailbox_id in [mailbox_id, other_mailbox_id] {
+        client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    }
+    server.store.assert_is_empty();
+}
+
+async fn color_and_icon<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mailbox_id = client
+        .mailbox_create("Colorful", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let set_properties = |properties: serde_json::Value| {
+        let mut request =
+            serde_json::from_value::<JMAPSetRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": JMAPId::new(1).to_string(),
+                "update": {
+                    &mailbox_id: properties
+                }
+            }))
+            .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mailbox_set(request).unwrap()).unwrap()
+    };
+    let get_properties = || {
+        let mut request =
+            serde_json::from_value::<GetRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": JMAPId::new(1).to_string(),
+                "ids": [&mailbox_id],
+                "properties": ["color", "icon"]
+            }))
+            .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(&server.store.mailbox_get(request).unwrap()).unwrap()["list"][0]
+            .clone()
+    };
+
+    // Invalid colors are rejected
+    for color in ["red", "#12345", "#ggg", "123456"] {
+        let response = set_properties(serde_json::json!({ "color": color }));
+        assert_eq!(
+            response["notUpdated"][&mailbox_id]["type"], "invalidProperties",
+            "{:?}",
+            response
+        );
+        assert_eq!(
+            response["notUpdated"][&mailbox_id]["properties"],
+            serde_json::json!(["color"]),
+            "{:?}",
+            response
+        );
+    }
+
+    // Set a color and an icon
+    let response = set_properties(serde_json::json!({
+        "color": "#FF8800",
+        "icon": "briefcase"
+    }));
+    assert!(
+        response["updated"].get(&mailbox_id).is_some(),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        get_properties(),
+        serde_json::json!({
+            "id": &mailbox_id,
+            "color": "#ff8800",
+            "icon": "briefcase"
+        })
+    );
+
+    // Clear the color
+    let response = set_properties(serde_json::json!({ "color": null }));
+    assert!(
+        response["updated"].get(&mailbox_id).is_some(),
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        get_properties(),
+        serde_json::json!({
+            "id": &mailbox_id,
+            "color": null,
+            "icon": "briefcase"
+        })
+    );
+
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn rename_cascade<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let parent_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let child_id = client
+        .mailbox_create("Active", Some(&parent_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let grandchild_id = client
+        .mailbox_create("Q3", Some(&child_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let other_id = client
+        .mailbox_create("Archive", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let mut state = client
+        .mailbox_changes(JMAPState::Initial.to_string(), 0)
+        .await
+        .unwrap()
+        .new_state()
+        .to_string();
+
+    // Renaming or moving a mailbox logs its descendants as updated
+    for (mailbox_id, update, expected_ids) in [
+        (
+            &parent_id,
+            serde_json::json!({"name": "Clients"}),
+            vec![&parent_id, &child_id, &grandchild_id],
+        ),
+        (
+            &child_id,
+            serde_json::json!({"parentId": &other_id}),
+            vec![&child_id, &grandchild_id],
+        ),
+        (
+            &other_id,
+            serde_json::json!({"sortOrder": 3}),
+            vec![&other_id],
+        ),
+    ] {
+        let mut request =
+            serde_json::from_value::<JMAPSetRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": JMAPId::new(1).to_string(),
+                "update": {
+                    mailbox_id: update
+                }
+            }))
+            .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        let response = serde_json::to_value(&server.store.mailbox_set(request).unwrap()).unwrap();
+        assert!(
+            response["updated"].get(mailbox_id).is_some(),
+            "{:?}",
+            response
+        );
+
+        let changes = client.mailbox_changes(state, 0).await.unwrap();
+        let mut updated = changes.updated().to_vec();
+        let mut expected_ids = expected_ids
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        updated.sort_unstable();
+        expected_ids.sort_unstable();
+        assert_eq!(updated, expected_ids);
+        assert_eq!(changes.arguments().updated_properties(), None);
+        state = changes.new_state().to_string();
+    }
+
+    // Children reflect the new hierarchy
+    let mailbox = client
+        .mailbox_get(
+            &child_id,
+            [mailbox::Property::Name, mailbox::Property::ParentId].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(mailbox.name(), Some("Active"));
+    assert_eq!(mailbox.parent_id(), Some(other_id.as_str()));
+
+    for mailbox_id in [&grandchild_id, &child_id, &other_id, &parent_id] {
+        client.mailbox_destroy(mailbox_id, true).await.unwrap();
+    }
+    server.store.assert_is_empty();
+}
+
+async fn localized_names<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Create a test account, which will contain the default mailboxes
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let account_document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let sent_id = JMAPId::from(
+        server
+            .store
+            .mailbox_get_by_role(account_document_id, "sent")
+            .unwrap()
+            .unwrap(),
+    )
+    .to_string();
+
+    let get_name = |is_renamed: bool| {
+        let mut request =
+            serde_json::from_value::<GetRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": account_id,
+                "ids": [sent_id],
+                "properties": ["name", "displayName", "role"]
+            }))
+            .unwrap();
+        request.acl = server
+            .store
+            .get_acl_token(account_document_id)
+            .unwrap()
+            .into();
+        let response = serde_json::to_value(&server.store.mailbox_get(request).unwrap()).unwrap();
+        assert_eq!(response["list"][0]["role"], "sent", "{:?}", response);
+        assert_eq!(
+            response["list"][0]["name"],
+            if is_renamed { "Outgoing" } else { "Sent Items" },
+            "{:?}",
+            response
+        );
+        response["list"][0]["displayName"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    let set_locale = |locale: serde_json::Value| {
+        let mut request = serde_json::from_value::<JMAPSetRequest<Principal>>(serde_json::json!({
+            "accountId": JMAPId::new(SUPERUSER_ID as u64).to_string(),
+            "update": {
+                &account_id: {
+                    "locale": locale
+                }
+            }
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+        let response = serde_json::to_value(&server.store.principal_set(request).unwrap()).unwrap();
+        assert!(
+            response["updated"].get(&account_id).is_some(),
+            "{:?}",
+            response
+        );
+    };
+
+    // Without a locale the canonical name is displayed
+    assert_eq!(get_name(false), "Sent Items");
+
+    // The display name follows the account's locale, the name is left unchanged
+    set_locale(serde_json::json!("de-DE"));
+    assert_eq!(get_name(false), "Gesendet");
+    set_locale(serde_json::json!("es"));
+    assert_eq!(get_name(false), "Enviados");
+    set_locale(serde_json::json!("xx"));
+    assert_eq!(get_name(false), "Sent Items");
+
+    // Renamed special-use mailboxes are not localized
+    set_locale(serde_json::json!("de"));
+    client
+        .set_default_account_id(&account_id)
+        .mailbox_rename(&sent_id, "Outgoing")
+        .await
+        .unwrap();
+    assert_eq!(get_name(true), "Outgoing");
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+    client.set_default_account_id(JMAPId::new(1));
+}
+
+async fn sort_by_unread<T>(server: &JMAPServer<T>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Create mailboxes with a varying number of unread messages
+    let mut id_map = AHashMap::new();
+    for (name, unread, seen) in [
+        ("Few", 1, 1),
+        ("Many", 3, 0),
+        ("Read", 0, 2),
+        ("Empty", 0, 0),
+    ] {
+        let mailbox_id = client
+            .mailbox_create(name, None::<String>, Role::None)
+            .await
+            .unwrap()
+            .take_id();
+        for (num, keywords) in std::iter::repeat(vec![])
+            .take(unread)
+            .chain(std::iter::repeat(vec!["$seen"]).take(seen))
+            .enumerate()
+        {
+            client
+                .email_import(
+                    format!("Subject: {} {}\r\n\r\ntest", name, num).into_bytes(),
+                    [&mailbox_id],
+                    Some(keywords),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        id_map.insert(mailbox_id, name);
+    }
+
+    let query = |sort: serde_json::Value| {
+        let mut request =
+            serde_json::from_value::<QueryRequest<schema::Mailbox>>(serde_json::json!({
+                "accountId": JMAPId::new(1).to_string(),
+                "sort": sort
+            }))
+            .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        server.store.mailbox_query(request).map(|response| {
+            response
+                .ids
+                .iter()
+                .map(|id| *id_map.get(&id.to_string()).unwrap())
+                .collect::<Vec<_>>()
+        })
+    };
+
+    // Mailboxes with the same number of unread messages are sorted by the next comparator
+    assert_eq!(
+        query(serde_json::json!([
+            {"property": "unreadEmails", "isAscending": false},
+            {"property": "name"}
+        ]))
+        .unwrap(),
+        ["Many", "Few", "Empty", "Read"]
+    );
+    assert_eq!(
+        query(serde_json::json!([
+            {"property": "unreadEmails"},
+            {"property": "name", "isAscending": false}
+        ]))
+        .unwrap(),
+        ["Read", "Empty", "Few", "Many"]
+    );
+
+    // Unread counts can only be used as the first sort criterion
+    assert!(matches!(
+        query(serde_json::json!([
+            {"property": "name"},
+            {"property": "unreadEmails"}
+        ])),
+        Err(MethodError::UnsupportedSort(_))
+    ));
+
+    for mailbox_id in id_map.keys() {
+        client.mailbox_destroy(mailbox_id, true).await.unwrap();
+    }
+    server.store.assert_is_empty();
 }
 
 async fn create_test_mailboxes(client: &mut Client) -> AHashMap<String, String> {
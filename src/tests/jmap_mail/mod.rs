@@ -28,6 +28,7 @@ use super::{jmap::init_jmap_tests, store::utils::destroy_temp_dir};
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
+pub mod email_import;
 pub mod email_parse;
 pub mod email_query;
 pub mod email_query_changes;
@@ -37,14 +38,27 @@ pub mod email_thread;
 pub mod email_thread_merge;
 pub mod lmtp;
 pub mod mailbox;
+pub mod search_folder;
 pub mod search_snippet;
+pub mod shutdown;
 pub mod sieve;
 pub mod vacation_response;
 
 #[actix_web::test]
 #[ignore]
 async fn jmap_mail_tests() {
-    let (server, mut client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_mail_tests").await;
+    let (server, mut client, temp_dir) = init_jmap_tests::<RocksDB>(
+        "jmap_mail_tests",
+        &[
+            email_query::SETTINGS,
+            email_set::SETTINGS,
+            email_submission::SETTINGS,
+            lmtp::SETTINGS,
+            sieve::SETTINGS,
+        ]
+        .concat(),
+    )
+    .await;
 
     // Run tests
     email_changes::test(server.clone(), &mut client).await;
@@ -53,6 +67,7 @@ async fn jmap_mail_tests() {
     email_thread_merge::test(server.clone(), &mut client).await;
     email_get::test(server.clone(), &mut client).await;
     email_parse::test(server.clone(), &mut client).await;
+    email_import::test(server.clone(), &mut client).await;
     email_set::test(server.clone(), &mut client).await;
     email_query::test(server.clone(), &mut client).await;
     email_copy::test(server.clone(), &mut client).await;
@@ -60,8 +75,10 @@ async fn jmap_mail_tests() {
     lmtp::test(server.clone(), &mut client).await;
     vacation_response::test(server.clone(), &mut client).await;
     mailbox::test(server.clone(), &mut client).await;
+    search_folder::test(server.clone(), &mut client).await;
     search_snippet::test(server.clone(), &mut client).await;
     sieve::test(server.clone(), &mut client).await;
+    shutdown::test(server.clone()).await;
 
     destroy_temp_dir(&temp_dir);
 }
@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+
+use jmap::{request::get::GetRequest, types::jmap::JMAPId};
+use jmap_client::{
+    client::Client,
+    core::set::{SetError, SetErrorType},
+    email,
+    mailbox::Role,
+};
+use jmap_mail::mailbox::{
+    get::JMAPGetMailbox,
+    schema,
+    search::{JMAPMailboxSearch, MailboxSetSearchRequest},
+    set::JMAPSetMailbox,
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{ahash::AHashMap, Store};
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Search Folder tests...");
+
+    let account_id = JMAPId::new(1).to_string();
+    let mailbox_id = client
+        .set_default_account_id(&account_id)
+        .mailbox_create("Search Inbox", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let search_id = client
+        .mailbox_create("Flagged", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let set_search = |id: &str, filter: serde_json::Value| {
+        let mut request = serde_json::from_value::<MailboxSetSearchRequest>(serde_json::json!({
+            "accountId": &account_id,
+            "update": {id: filter}
+        }))
+        .unwrap();
+        request.acl = server.store.get_acl_token(1).unwrap().into();
+        serde_json::to_value(server.store.mailbox_set_search(request).unwrap()).unwrap()
+    };
+
+    // Invalid filters and filters referencing search folders are rejected
+    for filter in [
+        serde_json::json!({"inMailbox": &search_id}),
+        serde_json::json!({"before": "yesterday"}),
+    ] {
+        let response = set_search(&search_id, filter);
+        assert_eq!(
+            response["notUpdated"][&search_id]["type"], "invalidProperties",
+            "{:?}",
+            response
+        );
+    }
+    let response = set_search(&search_id, serde_json::json!({"hasKeyword": "$flagged"}));
+    assert!(
+        response["updated"]
+            .as_array()
+            .map_or(false, |ids| ids.contains(&search_id.clone().into())),
+        "{:?}",
+        response
+    );
+
+    // Import messages into a regular mailbox, some of them flagged
+    let mut ids = AHashMap::new();
+    for (name, keywords) in [
+        ("a", vec!["$flagged"]),
+        ("b", vec![]),
+        ("c", vec!["$flagged", "$seen"]),
+        ("d", vec!["$seen"]),
+    ] {
+        let id = client
+            .email_import(
+                format!("Subject: {}\n\ntest", name).into_bytes(),
+                [&mailbox_id],
+                Some(keywords),
+                None,
+            )
+            .await
+            .unwrap()
+            .take_id();
+        ids.insert(id, name);
+    }
+
+    // The search folder lists the messages matching its filter
+    let mut results = client
+        .email_query(
+            email::query::Filter::in_mailbox(&search_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .iter()
+        .map(|id| *ids.get(id).unwrap())
+        .collect::<Vec<_>>();
+    results.sort_unstable();
+    assert_eq!(results, vec!["a", "c"]);
+
+    // Counts are obtained from the filter results and messages cannot be added
+    let mut request = serde_json::from_value::<GetRequest<schema::Mailbox>>(serde_json::json!({
+        "accountId": &account_id,
+        "ids": [&search_id],
+        "properties": ["totalEmails", "unreadEmails", "myRights", "searchFilter"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(1).unwrap().into();
+    let response = serde_json::to_value(&server.store.mailbox_get(request).unwrap()).unwrap();
+    assert_eq!(response["list"][0]["totalEmails"], 2, "{:?}", response);
+    assert_eq!(response["list"][0]["unreadEmails"], 1, "{:?}", response);
+    assert_eq!(
+        response["list"][0]["myRights"]["mayAddItems"], false,
+        "{:?}",
+        response
+    );
+    assert_eq!(
+        response["list"][0]["searchFilter"], "{\"hasKeyword\":\"$flagged\"}",
+        "{:?}",
+        response
+    );
+    for (right, value) in [
+        ("mayRemoveItems", false),
+        ("maySetSeen", false),
+        ("maySetKeywords", false),
+        ("mayRename", true),
+    ] {
+        assert_eq!(
+            response["list"][0]["myRights"][right], value,
+            "{:?}",
+            response
+        );
+    }
+
+    // Search folders can't be used as filing targets by Sieve or LMTP delivery
+    assert_eq!(
+        server.store.mailbox_get_by_name(1, "Flagged").unwrap(),
+        None
+    );
+    assert!(server
+        .store
+        .mailbox_create_path(1, "Flagged")
+        .unwrap()
+        .is_none());
+
+    let email_id = ids
+        .iter()
+        .find_map(|(id, name)| if *name == "b" { Some(id.clone()) } else { None })
+        .unwrap();
+    assert!(matches!(
+        client.email_set_mailbox(&email_id, &search_id, true).await,
+        Err(jmap_client::Error::Set(SetError {
+            type_: SetErrorType::InvalidProperties,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        client
+            .email_import(
+                b"Subject: e\n\ntest".to_vec(),
+                [&search_id],
+                None::<Vec<String>>,
+                None,
+            )
+            .await,
+        Err(jmap_client::Error::Set(SetError {
+            type_: SetErrorType::InvalidProperties,
+            ..
+        }))
+    ));
+
+    // Removing the filter turns the search folder back into an empty mailbox
+    let response = set_search(&search_id, serde_json::Value::Null);
+    assert!(response["updated"].is_array(), "{:?}", response);
+    assert!(client
+        .email_query(
+            email::query::Filter::in_mailbox(&search_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+
+    client.mailbox_destroy(&search_id, true).await.unwrap();
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use store::Store;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+};
+
+use crate::{
+    lmtp::InFlightDelivery,
+    services::email_delivery::{self, DeliveryQueue, DELIVERY_QUEUE_KEY},
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running shutdown tests...");
+
+    // Wait for the mock SMTP server to release its port
+    let mut listener = None;
+    for _ in 0..50 {
+        match TcpListener::bind("127.0.0.1:9999").await {
+            Ok(listener_) => {
+                listener = listener_.into();
+                break;
+            }
+            Err(_) => {
+                if let Ok(mut stream) = TcpStream::connect("127.0.0.1:9999").await {
+                    stream.write_all(b"QUIT\r\n").await.ok();
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+    let listener = listener.expect("Failed to bind to 127.0.0.1:9999");
+
+    // Start a relay that stalls until released
+    let (release_tx, release_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        release_rx.await.ok();
+        drop(stream);
+    });
+
+    // Keep the relay busy with one message and queue two more
+    for num in 0..3 {
+        server
+            .notify_email_delivery(email_delivery::Event::outgoing_message(
+                "jdoe@example.com".to_string(),
+                vec!["jane@example.com".to_string()],
+                format!("Subject: Message {}\r\n\r\nTest", num).into_bytes(),
+            ))
+            .await
+            .unwrap();
+        if num == 0 {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    // Shutdown waits for deliveries in progress
+    let in_flight = InFlightDelivery::new(&server.lmtp_deliveries);
+    let mut shutdown = tokio::spawn({
+        let server = server.clone();
+        async move { server.shutdown().await }
+    });
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), &mut shutdown)
+            .await
+            .is_err(),
+        "Shutdown did not wait for the LMTP delivery."
+    );
+    drop(in_flight);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(300), &mut shutdown)
+            .await
+            .is_err(),
+        "Shutdown did not wait for the message being relayed."
+    );
+    release_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), shutdown)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Queued messages are persisted, followed by the retry of the message that failed
+    let queue = server
+        .get_key::<DeliveryQueue>(DELIVERY_QUEUE_KEY)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(queue.submissions.is_empty());
+    assert_eq!(
+        queue
+            .messages
+            .into_iter()
+            .map(|(_, _, message)| String::from_utf8(message).unwrap())
+            .collect::<Vec<_>>(),
+        vec![
+            "Subject: Message 1\r\n\r\nTest".to_string(),
+            "Subject: Message 2\r\n\r\nTest".to_string(),
+            "Subject: Message 0\r\n\r\nTest".to_string()
+        ]
+    );
+
+    // New LMTP connections are no longer accepted
+    assert!(TcpStream::connect("127.0.0.1:11201").await.is_err());
+}
@@ -21,10 +21,18 @@
  * for more details.
 */
 
-use std::{fs, path::PathBuf, time::Duration};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use actix_web::web;
-use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap::{
+    request::{get::GetRequest, set::SetRequest},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
 use jmap_client::{
     client::Client,
     core::set::{SetError, SetErrorType},
@@ -32,13 +40,19 @@ use jmap_client::{
     sieve::query::{Comparator, Filter},
     Error,
 };
-use jmap_sharing::principal::set::JMAPSetPrincipal;
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use jmap_sieve::sieve_script::{
+    get::JMAPGetSieveScript, schema::SieveScript, set::JMAPSetSieveScript,
+};
 use store::Store;
 
 use crate::{
+    lmtp::srs::Srs,
     tests::{
         jmap_mail::{
-            email_submission::{assert_message_delivery, spawn_mock_smtp_server, MockMessage},
+            email_submission::{
+                assert_message_delivery, expect_nothing, spawn_mock_smtp_server, MockMessage,
+            },
             lmtp::{AssertResult, SmtpConnection},
         },
         store::utils::StoreCompareWith,
@@ -46,6 +60,17 @@ use crate::{
     JMAPServer,
 };
 
+pub const SETTINGS: &[(&str, &str)] = &[
+    (
+        "sieve-global-before",
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/tests/resources/jmap_sieve/global_external.sieve"
+        ),
+    ),
+    ("sieve-discard-folder", "Discarded"),
+];
+
 pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
 where
     T: for<'x> Store<'x> + 'static,
@@ -274,9 +299,42 @@ where
         ),
     )
     .await;
+
+    // Discarded messages are retained in the fallback folder
+    let discarded_id = client
+        .mailbox_query(
+            mailbox::query::Filter::name("Discarded").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .expect("Mailbox Discarded was not created.");
+    let email_ids = client
+        .email_query(
+            email::query::Filter::in_mailbox(&discarded_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(email_ids.len(), 1, "Discarded message was not retained.");
     assert_eq!(
         client
-            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .email_get(&email_ids[0], [email::Property::Subject].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .subject(),
+        Some("Holidays")
+    );
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox_other_than([&discarded_id]).into(),
+                None::<Vec<_>>
+            )
             .await
             .unwrap()
             .ids()
@@ -307,7 +365,10 @@ where
     .assert_contains("No soup for you");
     assert_eq!(
         client
-            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .email_query(
+                email::query::Filter::in_mailbox_other_than([&discarded_id]).into(),
+                None::<Vec<_>>
+            )
             .await
             .unwrap()
             .ids()
@@ -342,6 +403,63 @@ where
     .await
     .assert_contains("Rejected from an included script");
 
+    // Create a script from its source text and read it back
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let script_text = "require \"reject\";\r\nreject \"Rejected by a text script\";\r\n";
+    let mut request = serde_json::from_value::<SetRequest<SieveScript>>(serde_json::json!({
+        "accountId": &account_id,
+        "create": {
+            "s": {
+                "name": "test_text",
+                "script": script_text
+            }
+        },
+        "onSuccessActivateScript": "#s"
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(document_id).unwrap().into();
+    let response = serde_json::to_value(&server.store.sieve_script_set(request).unwrap()).unwrap();
+    let script_id = response["created"]["s"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("Script not created: {:?}", response))
+        .to_string();
+
+    let mut request = serde_json::from_value::<GetRequest<SieveScript>>(serde_json::json!({
+        "accountId": &account_id,
+        "ids": [&script_id],
+        "properties": ["name", "blobId", "script"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(document_id).unwrap().into();
+    let response = serde_json::to_value(&server.store.sieve_script_get(request).unwrap()).unwrap();
+    assert_eq!(response["list"][0]["name"], "test_text", "{:?}", response);
+    assert_eq!(response["list"][0]["script"], script_text, "{:?}", response);
+    assert_eq!(
+        String::from_utf8(
+            client
+                .download(response["list"][0]["blobId"].as_str().unwrap())
+                .await
+                .unwrap()
+        )
+        .unwrap(),
+        script_text
+    );
+
+    // The compiled text script should run on delivery
+    lmtp.ingest_with_code(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "Here's my TPS report."
+        ),
+        5,
+    )
+    .await
+    .assert_contains("Rejected by a text script");
+
     // Start mock SMTP server
     let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
 
@@ -367,19 +485,53 @@ where
         ),
     )
     .await;
+    // The envelope sender of redirected messages is rewritten using SRS
+    let srs = Srs::new("srs-test-secret");
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let srs_address = srs.encode("bill@example.com", "example.com", now).unwrap();
     assert_message_delivery(
         &mut smtp_rx,
         MockMessage::new(
-            "<jdoe@example.com>",
-            ["<jane@example.com>"],
-            "@Attached you'll find",
+            format!("<{}>", srs_address),
+            [String::from("<jane@example.com>")],
+            String::from("@Attached you'll find"),
+        ),
+        false,
+    )
+    .await;
+
+    // Bounces sent to the SRS address are relayed to the original sender
+    lmtp.ingest(
+        "mailer-daemon@example.net",
+        &[&srs_address],
+        concat!(
+            "From: mailer-daemon@example.net\r\n",
+            "To: bill@example.com\r\n",
+            "Subject: Undelivered Mail Returned to Sender\r\n",
+            "\r\n",
+            "Your message could not be delivered."
+        ),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<>",
+            ["<bill@example.com>"],
+            "@Your message could not be delivered.",
         ),
         false,
     )
     .await;
     assert_eq!(
         client
-            .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+            .email_query(
+                email::query::Filter::in_mailbox_other_than([&discarded_id]).into(),
+                None::<Vec<_>>
+            )
             .await
             .unwrap()
             .ids()
@@ -424,7 +576,14 @@ where
         email::Property::Keywords,
         email::Property::Subject,
     ]);
-    let emails = request.send_get_email().await.unwrap().take_list();
+    let emails = request
+        .send_get_email()
+        .await
+        .unwrap()
+        .take_list()
+        .into_iter()
+        .filter(|email| !email.mailbox_ids().contains(&discarded_id.as_str()))
+        .collect::<Vec<_>>();
 
     assert_eq!(
         emails.len(),
@@ -471,10 +630,444 @@ where
         panic!("Email {:?} not found in: {:#?}", subject, emails);
     }
 
+    // Redirects rejected by the relay are stored in the dead-letter mailbox
+    let postmaster_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .individual_create("postmaster@example.com", "12345", "Postmaster")
+        .await
+        .unwrap()
+        .take_id();
+    client
+        .set_default_account_id(&account_id)
+        .sieve_script_create(
+            "test_redirect_invalid",
+            b"redirect \"nobody@invalid.example\";\r\ndiscard;\r\n".to_vec(),
+            true,
+        )
+        .await
+        .unwrap();
+    smtp_settings.lock().fail_rcpt_to = true;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Lost TPS Report\r\n",
+            "\r\n",
+            "Have you seen my TPS report?"
+        ),
+    )
+    .await;
+
+    client.set_default_account_id(&postmaster_id);
+    let mut email_ids = Vec::new();
+    for _ in 0..30 {
+        email_ids = client
+            .email_query(
+                email::query::Filter::subject("Lost TPS Report").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids();
+        if !email_ids.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(email_ids.len(), 1, "Dead letter was not stored.");
+    let mailbox_id = client
+        .mailbox_query(
+            mailbox::query::Filter::name("Dead Letters").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email = client
+        .email_get(
+            &email_ids[0],
+            [email::Property::MailboxIds, email::Property::BlobId].into(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), [mailbox_id.as_str()]);
+    let message =
+        String::from_utf8(client.download(email.blob_id().unwrap()).await.unwrap()).unwrap();
+    assert!(message.starts_with("X-Dead-Letter-Reason: "), "{}", message);
+    assert!(
+        message.contains(concat!(
+            "X-Dead-Letter-Envelope: from=<jdoe@example.com> ",
+            "to=<nobody@invalid.example>\r\n"
+        )),
+        "{}",
+        message
+    );
+    smtp_settings.lock().fail_rcpt_to = false;
+
+    // List members rejecting a message after the list accepted it trigger a bounce
+    let list_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .list_create(
+            "tps-reports@example.com",
+            "TPS Reports",
+            [&account_id, &postmaster_id],
+        )
+        .await
+        .unwrap()
+        .take_id();
+    client
+        .set_default_account_id(&account_id)
+        .sieve_script_create(
+            "test_reject_list",
+            b"require \"reject\";\r\nreject \"No more TPS reports, please.\";\r\n".to_vec(),
+            true,
+        )
+        .await
+        .unwrap();
+    lmtp.ingest(
+        "bill@example.com",
+        &["tps-reports@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: tps-reports@example.com\r\n",
+            "Subject: Yet another TPS Report\r\n",
+            "\r\n",
+            "Did you get the memo?"
+        ),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<>",
+            ["<bill@example.com>"],
+            "@No more TPS reports, please.",
+        ),
+        false,
+    )
+    .await;
+
+    // Messages from the null sender are never bounced
+    lmtp.ingest(
+        "",
+        &["tps-reports@example.com"],
+        concat!(
+            "From: MAILER-DAEMON@example.com\r\n",
+            "To: tps-reports@example.com\r\n",
+            "Subject: Delivery Failure\r\n",
+            "\r\n",
+            "Your TPS report could not be delivered."
+        ),
+    )
+    .await;
+    expect_nothing(&mut smtp_rx).await;
+
+    // Scripts exceeding the redirect limit (2 for this account) are aborted
+    let script_id = client
+        .sieve_script_create(
+            "test_redirect_limit",
+            concat!(
+                "redirect \"jane@example.com\";\r\n",
+                "redirect \"john@example.com\";\r\n",
+                "redirect \"bill@example.com\";\r\n",
+                "discard;\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            true,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Redirect loop\r\n",
+            "\r\n",
+            "Pass it on."
+        ),
+    )
+    .await;
+
+    // No redirects are sent and the message is kept instead of discarded
+    expect_nothing(&mut smtp_rx).await;
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::subject("Redirect loop").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        1
+    );
+
+    // The error is recorded on the script
+    let mut request = serde_json::from_value::<GetRequest<SieveScript>>(serde_json::json!({
+        "accountId": &account_id,
+        "ids": [&script_id],
+        "properties": ["name", "lastError"]
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(document_id).unwrap().into();
+    let response = serde_json::to_value(&server.store.sieve_script_get(request).unwrap()).unwrap();
+    assert_eq!(
+        response["list"][0]["lastError"], "Script exceeded the maximum of 2 redirects.",
+        "{:?}",
+        response
+    );
+
+    // Each recipient of a multi-recipient delivery runs its own script
+    for (id, script) in [
+        (
+            &account_id,
+            concat!(
+                "require [\"fileinto\", \"mailbox\", \"imap4flags\"];\r\n",
+                "fileinto :flags \"$seen\" :create \"John Reports\";\r\n"
+            ),
+        ),
+        (
+            &postmaster_id,
+            concat!(
+                "require [\"fileinto\", \"mailbox\"];\r\n",
+                "fileinto :create \"Postmaster Reports\";\r\n"
+            ),
+        ),
+    ] {
+        client
+            .set_default_account_id(id)
+            .sieve_script_create("test_per_recipient", script.as_bytes().to_vec(), true)
+            .await
+            .unwrap();
+    }
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com", "postmaster@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com, postmaster@example.com\r\n",
+            "Subject: Quarterly TPS Report\r\n",
+            "\r\n",
+            "Both of you need to read this."
+        ),
+    )
+    .await;
+    for (id, folder, other_folder, keywords) in [
+        (
+            &account_id,
+            "John Reports",
+            "Postmaster Reports",
+            vec!["$seen"],
+        ),
+        (&postmaster_id, "Postmaster Reports", "John Reports", vec![]),
+    ] {
+        let mailbox_id = client
+            .set_default_account_id(id)
+            .mailbox_query(mailbox::query::Filter::name(folder).into(), None::<Vec<_>>)
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap_or_else(|| panic!("Mailbox {} was not created.", folder));
+        assert!(client
+            .mailbox_query(
+                mailbox::query::Filter::name(other_folder).into(),
+                None::<Vec<_>>
+            )
+            .await
+            .unwrap()
+            .ids()
+            .is_empty());
+        let email_id = client
+            .email_query(
+                email::query::Filter::subject("Quarterly TPS Report").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        let email = client
+            .email_get(
+                &email_id,
+                [email::Property::MailboxIds, email::Property::Keywords].into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(email.mailbox_ids(), [mailbox_id.as_str()]);
+        assert_eq!(email.keywords(), keywords);
+    }
+
+    // Missing mailboxes are created on delivery, invalid names fall back to the Inbox
+    let long_name = "a".repeat(server.store.config.mailbox_name_max_len);
+    for (path, subject) in [
+        ("Projects/New".to_string(), "Project kickoff"),
+        (format!("Projects/{}", long_name), "Project backlog"),
+    ] {
+        client
+            .set_default_account_id(&account_id)
+            .sieve_script_create(
+                "test_create",
+                format!(
+                    "require [\"fileinto\", \"mailbox\"];\r\nfileinto :create \"{}\";\r\n",
+                    path
+                )
+                .into_bytes(),
+                true,
+            )
+            .await
+            .unwrap();
+        lmtp.ingest(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &format!(
+                "From: bill@example.com\r\nTo: jdoe@example.com\r\nSubject: {}\r\n\r\nHi!",
+                subject
+            ),
+        )
+        .await;
+    }
+    let mut mailbox_ids = Vec::new();
+    for name in ["Projects", "New"] {
+        let mailbox_id = client
+            .mailbox_query(mailbox::query::Filter::name(name).into(), None::<Vec<_>>)
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap_or_else(|| panic!("Mailbox {} was not created.", name));
+        let parent_id = client
+            .mailbox_get(&mailbox_id, [mailbox::Property::ParentId].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .parent_id()
+            .map(|id| id.to_string());
+        assert_eq!(parent_id, mailbox_ids.last().cloned());
+        mailbox_ids.push(mailbox_id);
+    }
+    assert!(client
+        .mailbox_query(
+            mailbox::query::Filter::name(&long_name).into(),
+            None::<Vec<_>>
+        )
+        .await
+        .unwrap()
+        .ids()
+        .is_empty());
+    let inbox_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(mailbox::Role::Inbox).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    for (subject, mailbox_id) in [
+        ("Project kickoff", mailbox_ids.last().unwrap()),
+        ("Project backlog", &inbox_id),
+    ] {
+        let email_id = client
+            .email_query(
+                email::query::Filter::subject(subject).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        let email = client
+            .email_get(&email_id, [email::Property::MailboxIds].into())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(email.mailbox_ids(), [mailbox_id.as_str()]);
+    }
+
+    // The global script tags external senders, whatever the user's script does
+    client
+        .sieve_script_create(
+            "test_global",
+            concat!(
+                "require [\"fileinto\", \"mailbox\"];\r\n",
+                "if header :contains \"subject\" \"report\" {\r\n",
+                "\tfileinto :create \"Work\";\r\n",
+                "}\r\n"
+            )
+            .as_bytes()
+            .to_vec(),
+            true,
+        )
+        .await
+        .unwrap();
+    for (from, subject) in [
+        ("john@external.org", "Quokka report"),
+        ("jane@example.com", "Quokka lunch"),
+        ("john@external.org", "Quokka greetings"),
+    ] {
+        lmtp.ingest(
+            from,
+            &["jdoe@example.com"],
+            &format!(
+                "From: {}\r\nTo: jdoe@example.com\r\nSubject: {}\r\n\r\nHello.",
+                from, subject
+            ),
+        )
+        .await;
+    }
+    let work_id = client
+        .mailbox_query(mailbox::query::Filter::name("Work").into(), None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    for (subject, mailbox_id, keywords) in [
+        ("Quokka report", &work_id, vec!["$external"]),
+        ("Quokka lunch", &inbox_id, vec![]),
+        ("Quokka greetings", &inbox_id, vec!["$external"]),
+    ] {
+        let email_id = client
+            .email_query(
+                email::query::Filter::subject(subject).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids()
+            .pop()
+            .unwrap();
+        let email = client
+            .email_get(
+                &email_id,
+                [email::Property::MailboxIds, email::Property::Keywords].into(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(email.mailbox_ids(), [mailbox_id.as_str()], "{}", subject);
+        assert_eq!(email.keywords(), keywords, "{}", subject);
+    }
+
     smtp_settings.lock().do_stop = true;
 
     // Remove test data
-    for account_id in [&account_id, &domain_id] {
+    for account_id in [&list_id, &account_id, &postmaster_id, &domain_id] {
         client
             .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
             .principal_destroy(account_id)
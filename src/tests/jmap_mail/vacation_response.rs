@@ -136,6 +136,83 @@ where
 
     expect_nothing(&mut smtp_rx).await;
 
+    // Automated and bulk messages should not trigger a vacation response
+    for (from, header) in [
+        ("robot@example.com", "Auto-Submitted: auto-generated"),
+        ("news@example.com", "Precedence: bulk"),
+    ] {
+        lmtp.ingest(
+            from,
+            &["jdoe@example.com"],
+            &format!(
+                "From: {}\r\nTo: jdoe@example.com\r\n{}\r\nSubject: Digest\r\n\r\nHi!",
+                from, header
+            ),
+        )
+        .await;
+
+        expect_nothing(&mut smtp_rx).await;
+    }
+
+    // Two accounts on vacation should not keep replying to each other,
+    // even when a relay strips the Auto-Submitted header
+    let jane_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .individual_create("jane@example.com", "12345", "Jane Doe")
+        .await
+        .unwrap()
+        .take_id();
+    client
+        .set_default_account_id(&jane_id)
+        .vacation_response_create(
+            "Gone fishing",
+            "Back next week".into(),
+            "Back <i>next week</i>".into(),
+        )
+        .await
+        .unwrap();
+    client.set_default_account_id(&account_id);
+
+    let mut message = concat!(
+        "From: jane@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: Lunch?\r\n",
+        "\r\n",
+        "Are you around for lunch on Friday?",
+    )
+    .to_string();
+    for (from, to) in [
+        ("jane@example.com", "jdoe@example.com"),
+        ("jdoe@example.com", "jane@example.com"),
+    ] {
+        lmtp.ingest(from, &[to], &message).await;
+        let reply = tokio::time::timeout(std::time::Duration::from_millis(3000), smtp_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.mail_from, format!("<{}>", to));
+        assert_eq!(reply.rcpt_to, vec![format!("<{}>", from)]);
+        assert!(
+            reply.message.contains("Auto-Submitted: auto-replied\r\n"),
+            "{}",
+            reply.message
+        );
+        message = reply
+            .message
+            .replace("Auto-Submitted: auto-replied\r\n", "");
+    }
+
+    // Resetting the vacation response clears its Sieve duplicate tracking,
+    // the reply is suppressed by the server-wide auto-reply window
+    client
+        .vacation_response_set_dates(None, None)
+        .await
+        .unwrap();
+    lmtp.ingest("jane@example.com", &["jdoe@example.com"], &message)
+        .await;
+
+    expect_nothing(&mut smtp_rx).await;
+
     // Vacation responses should honor the configured date ranges
     client
         .vacation_response_set_dates((Utc::now() + Duration::days(1)).timestamp().into(), None)
@@ -187,7 +264,7 @@ where
     .await;
 
     // Remove test data
-    for account_id in [&account_id, &domain_id] {
+    for account_id in [&account_id, &jane_id, &domain_id] {
         client
             .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
             .principal_destroy(account_id)
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Mock virus scanner, reports the message read from stdin as infected
+# when it contains the EICAR marker.
+if grep -q EICAR; then
+    echo "Eicar-Test-Signature"
+    exit 1
+fi
+exit 0
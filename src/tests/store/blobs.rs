@@ -21,7 +21,11 @@
  * for more details.
 */
 
-use std::{sync::Arc, time::SystemTime};
+use std::{
+    fs::File,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use store::{
     ahash::AHashMap,
@@ -130,6 +134,69 @@ where
     db.purge_blobs().unwrap();
     expected_count.remove(&blob_external);
     assert_eq!(expected_count, db.get_all_blobs());
+
+    archive(db);
+}
+
+fn archive<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob = vec![b'c'; 1024];
+    let blob_id = BlobId::new_external(&blob);
+    db.blob_store(&blob_id, blob.clone()).unwrap();
+    let mut document = Document::new(Collection::Mail, 3);
+    document.blob(blob_id.clone(), IndexOptions::new());
+    db.write(WriteBatch::insert(3, document)).unwrap();
+
+    let hot_path = db.blob_store.hot.get_path(&blob_id).unwrap();
+    let cold_path = db
+        .blob_store
+        .cold
+        .as_ref()
+        .unwrap()
+        .get_path(&blob_id)
+        .unwrap();
+
+    // Recent blobs are not archived
+    db.archive_blobs().unwrap();
+    assert!(hot_path.exists());
+    assert!(!cold_path.exists());
+
+    // Age the blob past the archival threshold and archive it
+    File::options()
+        .write(true)
+        .open(&hot_path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(db.blob_store.cold_after + 86400))
+        .unwrap();
+    db.archive_blobs().unwrap();
+    assert!(!hot_path.exists());
+    assert!(cold_path.exists());
+
+    // Archived blobs are still retrievable
+    assert_eq!(db.blob_get(&blob_id).unwrap(), Some(blob.clone()));
+    assert_eq!(
+        db.blob_get_range(&blob_id, 10..20).unwrap(),
+        Some(blob[10..20].to_vec())
+    );
+
+    // Unlink blob, purge and make sure it is removed from cold storage
+    let mut document = Document::new(Collection::Mail, 3);
+    document.blob(blob_id.clone(), IndexOptions::new().clear());
+    let mut wb = WriteBatch::new(3);
+    wb.update_document(document);
+    db.write(wb).unwrap();
+    db.db
+        .set(
+            ColumnFamily::Blobs,
+            &BlobKey::serialize_prefix(&blob_id, 0),
+            &0u64.serialize().unwrap(),
+        )
+        .unwrap();
+    db.purge_blobs().unwrap();
+    assert!(!cold_path.exists());
+    assert_eq!(db.blob_get(&blob_id).unwrap(), None);
 }
 
 trait GetAllBlobs {
@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    error::method::MethodError,
+    request::changes::ChangesRequest,
+    types::{jmap::JMAPId, state::JMAPState},
+};
+use jmap_mail::mail::changes::JMAPMailChanges;
+use store::{
+    core::{acl::ACLToken, collection::Collection},
+    log::changes::{ChangeId, Query},
+    write::batch::WriteBatch,
+    JMAPStore, Store,
+};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let log_change = |collection: Collection, id: u64, is_insert: bool| -> ChangeId {
+        let mut batch = WriteBatch::new(0);
+        if is_insert {
+            batch.log_insert(collection, id);
+        } else {
+            batch.log_delete(collection, id);
+        }
+        db.write(batch).unwrap().unwrap().change_id
+    };
+    let mail_changes = |since_state: JMAPState, max_changes: Option<usize>| {
+        db.mail_changes(ChangesRequest {
+            acl: Some(Arc::new(ACLToken {
+                member_of: vec![0],
+                access_to: vec![],
+            })),
+            account_id: JMAPId::new(0),
+            since_state,
+            max_changes,
+        })
+    };
+
+    // Log 20 messages and mailboxes, deleting a few messages halfway
+    let mut mail_states = Vec::new();
+    let mut mailbox_states = Vec::new();
+    for id in 0..20 {
+        mail_states.push(log_change(Collection::Mail, id, true));
+        mailbox_states.push(log_change(Collection::Mailbox, id, true));
+        if id == 9 {
+            for id in 0..3 {
+                log_change(Collection::Mail, id, false);
+            }
+        }
+    }
+
+    // Another account with a short history
+    let mut batch = WriteBatch::new(1);
+    for id in 0..5u64 {
+        batch.log_insert(Collection::Mail, id);
+    }
+    let other_state = db.write(batch).unwrap().unwrap().change_id;
+    let mut batch = WriteBatch::new(1);
+    batch.log_delete(Collection::Mail, 0u64);
+    db.write(batch).unwrap();
+
+    // Changes still referenced by the Raft log of a cluster are kept
+    db.compact_changes(Collection::Mail, 9, mail_states[5])
+        .unwrap();
+    assert!(matches!(
+        mail_changes(JMAPState::new_exact(mail_states[4]), None),
+        Err(MethodError::CannotCalculateChanges)
+    ));
+    assert_eq!(
+        mail_changes(JMAPState::new_exact(mail_states[5]), None)
+            .unwrap()
+            .created,
+        (6..20).map(JMAPId::new).collect::<Vec<_>>()
+    );
+
+    // Keep the 9 changes made after message 10 was logged
+    let horizon = mail_states[10];
+    for _ in 0..2 {
+        db.compact_changes(Collection::Mail, 9, ChangeId::MAX)
+            .unwrap();
+
+        // States older than the horizon can no longer be synchronized
+        for state in [mail_states[0], mail_states[9], horizon - 1] {
+            assert!(
+                matches!(
+                    mail_changes(JMAPState::new_exact(state), None),
+                    Err(MethodError::CannotCalculateChanges)
+                ),
+                "{}",
+                state
+            );
+        }
+
+        // Recent states are unaffected
+        assert_eq!(
+            mail_changes(JMAPState::new_exact(horizon), None)
+                .unwrap()
+                .created,
+            (11..20).map(JMAPId::new).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            mail_changes(JMAPState::new_exact(mail_states[15]), None)
+                .unwrap()
+                .created,
+            (16..20).map(JMAPId::new).collect::<Vec<_>>()
+        );
+
+        // Full listings start from the snapshot, also when paginated
+        let changes = mail_changes(JMAPState::Initial, None).unwrap();
+        assert_eq!(
+            changes.created,
+            (3..20).map(JMAPId::new).collect::<Vec<_>>()
+        );
+        assert!(changes.destroyed.is_empty());
+        let mut state = JMAPState::Initial;
+        let mut total_changes = 0;
+        loop {
+            let changes = mail_changes(state, Some(5)).unwrap();
+            total_changes += changes.created.len();
+            state = changes.new_state;
+            if !changes.has_more_changes {
+                break;
+            }
+        }
+        assert_eq!(total_changes, 17);
+
+        // Accounts with fewer changes keep their full history
+        assert_eq!(
+            db.get_changes(1, Collection::Mail, Query::Since(other_state))
+                .unwrap()
+                .unwrap()
+                .changes
+                .len(),
+            1
+        );
+
+        // Other collections keep their full history
+        assert_eq!(
+            db.get_changes(0, Collection::Mailbox, Query::Since(mailbox_states[0]))
+                .unwrap()
+                .unwrap()
+                .changes
+                .len(),
+            19
+        );
+    }
+}
@@ -30,10 +30,10 @@ use jmap::{
 use jmap_mail::mail::changes::JMAPMailChanges;
 use store::{
     ahash::AHashSet,
-    core::{acl::ACLToken, collection::Collection, error::StoreError},
+    core::{acl::ACLToken, bitmap::Bitmap, collection::Collection, error::StoreError},
     log::{entry::Entry, raft::RaftId},
     serialize::{key::LogKey, StoreDeserialize},
-    write::batch::WriteBatch,
+    write::batch::{self, WriteBatch},
     AccountId, ColumnFamily, Direction, JMAPStore, Store,
 };
 
@@ -92,6 +92,22 @@ where
         }
     }
 
+    // Corrupted raft entries fail their checksum
+    let (raft_id, mut entry) = mail_store
+        .get_raft_raw_entries(RaftId::none(), 1)
+        .unwrap()
+        .pop()
+        .unwrap();
+    let key = LogKey::serialize_raft(&raft_id);
+    assert!(Entry::deserialize_checked(&key, &entry).is_ok());
+    let pos = entry.len() - Entry::CHECKSUM_LEN - 1;
+    entry[pos] ^= 0xff;
+    assert!(Entry::deserialize(&entry).is_none());
+    assert!(matches!(
+        Entry::deserialize_checked(&key, &entry),
+        Err(StoreError::DataCorruption(_))
+    ));
+
     for (num, expected_inserted_id) in expected_inserted_ids.into_iter().enumerate() {
         let changes = mail_store
             .mail_changes(ChangesRequest {
@@ -109,6 +125,48 @@ where
         assert_eq!(changes.updated, vec![]);
         assert_eq!(changes.destroyed, vec![]);
     }
+
+    // Entries written before checksums were introduced are still readable
+    let legacy_account_id = (NUM_ACCOUNTS * 3) as AccountId;
+    let mut legacy_entry = vec![batch::Change::ENTRY];
+    legacy_entry.extend_from_slice(&legacy_account_id.to_le_bytes());
+    legacy_entry.extend_from_slice(&Bitmap::from(Collection::Mail).bitmap.to_le_bytes());
+    let legacy_id = RaftId::new(raft_id.term, raft_id.index + 1);
+    let key = LogKey::serialize_raft(&legacy_id);
+    match Entry::deserialize_checked(&key, &legacy_entry).unwrap() {
+        Entry::Item {
+            account_id,
+            changed_collections,
+        } => {
+            assert_eq!(account_id, legacy_account_id);
+            assert!(changed_collections.contains(Collection::Mail));
+        }
+        Entry::Snapshot { .. } => panic!("Expected log entry to be an item."),
+    }
+
+    // and compacted along with checksummed entries
+    mail_store
+        .db
+        .set(ColumnFamily::Logs, &key, &legacy_entry)
+        .unwrap();
+    mail_store.compact_log_up_to(legacy_id.index).unwrap();
+    match Entry::deserialize(
+        &mail_store
+            .get_raft_raw_entries(RaftId::none(), 1)
+            .unwrap()
+            .pop()
+            .unwrap()
+            .1,
+    )
+    .unwrap()
+    {
+        Entry::Item { .. } => panic!("Expected log entry to be a snapshot."),
+        Entry::Snapshot { changed_accounts } => {
+            assert!(changed_accounts
+                .into_iter()
+                .any(|(_, account_ids)| account_ids.contains(&legacy_account_id)));
+        }
+    }
 }
 
 pub fn assert_compaction<T>(mail_store: &JMAPStore<T>, num_accounts: usize)
@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{request::set::SetRequest, types::jmap::JMAPId};
+use jmap_mail::mailbox::{
+    get::JMAPGetMailbox, join_mailbox_path, schema::Mailbox, set::JMAPSetMailbox,
+    split_mailbox_path,
+};
+use store::{core::acl::ACLToken, core::collection::Collection, JMAPStore, Store};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let separator = db.config.mailbox_path_separator;
+    let path = |names: &[&str]| join_mailbox_path(names.iter().copied(), separator);
+
+    // Names containing separators or backslashes survive a round trip
+    for names in [
+        vec!["Inbox"],
+        vec!["Work", "Q1/Q2", "v1.2"],
+        vec!["Archive", "C:\\Temp\\", "a\\/b", "a\\.b"],
+    ] {
+        let joined = path(&names);
+        assert_eq!(split_mailbox_path(&joined, separator), names, "{}", joined);
+    }
+    assert_eq!(
+        split_mailbox_path(&format!(" {0}Work{0}{0} Reports {0}", separator), separator),
+        ["Work", "Reports"]
+    );
+
+    let account_id = JMAPId::new(0).to_string();
+    let mut request = serde_json::from_value::<SetRequest<Mailbox>>(serde_json::json!({
+        "accountId": &account_id,
+        "create": {
+            "w": {"name": "Work"},
+            "q": {"name": "Q1/Q2", "parentId": "#w"},
+            "v": {"name": "v1.2", "parentId": "#w"}
+        }
+    }))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![0],
+        access_to: vec![],
+    })
+    .into();
+    let response = serde_json::to_value(&db.mailbox_set(request).unwrap()).unwrap();
+    let created_id = |id: &str| {
+        JMAPId::parse(response["created"][id]["id"].as_str().unwrap())
+            .unwrap()
+            .get_document_id()
+    };
+    let quarter_id = created_id("q");
+    let version_id = created_id("v");
+
+    // Separators within names are escaped in paths
+    let (quarter_path, version_path) = match separator {
+        '/' => ("Work/Q1\\/Q2", "Work/v1.2"),
+        '.' => ("Work.Q1/Q2", "Work.v1\\.2"),
+        _ => unreachable!(),
+    };
+    assert_eq!(path(&["Work", "Q1/Q2"]), quarter_path);
+    assert_eq!(path(&["Work", "v1.2"]), version_path);
+    assert_eq!(
+        db.mailbox_get_by_name(0, quarter_path).unwrap(),
+        Some(quarter_id)
+    );
+    assert_eq!(
+        db.mailbox_get_by_name(0, version_path).unwrap(),
+        Some(version_id)
+    );
+
+    // Unescaped separators always denote a level in the hierarchy
+    assert_eq!(
+        db.mailbox_get_by_name(0, &path(&["Work", "Q1", "Q2"]))
+            .unwrap(),
+        None
+    );
+
+    // Only the missing mailboxes along a path are created
+    let reports_path = path(&["Work", "Reports/2022.1"]);
+    let (reports_id, _) = db.mailbox_create_path(0, &reports_path).unwrap().unwrap();
+    assert_eq!(
+        db.mailbox_get_by_name(0, &reports_path).unwrap(),
+        Some(reports_id)
+    );
+    assert_eq!(
+        db.get_document_ids(0, Collection::Mailbox)
+            .unwrap()
+            .unwrap()
+            .len(),
+        4
+    );
+}
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::set::SetRequest,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::{
+    mail::{
+        import::{EmailImportRequest, JMAPMailImport},
+        schema::Email,
+        set::JMAPSetMail,
+    },
+    mailbox::{schema::Mailbox, set::JMAPSetMailbox},
+};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    assert_eq!(db.config.mail_max_messages, 3);
+
+    let account_id = JMAPId::new(0).to_string();
+    let acl = Arc::new(ACLToken {
+        member_of: vec![0],
+        access_to: vec![],
+    });
+
+    let mut request = serde_json::from_value::<SetRequest<Mailbox>>(serde_json::json!({
+        "accountId": &account_id,
+        "create": {"i": {"name": "Inbox"}}
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = serde_json::to_value(&db.mailbox_set(request).unwrap()).unwrap();
+    let mailbox_id = response["created"]["i"]["id"].as_str().unwrap().to_string();
+
+    // Imports past the maximum number of messages are rejected
+    let mut emails = serde_json::Map::new();
+    for message_num in 0..4 {
+        let raw_message =
+            format!("Subject: Message {}\r\n\r\nHello.\r\n", message_num).into_bytes();
+        let blob_id = BlobId::new_external(&raw_message);
+        db.blob_store(&blob_id, raw_message).unwrap();
+        db.blob_link_ephemeral(&blob_id, 0).unwrap();
+        emails.insert(
+            format!("m{}", message_num),
+            serde_json::json!({
+                "blobId": JMAPBlob::new(blob_id).to_string(),
+                "mailboxIds": {&mailbox_id: true}
+            }),
+        );
+    }
+    let mut request = serde_json::from_value::<EmailImportRequest>(serde_json::json!({
+        "accountId": &account_id,
+        "emails": emails
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = serde_json::to_value(&db.mail_import(request).unwrap()).unwrap();
+    for message_num in 0..3 {
+        assert!(
+            response["created"][&format!("m{}", message_num)]["id"].is_string(),
+            "{:?}",
+            response
+        );
+    }
+    assert_eq!(
+        response["notCreated"]["m3"]["type"], "overQuota",
+        "{:?}",
+        response
+    );
+    let email_id = response["created"]["m0"]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Creating messages past the limit is rejected as well
+    let set = |arguments: serde_json::Value| {
+        let mut request = serde_json::json!({ "accountId": &account_id });
+        for (key, value) in arguments.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+        let mut request = serde_json::from_value::<SetRequest<Email>>(request).unwrap();
+        request.acl = acl.clone().into();
+        serde_json::to_value(&db.mail_set(request).unwrap()).unwrap()
+    };
+    let create = serde_json::json!({"create": {"d": {
+        "mailboxIds": {&mailbox_id: true},
+        "subject": "Draft",
+        "textBody": [{"partId": "1", "type": "text/plain"}],
+        "bodyValues": {"1": {"value": "Hello."}}
+    }}});
+    let response = set(create.clone());
+    assert_eq!(
+        response["notCreated"]["d"]["type"], "overQuota",
+        "{:?}",
+        response
+    );
+
+    // Messages can be created again once others are destroyed
+    let response = set(serde_json::json!({ "destroy": [&email_id] }));
+    assert_eq!(response["destroyed"], serde_json::json!([&email_id]));
+    let response = set(create);
+    assert!(response["created"]["d"]["id"].is_string(), "{:?}", response);
+}
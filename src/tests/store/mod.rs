@@ -22,8 +22,14 @@
 */
 
 pub mod blobs;
+pub mod changelog_retention;
 pub mod log;
+pub mod mailbox_path;
+pub mod message_limit;
 pub mod query;
+pub mod query_limit;
+pub mod threads;
+pub mod unseen_query;
 pub mod utils;
 
 use std::{path::PathBuf, sync::Arc};
@@ -64,8 +70,18 @@ where
 #[test]
 #[ignore]
 fn store_tests() {
-    let (db, temp_dir) = init_db::<RocksDB>("strdb_store", true);
-    let db = Arc::new(db);
+    let (mut settings, temp_dir) = init_settings("strdb_store", 1, 1, true);
+    let mut cold_dir = temp_dir.clone();
+    cold_dir.push("cold");
+    settings.set_value(
+        "blob-cold-path".to_string(),
+        cold_dir.to_str().unwrap().to_string(),
+    );
+    let db = Arc::new(JMAPStore::new(
+        RocksDB::open(&settings).unwrap(),
+        JMAPConfig::from(&settings),
+        &settings,
+    ));
 
     blobs::test(db.clone());
     log::test(db.clone());
@@ -73,3 +89,54 @@ fn store_tests() {
 
     destroy_temp_dir(&temp_dir);
 }
+
+#[test]
+#[ignore]
+fn store_config_tests() {
+    run_with_config(
+        "strdb_threads",
+        |config| config.mail_max_thread_size = 3,
+        threads::test,
+    );
+    run_with_config(
+        "strdb_query_limit",
+        |config| config.query_max_total = 5,
+        query_limit::test,
+    );
+    run_with_config(
+        "strdb_message_limit",
+        |config| config.mail_max_messages = 3,
+        message_limit::test,
+    );
+    for separator in ['/', '.'] {
+        run_with_config(
+            "strdb_mailbox_path",
+            |config| config.mailbox_path_separator = separator,
+            mailbox_path::test,
+        );
+    }
+    run_with_config("strdb_unseen_query", |_| (), unseen_query::test);
+    run_with_config(
+        "strdb_changelog_retention",
+        |_| (),
+        changelog_retention::test,
+    );
+}
+
+fn run_with_config(
+    name: &str,
+    configure: impl FnOnce(&mut JMAPConfig),
+    test: impl FnOnce(JMAPStore<RocksDB>),
+) {
+    let (settings, temp_dir) = init_settings(name, 1, 1, true);
+    let mut config = JMAPConfig::from(&settings);
+    configure(&mut config);
+
+    test(JMAPStore::new(
+        RocksDB::open(&settings).unwrap(),
+        config,
+        &settings,
+    ));
+
+    destroy_temp_dir(&temp_dir);
+}
@@ -209,6 +209,9 @@ where
     println!("Running filter tests...");
     test_filter(db.clone());
 
+    println!("Running filter order benchmark...");
+    bench_filter_order(db.clone());
+
     println!("Running sort tests...");
     test_sort(db);
 }
@@ -468,6 +471,84 @@ where
         }
         assert_eq!(results, expected_results);
     }
+
+    // Range boundaries must match exactly the entries equal to the value
+    for year in [1830, 1900, 2000] {
+        let count = |op| {
+            db.query_store::<FilterMapper>(
+                0,
+                Collection::Mail,
+                Filter::new_condition(fields["year"], op, Query::Integer(year)),
+                Comparator::None,
+            )
+            .unwrap()
+            .count()
+        };
+        let equal = count(ComparisonOperator::Equal);
+        assert!(equal > 0, "{}", year);
+        assert_eq!(
+            count(ComparisonOperator::LowerEqualThan),
+            count(ComparisonOperator::LowerThan) + equal,
+            "{}",
+            year
+        );
+        assert_eq!(
+            count(ComparisonOperator::GreaterEqualThan),
+            count(ComparisonOperator::GreaterThan) + equal,
+            "{}",
+            year
+        );
+    }
+}
+
+pub fn bench_filter_order<T>(db: Arc<JMAPStore<T>>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut fields = AHashMap::default();
+    for (field_num, field) in FIELDS.iter().enumerate() {
+        fields.insert(field.to_string(), field_num as u8);
+    }
+
+    // A selective structured filter combined with a full-text phrase should
+    // return the same results regardless of the order of the conditions.
+    let selective = || {
+        Filter::new_condition(
+            fields["year"],
+            ComparisonOperator::Equal,
+            Query::Integer(1830),
+        )
+    };
+    let full_text = || {
+        Filter::new_condition(
+            fields["title"],
+            ComparisonOperator::Equal,
+            Query::match_english("'study for'".into()),
+        )
+    };
+
+    let mut results = Vec::new();
+    for filter in [
+        Filter::and(vec![full_text(), selective()]),
+        Filter::and(vec![selective(), full_text()]),
+        Filter::and(vec![full_text()]),
+    ] {
+        let now = Instant::now();
+        let ids = db
+            .query_store::<FilterMapper>(0, Collection::Mail, filter, Comparator::None)
+            .unwrap()
+            .collect::<Vec<_>>();
+        println!(
+            "Filtered {} entries in {} ms.",
+            ids.len(),
+            now.elapsed().as_millis()
+        );
+        results.push(ids);
+    }
+
+    assert!(!results[0].is_empty());
+    assert_eq!(results[0], results[1]);
+    assert!(results[0].len() < results[2].len());
 }
 
 pub fn test_sort<T>(db: Arc<JMAPStore<T>>)
@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{request::query::QueryRequest, types::jmap::JMAPId};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    schema::{Email, Property, Value},
+};
+use store::{blob::BlobId, core::acl::ACLToken, JMAPStore, Store};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    assert_eq!(db.config.query_max_total, 5);
+
+    let mut ids = Vec::new();
+    for message_num in 0..10 {
+        let raw_message =
+            format!("Subject: Message {}\r\n\r\nHello.\r\n", message_num).into_bytes();
+        let blob_id = BlobId::new_external(&raw_message);
+        let raw_message = db.blob_store(&blob_id, raw_message).unwrap();
+
+        let (email, _) = db
+            .mail_import_item(
+                0,
+                blob_id,
+                &raw_message,
+                vec![0],
+                vec![],
+                Some(1000 + message_num),
+            )
+            .unwrap();
+        match email.properties.get(&Property::Id) {
+            Some(Value::Id { value }) => ids.push(value.to_string()),
+            _ => panic!("Missing id: {:?}", email),
+        }
+    }
+
+    // Queries matching more than 'query-max-total' messages are truncated
+    for (position, limit, expected_ids) in [
+        (None, None, &ids[0..5]),
+        (Some(3), Some(10), &ids[3..5]),
+        (Some(1), Some(2), &ids[1..3]),
+        (Some(6), None, &ids[0..0]),
+    ] {
+        let mut request = serde_json::from_value::<QueryRequest<Email>>(serde_json::json!({
+            "accountId": JMAPId::new(0).to_string(),
+            "sort": [{"property": "receivedAt", "isAscending": true}],
+            "position": position,
+            "limit": limit,
+            "calculateTotal": true
+        }))
+        .unwrap();
+        request.acl = Arc::new(ACLToken {
+            member_of: vec![0],
+            access_to: vec![],
+        })
+        .into();
+        let response = serde_json::to_value(&db.mail_query(request).unwrap()).unwrap();
+
+        assert_eq!(
+            response["ids"],
+            serde_json::json!(expected_ids),
+            "{:?}",
+            response
+        );
+        assert_eq!(response["total"], 5, "{:?}", response);
+        assert_eq!(response["isTruncated"], true, "{:?}", response);
+    }
+
+    // Collapsed threads do not count towards the limit
+    for message_num in 0..8 {
+        let raw_message = if message_num < 6 {
+            format!(
+                concat!(
+                    "Message-ID: <thread-{}@example.org>\r\n",
+                    "References: <thread-0@example.org>\r\n",
+                    "Subject: Re: Thread\r\n\r\nHello.\r\n"
+                ),
+                message_num
+            )
+        } else {
+            format!("Subject: Other {}\r\n\r\nHello.\r\n", message_num)
+        }
+        .into_bytes();
+        let blob_id = BlobId::new_external(&raw_message);
+        let raw_message = db.blob_store(&blob_id, raw_message).unwrap();
+        db.mail_import_item(
+            1,
+            blob_id,
+            &raw_message,
+            vec![0],
+            vec![],
+            Some(1000 + message_num),
+        )
+        .unwrap();
+    }
+
+    let mut request = serde_json::from_value::<QueryRequest<Email>>(serde_json::json!({
+        "accountId": JMAPId::new(1).to_string(),
+        "collapseThreads": true,
+        "calculateTotal": true
+    }))
+    .unwrap();
+    request.acl = Arc::new(ACLToken {
+        member_of: vec![1],
+        access_to: vec![],
+    })
+    .into();
+    let response = serde_json::to_value(&db.mail_query(request).unwrap()).unwrap();
+
+    assert_eq!(
+        response["ids"].as_array().unwrap().len(),
+        3,
+        "{:?}",
+        response
+    );
+    assert_eq!(response["total"], 3, "{:?}", response);
+    assert!(response.get("isTruncated").is_none(), "{:?}", response);
+}
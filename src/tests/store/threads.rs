@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    schema::{Property, Value},
+};
+use store::{blob::BlobId, JMAPStore, Store};
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    assert_eq!(db.config.mail_max_thread_size, 3);
+
+    // Threads stop growing once they reach the maximum size
+    let mut thread_ids = Vec::new();
+    for message_num in 1..=5 {
+        let raw_message = if message_num == 1 {
+            "Message-ID: <1@example.com>\r\nSubject: Hello\r\n\r\nHello.\r\n".to_string()
+        } else {
+            format!(
+                concat!(
+                    "Message-ID: <{}@example.com>\r\n",
+                    "References: <1@example.com>\r\n",
+                    "Subject: Re: Hello\r\n",
+                    "\r\n",
+                    "Reply {}.\r\n"
+                ),
+                message_num, message_num
+            )
+        }
+        .into_bytes();
+        let blob_id = BlobId::new_external(&raw_message);
+        let raw_message = db.blob_store(&blob_id, raw_message).unwrap();
+
+        let (email, _) = db
+            .mail_import_item(0, blob_id, &raw_message, vec![0], vec![], None)
+            .unwrap();
+        match email.properties.get(&Property::ThreadId) {
+            Some(Value::Id { value }) => thread_ids.push(*value),
+            _ => panic!("Missing threadId: {:?}", email),
+        }
+    }
+
+    // The fourth message starts a new thread, the fifth one joins it
+    assert_eq!(thread_ids[0], thread_ids[1]);
+    assert_eq!(thread_ids[0], thread_ids[2]);
+    assert_ne!(thread_ids[0], thread_ids[3]);
+    assert_eq!(thread_ids[3], thread_ids[4]);
+}
@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    request::query::QueryRequest,
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    import::JMAPMailImport,
+    query::JMAPMailQuery,
+    schema::{Email, Keyword, Property, Value},
+    MessageField,
+};
+use store::{
+    ahash::AHashSet,
+    blob::BlobId,
+    core::{acl::ACLToken, collection::Collection, tag::Tag},
+    read::filter::ComparisonOperator,
+    roaring::RoaringBitmap,
+    serialize::key::IndexKey,
+    DocumentId, JMAPStore, LongInteger, Store,
+};
+
+const NUM_MESSAGES: i64 = 1000;
+const BASE_TIME: i64 = 1_600_000_000;
+
+pub fn test<T>(db: JMAPStore<T>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Import a large mailbox, two messages per timestamp, most of them already read
+    let mut unseen = Vec::new();
+    for message_num in 0..NUM_MESSAGES {
+        let raw_message =
+            format!("Subject: Message {}\r\n\r\nHello.\r\n", message_num).into_bytes();
+        let blob_id = BlobId::new_external(&raw_message);
+        let raw_message = db.blob_store(&blob_id, raw_message).unwrap();
+        let received_at = BASE_TIME + (message_num / 2) * 60;
+        let is_unseen =
+            message_num % 17 == 0 || (message_num >= NUM_MESSAGES - 30 && message_num % 3 == 0);
+
+        let (email, _) = db
+            .mail_import_item(
+                0,
+                blob_id,
+                &raw_message,
+                vec![0],
+                if is_unseen {
+                    vec![]
+                } else {
+                    vec![Tag::Static(Keyword::SEEN)]
+                },
+                Some(received_at),
+            )
+            .unwrap();
+        if is_unseen {
+            match email.properties.get(&Property::Id) {
+                Some(Value::Id { value }) => unseen.push((value.to_string(), received_at)),
+                _ => panic!("Missing id: {:?}", email),
+            }
+        }
+    }
+
+    let query = |filter: serde_json::Value| -> AHashSet<String> {
+        let mut request = serde_json::from_value::<QueryRequest<Email>>(serde_json::json!({
+            "accountId": JMAPId::new(0).to_string(),
+            "filter": filter,
+        }))
+        .unwrap();
+        request.acl = Arc::new(ACLToken {
+            member_of: vec![0],
+            access_to: vec![],
+        })
+        .into();
+        let response = serde_json::to_value(&db.mail_query(request).unwrap()).unwrap();
+        response["ids"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|id| id.as_str().unwrap().to_string())
+            .collect()
+    };
+
+    // Combining 'notKeyword' with a receivedAt range returns the same results as
+    // evaluating both conditions separately and intersecting them
+    let unseen_ids = query(serde_json::json!({"notKeyword": "$seen"}));
+    assert_eq!(unseen_ids.len(), unseen.len());
+    for (timestamp, is_after) in [
+        (BASE_TIME - 1, true),
+        (BASE_TIME, true),
+        (BASE_TIME + 100 * 60, true),
+        (BASE_TIME + 100 * 60 + 1, true),
+        (BASE_TIME + 490 * 60, true),
+        (BASE_TIME + 499 * 60, true),
+        (BASE_TIME + 500 * 60, true),
+        (BASE_TIME, false),
+        (BASE_TIME + 1, false),
+        (BASE_TIME + 250 * 60, false),
+        (BASE_TIME + 499 * 60, false),
+        (BASE_TIME + 500 * 60, false),
+    ] {
+        let date = JMAPDate::from_timestamp(timestamp).to_string();
+        let range = if is_after {
+            serde_json::json!({ "after": date })
+        } else {
+            serde_json::json!({ "before": date })
+        };
+        let expected = unseen
+            .iter()
+            .filter(|(_, received_at)| {
+                if is_after {
+                    *received_at > timestamp
+                } else {
+                    *received_at < timestamp
+                }
+            })
+            .map(|(id, _)| id.clone())
+            .collect::<AHashSet<_>>();
+
+        let naive = query(range.clone())
+            .intersection(&unseen_ids)
+            .cloned()
+            .collect::<AHashSet<_>>();
+        assert_eq!(naive, expected, "{}", range);
+
+        let mut combined = range.clone();
+        combined["notKeyword"] = "$seen".into();
+        assert_eq!(query(combined), expected, "{}", range);
+        assert_eq!(
+            query(serde_json::json!({
+                "operator": "AND",
+                "conditions": [range.clone(), {"notKeyword": "$seen"}]
+            })),
+            expected,
+            "{}",
+            range
+        );
+    }
+
+    // Scanning a range for the remaining candidates only returns the documents
+    // that scanning the whole range would have returned
+    let unseen_document_ids = unseen
+        .iter()
+        .map(|(id, _)| JMAPId::parse(id).unwrap().get_document_id())
+        .collect::<RoaringBitmap>();
+    for candidates in [
+        unseen_document_ids,
+        (0..NUM_MESSAGES as DocumentId)
+            .step_by(7)
+            .collect::<RoaringBitmap>(),
+        RoaringBitmap::new(),
+    ] {
+        for timestamp in [
+            BASE_TIME - 1,
+            BASE_TIME,
+            BASE_TIME + 250 * 60,
+            BASE_TIME + 499 * 60,
+            BASE_TIME + 500 * 60,
+        ] {
+            let key = IndexKey::serialize_key(
+                0,
+                Collection::Mail,
+                MessageField::ReceivedAt.into(),
+                &(timestamp as LongInteger).to_be_bytes(),
+            );
+            for op in [
+                ComparisonOperator::LowerThan,
+                ComparisonOperator::LowerEqualThan,
+                ComparisonOperator::GreaterThan,
+                ComparisonOperator::GreaterEqualThan,
+                ComparisonOperator::Equal,
+            ] {
+                assert_eq!(
+                    db.range_to_bitmap_with_candidates(&key, op, &candidates)
+                        .unwrap(),
+                    db.range_to_bitmap(&key, op)
+                        .unwrap()
+                        .map(|bm| bm & &candidates),
+                    "{:?} {}",
+                    op,
+                    timestamp
+                );
+            }
+        }
+    }
+}
@@ -34,7 +34,7 @@ use jmap_mail::mail::schema::Email;
 use jmap_mail::mailbox::schema::Mailbox;
 use jmap_sieve::sieve_script::schema::SieveScript;
 use store::ahash::AHashSet;
-use store::serialize::key::ValueKey;
+use store::serialize::key::{ValueKey, SCHEMA_VERSIONS_KEY};
 use store::serialize::leb128::Leb128Reader;
 use store::{ahash::AHashMap, blob::BLOB_HASH_LEN};
 use store::{
@@ -176,16 +176,88 @@ pub fn init_settings(
                 format!("http://127.0.0.1:{}", 8000 + peer_num),
             ),
             ("lmtp-port".to_string(), (11200 + peer_num).to_string()),
+            (
+                "lmtp-proxy-trusted-ips".to_string(),
+                "127.0.0.2".to_string(),
+            ),
+            ("lmtp-duplicate-rcpt".to_string(), "reject".to_string()),
+            (
+                "lmtp-rcpt-callout-url".to_string(),
+                "http://127.0.0.1:9010/verify".to_string(),
+            ),
+            (
+                "lmtp-rcpt-callout-domains".to_string(),
+                "example.edu".to_string(),
+            ),
+            (
+                "lmtp-group-per-member".to_string(),
+                "team@example.com".to_string(),
+            ),
+            (
+                "lmtp-header-keywords".to_string(),
+                "Importance:high:$important;jane@example.com/Precedence:bulk:$bulk".to_string(),
+            ),
+            (
+                "lmtp-postmaster".to_string(),
+                "postmaster@example.com".to_string(),
+            ),
+            (
+                "lmtp-greeting".to_string(),
+                "{hostname} Test LMTP ready".to_string(),
+            ),
+            (
+                "lmtp-help".to_string(),
+                "Ask the {hostname} postmaster".to_string(),
+            ),
+            ("srs-secret".to_string(), "srs-test-secret".to_string()),
+            // Test accounts use short passwords such as "12345"
+            ("password-min-length".to_string(), "5".to_string()),
             ("max-objects-in-set".to_string(), "100000".to_string()),
             ("query-max-results".to_string(), "100000".to_string()),
             ("jmap-port".to_string(), (8000 + peer_num).to_string()),
+            ("http-header-timeout".to_string(), "1000".to_string()),
+            ("http-body-timeout".to_string(), "2000".to_string()),
             ("smtp-relay-host".to_string(), "127.0.0.1".to_string()),
             ("smtp-relay-port".to_string(), "9999".to_string()),
-            ("smtp-relay-tls".to_string(), "false".to_string()),
+            ("smtp-relay-tls".to_string(), "opportunistic".to_string()),
+            (
+                "smtp-relay-allow-invalid-certs".to_string(),
+                "true".to_string(),
+            ),
+            (
+                "smtp-relay-require-tls".to_string(),
+                "tls-required.org".to_string(),
+            ),
+            (
+                "smtp-relay-strip-headers".to_string(),
+                "Received;X-Originating-IP;User-Agent".to_string(),
+            ),
+            (
+                "dead-letter-account".to_string(),
+                "postmaster@example.com".to_string(),
+            ),
+            (
+                "submission-max-delay".to_string(),
+                (100 * 365 * 86400).to_string(),
+            ),
             ("max-concurrent-uploads".to_string(), "4".to_string()),
+            ("max-download-rate".to_string(), "1000000".to_string()),
+            ("mail-import-partial".to_string(), "true".to_string()),
+            (
+                "mail-inline-images-min-size".to_string(),
+                "1000".to_string(),
+            ),
             ("max-concurrent-requests".to_string(), "8".to_string()),
             ("push-attempt-interval".to_string(), "500".to_string()),
             ("push-throttle".to_string(), "500".to_string()),
+            (
+                "push-vapid-key".to_string(),
+                "cKDeqOb5PNE91TKUDNKgSl4nAgitL4IcVxAwpg_S0UY".to_string(),
+            ),
+            (
+                "push-vapid-subject".to_string(),
+                "mailto:admin@example.com".to_string(),
+            ),
             ("event-source-throttle".to_string(), "500".to_string()),
             ("ws-throttle".to_string(), "500".to_string()),
             ("oauth-user-code-expiry".to_string(), "1".to_string()),
@@ -200,6 +272,15 @@ pub fn init_settings(
                 "1000/60".to_string(),
             ),
             ("max-size-upload".to_string(), "50000000".to_string()),
+            (
+                "mail-attachments-max-size".to_string(),
+                "5000000".to_string(),
+            ),
+            ("mail-build-max-size".to_string(), "6000000".to_string()),
+            (
+                "sieve-account-limits".to_string(),
+                "jdoe@example.com: redirects=2".to_string(),
+            ),
             (
                 "encryption-key".to_string(),
                 "parerga_und_paralipomena".to_string(),
@@ -207,6 +288,15 @@ pub fn init_settings(
         ]
         .into_iter(),
     );
+    let mut pgp_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    pgp_dir.push("src");
+    pgp_dir.push("tests");
+    pgp_dir.push("resources");
+    pgp_dir.push("pgp");
+    args.insert(
+        "mail-pgp-keys-path".to_string(),
+        pgp_dir.to_str().unwrap().to_string(),
+    );
     if total_peers > 1 {
         let mut pem_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pem_dir.push("src");
@@ -633,7 +723,9 @@ where
                             value
                         );
                     }
-                    ColumnFamily::Values if (0..=9).contains(&key[0]) => {
+                    ColumnFamily::Values
+                        if (0..=9).contains(&key[0]) && &key[..] != SCHEMA_VERSIONS_KEY =>
+                    {
                         panic!("{:?} {:?}={:?}", cf, key, value);
                     }
                     ColumnFamily::Indexes => {